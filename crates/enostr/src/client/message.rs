@@ -3,11 +3,15 @@ use nostrdb::Filter;
 use serde_json::json;
 
 /// Messages sent by clients, received by relays
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum ClientMessage {
     Event {
         note: Note,
     },
+    /// NIP-42 response to a relay's AUTH challenge
+    Auth {
+        note: Note,
+    },
     Req {
         sub_id: String,
         filters: Vec<Filter>,
@@ -15,6 +19,24 @@ pub enum ClientMessage {
     Close {
         sub_id: String,
     },
+    /// NIP-45 request for the number of events matching some filters
+    Count {
+        sub_id: String,
+        filters: Vec<Filter>,
+    },
+    /// NIP-77 start of a negentropy reconciliation for a filter
+    NegOpen {
+        sub_id: String,
+        filter: Filter,
+        message: String,
+    },
+    NegMsg {
+        sub_id: String,
+        message: String,
+    },
+    NegClose {
+        sub_id: String,
+    },
     Raw(String),
 }
 
@@ -23,6 +45,10 @@ impl ClientMessage {
         ClientMessage::Event { note }
     }
 
+    pub fn auth(note: Note) -> Self {
+        ClientMessage::Auth { note }
+    }
+
     pub fn raw(raw: String) -> Self {
         ClientMessage::Raw(raw)
     }
@@ -35,25 +61,67 @@ impl ClientMessage {
         ClientMessage::Close { sub_id }
     }
 
+    pub fn count(sub_id: String, filters: Vec<Filter>) -> Self {
+        ClientMessage::Count { sub_id, filters }
+    }
+
+    pub fn neg_open(sub_id: String, filter: Filter, message: String) -> Self {
+        ClientMessage::NegOpen {
+            sub_id,
+            filter,
+            message,
+        }
+    }
+
+    pub fn neg_msg(sub_id: String, message: String) -> Self {
+        ClientMessage::NegMsg { sub_id, message }
+    }
+
+    pub fn neg_close(sub_id: String) -> Self {
+        ClientMessage::NegClose { sub_id }
+    }
+
     pub fn to_json(&self) -> Result<String, Error> {
         Ok(match self {
             Self::Event { note } => json!(["EVENT", note]).to_string(),
+            Self::Auth { note } => json!(["AUTH", note]).to_string(),
             Self::Raw(raw) => raw.clone(),
-            Self::Req { sub_id, filters } => {
-                if filters.is_empty() {
-                    format!("[\"REQ\",\"{}\",{{ }}]", sub_id)
-                } else if filters.len() == 1 {
-                    let filters_json_str = filters[0].json()?;
-                    format!("[\"REQ\",\"{}\",{}]", sub_id, filters_json_str)
-                } else {
-                    let filters_json_str: Result<Vec<String>, Error> = filters
-                        .iter()
-                        .map(|f| f.json().map_err(Into::<Error>::into))
-                        .collect();
-                    format!("[\"REQ\",\"{}\",{}]", sub_id, filters_json_str?.join(","))
-                }
-            }
+            Self::Req { sub_id, filters } => filters_msg("REQ", sub_id, filters)?,
+            Self::Count { sub_id, filters } => filters_msg("COUNT", sub_id, filters)?,
             Self::Close { sub_id } => json!(["CLOSE", sub_id]).to_string(),
+            Self::NegOpen {
+                sub_id,
+                filter,
+                message,
+            } => format!(
+                "[\"NEG-OPEN\",{},{},{}]",
+                json!(sub_id),
+                filter.json()?,
+                json!(message)
+            ),
+            Self::NegMsg { sub_id, message } => json!(["NEG-MSG", sub_id, message]).to_string(),
+            Self::NegClose { sub_id } => json!(["NEG-CLOSE", sub_id]).to_string(),
         })
     }
 }
+
+/// REQ and COUNT share the same layout
+fn filters_msg(cmd: &str, sub_id: &str, filters: &[Filter]) -> Result<String, Error> {
+    Ok(if filters.is_empty() {
+        format!("[\"{}\",\"{}\",{{ }}]", cmd, sub_id)
+    } else if filters.len() == 1 {
+        let filters_json_str = filters[0].json()?;
+        format!("[\"{}\",\"{}\",{}]", cmd, sub_id, filters_json_str)
+    } else {
+        let filters_json_str: Result<Vec<String>, Error> = filters
+            .iter()
+            .map(|f| f.json().map_err(Into::<Error>::into))
+            .collect();
+        format!(
+            "[\"{}\",\"{}\",{}]",
+            cmd,
+            sub_id,
+            filters_json_str?.join(",")
+        )
+    })
+}
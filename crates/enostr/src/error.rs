@@ -1,4 +1,5 @@
 //use nostr::prelude::secp256k1;
+use crate::filter::InvalidFilter;
 use std::array::TryFromSliceError;
 use thiserror::Error;
 
@@ -25,10 +26,22 @@ pub enum Error {
     #[error("invalid public key")]
     InvalidPublicKey,
 
+    #[error("invalid secret key")]
+    InvalidSecretKey,
+
+    #[error("encryption failed: {0}")]
+    EncryptFailed(&'static str),
+
+    #[error("decryption failed: {0}")]
+    DecryptFailed(&'static str),
+
     // Secp(secp256k1::Error),
     #[error("json error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[error("invalid filter: {0}")]
+    InvalidFilter(#[from] InvalidFilter),
+
     #[error("nostrdb error: {0}")]
     Nostrdb(#[from] nostrdb::Error),
 
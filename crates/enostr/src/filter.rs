@@ -1 +1,435 @@
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
 pub type Filter = nostrdb::Filter;
+
+/// Kinds are 16 bit, see NIP-01
+const MAX_KIND: u64 = 65535;
+
+/// What's wrong with a filter, worded so it can be shown to whoever wrote
+/// it
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InvalidFilter {
+    #[error("not valid json: {0}")]
+    Json(String),
+
+    #[error("a filter has to be a json object")]
+    NotAnObject,
+
+    #[error("unknown field \"{0}\"")]
+    UnknownField(String),
+
+    #[error("\"{field}\" should be {expected}")]
+    WrongType {
+        field: String,
+        expected: &'static str,
+    },
+
+    #[error("\"{0}\" is empty, it would never match anything")]
+    Empty(String),
+
+    #[error("\"{value}\" in \"{field}\" is not a 64 character hex string")]
+    BadHex { field: String, value: String },
+
+    #[error("\"{0}\" is not a tag filter, those are # and a single letter")]
+    BadTag(String),
+
+    #[error("kind {0} is too big, kinds go up to 65535")]
+    KindTooBig(u64),
+
+    #[error("since ({since}) is after until ({until})")]
+    SinceAfterUntil { since: u64, until: u64 },
+
+    #[error("search is empty")]
+    EmptySearch,
+}
+
+/// A filter we can look at and check before handing it to nostrdb or a
+/// relay. Lists are sets, so two specs that match the same thing compare
+/// equal and have the same [`FilterSpec::key`], no matter how they were
+/// written.
+///
+/// Fields that are `None` don't constrain anything. An empty set is kept
+/// as is, and is an error, since it matches nothing.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct FilterSpec {
+    pub ids: Option<BTreeSet<[u8; 32]>>,
+    pub authors: Option<BTreeSet<[u8; 32]>>,
+    pub kinds: Option<BTreeSet<u64>>,
+    /// Tag filters by letter, `#e` is under `'e'`
+    pub tags: BTreeMap<char, BTreeSet<String>>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub limit: Option<u64>,
+    /// NIP-50 full text search
+    pub search: Option<String>,
+}
+
+impl FilterSpec {
+    pub fn new() -> Self {
+        FilterSpec::default()
+    }
+
+    pub fn ids<'a>(mut self, ids: impl IntoIterator<Item = &'a [u8; 32]>) -> Self {
+        self.ids
+            .get_or_insert_with(BTreeSet::new)
+            .extend(ids.into_iter().copied());
+        self
+    }
+
+    pub fn authors<'a>(mut self, authors: impl IntoIterator<Item = &'a [u8; 32]>) -> Self {
+        self.authors
+            .get_or_insert_with(BTreeSet::new)
+            .extend(authors.into_iter().copied());
+        self
+    }
+
+    pub fn kinds(mut self, kinds: impl IntoIterator<Item = u64>) -> Self {
+        self.kinds.get_or_insert_with(BTreeSet::new).extend(kinds);
+        self
+    }
+
+    /// Notes that reference these events, `#e`
+    pub fn events<'a>(self, ids: impl IntoIterator<Item = &'a [u8; 32]>) -> Self {
+        self.tag('e', ids.into_iter().map(hex::encode))
+    }
+
+    /// Notes that mention these pubkeys, `#p`
+    pub fn pubkeys<'a>(self, pks: impl IntoIterator<Item = &'a [u8; 32]>) -> Self {
+        self.tag('p', pks.into_iter().map(hex::encode))
+    }
+
+    /// Notes with these hashtags, `#t`. Hashtags are lowercase on the
+    /// wire, so these are lowercased.
+    pub fn hashtags<S: AsRef<str>>(self, hashtags: impl IntoIterator<Item = S>) -> Self {
+        self.tag('t', hashtags.into_iter().map(|t| t.as_ref().to_lowercase()))
+    }
+
+    /// Any single letter tag filter
+    pub fn tag(mut self, letter: char, values: impl IntoIterator<Item = String>) -> Self {
+        self.tags.entry(letter).or_default().extend(values);
+        self
+    }
+
+    pub fn since(mut self, since: u64) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    pub fn until(mut self, until: u64) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn search(mut self, search: impl Into<String>) -> Self {
+        self.search = Some(search.into());
+        self
+    }
+
+    /// Check everything a relay or nostrdb would choke on, or silently
+    /// treat differently than we meant
+    pub fn validate(&self) -> Result<(), InvalidFilter> {
+        check_not_empty("ids", self.ids.as_ref())?;
+        check_not_empty("authors", self.authors.as_ref())?;
+        check_not_empty("kinds", self.kinds.as_ref())?;
+
+        if let Some(kind) = self.kinds.iter().flatten().find(|k| **k > MAX_KIND) {
+            return Err(InvalidFilter::KindTooBig(*kind));
+        }
+
+        for (letter, values) in &self.tags {
+            let field = format!("#{}", letter);
+            if !letter.is_ascii_alphabetic() {
+                return Err(InvalidFilter::BadTag(field));
+            }
+            check_not_empty(&field, Some(values))?;
+
+            if *letter == 'e' || *letter == 'p' {
+                if let Some(value) = values.iter().find(|v| parse_id(v).is_none()) {
+                    return Err(InvalidFilter::BadHex {
+                        field,
+                        value: value.to_owned(),
+                    });
+                }
+            }
+        }
+
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since > until {
+                return Err(InvalidFilter::SinceAfterUntil { since, until });
+            }
+        }
+
+        if self.search.as_deref().is_some_and(|s| s.trim().is_empty()) {
+            return Err(InvalidFilter::EmptySearch);
+        }
+
+        Ok(())
+    }
+
+    /// Parse and validate a filter
+    pub fn from_json(json: &str) -> Result<Self, InvalidFilter> {
+        let value: Value =
+            serde_json::from_str(json).map_err(|e| InvalidFilter::Json(e.to_string()))?;
+        Self::from_value(&value)
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self, InvalidFilter> {
+        let obj = value.as_object().ok_or(InvalidFilter::NotAnObject)?;
+
+        let mut spec = FilterSpec::default();
+        for (field, value) in obj {
+            match field.as_str() {
+                "ids" => spec.ids = Some(parse_ids(field, value)?),
+                "authors" => spec.authors = Some(parse_ids(field, value)?),
+                "kinds" => {
+                    let kinds = array(field, value, "an array of kinds")?
+                        .iter()
+                        .map(|k| int(field, k, "an array of kinds"))
+                        .collect::<Result<_, _>>()?;
+                    spec.kinds = Some(kinds);
+                }
+                "since" => spec.since = Some(int(field, value, "a unix timestamp")?),
+                "until" => spec.until = Some(int(field, value, "a unix timestamp")?),
+                "limit" => spec.limit = Some(int(field, value, "a number")?),
+                "search" => {
+                    let search = value
+                        .as_str()
+                        .ok_or_else(|| wrong_type(field, "a string"))?;
+                    spec.search = Some(search.to_owned());
+                }
+                tag if tag.starts_with('#') => {
+                    let mut chars = tag[1..].chars();
+                    let letter = match (chars.next(), chars.next()) {
+                        (Some(letter), None) => letter,
+                        _ => return Err(InvalidFilter::BadTag(tag.to_owned())),
+                    };
+
+                    let values = array(field, value, "an array of strings")?
+                        .iter()
+                        .map(|v| {
+                            v.as_str()
+                                .map(str::to_owned)
+                                .ok_or_else(|| wrong_type(field, "an array of strings"))
+                        })
+                        .collect::<Result<_, _>>()?;
+                    spec.tags.insert(letter, values);
+                }
+                _ => return Err(InvalidFilter::UnknownField(field.to_owned())),
+            }
+        }
+
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// The canonical json for this filter: fields in a fixed order, lists
+    /// sorted and deduplicated
+    pub fn to_json(&self) -> String {
+        let mut fields: Vec<(String, Value)> = Vec::new();
+
+        if let Some(ids) = &self.ids {
+            fields.push(("ids".to_owned(), hex_array(ids)));
+        }
+        if let Some(authors) = &self.authors {
+            fields.push(("authors".to_owned(), hex_array(authors)));
+        }
+        if let Some(kinds) = &self.kinds {
+            fields.push(("kinds".to_owned(), kinds.iter().copied().collect()));
+        }
+        for (letter, values) in &self.tags {
+            fields.push((format!("#{}", letter), values.iter().cloned().collect()));
+        }
+        if let Some(since) = self.since {
+            fields.push(("since".to_owned(), since.into()));
+        }
+        if let Some(until) = self.until {
+            fields.push(("until".to_owned(), until.into()));
+        }
+        if let Some(limit) = self.limit {
+            fields.push(("limit".to_owned(), limit.into()));
+        }
+        if let Some(search) = &self.search {
+            fields.push(("search".to_owned(), search.as_str().into()));
+        }
+
+        let mut json = String::from("{");
+        for (i, (key, value)) in fields.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(json, "{}:{}", Value::from(key.as_str()), value);
+        }
+        json.push('}');
+        json
+    }
+
+    /// Identifies what the filter matches, for caching and de-duplicating
+    /// subscriptions
+    pub fn key(&self) -> String {
+        self.to_json()
+    }
+
+    /// Validate, then make a filter nostrdb and the pool can use
+    pub fn build(&self) -> crate::Result<Filter> {
+        self.validate()?;
+        Ok(Filter::from_json(&self.to_json())?)
+    }
+
+    /// Read back a nostrdb filter
+    pub fn from_filter(filter: &Filter) -> crate::Result<Self> {
+        Ok(Self::from_json(&filter.json()?)?)
+    }
+
+    /// A key for a whole subscription. The order of the filters doesn't
+    /// matter, so it is sorted away.
+    pub fn key_of(filters: &[Filter]) -> Option<String> {
+        let mut keys = filters
+            .iter()
+            .map(|f| Self::from_filter(f).ok().map(|spec| spec.key()))
+            .collect::<Option<Vec<String>>>()?;
+        keys.sort_unstable();
+        keys.dedup();
+
+        Some(format!("[{}]", keys.join(",")))
+    }
+}
+
+fn check_not_empty<T>(field: &str, set: Option<&BTreeSet<T>>) -> Result<(), InvalidFilter> {
+    if set.is_some_and(|s| s.is_empty()) {
+        Err(InvalidFilter::Empty(field.to_owned()))
+    } else {
+        Ok(())
+    }
+}
+
+fn wrong_type(field: &str, expected: &'static str) -> InvalidFilter {
+    InvalidFilter::WrongType {
+        field: field.to_owned(),
+        expected,
+    }
+}
+
+fn array<'a>(
+    field: &str,
+    value: &'a Value,
+    expected: &'static str,
+) -> Result<&'a Vec<Value>, InvalidFilter> {
+    value.as_array().ok_or_else(|| wrong_type(field, expected))
+}
+
+fn int(field: &str, value: &Value, expected: &'static str) -> Result<u64, InvalidFilter> {
+    value.as_u64().ok_or_else(|| wrong_type(field, expected))
+}
+
+fn parse_id(value: &str) -> Option<[u8; 32]> {
+    if value.len() != 64 {
+        return None;
+    }
+    hex::decode(value).ok()?.try_into().ok()
+}
+
+fn parse_ids(field: &str, value: &Value) -> Result<BTreeSet<[u8; 32]>, InvalidFilter> {
+    array(field, value, "an array of hex strings")?
+        .iter()
+        .map(|v| {
+            let s = v
+                .as_str()
+                .ok_or_else(|| wrong_type(field, "an array of hex strings"))?;
+            parse_id(s).ok_or_else(|| InvalidFilter::BadHex {
+                field: field.to_owned(),
+                value: s.to_owned(),
+            })
+        })
+        .collect()
+}
+
+fn hex_array(ids: &BTreeSet<[u8; 32]>) -> Value {
+    Value::Array(
+        ids.iter()
+            .map(|id| Value::String(hex::encode(id)))
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_round_trip() {
+        let pk = [0xab; 32];
+        let spec = FilterSpec::new()
+            .kinds([1, 6])
+            .authors([&pk])
+            .hashtags(["Nostr"])
+            .tag('d', ["x".to_owned()])
+            .since(100)
+            .limit(20)
+            .search("coffee");
+
+        spec.validate().unwrap();
+        let json = spec.to_json();
+        assert_eq!(
+            json,
+            format!(
+                r##"{{"authors":["{}"],"kinds":[1,6],"#d":["x"],"#t":["nostr"],"since":100,"limit":20,"search":"coffee"}}"##,
+                hex::encode(pk)
+            )
+        );
+        assert_eq!(FilterSpec::from_json(&json).unwrap(), spec);
+    }
+
+    #[test]
+    fn test_canonical_key() {
+        let a = FilterSpec::from_json(r#"{"kinds":[6,1,1],"limit":10}"#).unwrap();
+        let b = FilterSpec::from_json(r#"{"limit":10,"kinds":[1,6]}"#).unwrap();
+        assert_eq!(a.key(), b.key());
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn test_errors() {
+        let err = |json: &str| FilterSpec::from_json(json).unwrap_err();
+
+        assert_eq!(err("[]"), InvalidFilter::NotAnObject);
+        assert_eq!(
+            err(r#"{"kind":[1]}"#),
+            InvalidFilter::UnknownField("kind".into())
+        );
+        assert_eq!(err(r#"{"kinds":[]}"#), InvalidFilter::Empty("kinds".into()));
+        assert_eq!(
+            err(r#"{"kinds":[70000]}"#),
+            InvalidFilter::KindTooBig(70000)
+        );
+        assert_eq!(
+            err(r##"{"#pp":["a"]}"##),
+            InvalidFilter::BadTag("#pp".into())
+        );
+        assert_eq!(
+            err(r#"{"since":10,"until":5}"#),
+            InvalidFilter::SinceAfterUntil {
+                since: 10,
+                until: 5
+            }
+        );
+        assert_eq!(
+            err(r##"{"#p":["abc"]}"##),
+            InvalidFilter::BadHex {
+                field: "#p".into(),
+                value: "abc".into()
+            }
+        );
+        assert!(matches!(
+            err(r#"{"limit":"10"}"#),
+            InvalidFilter::WrongType { .. }
+        ));
+        assert!(matches!(err("{"), InvalidFilter::Json(_)));
+    }
+}
@@ -1,9 +1,12 @@
-use nostr::nips::nip49::EncryptedSecretKey;
+use nostr::nips::nip49::{EncryptedSecretKey, KeySecurity};
 use serde::Deserialize;
 use serde::Serialize;
 
 use crate::Pubkey;
 use crate::SecretKey;
+use crate::{nip04, nip44, Error, Result};
+
+static HRP_NCRYPTSEC: bech32::Hrp = bech32::Hrp::parse_unchecked("ncryptsec");
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct Keypair {
@@ -63,6 +66,34 @@ impl<'a> FilledKeypair<'a> {
             secret_key: self.secret_key.to_owned(),
         }
     }
+
+    /// NIP-44 encrypt `plaintext` so only `recipient` (and we) can read it
+    pub fn nip44_encrypt(&self, recipient: &Pubkey, plaintext: &str) -> Result<String> {
+        nip44::encrypt(self.secret_key, recipient, plaintext)
+    }
+
+    /// Decrypt a NIP-44 payload from `sender`
+    pub fn nip44_decrypt(&self, sender: &Pubkey, payload: &str) -> Result<String> {
+        nip44::decrypt(self.secret_key, sender, payload)
+    }
+
+    /// Legacy NIP-04 encryption, prefer [`FilledKeypair::nip44_encrypt`]
+    pub fn nip04_encrypt(&self, recipient: &Pubkey, plaintext: &str) -> Result<String> {
+        nip04::encrypt(self.secret_key, recipient, plaintext)
+    }
+
+    pub fn nip04_decrypt(&self, sender: &Pubkey, payload: &str) -> Result<String> {
+        nip04::decrypt(self.secret_key, sender, payload)
+    }
+
+    /// The secret key NIP-49 encrypted with `pass`, as an `ncryptsec1`
+    /// string. Higher `log_n` is slower to encrypt, and to brute force.
+    pub fn to_ncryptsec(&self, pass: &str, log_n: u8) -> Result<String> {
+        let encrypted = EncryptedSecretKey::new(self.secret_key, pass, log_n, KeySecurity::Unknown)
+            .map_err(|_| Error::EncryptFailed("nip49"))?;
+        bech32::encode::<bech32::Bech32>(HRP_NCRYPTSEC, &encrypted.to_bytes())
+            .map_err(|_| Error::InvalidBech32)
+    }
 }
 
 impl FullKeypair {
@@ -74,6 +105,22 @@ impl FullKeypair {
         FilledKeypair::new(&self.pubkey, &self.secret_key)
     }
 
+    pub fn nip44_encrypt(&self, recipient: &Pubkey, plaintext: &str) -> Result<String> {
+        self.to_filled().nip44_encrypt(recipient, plaintext)
+    }
+
+    pub fn nip44_decrypt(&self, sender: &Pubkey, payload: &str) -> Result<String> {
+        self.to_filled().nip44_decrypt(sender, payload)
+    }
+
+    pub fn nip04_encrypt(&self, recipient: &Pubkey, plaintext: &str) -> Result<String> {
+        self.to_filled().nip04_encrypt(recipient, plaintext)
+    }
+
+    pub fn nip04_decrypt(&self, sender: &Pubkey, payload: &str) -> Result<String> {
+        self.to_filled().nip04_decrypt(sender, payload)
+    }
+
     pub fn generate() -> Self {
         let mut rng = nostr::secp256k1::rand::rngs::OsRng;
         let (secret_key, _) = &nostr::SECP256K1.generate_keypair(&mut rng);
@@ -91,6 +138,28 @@ impl FullKeypair {
             secret_key: Some(self.secret_key),
         }
     }
+
+    pub fn to_ncryptsec(&self, pass: &str, log_n: u8) -> Result<String> {
+        self.to_filled().to_ncryptsec(pass, log_n)
+    }
+
+    /// Decrypt an `ncryptsec1` string made with `pass`
+    pub fn from_ncryptsec(ncryptsec: &str, pass: &str) -> Result<Self> {
+        let (hrp, data) = bech32::decode(ncryptsec).map_err(|_| Error::InvalidBech32)?;
+        if hrp != HRP_NCRYPTSEC {
+            return Err(Error::InvalidBech32);
+        }
+
+        let encrypted = EncryptedSecretKey::from_slice(&data).map_err(|_| Error::DecodeFailed)?;
+        let secret_key = encrypted
+            .to_secret_key(pass)
+            .map_err(|_| Error::DecryptFailed("wrong passphrase"))?;
+        let keypair = Keypair::from_secret(secret_key);
+        Ok(FullKeypair::new(
+            keypair.pubkey,
+            keypair.secret_key.expect("from_secret keeps the secret"),
+        ))
+    }
 }
 
 impl std::fmt::Display for Keypair {
@@ -136,4 +205,50 @@ impl SerializableKeypair {
                 .and_then(|e| e.to_secret_key(pass).ok()),
         )
     }
+
+    /// Like [`SerializableKeypair::to_keypair`], but a wrong `pass` is an
+    /// error instead of a keypair without its secret key
+    pub fn decrypt(&self, pass: &str) -> Result<Keypair> {
+        let secret_key = if let Some(encrypted) = self.encrypted_secret_key {
+            let secret_key = encrypted
+                .to_secret_key(pass)
+                .map_err(|_| Error::DecryptFailed("wrong passphrase"))?;
+            Some(secret_key)
+        } else {
+            None
+        };
+        Ok(Keypair::new(self.pubkey, secret_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ncryptsec_roundtrip() -> Result<()> {
+        let keypair = FullKeypair::generate();
+        let ncryptsec = keypair.to_ncryptsec("correct horse", 7)?;
+        assert!(ncryptsec.starts_with("ncryptsec1"));
+
+        assert_eq!(
+            FullKeypair::from_ncryptsec(&ncryptsec, "correct horse")?,
+            keypair
+        );
+        assert!(FullKeypair::from_ncryptsec(&ncryptsec, "battery staple").is_err());
+        assert!(FullKeypair::from_ncryptsec(&keypair.pubkey.to_bech().unwrap(), "").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decrypt_serializable() -> Result<()> {
+        let keypair = FullKeypair::generate().to_keypair();
+        let stored = SerializableKeypair::from_keypair(&keypair, "s3cret", 7);
+
+        assert_eq!(stored.decrypt("s3cret")?, keypair);
+        assert!(stored.decrypt("").is_err());
+        // the lenient version just loses the secret
+        assert_eq!(stored.to_keypair("").secret_key, None);
+        Ok(())
+    }
 }
@@ -2,22 +2,35 @@ mod client;
 mod error;
 mod filter;
 mod keypair;
+mod negentropy;
+pub mod nip04;
+pub mod nip44;
+pub mod nip46;
+pub mod nip59;
 mod note;
 mod profile;
+mod proxy;
 mod pubkey;
 mod relay;
+mod signer;
 
 pub use client::ClientMessage;
 pub use error::Error;
 pub use ewebsock;
-pub use filter::Filter;
+pub use filter::{Filter, FilterSpec, InvalidFilter};
 pub use keypair::{FilledKeypair, FullKeypair, Keypair, SerializableKeypair};
+pub use negentropy::Negentropy;
 pub use nostr::SecretKey;
-pub use note::{Note, NoteId};
+pub use note::{Note, NoteId, UnsignedNote};
 pub use profile::Profile;
+pub use proxy::{socks5_connect, ProxyConfig};
 pub use pubkey::Pubkey;
 pub use relay::message::{RelayEvent, RelayMessage};
 pub use relay::pool::{PoolEvent, RelayPool};
-pub use relay::{Relay, RelayStatus};
+pub use relay::{
+    auth_note_builder, info_url, NegentropySupport, Publish, PublishState, PublishTracker, Relay,
+    RelayAuthStatus, RelayInfoDocument, RelayLimitation, RelaySender, RelayStats, RelayStatus,
+};
+pub use signer::{Crypt, Encryption, Signature, Signer};
 
 pub type Result<T> = std::result::Result<T, error::Error>;
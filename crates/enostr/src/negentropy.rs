@@ -0,0 +1,574 @@
+//! NIP-77 negentropy set reconciliation. We describe the notes we have
+//! for a filter with ranges of fingerprints, and the relay narrows down
+//! the ranges that differ until both sides know which ids the other is
+//! missing. See <https://github.com/hoytech/negentropy> for the protocol.
+
+use crate::{Error, Result};
+use nostr::hashes::{sha256, Hash};
+use std::collections::HashSet;
+
+const PROTOCOL_VERSION: u8 = 0x61;
+
+/// How many ranges we split a differing range into
+const BUCKETS: usize = 16;
+
+const ID_SIZE: usize = 32;
+const FINGERPRINT_SIZE: usize = 16;
+
+const MODE_SKIP: u64 = 0;
+const MODE_FINGERPRINT: u64 = 1;
+const MODE_ID_LIST: u64 = 2;
+
+pub type Id = [u8; ID_SIZE];
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+struct Item {
+    timestamp: u64,
+    id: Id,
+}
+
+/// The upper end of a range. Items are ordered by timestamp and then
+/// id, so a bound only needs as much of an id as it takes to tell the
+/// items on either side apart.
+#[derive(Debug, Clone)]
+struct Bound {
+    timestamp: u64,
+    prefix: Vec<u8>,
+}
+
+impl Bound {
+    fn zero() -> Self {
+        Bound {
+            timestamp: 0,
+            prefix: Vec::new(),
+        }
+    }
+
+    fn infinity() -> Self {
+        Bound {
+            timestamp: u64::MAX,
+            prefix: Vec::new(),
+        }
+    }
+
+    /// The smallest bound that sits between two neighbouring items
+    fn between(prev: &Item, curr: &Item) -> Self {
+        if prev.timestamp != curr.timestamp {
+            return Bound {
+                timestamp: curr.timestamp,
+                prefix: Vec::new(),
+            };
+        }
+
+        let shared = prev
+            .id
+            .iter()
+            .zip(curr.id.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        Bound {
+            timestamp: curr.timestamp,
+            prefix: curr.id[..(shared + 1).min(ID_SIZE)].to_vec(),
+        }
+    }
+
+    fn is_above(&self, item: &Item) -> bool {
+        if item.timestamp != self.timestamp {
+            return item.timestamp < self.timestamp;
+        }
+
+        let mut padded = [0u8; ID_SIZE];
+        padded[..self.prefix.len()].copy_from_slice(&self.prefix);
+        item.id < padded
+    }
+}
+
+/// One side of a negentropy reconciliation. Clients start it with
+/// [`Negentropy::initiate`] and feed the relay's NEG-MSG payloads to
+/// [`Negentropy::reconcile`] until it returns `None`.
+#[derive(Debug)]
+pub struct Negentropy {
+    items: Vec<Item>,
+    initiator: bool,
+
+    /// Ids we have that the other side doesn't
+    have: Vec<Id>,
+
+    /// Ids the other side has that we don't
+    need: Vec<Id>,
+}
+
+impl Negentropy {
+    /// Start a reconciliation over the notes we have, as their
+    /// `created_at` and id
+    pub fn new(items: impl IntoIterator<Item = (u64, Id)>) -> Self {
+        Self::with_role(items, true)
+    }
+
+    fn with_role(items: impl IntoIterator<Item = (u64, Id)>, initiator: bool) -> Self {
+        let mut items: Vec<Item> = items
+            .into_iter()
+            .map(|(timestamp, id)| Item { timestamp, id })
+            .collect();
+        items.sort();
+        items.dedup();
+
+        Negentropy {
+            items,
+            initiator,
+            have: Vec::new(),
+            need: Vec::new(),
+        }
+    }
+
+    /// The hex encoded message that goes in NEG-OPEN
+    pub fn initiate(&self) -> String {
+        let mut out = Writer::new();
+        self.split_range(0, self.items.len(), &Bound::infinity(), &mut out);
+        hex::encode(out.buf)
+    }
+
+    /// Handle a message from the other side. Returns the next message to
+    /// send, or `None` when we are done and [`Negentropy::need`] is
+    /// complete.
+    pub fn reconcile(&mut self, msg: &str) -> Result<Option<String>> {
+        let msg = hex::decode(msg)?;
+        let mut reader = Reader::new(&msg);
+
+        if reader.byte()? != PROTOCOL_VERSION {
+            return Err(Error::Generic(
+                "unsupported negentropy protocol version".to_owned(),
+            ));
+        }
+
+        let mut out = Writer::new();
+        let mut prev_bound = Bound::zero();
+        let mut prev_index = 0;
+        let mut skip = false;
+
+        while !reader.is_empty() {
+            let curr_bound = reader.bound()?;
+            let mode = reader.varint()?;
+
+            let lower = prev_index;
+            let upper =
+                lower + self.items[lower..].partition_point(|item| curr_bound.is_above(item));
+
+            match mode {
+                MODE_SKIP => skip = true,
+
+                MODE_FINGERPRINT => {
+                    let theirs = reader.bytes(FINGERPRINT_SIZE)?;
+                    if theirs == fingerprint(&self.items[lower..upper]) {
+                        skip = true;
+                    } else {
+                        out.skip(&mut skip, &prev_bound);
+                        self.split_range(lower, upper, &curr_bound, &mut out);
+                    }
+                }
+
+                MODE_ID_LIST => {
+                    let n = reader.varint()?;
+                    let mut theirs = HashSet::new();
+                    for _ in 0..n {
+                        theirs.insert(reader.id()?);
+                    }
+
+                    if self.initiator {
+                        for item in &self.items[lower..upper] {
+                            if !theirs.remove(&item.id) {
+                                self.have.push(item.id);
+                            }
+                        }
+                        self.need.extend(theirs);
+                        skip = true;
+                    } else {
+                        // we don't bother working out differences as the
+                        // responder, the initiator does that with our ids
+                        out.skip(&mut skip, &prev_bound);
+                        out.bound(&curr_bound);
+                        out.varint(MODE_ID_LIST);
+                        out.varint((upper - lower) as u64);
+                        for item in &self.items[lower..upper] {
+                            out.buf.extend_from_slice(&item.id);
+                        }
+                    }
+                }
+
+                mode => return Err(Error::Generic(format!("unknown negentropy mode {}", mode))),
+            }
+
+            prev_index = upper;
+            prev_bound = curr_bound;
+        }
+
+        if self.initiator && out.buf.len() == 1 {
+            Ok(None)
+        } else {
+            Ok(Some(hex::encode(out.buf)))
+        }
+    }
+
+    pub fn have(&self) -> &[Id] {
+        &self.have
+    }
+
+    pub fn need(&self) -> &[Id] {
+        &self.need
+    }
+
+    fn split_range(&self, lower: usize, upper: usize, upper_bound: &Bound, out: &mut Writer) {
+        let n = upper - lower;
+
+        if n < BUCKETS * 2 {
+            out.bound(upper_bound);
+            out.varint(MODE_ID_LIST);
+            out.varint(n as u64);
+            for item in &self.items[lower..upper] {
+                out.buf.extend_from_slice(&item.id);
+            }
+            return;
+        }
+
+        let per_bucket = n / BUCKETS;
+        let with_extra = n % BUCKETS;
+        let mut curr = lower;
+
+        for i in 0..BUCKETS {
+            let size = per_bucket + usize::from(i < with_extra);
+            let fp = fingerprint(&self.items[curr..curr + size]);
+            curr += size;
+
+            let bound = if curr == upper {
+                upper_bound.clone()
+            } else {
+                Bound::between(&self.items[curr - 1], &self.items[curr])
+            };
+
+            out.bound(&bound);
+            out.varint(MODE_FINGERPRINT);
+            out.buf.extend_from_slice(&fp);
+        }
+    }
+}
+
+/// The ids summed as little endian 256-bit numbers, plus the count,
+/// hashed and truncated
+fn fingerprint(items: &[Item]) -> [u8; FINGERPRINT_SIZE] {
+    let mut sum = [0u8; ID_SIZE];
+    for item in items {
+        let mut carry = 0u16;
+        for (acc, byte) in sum.iter_mut().zip(item.id.iter()) {
+            let total = *acc as u16 + *byte as u16 + carry;
+            *acc = total as u8;
+            carry = total >> 8;
+        }
+    }
+
+    let mut data = sum.to_vec();
+    data.extend(varint(items.len() as u64));
+
+    let hash = sha256::Hash::hash(&data).to_byte_array();
+    let mut fp = [0u8; FINGERPRINT_SIZE];
+    fp.copy_from_slice(&hash[..FINGERPRINT_SIZE]);
+    fp
+}
+
+/// Base 128, most significant group first, with the high bit set on all
+/// but the last byte
+fn varint(mut n: u64) -> Vec<u8> {
+    let mut out = vec![(n & 0x7f) as u8];
+    n >>= 7;
+    while n > 0 {
+        out.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    out.reverse();
+    out
+}
+
+struct Writer {
+    buf: Vec<u8>,
+    /// Timestamps are sent as the difference from the previous one
+    last_timestamp: u64,
+}
+
+impl Writer {
+    fn new() -> Self {
+        Writer {
+            buf: vec![PROTOCOL_VERSION],
+            last_timestamp: 0,
+        }
+    }
+
+    fn varint(&mut self, n: u64) {
+        self.buf.extend(varint(n));
+    }
+
+    fn timestamp(&mut self, timestamp: u64) {
+        if timestamp == u64::MAX {
+            self.last_timestamp = u64::MAX;
+            self.varint(0);
+            return;
+        }
+
+        let delta = timestamp.saturating_sub(self.last_timestamp);
+        self.last_timestamp = timestamp;
+        self.varint(delta + 1);
+    }
+
+    fn bound(&mut self, bound: &Bound) {
+        self.timestamp(bound.timestamp);
+        self.varint(bound.prefix.len() as u64);
+        self.buf.extend_from_slice(&bound.prefix);
+    }
+
+    /// Write out a pending skip, now that something follows it
+    fn skip(&mut self, skip: &mut bool, prev_bound: &Bound) {
+        if *skip {
+            *skip = false;
+            self.bound(prev_bound);
+            self.varint(MODE_SKIP);
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    last_timestamp: u64,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Reader {
+            buf,
+            pos: 0,
+            last_timestamp: 0,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.buf.len()
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let byte = *self.buf.get(self.pos).ok_or(Error::DecodeFailed)?;
+        self.pos += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + n)
+            .ok_or(Error::DecodeFailed)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn id(&mut self) -> Result<Id> {
+        Ok(self.bytes(ID_SIZE)?.try_into()?)
+    }
+
+    fn varint(&mut self) -> Result<u64> {
+        let mut n: u64 = 0;
+        loop {
+            let byte = self.byte()?;
+            n = n
+                .checked_mul(128)
+                .ok_or(Error::DecodeFailed)?
+                .checked_add((byte & 0x7f) as u64)
+                .ok_or(Error::DecodeFailed)?;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+    }
+
+    fn timestamp(&mut self) -> Result<u64> {
+        let encoded = self.varint()?;
+        let timestamp = if encoded == 0 { u64::MAX } else { encoded - 1 };
+
+        if timestamp == u64::MAX || self.last_timestamp == u64::MAX {
+            self.last_timestamp = u64::MAX;
+            return Ok(u64::MAX);
+        }
+
+        self.last_timestamp = self.last_timestamp.saturating_add(timestamp);
+        Ok(self.last_timestamp)
+    }
+
+    fn bound(&mut self) -> Result<Bound> {
+        let timestamp = self.timestamp()?;
+        let len = self.varint()? as usize;
+        if len > ID_SIZE {
+            return Err(Error::InvalidByteSize);
+        }
+
+        Ok(Bound {
+            timestamp,
+            prefix: self.bytes(len)?.to_vec(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn items(range: std::ops::Range<u8>) -> Vec<(u64, Id)> {
+        range.map(|i| (1000 + i as u64 / 3, [i; 32])).collect()
+    }
+
+    /// Run both sides to completion, returning what the client needs and
+    /// has
+    fn sync(client_items: Vec<(u64, Id)>, relay_items: Vec<(u64, Id)>) -> (Vec<Id>, Vec<Id>) {
+        let mut client = Negentropy::new(client_items);
+        let mut relay = Negentropy::with_role(relay_items, false);
+
+        let mut msg = client.initiate();
+        for _ in 0..32 {
+            let reply = relay.reconcile(&msg).unwrap().unwrap();
+            match client.reconcile(&reply).unwrap() {
+                Some(next) => msg = next,
+                None => {
+                    let mut need = client.need().to_vec();
+                    let mut have = client.have().to_vec();
+                    need.sort();
+                    have.sort();
+                    return (need, have);
+                }
+            }
+        }
+
+        panic!("reconciliation didn't finish");
+    }
+
+    #[test]
+    fn test_varint() {
+        assert_eq!(varint(0), vec![0]);
+        assert_eq!(varint(127), vec![0x7f]);
+        assert_eq!(varint(128), vec![0x81, 0x00]);
+
+        let encoded = varint(1_700_000_000);
+        let mut reader = Reader::new(&encoded);
+        assert_eq!(reader.varint().unwrap(), 1_700_000_000);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn test_sync_small_sets() {
+        let (need, have) = sync(items(0..10), items(5..15));
+        assert_eq!(
+            need,
+            items(10..15).into_iter().map(|i| i.1).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            have,
+            items(0..5).into_iter().map(|i| i.1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_sync_large_sets() {
+        // big enough that we have to go through a few rounds of
+        // fingerprints
+        let mut client = items(0..250);
+        client.retain(|(_, id)| id[0] % 50 != 7);
+        let (need, have) = sync(client, items(0..250));
+
+        assert_eq!(have, Vec::<Id>::new());
+        assert_eq!(
+            need,
+            vec![[7; 32], [57; 32], [107; 32], [157; 32], [207; 32]]
+        );
+    }
+
+    #[test]
+    fn test_sync_identical_sets() {
+        let (need, have) = sync(items(0..200), items(0..200));
+        assert!(need.is_empty());
+        assert!(have.is_empty());
+    }
+
+    // The vectors below are worked out by hand from the protocol
+    // description in hoytech/negentropy, so they don't only check that
+    // we agree with ourselves. Messages are the version byte 0x61, then
+    // ranges of (bound, mode, payload). The bound at infinity is
+    // timestamp 0 with an empty id prefix.
+
+    #[test]
+    fn test_fixed_fingerprints() {
+        // sha256 of the 32 byte sum and the count, cut to 16 bytes
+        assert_eq!(
+            hex::encode(fingerprint(&[])),
+            "7f9c9e31ac8256ca2f258583df262dbc"
+        );
+
+        let ab = Item {
+            timestamp: 1_700_000_000,
+            id: [0xab; 32],
+        };
+        assert_eq!(
+            hex::encode(fingerprint(&[ab])),
+            "e58a2a5c80c2434d22fc49ccefe93c30"
+        );
+
+        // ids add up as little endian numbers mod 2^256, so these wrap
+        // around to zero
+        let mut one = [0; 32];
+        one[0] = 1;
+        let wrap = [
+            Item {
+                timestamp: 1,
+                id: [0xff; 32],
+            },
+            Item {
+                timestamp: 2,
+                id: one,
+            },
+        ];
+        assert_eq!(
+            hex::encode(fingerprint(&wrap)),
+            "58cc2f44d3a27866874701fbad573da9"
+        );
+    }
+
+    #[test]
+    fn test_fixed_messages() {
+        let ab_list = format!("6100000201{}", "ab".repeat(32));
+
+        // small sets are sent as a single IdList range
+        assert_eq!(Negentropy::new(std::iter::empty()).initiate(), "6100000200");
+        let ab = || Negentropy::new([(1_700_000_000, [0xab; 32])]);
+        assert_eq!(ab().initiate(), ab_list);
+
+        // the relay has the same ids, or skips the range: we're done
+        assert_eq!(ab().reconcile(&ab_list).unwrap(), None);
+        assert_eq!(ab().reconcile("6100000000").unwrap(), None);
+
+        // a matching fingerprint means the same, a different one gets
+        // our ids back
+        let mut neg = ab();
+        let msg = "61000001e58a2a5c80c2434d22fc49ccefe93c30";
+        assert_eq!(neg.reconcile(msg).unwrap(), None);
+        let msg = "610000017f9c9e31ac8256ca2f258583df262dbc";
+        assert_eq!(neg.reconcile(msg).unwrap(), Some(ab_list.clone()));
+
+        // the relay lists an id we don't have, and not ours
+        let mut neg = ab();
+        let theirs = format!("6100000201{}", "cd".repeat(32));
+        assert_eq!(neg.reconcile(&theirs).unwrap(), None);
+        assert_eq!(neg.need(), &[[0xcd; 32]]);
+        assert_eq!(neg.have(), &[[0xab; 32]]);
+    }
+
+    #[test]
+    fn test_bad_version() {
+        let mut neg = Negentropy::new(items(0..3));
+        assert!(neg.reconcile("62").is_err());
+        assert!(neg.reconcile("zz").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+//! Legacy NIP-04 encryption: AES-256-CBC under the raw ECDH secret. It
+//! isn't authenticated, only use it to talk to clients that don't do
+//! NIP-44 yet.
+
+use aes::Aes256;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use nostr::secp256k1::rand::RngCore;
+
+use crate::nip44::shared_secret;
+use crate::{Error, Pubkey, Result, SecretKey};
+
+type Encryptor = cbc::Encryptor<Aes256>;
+type Decryptor = cbc::Decryptor<Aes256>;
+
+/// Encrypt `plaintext` for `pubkey`, as `<ciphertext>?iv=<iv>`
+pub fn encrypt(secret_key: &SecretKey, pubkey: &Pubkey, plaintext: &str) -> Result<String> {
+    let mut iv = [0u8; 16];
+    nostr::secp256k1::rand::rngs::OsRng.fill_bytes(&mut iv);
+    encrypt_with_iv(secret_key, pubkey, &iv, plaintext)
+}
+
+fn encrypt_with_iv(
+    secret_key: &SecretKey,
+    pubkey: &Pubkey,
+    iv: &[u8; 16],
+    plaintext: &str,
+) -> Result<String> {
+    let key = shared_secret(secret_key, pubkey)?;
+    let ciphertext = Encryptor::new(&key.into(), iv.into())
+        .encrypt_padded_vec_mut::<Pkcs7>(plaintext.as_bytes());
+
+    Ok(format!(
+        "{}?iv={}",
+        BASE64.encode(ciphertext),
+        BASE64.encode(iv)
+    ))
+}
+
+/// Decrypt a payload `pubkey` encrypted for us
+pub fn decrypt(secret_key: &SecretKey, pubkey: &Pubkey, payload: &str) -> Result<String> {
+    let (ciphertext, iv) = if let Some(parts) = payload.split_once("?iv=") {
+        parts
+    } else {
+        return Err(Error::DecryptFailed("missing iv"));
+    };
+
+    let ciphertext = BASE64
+        .decode(ciphertext)
+        .map_err(|_| Error::DecryptFailed("invalid base64"))?;
+    let iv: [u8; 16] = BASE64
+        .decode(iv)
+        .map_err(|_| Error::DecryptFailed("invalid base64"))?
+        .as_slice()
+        .try_into()
+        .map_err(|_| Error::DecryptFailed("invalid iv"))?;
+
+    let key = shared_secret(secret_key, pubkey)?;
+    let plaintext = Decryptor::new(&key.into(), &iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(&ciphertext)
+        .map_err(|_| Error::DecryptFailed("invalid padding"))?;
+
+    String::from_utf8(plaintext).map_err(|_| Error::DecryptFailed("invalid utf8"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn secret(n: u8) -> SecretKey {
+        let mut bytes = [0u8; 32];
+        bytes[31] = n;
+        SecretKey::from_str(&hex::encode(bytes)).unwrap()
+    }
+
+    fn pubkey(n: u8) -> Pubkey {
+        Pubkey::new(nostr::Keys::new(secret(n)).public_key().to_bytes())
+    }
+
+    #[test]
+    fn test_decrypt_known_payload() {
+        // made with an independent AES-256-CBC implementation
+        let payload = "UAVXjEpidHQ3T9Ojb7oEhba0DT27grxJcQ5ZwMJaGBs=?iv=AAECAwQFBgcICQoLDA0ODw==";
+        assert_eq!(
+            decrypt(&secret(2), &pubkey(1), payload).unwrap(),
+            "hello nip04 🙂"
+        );
+
+        let iv: [u8; 16] = std::array::from_fn(|i| i as u8);
+        assert_eq!(
+            encrypt_with_iv(&secret(1), &pubkey(2), &iv, "hello nip04 🙂").unwrap(),
+            payload
+        );
+    }
+
+    #[test]
+    fn test_decrypt_go_nostr_payload() {
+        // the "message from go-nostr" vector in nostr-tools' nip04 tests
+        let sender =
+            Pubkey::from_hex("b38ce15d3d9874ee710dfabb7ff9801b1e0e20aace6e9a1a05fa7482a04387d1")
+                .unwrap();
+        let receiver =
+            SecretKey::from_str("96f6fa197aa07477ab88f6981118466ae3a982faab8ad5db9d5426870c73d220")
+                .unwrap();
+
+        let payload = "zJxfaJ32rN5Dg1ODjOlEew==?iv=EV5bUjcc4OX2Km/zPp4ndQ==";
+        assert_eq!(decrypt(&receiver, &sender, payload).unwrap(), "nanana");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let payload = encrypt(&secret(3), &pubkey(4), "hi there").unwrap();
+        assert_eq!(
+            decrypt(&secret(4), &pubkey(3), &payload).unwrap(),
+            "hi there"
+        );
+
+        // someone else can't read it
+        let wrong = decrypt(&secret(5), &pubkey(3), &payload);
+        assert_ne!(wrong.ok().as_deref(), Some("hi there"));
+    }
+
+    #[test]
+    fn test_invalid_payloads() {
+        assert!(decrypt(&secret(2), &pubkey(1), "no iv here").is_err());
+        assert!(decrypt(&secret(2), &pubkey(1), "AAAA?iv=AAAA").is_err());
+        assert!(decrypt(&secret(2), &pubkey(1), "!!!?iv=AAECAwQFBgcICQoLDA0ODw==").is_err());
+    }
+}
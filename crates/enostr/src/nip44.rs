@@ -0,0 +1,417 @@
+//! NIP-44 v2 encryption: ChaCha20 with an HMAC-SHA256 tag, under keys
+//! derived from a conversation key that both sides can compute.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+use chacha20::ChaCha20;
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use nostr::secp256k1::rand::RngCore;
+use sha2::Sha256;
+
+use crate::{Error, Pubkey, Result, SecretKey};
+
+const VERSION: u8 = 2;
+const SALT: &[u8] = b"nip44-v2";
+
+const MIN_PLAINTEXT_LEN: usize = 1;
+const MAX_PLAINTEXT_LEN: usize = 65535;
+
+/// Version, nonce and tag around the smallest padded ciphertext
+const MIN_PAYLOAD_LEN: usize = 1 + 32 + 2 + 32 + 32;
+const MAX_PAYLOAD_LEN: usize = 1 + 32 + 2 + 65536 + 32;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The key two parties share, whichever of them computes it
+#[derive(Clone, PartialEq, Eq)]
+pub struct ConversationKey([u8; 32]);
+
+impl ConversationKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        ConversationKey(bytes)
+    }
+
+    pub fn derive(secret_key: &SecretKey, pubkey: &Pubkey) -> Result<Self> {
+        let shared_x = shared_secret(secret_key, pubkey)?;
+        let (prk, _) = Hkdf::<Sha256>::extract(Some(SALT), &shared_x);
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&prk);
+        Ok(ConversationKey(key))
+    }
+
+    pub fn bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for ConversationKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ConversationKey(<hidden>)")
+    }
+}
+
+/// The per-message keys, derived from the conversation key and nonce
+struct MessageKeys {
+    chacha_key: [u8; 32],
+    chacha_nonce: [u8; 12],
+    hmac_key: [u8; 32],
+}
+
+impl MessageKeys {
+    fn derive(conversation_key: &ConversationKey, nonce: &[u8; 32]) -> Self {
+        let hkdf = Hkdf::<Sha256>::from_prk(conversation_key.bytes())
+            .expect("32 bytes is a valid sha256 prk");
+        let mut okm = [0u8; 76];
+        hkdf.expand(nonce, &mut okm)
+            .expect("76 bytes is a valid sha256 hkdf length");
+
+        let mut keys = MessageKeys {
+            chacha_key: [0; 32],
+            chacha_nonce: [0; 12],
+            hmac_key: [0; 32],
+        };
+        keys.chacha_key.copy_from_slice(&okm[0..32]);
+        keys.chacha_nonce.copy_from_slice(&okm[32..44]);
+        keys.hmac_key.copy_from_slice(&okm[44..76]);
+        keys
+    }
+
+    fn mac(&self, nonce: &[u8; 32], ciphertext: &[u8]) -> HmacSha256 {
+        let mut mac =
+            HmacSha256::new_from_slice(&self.hmac_key).expect("hmac takes keys of any size");
+        mac.update(nonce);
+        mac.update(ciphertext);
+        mac
+    }
+}
+
+/// Encrypt `plaintext` for `pubkey`, returning the base64 payload
+pub fn encrypt(secret_key: &SecretKey, pubkey: &Pubkey, plaintext: &str) -> Result<String> {
+    let conversation_key = ConversationKey::derive(secret_key, pubkey)?;
+    let mut nonce = [0u8; 32];
+    nostr::secp256k1::rand::rngs::OsRng.fill_bytes(&mut nonce);
+    encrypt_with_nonce(&conversation_key, &nonce, plaintext)
+}
+
+/// Decrypt a payload `pubkey` encrypted for us
+pub fn decrypt(secret_key: &SecretKey, pubkey: &Pubkey, payload: &str) -> Result<String> {
+    let conversation_key = ConversationKey::derive(secret_key, pubkey)?;
+    decrypt_with_key(&conversation_key, payload)
+}
+
+/// Encrypt with a nonce of your choosing. Never reuse a nonce, this is
+/// mostly here for test vectors.
+pub fn encrypt_with_nonce(
+    conversation_key: &ConversationKey,
+    nonce: &[u8; 32],
+    plaintext: &str,
+) -> Result<String> {
+    let keys = MessageKeys::derive(conversation_key, nonce);
+
+    let mut buf = pad(plaintext.as_bytes())?;
+    ChaCha20::new(&keys.chacha_key.into(), &keys.chacha_nonce.into()).apply_keystream(&mut buf);
+    let tag = keys.mac(nonce, &buf).finalize().into_bytes();
+
+    let mut payload = Vec::with_capacity(1 + 32 + buf.len() + 32);
+    payload.push(VERSION);
+    payload.extend_from_slice(nonce);
+    payload.extend_from_slice(&buf);
+    payload.extend_from_slice(&tag);
+
+    Ok(BASE64.encode(payload))
+}
+
+pub fn decrypt_with_key(conversation_key: &ConversationKey, payload: &str) -> Result<String> {
+    if payload.starts_with('#') {
+        return Err(Error::DecryptFailed("unknown encryption version"));
+    }
+
+    let data = BASE64
+        .decode(payload)
+        .map_err(|_| Error::DecryptFailed("invalid base64"))?;
+    if data.len() < MIN_PAYLOAD_LEN || data.len() > MAX_PAYLOAD_LEN {
+        return Err(Error::DecryptFailed("invalid payload length"));
+    }
+    if data[0] != VERSION {
+        return Err(Error::DecryptFailed("unknown encryption version"));
+    }
+
+    let nonce: [u8; 32] = data[1..33].try_into()?;
+    let (ciphertext, tag) = data[33..].split_at(data.len() - 33 - 32);
+
+    let keys = MessageKeys::derive(conversation_key, &nonce);
+    keys.mac(&nonce, ciphertext)
+        .verify_slice(tag)
+        .map_err(|_| Error::DecryptFailed("invalid MAC"))?;
+
+    let mut buf = ciphertext.to_vec();
+    ChaCha20::new(&keys.chacha_key.into(), &keys.chacha_nonce.into()).apply_keystream(&mut buf);
+    let plaintext = unpad(&buf)?;
+
+    String::from_utf8(plaintext.to_vec()).map_err(|_| Error::DecryptFailed("invalid utf8"))
+}
+
+/// The size a message of `len` bytes is padded to, so ciphertexts don't
+/// give away exact lengths
+pub fn calc_padded_len(len: usize) -> usize {
+    if len <= 32 {
+        return 32;
+    }
+
+    let next_power = 1usize << (usize::BITS - (len - 1).leading_zeros());
+    let chunk = if next_power <= 256 {
+        32
+    } else {
+        next_power / 8
+    };
+    chunk * ((len - 1) / chunk + 1)
+}
+
+fn pad(plaintext: &[u8]) -> Result<Vec<u8>> {
+    let len = plaintext.len();
+    if !(MIN_PLAINTEXT_LEN..=MAX_PLAINTEXT_LEN).contains(&len) {
+        return Err(Error::EncryptFailed("invalid plaintext length"));
+    }
+
+    let mut padded = Vec::with_capacity(2 + calc_padded_len(len));
+    padded.extend_from_slice(&(len as u16).to_be_bytes());
+    padded.extend_from_slice(plaintext);
+    padded.resize(2 + calc_padded_len(len), 0);
+    Ok(padded)
+}
+
+fn unpad(padded: &[u8]) -> Result<&[u8]> {
+    let len = u16::from_be_bytes([padded[0], padded[1]]) as usize;
+    if len < MIN_PLAINTEXT_LEN || padded.len() != 2 + calc_padded_len(len) {
+        return Err(Error::DecryptFailed("invalid padding"));
+    }
+
+    Ok(&padded[2..2 + len])
+}
+
+/// The x coordinate of the ECDH point, unhashed. NIP-04 uses it as is,
+/// NIP-44 runs it through HKDF.
+pub(crate) fn shared_secret(secret_key: &SecretKey, pubkey: &Pubkey) -> Result<[u8; 32]> {
+    let secret_key = nostr::secp256k1::SecretKey::from_slice(&secret_key.to_secret_bytes())
+        .map_err(|_| Error::InvalidSecretKey)?;
+    let pubkey = nostr::secp256k1::XOnlyPublicKey::from_slice(pubkey.bytes())
+        .map_err(|_| Error::InvalidPublicKey)?
+        .public_key(nostr::secp256k1::Parity::Even);
+
+    let point = nostr::secp256k1::ecdh::shared_secret_point(&pubkey, &secret_key);
+    let mut x = [0u8; 32];
+    x.copy_from_slice(&point[..32]);
+    Ok(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn secret(hex_str: &str) -> SecretKey {
+        SecretKey::from_str(hex_str).unwrap()
+    }
+
+    fn pubkey_of(hex_str: &str) -> Pubkey {
+        let keys = nostr::Keys::new(secret(hex_str));
+        Pubkey::new(keys.public_key().to_bytes())
+    }
+
+    fn bytes32(hex_str: &str) -> [u8; 32] {
+        hex::decode(hex_str).unwrap().try_into().unwrap()
+    }
+
+    // the vectors are from the official nip44.vectors.json
+
+    #[test]
+    fn test_conversation_key() {
+        let vectors = [
+            (
+                "315e59ff51cb9209768cf7da80791ddcaae56ac9775eb25b6dee1234bc5d2268",
+                "c2f9d9948dc8c7c38321e4b85c8558872eafa0641cd269db76848a6073e69133",
+                "3dfef0ce2a4d80a25e7a328accf73448ef67096f65f79588e358d9a0eb9013f1",
+            ),
+            (
+                "a1e37752c9fdc1273be53f68c5f74be7c8905728e8de75800b94262f9497c86e",
+                "03bb7947065dde12ba991ea045132581d0954f042c84e06d8c00066e23c1a800",
+                "4d14f36e81b8452128da64fe6f1eae873baae2f444b02c950b90e43553f2178b",
+            ),
+        ];
+
+        for (sec1, pub2, expected) in vectors {
+            let key = ConversationKey::derive(&secret(sec1), &Pubkey::from_hex(pub2).unwrap());
+            assert_eq!(hex::encode(key.unwrap().bytes()), expected);
+        }
+    }
+
+    #[test]
+    fn test_invalid_conversation_key() {
+        // not on the curve
+        let sec1 = secret("315e59ff51cb9209768cf7da80791ddcaae56ac9775eb25b6dee1234bc5d2268");
+        for pub2 in [
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "1000000000000000000000000000000000000000000000000000000000000000",
+            "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        ] {
+            let res = ConversationKey::derive(&sec1, &Pubkey::from_hex(pub2).unwrap());
+            assert!(matches!(res, Err(Error::InvalidPublicKey)), "{}", pub2);
+        }
+    }
+
+    #[test]
+    fn test_message_keys() {
+        let conversation_key = ConversationKey::new(bytes32(
+            "a1a3d60f3470a8612633924e91febf96dc5366ce130f658b1f0fc652c20b3b54",
+        ));
+        let keys = MessageKeys::derive(
+            &conversation_key,
+            &bytes32("e1e6f880560d6d149ed83dcc7e5861ee62a5ee051f7fde9975fe5d25d2a02d72"),
+        );
+
+        assert_eq!(
+            hex::encode(keys.chacha_key),
+            "f145f3bed47cb70dbeaac07f3a3fe683e822b3715edb7c4fe310829014ce7d76"
+        );
+        assert_eq!(hex::encode(keys.chacha_nonce), "c4ad129bb01180c0933a160c");
+        assert_eq!(
+            hex::encode(keys.hmac_key),
+            "027c1db445f05e2eee864a0975b0ddef5b7110583c8c192de3732571ca5838c4"
+        );
+    }
+
+    #[test]
+    fn test_padded_len() {
+        let vectors = [
+            (16, 32),
+            (32, 32),
+            (33, 64),
+            (37, 64),
+            (45, 64),
+            (49, 64),
+            (64, 64),
+            (65, 96),
+            (100, 128),
+            (111, 128),
+            (200, 224),
+            (250, 256),
+            (320, 320),
+            (383, 384),
+            (384, 384),
+            (400, 448),
+            (500, 512),
+            (512, 512),
+            (515, 640),
+            (700, 768),
+            (800, 896),
+            (900, 1024),
+            (1020, 1024),
+            (65536, 65536),
+        ];
+
+        for (len, padded) in vectors {
+            assert_eq!(calc_padded_len(len), padded, "{}", len);
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let vectors = [
+            (
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "a",
+                "AgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABee0G5VSK0/9YypIObAtDKfYEAjD35uVkHyB0F4DwrcNaCXlCWZKaArsGrY6M9wnuTMxWfp1RTN9Xga8no+kF5Vsb",
+            ),
+            (
+                "0000000000000000000000000000000000000000000000000000000000000002",
+                "0000000000000000000000000000000000000000000000000000000000000001",
+                "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+                "f00000000000000000000000000000f00000000000000000000000000000000f",
+                "🍕🫃",
+                "AvAAAAAAAAAAAAAAAAAAAPAAAAAAAAAAAAAAAAAAAAAPSKSK6is9ngkX2+cSq85Th16oRTISAOfhStnixqZziKMDvB0QQzgFZdjLTPicCJaV8nDITO+QfaQ61+KbWQIOO2Yj",
+            ),
+            (
+                "5c0c523f52a5b6fad39ed2403092df8cebc36318b39383bca6c00808626fab3a",
+                "4b22aa260e4acb7021e32f38a6cdf4b673c6a277755bfce287e370c924dc936d",
+                "3e2b52a63be47d34fe0a80e34e73d436d6963bc8f39827f327057a9986c20a45",
+                "b635236c42db20f021bb8d1cdff5ca75dd1a0cc72ea742ad750f33010b24f73b",
+                "表ポあA鷗ŒéＢ逍Üßªąñ丂㐀𠀀",
+                "ArY1I2xC2yDwIbuNHN/1ynXdGgzHLqdCrXUPMwELJPc7s7JqlCMJBAIIjfkpHReBPXeoMCyuClwgbT419jUWU1PwaNl4FEQYKCDKVJz+97Mp3K+Q2YGa77B6gpxB/lr1QgoqpDf7wDVrDmOqGoiPjWDqy8KzLueKDcm9BVP8xeTJIxs=",
+            ),
+        ];
+
+        for (sec1, sec2, expected_key, nonce, plaintext, payload) in vectors {
+            let key = ConversationKey::derive(&secret(sec1), &pubkey_of(sec2)).unwrap();
+            assert_eq!(hex::encode(key.bytes()), expected_key);
+
+            // both sides get the same key
+            let other = ConversationKey::derive(&secret(sec2), &pubkey_of(sec1)).unwrap();
+            assert_eq!(key, other);
+
+            let encrypted = encrypt_with_nonce(&key, &bytes32(nonce), plaintext).unwrap();
+            assert_eq!(encrypted, payload);
+            assert_eq!(decrypt_with_key(&other, payload).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_invalid_payloads() {
+        let key = ConversationKey::new(bytes32(
+            "c41c775356fd92eadc63ff5a0dc1da211b268cbea22316767095b2871ea1412d",
+        ));
+        let payload = "AgAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAABee0G5VSK0/9YypIObAtDKfYEAjD35uVkHyB0F4DwrcNaCXlCWZKaArsGrY6M9wnuTMxWfp1RTN9Xga8no+kF5Vsb";
+        assert!(decrypt_with_key(&key, payload).is_ok());
+
+        let mut raw = BASE64.decode(payload).unwrap();
+
+        // flip a bit in the ciphertext
+        let mut tampered = raw.clone();
+        tampered[40] ^= 1;
+        assert!(matches!(
+            decrypt_with_key(&key, &BASE64.encode(&tampered)),
+            Err(Error::DecryptFailed("invalid MAC"))
+        ));
+
+        // a different version
+        raw[0] = 1;
+        assert!(decrypt_with_key(&key, &BASE64.encode(&raw)).is_err());
+
+        assert!(decrypt_with_key(&key, &format!("#{}", payload)).is_err());
+        assert!(decrypt_with_key(&key, "not base64 at all!").is_err());
+        assert!(decrypt_with_key(&key, "").is_err());
+        assert!(decrypt_with_key(&key, &BASE64.encode([2u8; 50])).is_err());
+    }
+
+    #[test]
+    fn test_plaintext_length_limits() {
+        let key = ConversationKey::new([7; 32]);
+        let nonce = [1; 32];
+
+        assert!(encrypt_with_nonce(&key, &nonce, "").is_err());
+        assert!(encrypt_with_nonce(&key, &nonce, &"x".repeat(65536)).is_err());
+
+        let longest = "x".repeat(65535);
+        let payload = encrypt_with_nonce(&key, &nonce, &longest).unwrap();
+        assert_eq!(decrypt_with_key(&key, &payload).unwrap(), longest);
+    }
+
+    #[test]
+    fn test_round_trip_with_keys() {
+        let alice = "5c0c523f52a5b6fad39ed2403092df8cebc36318b39383bca6c00808626fab3a";
+        let bob = "4b22aa260e4acb7021e32f38a6cdf4b673c6a277755bfce287e370c924dc936d";
+
+        let payload = encrypt(&secret(alice), &pubkey_of(bob), "hello bob").unwrap();
+        let again = encrypt(&secret(alice), &pubkey_of(bob), "hello bob").unwrap();
+        assert_ne!(payload, again, "nonces should be random");
+
+        assert_eq!(
+            decrypt(&secret(bob), &pubkey_of(alice), &payload).unwrap(),
+            "hello bob"
+        );
+    }
+}
@@ -0,0 +1,603 @@
+//! NIP-46 remote signing. The user's secret key stays with a signer
+//! (a "bunker"), we talk to it over relays with a key of our own and ask
+//! it to sign notes for us.
+
+use nostr::secp256k1::rand::{rngs::OsRng, RngCore};
+use nostrdb::Filter;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tracing::{debug, error, info, warn};
+
+use crate::nip59::{self, now};
+use crate::{
+    ClientMessage, Crypt, Encryption, Error, FilledKeypair, FullKeypair, Note, NoteId, ProxyConfig,
+    Pubkey, RelayEvent, RelayMessage, RelayPool, Result, Signature, Signer, UnsignedNote,
+};
+
+pub const NOSTR_CONNECT_KIND: u64 = 24133;
+
+/// How far back we look for answers when we subscribe, in case the
+/// signer's clock is a little behind ours
+const SINCE_SLACK_SECS: u64 = 30;
+
+/// What the user gives us to reach their signer:
+/// `bunker://<signer pubkey>?relay=wss://...&secret=...`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BunkerUri {
+    /// The key the signer talks to us with. This isn't necessarily the
+    /// key it signs with, we ask for that after connecting.
+    pub remote_signer: Pubkey,
+    pub relays: Vec<String>,
+    /// A one time secret that lets us connect
+    pub secret: Option<String>,
+}
+
+impl BunkerUri {
+    pub fn parse(uri: &str) -> Result<Self> {
+        let invalid = |why: &str| Error::Generic(format!("invalid bunker url: {}", why));
+
+        let url = url::Url::parse(uri.trim()).map_err(|_| invalid("can't parse it"))?;
+        if url.scheme() != "bunker" {
+            return Err(invalid("it should start with bunker://"));
+        }
+
+        let remote_signer = url
+            .host_str()
+            .and_then(|host| Pubkey::from_hex(host).ok())
+            .ok_or_else(|| invalid("missing the signer's pubkey"))?;
+
+        let mut relays: Vec<String> = Vec::new();
+        let mut secret = None;
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "relay" => {
+                    let relay = if let Ok(relay) = url::Url::parse(&value) {
+                        relay
+                    } else {
+                        continue;
+                    };
+
+                    if matches!(relay.scheme(), "ws" | "wss")
+                        && !relays.contains(&relay.to_string())
+                    {
+                        relays.push(relay.to_string());
+                    }
+                }
+                "secret" if !value.is_empty() => secret = Some(value.into_owned()),
+                _ => {}
+            }
+        }
+
+        if relays.is_empty() {
+            return Err(invalid("no relays to reach the signer on"));
+        }
+
+        Ok(BunkerUri {
+            remote_signer,
+            relays,
+            secret,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Request {
+    pub id: String,
+    pub method: String,
+    pub params: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Response {
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A kind 24133 carrying `json` to `to`, encrypted with NIP-44
+pub fn message(from: FilledKeypair, to: &Pubkey, json: &str) -> Result<Note> {
+    let content = from.nip44_encrypt(to, json)?;
+    let tags = vec![vec!["p".to_owned(), to.hex()]];
+    nip59::sign_event(from.secret_key, now(), NOSTR_CONNECT_KIND, tags, content)
+}
+
+/// Read a kind 24133 sent to us. Some signers still use NIP-04.
+pub fn open_message(keypair: FilledKeypair, note: &Note) -> Result<String> {
+    if note.content.contains("?iv=") {
+        keypair.nip04_decrypt(&note.pubkey, &note.content)
+    } else {
+        keypair.nip44_decrypt(&note.pubkey, &note.content)
+    }
+}
+
+/// Where a session is at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionStatus {
+    /// Waiting for the signer to accept us
+    Connecting,
+    /// The signer signs for this pubkey
+    Connected(Pubkey),
+    /// The signer turned us down
+    Failed(String),
+}
+
+/// Things the signer told us, see [`BunkerSession::poll`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BunkerEvent {
+    /// The signer accepted us and signs for this pubkey
+    Connected(Pubkey),
+    ConnectFailed(String),
+    Signed {
+        request: String,
+        note: Note,
+    },
+    SignFailed {
+        request: String,
+        error: String,
+    },
+    /// The answer to an encrypt or decrypt request
+    Crypted {
+        request: String,
+        text: String,
+    },
+    CryptFailed {
+        request: String,
+        error: String,
+    },
+    /// The user has to approve a request at `url`. The signer answers
+    /// it once they do.
+    AuthUrl {
+        request: String,
+        url: String,
+    },
+}
+
+/// What we asked the signer for
+#[derive(Debug, Clone, Copy)]
+enum Pending {
+    Connect,
+    GetPublicKey,
+    /// The id the signed note should have
+    Sign(NoteId),
+    /// Encrypting or decrypting
+    Crypt,
+}
+
+/// A connection to a remote signer. It has relay connections of its own,
+/// so call [`BunkerSession::poll`] regularly to hear back from it.
+pub struct BunkerSession {
+    uri: BunkerUri,
+    /// Our side of the conversation. The signer knows us by this key,
+    /// it has nothing to do with the user's.
+    client: FullKeypair,
+    status: SessionStatus,
+    pool: RelayPool,
+    sub_id: String,
+    pending: HashMap<String, Pending>,
+}
+
+impl BunkerSession {
+    /// Connect to the signer with a new client key
+    pub fn connect(
+        uri: BunkerUri,
+        proxy: &ProxyConfig,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    ) -> Result<Self> {
+        let mut session = Self::open(
+            uri,
+            FullKeypair::generate(),
+            SessionStatus::Connecting,
+            proxy,
+            wakeup,
+        )?;
+
+        let mut params = vec![session.uri.remote_signer.hex()];
+        if let Some(secret) = &session.uri.secret {
+            params.push(secret.clone());
+        }
+        session.request(Pending::Connect, "connect", params)?;
+
+        Ok(session)
+    }
+
+    /// Pick a session back up. The signer already knows our client key,
+    /// so there's no need to connect again.
+    pub fn restore(
+        uri: BunkerUri,
+        client: FullKeypair,
+        user: Pubkey,
+        proxy: &ProxyConfig,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    ) -> Result<Self> {
+        Self::open(uri, client, SessionStatus::Connected(user), proxy, wakeup)
+    }
+
+    fn open(
+        uri: BunkerUri,
+        client: FullKeypair,
+        status: SessionStatus,
+        proxy: &ProxyConfig,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    ) -> Result<Self> {
+        let mut pool = RelayPool::new();
+        pool.set_proxy(proxy.clone());
+        pool.add_urls(uri.relays.iter().cloned().collect(), wakeup)?;
+
+        let sub_id = format!("nip46-{}", &client.pubkey.hex()[..8]);
+        let filter = Filter::new()
+            .kinds([NOSTR_CONNECT_KIND])
+            .pubkeys([client.pubkey.bytes()])
+            .since(now().saturating_sub(SINCE_SLACK_SECS))
+            .build();
+        pool.subscribe(sub_id.clone(), vec![filter]);
+
+        Ok(BunkerSession {
+            uri,
+            client,
+            status,
+            pool,
+            sub_id,
+            pending: HashMap::new(),
+        })
+    }
+
+    /// Reach the signer's relays through `proxy`, like the rest of our
+    /// relays
+    pub fn set_proxy(&mut self, proxy: &ProxyConfig) {
+        self.pool.set_proxy(proxy.clone());
+    }
+
+    pub fn uri(&self) -> &BunkerUri {
+        &self.uri
+    }
+
+    pub fn client(&self) -> &FullKeypair {
+        &self.client
+    }
+
+    pub fn status(&self) -> &SessionStatus {
+        &self.status
+    }
+
+    /// Who we sign for, once we're connected
+    pub fn user(&self) -> Option<&Pubkey> {
+        if let SessionStatus::Connected(user) = &self.status {
+            Some(user)
+        } else {
+            None
+        }
+    }
+
+    fn request(&mut self, pending: Pending, method: &str, params: Vec<String>) -> Result<String> {
+        let id = new_request_id();
+        let request = Request {
+            id: id.clone(),
+            method: method.to_owned(),
+            params,
+        };
+        let note = message(
+            self.client.to_filled(),
+            &self.uri.remote_signer,
+            &serde_json::to_string(&request)?,
+        )?;
+
+        debug!("nip46 {} request {}", method, id);
+        let urls = self.pool.urls();
+        self.pool.publish(
+            note.id,
+            ClientMessage::event(note),
+            urls.iter().map(|url| url.as_str()),
+        );
+        self.pending.insert(id.clone(), pending);
+
+        Ok(id)
+    }
+
+    /// Stop waiting on `request`, its answer is ignored if it comes
+    pub fn forget(&mut self, request: &str) {
+        self.pending.remove(request);
+    }
+
+    /// Handle whatever the signer's relays sent us
+    pub fn poll(&mut self, wakeup: impl Fn() + Send + Sync + Clone + 'static) -> Vec<BunkerEvent> {
+        self.pool.keepalive_ping(wakeup);
+
+        let mut events = Vec::new();
+        while let Some(ev) = self.pool.try_recv() {
+            let ev = ev.into_owned();
+            let msg = if let RelayEvent::Message(msg) = RelayEvent::from(&ev.event) {
+                msg
+            } else {
+                if self.pool.needs_resubscribe(&ev.relay) {
+                    self.pool.resubscribe(&ev.relay, |_sub_id, filters| filters);
+                }
+                continue;
+            };
+
+            match msg {
+                RelayMessage::Event(sub_id, txt) if sub_id == self.sub_id.as_str() => {
+                    events.extend(self.handle_event(txt));
+                }
+                RelayMessage::Ok {
+                    event_id,
+                    accepted,
+                    message,
+                } => {
+                    self.pool
+                        .handle_ok(&ev.relay, &event_id, accepted, &message);
+                }
+                RelayMessage::Closed { sub_id, reason } => {
+                    self.pool.handle_closed(&ev.relay, &sub_id, &reason);
+                }
+                RelayMessage::Auth { challenge } => {
+                    self.pool
+                        .authenticate(&ev.relay, &challenge, Some(self.client.to_filled()));
+                }
+                RelayMessage::Notice(notice) => warn!("notice from {}: {}", ev.relay, notice),
+                _ => {}
+            }
+        }
+
+        events
+    }
+
+    fn handle_event(&mut self, txt: &str) -> Option<BunkerEvent> {
+        let note = match serde_json::from_str::<(String, String, Note)>(txt) {
+            Ok((_, _, note)) => note,
+            Err(err) => {
+                warn!("bad nip46 event: {}", err);
+                return None;
+            }
+        };
+
+        if note.pubkey != self.uri.remote_signer || note.kind != NOSTR_CONNECT_KIND {
+            return None;
+        }
+
+        if let Err(err) = nip59::verify_event(&note) {
+            warn!("nip46 message with a bad signature: {}", err);
+            return None;
+        }
+
+        let response = match open_message(self.client.to_filled(), &note)
+            .and_then(|json| Ok(serde_json::from_str::<Response>(&json)?))
+        {
+            Ok(response) => response,
+            Err(err) => {
+                warn!("couldn't read nip46 message: {}", err);
+                return None;
+            }
+        };
+
+        // relays send it more than once, or it's an old answer
+        let pending = self.pending.remove(&response.id)?;
+        self.handle_response(response, pending)
+    }
+
+    fn handle_response(&mut self, response: Response, pending: Pending) -> Option<BunkerEvent> {
+        if response.result.as_deref() == Some("auth_url") {
+            let url = response.error.unwrap_or_default();
+            info!("nip46 request {} needs approval at {}", response.id, url);
+            self.pending.insert(response.id.clone(), pending);
+            return Some(BunkerEvent::AuthUrl {
+                request: response.id,
+                url,
+            });
+        }
+
+        let result = match (response.result, response.error) {
+            (_, Some(error)) if !error.is_empty() => Err(error),
+            (Some(result), _) => Ok(result),
+            (None, _) => Err("empty response".to_owned()),
+        };
+
+        match pending {
+            // the result is "ack", or the secret we sent
+            Pending::Connect => {
+                let requested = result.and_then(|_| {
+                    self.request(Pending::GetPublicKey, "get_public_key", vec![])
+                        .map_err(|err| err.to_string())
+                });
+
+                if let Err(error) = requested {
+                    error!("nip46 connect failed: {}", error);
+                    self.status = SessionStatus::Failed(error.clone());
+                    return Some(BunkerEvent::ConnectFailed(error));
+                }
+                None
+            }
+
+            Pending::GetPublicKey => {
+                let user = result.and_then(|hex| {
+                    Pubkey::from_hex(&hex).map_err(|_| format!("invalid pubkey {}", hex))
+                });
+
+                match user {
+                    Ok(user) => {
+                        info!("nip46 signer connected for {}", user.hex());
+                        self.status = SessionStatus::Connected(user);
+                        Some(BunkerEvent::Connected(user))
+                    }
+                    Err(error) => {
+                        error!("nip46 get_public_key failed: {}", error);
+                        self.status = SessionStatus::Failed(error.clone());
+                        Some(BunkerEvent::ConnectFailed(error))
+                    }
+                }
+            }
+
+            Pending::Sign(expected) => {
+                match result.and_then(|json| check_signed(&json, &expected)) {
+                    Ok(note) => Some(BunkerEvent::Signed {
+                        request: response.id,
+                        note,
+                    }),
+                    Err(error) => {
+                        error!("nip46 sign_event {} failed: {}", response.id, error);
+                        Some(BunkerEvent::SignFailed {
+                            request: response.id,
+                            error,
+                        })
+                    }
+                }
+            }
+
+            Pending::Crypt => match result {
+                Ok(text) => Some(BunkerEvent::Crypted {
+                    request: response.id,
+                    text,
+                }),
+                Err(error) => {
+                    error!("nip46 request {} failed: {}", response.id, error);
+                    Some(BunkerEvent::CryptFailed {
+                        request: response.id,
+                        error,
+                    })
+                }
+            },
+        }
+    }
+
+    fn connected_user(&self) -> Result<Pubkey> {
+        self.user()
+            .copied()
+            .ok_or_else(|| Error::Generic("remote signer isn't connected".to_owned()))
+    }
+}
+
+impl Signer for BunkerSession {
+    fn sign(&mut self, note: UnsignedNote) -> Result<Signature> {
+        if note.pubkey != self.connected_user()? {
+            return Err(Error::InvalidPublicKey);
+        }
+
+        let id = note.id();
+        let unsigned = serde_json::json!({
+            "kind": note.kind,
+            "content": note.content,
+            "tags": note.tags,
+            "created_at": note.created_at,
+        });
+        let request = self.request(Pending::Sign(id), "sign_event", vec![unsigned.to_string()])?;
+
+        Ok(Signature::Requested(request))
+    }
+
+    fn encrypt(&mut self, encryption: Encryption, to: &Pubkey, plaintext: &str) -> Result<Crypt> {
+        self.connected_user()?;
+        let params = vec![to.hex(), plaintext.to_owned()];
+        let request = self.request(Pending::Crypt, encryption.encrypt_method(), params)?;
+        Ok(Crypt::Requested(request))
+    }
+
+    fn decrypt(
+        &mut self,
+        encryption: Encryption,
+        from: &Pubkey,
+        ciphertext: &str,
+    ) -> Result<Crypt> {
+        self.connected_user()?;
+        let params = vec![from.hex(), ciphertext.to_owned()];
+        let request = self.request(Pending::Crypt, encryption.decrypt_method(), params)?;
+        Ok(Crypt::Requested(request))
+    }
+}
+
+/// A signed note the signer sent back, as long as it's the one we asked
+/// for
+fn check_signed(json: &str, expected: &NoteId) -> std::result::Result<Note, String> {
+    let note = Note::from_json(json).map_err(|err| err.to_string())?;
+    if note.id != *expected {
+        return Err("the signer changed the note".to_owned());
+    }
+
+    nip59::verify_event(&note).map_err(|err| err.to_string())?;
+    Ok(note)
+}
+
+fn new_request_id() -> String {
+    let mut bytes = [0u8; 8];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bunker_uri() -> Result<()> {
+        let signer = FullKeypair::generate().pubkey;
+        let uri = BunkerUri::parse(&format!(
+            "bunker://{}?relay=wss%3A%2F%2Frelay.example.com&relay=wss://relay.example.com/&relay=https://web.example.com&secret=abc",
+            signer.hex()
+        ))?;
+
+        assert_eq!(uri.remote_signer, signer);
+        assert_eq!(uri.relays, vec!["wss://relay.example.com/".to_owned()]);
+        assert_eq!(uri.secret.as_deref(), Some("abc"));
+
+        let no_secret = BunkerUri::parse(&format!(
+            "bunker://{}?relay=ws://localhost:8080",
+            signer.hex()
+        ))?;
+        assert_eq!(no_secret.secret, None);
+
+        assert!(BunkerUri::parse(&format!("bunker://{}", signer.hex())).is_err());
+        assert!(BunkerUri::parse("bunker://npub1nope?relay=wss://relay.example.com").is_err());
+        assert!(BunkerUri::parse(&format!(
+            "nostrconnect://{}?relay=wss://relay.example.com",
+            signer.hex()
+        ))
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_message_roundtrip() -> Result<()> {
+        let client = FullKeypair::generate();
+        let signer = FullKeypair::generate();
+
+        let note = message(client.to_filled(), &signer.pubkey, r#"{"id":"1"}"#)?;
+        assert_eq!(note.kind, NOSTR_CONNECT_KIND);
+        assert_eq!(note.tags, vec![vec!["p".to_owned(), signer.pubkey.hex()]]);
+        nip59::verify_event(&note)?;
+        assert_eq!(open_message(signer.to_filled(), &note)?, r#"{"id":"1"}"#);
+
+        // older signers answer with nip04
+        let content = signer.nip04_encrypt(&client.pubkey, "ack")?;
+        let legacy = nip59::sign_event(
+            &signer.secret_key,
+            now(),
+            NOSTR_CONNECT_KIND,
+            vec![],
+            content,
+        )?;
+        assert_eq!(open_message(client.to_filled(), &legacy)?, "ack");
+        Ok(())
+    }
+
+    #[test]
+    fn test_only_the_asked_note() -> Result<()> {
+        let user = FullKeypair::generate();
+        let asked = UnsignedNote {
+            pubkey: user.pubkey,
+            created_at: 1_700_000_000,
+            kind: 1,
+            tags: vec![],
+            content: "gm".to_owned(),
+        };
+        let id = asked.id();
+
+        let mut swapped = asked.clone();
+        swapped.content = "gn".to_owned();
+        let swapped = serde_json::to_string(&swapped.sign(&user.secret_key)?)?;
+        assert!(check_signed(&swapped, &id).is_err());
+
+        let signed = serde_json::to_string(&asked.sign(&user.secret_key)?)?;
+        assert_eq!(check_signed(&signed, &id).map(|note| note.id), Ok(id));
+        Ok(())
+    }
+}
@@ -0,0 +1,316 @@
+//! NIP-59 gift wraps. A rumor (an event that is never signed) is sealed
+//! by its author in a kind 13, encrypted to the recipient, and the seal
+//! is wrapped again in a kind 1059 signed by a throwaway key, so relays
+//! only learn who a message is for.
+
+use nostr::secp256k1::rand::RngCore;
+use nostr::secp256k1::{schnorr, Keypair as SecpKeypair, Message, XOnlyPublicKey};
+use nostr::SECP256K1;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    nip44, Error, FilledKeypair, FullKeypair, Note, NoteId, Pubkey, Result, SecretKey, UnsignedNote,
+};
+
+pub const SEAL_KIND: u64 = 13;
+pub const GIFT_WRAP_KIND: u64 = 1059;
+
+/// Seals and wraps are backdated by up to this much, so their
+/// timestamps don't give away when a message was sent
+const MAX_TWEAK_SECS: u64 = 2 * 24 * 60 * 60;
+
+/// An unsigned event. Anyone who gets hold of one can't prove who wrote
+/// it, the seal around it is what tells the recipient.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Rumor {
+    pub id: NoteId,
+    pub pubkey: Pubkey,
+    pub created_at: u64,
+    pub kind: u64,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+}
+
+impl Rumor {
+    pub fn new(
+        pubkey: Pubkey,
+        created_at: u64,
+        kind: u64,
+        tags: Vec<Vec<String>>,
+        content: String,
+    ) -> Self {
+        let id = NoteId::new(event_id(&pubkey, created_at, kind, &tags, &content));
+        Rumor {
+            id,
+            pubkey,
+            created_at,
+            kind,
+            tags,
+            content,
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let rumor: Rumor = serde_json::from_str(json)?;
+        let id = event_id(
+            &rumor.pubkey,
+            rumor.created_at,
+            rumor.kind,
+            &rumor.tags,
+            &rumor.content,
+        );
+        if &id != rumor.id.bytes() {
+            return Err(Error::DecryptFailed("rumor id doesn't match its contents"));
+        }
+
+        Ok(rumor)
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    /// The values of the rumor's `p` tags
+    pub fn tagged_pubkeys(&self) -> Vec<Pubkey> {
+        self.tags
+            .iter()
+            .filter(|tag| tag.len() >= 2 && tag[0] == "p")
+            .filter_map(|tag| Pubkey::from_hex(&tag[1]).ok())
+            .collect()
+    }
+}
+
+/// The NIP-01 event id
+pub fn event_id(
+    pubkey: &Pubkey,
+    created_at: u64,
+    kind: u64,
+    tags: &[Vec<String>],
+    content: &str,
+) -> [u8; 32] {
+    let serialized =
+        serde_json::json!([0, pubkey.hex(), created_at, kind, tags, content]).to_string();
+    Sha256::digest(serialized.as_bytes()).into()
+}
+
+/// Sign an event with `secret_key`. nostrdb can't backdate notes, so
+/// seals and wraps are built here.
+pub fn sign_event(
+    secret_key: &SecretKey,
+    created_at: u64,
+    kind: u64,
+    tags: Vec<Vec<String>>,
+    content: String,
+) -> Result<Note> {
+    let keypair = SecpKeypair::from_seckey_slice(&SECP256K1, &secret_key.to_secret_bytes())
+        .map_err(|_| Error::InvalidSecretKey)?;
+    let pubkey = Pubkey::new(keypair.x_only_public_key().0.serialize());
+
+    let id = event_id(&pubkey, created_at, kind, &tags, &content);
+    let mut aux = [0u8; 32];
+    nostr::secp256k1::rand::rngs::OsRng.fill_bytes(&mut aux);
+    let sig = SECP256K1.sign_schnorr_with_aux_rand(&Message::from_digest(id), &keypair, &aux);
+
+    Ok(Note {
+        id: NoteId::new(id),
+        pubkey,
+        created_at,
+        kind,
+        tags,
+        content,
+        sig: hex::encode(sig.serialize()),
+    })
+}
+
+/// Check an event's id and signature
+pub fn verify_event(note: &Note) -> Result<()> {
+    let id = event_id(
+        &note.pubkey,
+        note.created_at,
+        note.kind,
+        &note.tags,
+        &note.content,
+    );
+    if &id != note.id.bytes() {
+        return Err(Error::InvalidSignature);
+    }
+
+    let sig = schnorr::Signature::from_slice(&hex::decode(&note.sig)?)
+        .map_err(|_| Error::InvalidSignature)?;
+    let pubkey =
+        XOnlyPublicKey::from_slice(note.pubkey.bytes()).map_err(|_| Error::InvalidPublicKey)?;
+
+    SECP256K1
+        .verify_schnorr(&sig, &Message::from_digest(id), &pubkey)
+        .map_err(|_| Error::InvalidSignature)
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Now, backdated by a random amount
+pub fn tweaked_now() -> u64 {
+    let tweak = nostr::secp256k1::rand::rngs::OsRng.next_u64() % MAX_TWEAK_SECS;
+    now().saturating_sub(tweak)
+}
+
+/// Seal `rumor` for `recipient`, signed by its author
+pub fn seal(author: FilledKeypair, recipient: &Pubkey, rumor: &Rumor) -> Result<Note> {
+    if rumor.pubkey != *author.pubkey {
+        return Err(Error::EncryptFailed("rumor isn't by the sealing key"));
+    }
+
+    let content = nip44::encrypt(author.secret_key, recipient, &rumor.to_json()?)?;
+    unsigned_seal(author.pubkey, content).sign(author.secret_key)
+}
+
+/// The seal for `content`, a rumor `author` encrypted to the recipient,
+/// for signers that don't hand us the author's secret key
+pub fn unsigned_seal(author: &Pubkey, content: String) -> UnsignedNote {
+    UnsignedNote {
+        pubkey: *author,
+        created_at: tweaked_now(),
+        kind: SEAL_KIND,
+        tags: vec![],
+        content,
+    }
+}
+
+/// Wrap a seal for `recipient` with a new throwaway key
+pub fn gift_wrap(recipient: &Pubkey, seal: &Note) -> Result<Note> {
+    let throwaway = FullKeypair::generate();
+    let json = serde_json::to_string(seal)?;
+    let content = nip44::encrypt(&throwaway.secret_key, recipient, &json)?;
+    let tags = vec![vec!["p".to_owned(), recipient.hex()]];
+    sign_event(
+        &throwaway.secret_key,
+        tweaked_now(),
+        GIFT_WRAP_KIND,
+        tags,
+        content,
+    )
+}
+
+/// Seal and wrap `rumor` for `recipient`
+pub fn wrap(author: FilledKeypair, recipient: &Pubkey, rumor: &Rumor) -> Result<Note> {
+    gift_wrap(recipient, &seal(author, recipient, rumor)?)
+}
+
+/// Open a gift wrap sent to us. The wrap is given by its author and
+/// content. The rumor inside is only returned if its author is the one
+/// who signed the seal, otherwise anyone could put words in someone
+/// else's mouth.
+pub fn unwrap(keypair: FilledKeypair, wrap_pubkey: &Pubkey, wrap_content: &str) -> Result<Rumor> {
+    let seal = open_seal(&nip44::decrypt(
+        keypair.secret_key,
+        wrap_pubkey,
+        wrap_content,
+    )?)?;
+    let rumor_json = nip44::decrypt(keypair.secret_key, &seal.pubkey, &seal.content)?;
+    open_rumor(&seal, &rumor_json)
+}
+
+/// The seal in a gift wrap, from the wrap's decrypted content
+pub fn open_seal(seal_json: &str) -> Result<Note> {
+    let seal = Note::from_json(seal_json)?;
+    if seal.kind != SEAL_KIND {
+        return Err(Error::DecryptFailed("gift wrap doesn't contain a seal"));
+    }
+    verify_event(&seal)?;
+    Ok(seal)
+}
+
+/// The rumor in `seal`, from the seal's decrypted content
+pub fn open_rumor(seal: &Note, rumor_json: &str) -> Result<Rumor> {
+    let rumor = Rumor::from_json(rumor_json)?;
+    if rumor.pubkey != seal.pubkey {
+        return Err(Error::DecryptFailed("rumor author didn't sign the seal"));
+    }
+
+    Ok(rumor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chat(author: &FullKeypair, to: &Pubkey, content: &str) -> Rumor {
+        Rumor::new(
+            author.pubkey,
+            1_700_000_000,
+            14,
+            vec![vec!["p".to_owned(), to.hex()]],
+            content.to_owned(),
+        )
+    }
+
+    #[test]
+    fn test_wrap_unwrap() -> Result<()> {
+        let alice = FullKeypair::generate();
+        let bob = FullKeypair::generate();
+
+        let rumor = chat(&alice, &bob.pubkey, "hola");
+        let wrapped = wrap(alice.to_filled(), &bob.pubkey, &rumor)?;
+        assert_eq!(wrapped.kind, GIFT_WRAP_KIND);
+        assert_ne!(wrapped.pubkey, alice.pubkey);
+        assert_eq!(wrapped.tags, vec![vec!["p".to_owned(), bob.pubkey.hex()]]);
+        assert!(wrapped.created_at <= now());
+        verify_event(&wrapped)?;
+
+        let opened = unwrap(bob.to_filled(), &wrapped.pubkey, &wrapped.content)?;
+        assert_eq!(opened, rumor);
+        assert_eq!(opened.tagged_pubkeys(), vec![bob.pubkey]);
+
+        // only bob can open it
+        assert!(unwrap(alice.to_filled(), &wrapped.pubkey, &wrapped.content).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rejects_impersonation() -> Result<()> {
+        let alice = FullKeypair::generate();
+        let bob = FullKeypair::generate();
+        let mallory = FullKeypair::generate();
+
+        // mallory seals a rumor that claims to be from alice
+        let rumor = chat(&alice, &bob.pubkey, "send me your nsec");
+        let content = nip44::encrypt(&mallory.secret_key, &bob.pubkey, &rumor.to_json()?)?;
+        let seal = sign_event(&mallory.secret_key, now(), SEAL_KIND, vec![], content)?;
+        let wrapped = gift_wrap(&bob.pubkey, &seal)?;
+
+        assert!(unwrap(bob.to_filled(), &wrapped.pubkey, &wrapped.content).is_err());
+
+        // and we won't seal it for her either
+        assert!(seal(mallory.to_filled(), &bob.pubkey, &rumor).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rumor_id() -> Result<()> {
+        let alice = FullKeypair::generate();
+        let mut rumor = chat(&alice, &alice.pubkey, "note to self");
+        assert!(Rumor::from_json(&rumor.to_json()?).is_ok());
+
+        rumor.content = "edited".to_owned();
+        assert!(Rumor::from_json(&rumor.to_json()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_signed_events_verify() -> Result<()> {
+        let alice = FullKeypair::generate();
+        let mut note = sign_event(&alice.secret_key, 1, 1, vec![], "hi".to_owned())?;
+        assert_eq!(note.pubkey, alice.pubkey);
+        verify_event(&note)?;
+
+        note.content = "bye".to_owned();
+        assert!(verify_event(&note).is_err());
+        Ok(())
+    }
+}
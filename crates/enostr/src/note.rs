@@ -1,5 +1,6 @@
-use crate::{Error, Pubkey};
+use crate::{nip59, Error, Pubkey, SecretKey};
 
+use nostrdb::NoteBuilder;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt;
 use std::hash::{Hash, Hasher};
@@ -105,6 +106,64 @@ impl Note {
     }
 }
 
+/// A note that still needs its author's signature, see [`crate::Signer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsignedNote {
+    pub pubkey: Pubkey,
+    pub created_at: u64,
+    pub kind: u64,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+}
+
+impl UnsignedNote {
+    /// Take what was put in a nostrdb builder that wasn't given a key.
+    /// The note will be signed by `pubkey`.
+    pub fn from_builder(pubkey: Pubkey, mut builder: NoteBuilder<'_>) -> Result<Self, Error> {
+        let built = builder
+            .build()
+            .ok_or_else(|| Error::Generic("failed to build note".to_owned()))?;
+        let note = Note::from_json(&built.json()?)?;
+
+        Ok(UnsignedNote {
+            pubkey,
+            created_at: note.created_at,
+            kind: note.kind,
+            tags: note.tags,
+            content: note.content,
+        })
+    }
+
+    /// The id the note will have, signing doesn't change it
+    pub fn id(&self) -> NoteId {
+        NoteId::new(nip59::event_id(
+            &self.pubkey,
+            self.created_at,
+            self.kind,
+            &self.tags,
+            &self.content,
+        ))
+    }
+
+    /// Sign with the author's secret key
+    pub fn sign(self, secret_key: &SecretKey) -> Result<Note, Error> {
+        let pubkey = self.pubkey;
+        let note = nip59::sign_event(
+            secret_key,
+            self.created_at,
+            self.kind,
+            self.tags,
+            self.content,
+        )?;
+
+        if note.pubkey != pubkey {
+            return Err(Error::InvalidSecretKey);
+        }
+
+        Ok(note)
+    }
+}
+
 impl std::str::FromStr for Note {
     type Err = Error;
 
@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// How we reach relays and the rest of the network
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyConfig {
+    /// `host:port` of a SOCKS5 proxy, Tor's is `127.0.0.1:9050`
+    pub socks5: Option<String>,
+
+    /// Never connect directly. When there is no proxy to go through,
+    /// connections fail instead.
+    pub proxy_only: bool,
+}
+
+impl ProxyConfig {
+    pub fn socks5(addr: impl Into<String>) -> Self {
+        ProxyConfig {
+            socks5: Some(addr.into()),
+            proxy_only: false,
+        }
+    }
+
+    /// Whether we may connect without a proxy
+    pub fn allows_direct(&self) -> bool {
+        self.socks5.is_none() && !self.proxy_only
+    }
+
+    /// The proxy as a url, the way http clients like it
+    pub fn url(&self) -> Option<String> {
+        self.socks5
+            .as_ref()
+            .map(|addr| format!("socks5://{}", addr))
+    }
+}
+
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTH: u8 = 0;
+const CONNECT: u8 = 1;
+const DOMAIN_NAME: u8 = 3;
+
+/// Open a TCP connection to `host:port` through a SOCKS5 proxy. The proxy
+/// resolves the host name, so with Tor DNS doesn't leak either.
+pub fn socks5_connect(proxy: &str, host: &str, port: u16) -> io::Result<TcpStream> {
+    let host = host.as_bytes();
+    let host_len = u8::try_from(host.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "host name too long"))?;
+
+    let mut stream = TcpStream::connect(proxy)?;
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+
+    stream.write_all(&[SOCKS_VERSION, 1, NO_AUTH])?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [SOCKS_VERSION, NO_AUTH] {
+        return Err(socks_error("proxy wants authentication we don't do"));
+    }
+
+    let mut req = vec![SOCKS_VERSION, CONNECT, 0, DOMAIN_NAME, host_len];
+    req.extend_from_slice(host);
+    req.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&req)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(socks_error("not a SOCKS5 proxy"));
+    }
+    if reply[1] != 0 {
+        return Err(socks_error(reply_error(reply[1])));
+    }
+
+    // the address the proxy bound, which we don't need
+    let addr_len = match reply[3] {
+        1 => 4,
+        4 => 16,
+        DOMAIN_NAME => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => return Err(socks_error("bad address in proxy reply")),
+    };
+    let mut bound = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound)?;
+
+    stream.set_read_timeout(None)?;
+    Ok(stream)
+}
+
+fn socks_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("socks5: {}", msg))
+}
+
+fn reply_error(code: u8) -> &'static str {
+    match code {
+        1 => "general failure",
+        2 => "connection not allowed by ruleset",
+        3 => "network unreachable",
+        4 => "host unreachable",
+        5 => "connection refused",
+        6 => "TTL expired",
+        7 => "command not supported",
+        8 => "address type not supported",
+        _ => "unknown error",
+    }
+}
@@ -0,0 +1,182 @@
+use crate::proxy::{socks5_connect, ProxyConfig};
+use crate::Result;
+use ewebsock::{Options, WsEvent, WsMessage, WsReceiver, WsSender};
+use std::io;
+use std::net::TcpStream;
+use std::ops::ControlFlow;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::Duration;
+use tracing::{debug, error};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+use url::Url;
+
+/// Our end of a relay's websocket. ewebsock can't go through a proxy, so
+/// proxied connections run on a thread of our own.
+pub enum RelaySender {
+    Direct(WsSender),
+    Proxied(mpsc::Sender<WsMessage>),
+    /// We weren't allowed to connect, messages go nowhere
+    Refused,
+}
+
+impl RelaySender {
+    pub fn send(&mut self, msg: WsMessage) {
+        match self {
+            RelaySender::Direct(sender) => sender.send(msg),
+            RelaySender::Proxied(sender) => {
+                // the thread is gone when the connection is, and we'll
+                // hear about that from the receiver
+                let _ = sender.send(msg);
+            }
+            RelaySender::Refused => {}
+        }
+    }
+}
+
+/// Open a websocket to a relay, through the proxy when there is one
+pub fn connect(
+    url: &str,
+    proxy: &ProxyConfig,
+    wakeup: impl Fn() + Send + Sync + 'static,
+) -> Result<(RelaySender, WsReceiver)> {
+    if let Some(proxy_addr) = &proxy.socks5 {
+        let (receiver, on_event) = WsReceiver::new_with_callback(wakeup);
+        let (tx, rx) = mpsc::channel();
+        let url = url.to_owned();
+        let proxy_addr = proxy_addr.to_owned();
+        std::thread::spawn(move || run_proxied(&url, &proxy_addr, rx, on_event));
+        return Ok((RelaySender::Proxied(tx), receiver));
+    }
+
+    if !proxy.allows_direct() {
+        let (receiver, on_event) = WsReceiver::new_with_callback(wakeup);
+        let _ = on_event(WsEvent::Error(
+            "direct connections are disabled and there is no proxy".to_owned(),
+        ));
+        return Ok((RelaySender::Refused, receiver));
+    }
+
+    let (sender, receiver) = ewebsock::connect_with_wakeup(url, Options::default(), wakeup)?;
+    Ok((RelaySender::Direct(sender), receiver))
+}
+
+type Socket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// How long a read blocks before we check for messages to send
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+fn run_proxied(
+    url: &str,
+    proxy_addr: &str,
+    rx: Receiver<WsMessage>,
+    on_event: ewebsock::EventHandler,
+) {
+    let mut socket = match open_proxied(url, proxy_addr) {
+        Ok(socket) => socket,
+        Err(err) => {
+            error!(
+                "could not connect to {} through {}: {}",
+                url, proxy_addr, err
+            );
+            let _ = on_event(WsEvent::Error(err));
+            return;
+        }
+    };
+
+    debug!("connected to {} through {}", url, proxy_addr);
+    if on_event(WsEvent::Opened).is_break() {
+        return;
+    }
+
+    loop {
+        loop {
+            let msg = match rx.try_recv() {
+                Ok(msg) => msg,
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    // the relay reconnected or went away
+                    let _ = socket.close(None);
+                    let _ = socket.flush();
+                    return;
+                }
+            };
+
+            if let Err(err) = socket.send(to_tungstenite(msg)) {
+                let _ = on_event(WsEvent::Error(err.to_string()));
+                return;
+            }
+        }
+
+        let flow = match socket.read() {
+            Ok(Message::Close(_)) => {
+                let _ = on_event(WsEvent::Closed);
+                return;
+            }
+            Ok(msg) => match from_tungstenite(msg) {
+                Some(msg) => on_event(WsEvent::Message(msg)),
+                None => ControlFlow::Continue(()),
+            },
+            Err(tungstenite::Error::Io(err))
+                if err.kind() == io::ErrorKind::WouldBlock
+                    || err.kind() == io::ErrorKind::TimedOut =>
+            {
+                ControlFlow::Continue(())
+            }
+            Err(tungstenite::Error::ConnectionClosed) => {
+                let _ = on_event(WsEvent::Closed);
+                return;
+            }
+            Err(err) => {
+                let _ = on_event(WsEvent::Error(err.to_string()));
+                return;
+            }
+        };
+
+        if flow.is_break() {
+            return;
+        }
+    }
+}
+
+fn open_proxied(url: &str, proxy_addr: &str) -> std::result::Result<Socket, String> {
+    let parsed = Url::parse(url).map_err(|e| e.to_string())?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| format!("no host in {}", url))?;
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| format!("no port for {}", url))?;
+
+    let stream = socks5_connect(proxy_addr, host, port).map_err(|e| e.to_string())?;
+    let (socket, _response) = tungstenite::client_tls(url, stream).map_err(|e| e.to_string())?;
+
+    let timeout = match socket.get_ref() {
+        MaybeTlsStream::Plain(stream) => stream.set_read_timeout(Some(POLL_INTERVAL)),
+        MaybeTlsStream::Rustls(stream) => stream.get_ref().set_read_timeout(Some(POLL_INTERVAL)),
+        _ => Ok(()),
+    };
+    timeout.map_err(|e| e.to_string())?;
+
+    Ok(socket)
+}
+
+fn to_tungstenite(msg: WsMessage) -> Message {
+    match msg {
+        WsMessage::Text(txt) => Message::Text(txt),
+        WsMessage::Binary(bs) => Message::Binary(bs),
+        WsMessage::Ping(bs) => Message::Ping(bs),
+        WsMessage::Pong(bs) => Message::Pong(bs),
+        WsMessage::Unknown(txt) => Message::Text(txt),
+    }
+}
+
+fn from_tungstenite(msg: Message) -> Option<WsMessage> {
+    match msg {
+        Message::Text(txt) => Some(WsMessage::Text(txt)),
+        Message::Binary(bs) => Some(WsMessage::Binary(bs)),
+        Message::Ping(bs) => Some(WsMessage::Ping(bs)),
+        Message::Pong(bs) => Some(WsMessage::Pong(bs)),
+        Message::Close(_) | Message::Frame(_) => None,
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use url::Url;
+
+/// A relay's NIP-11 information document, which it serves over HTTP at
+/// its websocket url when asked for `application/nostr+json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayInfoDocument {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// The admin's pubkey, in hex
+    pub pubkey: Option<String>,
+    /// How to reach the admin, usually an email or a url
+    pub contact: Option<String>,
+    #[serde(deserialize_with = "deserialize_nips")]
+    pub supported_nips: Vec<u32>,
+    pub software: Option<String>,
+    pub version: Option<String>,
+    pub icon: Option<String>,
+    pub limitation: Option<RelayLimitation>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelayLimitation {
+    /// Largest websocket message the relay will take, in bytes
+    pub max_message_length: Option<u64>,
+    pub max_subscriptions: Option<u64>,
+    pub max_filters: Option<u64>,
+    pub max_limit: Option<u64>,
+    pub auth_required: Option<bool>,
+    pub payment_required: Option<bool>,
+    pub restricted_writes: Option<bool>,
+}
+
+impl RelayInfoDocument {
+    pub fn from_json(json: &str) -> crate::Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn supports(&self, nip: u32) -> bool {
+        self.supported_nips.contains(&nip)
+    }
+
+    pub fn auth_required(&self) -> bool {
+        self.limitation
+            .as_ref()
+            .and_then(|l| l.auth_required)
+            .unwrap_or(false)
+    }
+
+    pub fn payment_required(&self) -> bool {
+        self.limitation
+            .as_ref()
+            .and_then(|l| l.payment_required)
+            .unwrap_or(false)
+    }
+}
+
+/// Where a relay serves its information document: the same url, but
+/// over http
+pub fn info_url(relay_url: &str) -> Option<String> {
+    let mut url = Url::parse(relay_url).ok()?;
+    let scheme = match url.scheme() {
+        "wss" => "https",
+        "ws" => "http",
+        _ => return None,
+    };
+    url.set_scheme(scheme).ok()?;
+    Some(url.to_string())
+}
+
+/// Some relays put strings or other junk in here, keep the numbers
+fn deserialize_nips<'de, D>(deserializer: D) -> Result<Vec<u32>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let values: Option<Vec<serde_json::Value>> = Option::deserialize(deserializer)?;
+    Ok(values
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|v| match v {
+            serde_json::Value::Number(n) => n.as_u64(),
+            serde_json::Value::String(s) => s.parse().ok(),
+            _ => None,
+        })
+        .filter_map(|n| u32::try_from(n).ok())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_info_document() {
+        let doc = RelayInfoDocument::from_json(
+            r#"{
+                "name": "relay.example.com",
+                "description": "a relay",
+                "supported_nips": [1, 11, "42", null, 45],
+                "software": "git+https://github.com/hoytech/strfry.git",
+                "version": "1.0.0",
+                "limitation": {"max_message_length": 131072, "auth_required": true},
+                "fees": {"admission": [{"amount": 1000, "unit": "msats"}]}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(doc.name.as_deref(), Some("relay.example.com"));
+        assert_eq!(doc.supported_nips, vec![1, 11, 42, 45]);
+        assert!(doc.supports(45));
+        assert!(!doc.supports(50));
+        assert!(doc.auth_required());
+        assert!(!doc.payment_required());
+        assert_eq!(doc.limitation.unwrap().max_message_length, Some(131072));
+
+        let empty = RelayInfoDocument::from_json("{}").unwrap();
+        assert!(empty.supported_nips.is_empty());
+    }
+
+    #[test]
+    fn test_info_url() {
+        assert_eq!(
+            info_url("wss://relay.damus.io/").as_deref(),
+            Some("https://relay.damus.io/")
+        );
+        assert_eq!(
+            info_url("ws://127.0.0.1:8080/").as_deref(),
+            Some("http://127.0.0.1:8080/")
+        );
+        assert_eq!(info_url("https://relay.damus.io"), None);
+    }
+}
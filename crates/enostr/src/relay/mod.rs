@@ -1,13 +1,27 @@
-use ewebsock::{Options, WsMessage, WsReceiver, WsSender};
+use ewebsock::{WsMessage, WsReceiver};
 
-use crate::{ClientMessage, Result};
-use nostrdb::Filter;
+use crate::negentropy::{Id, Negentropy};
+use crate::{ClientMessage, FilledKeypair, FilterSpec, Note, NoteId, ProxyConfig, Result};
+use nostrdb::{Filter, NoteBuilder};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use tracing::{debug, error, info};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
 
+mod connection;
+mod info;
 pub mod message;
 pub mod pool;
+mod publish;
+mod stats;
+mod sync;
+
+pub use connection::RelaySender;
+pub use info::{info_url, RelayInfoDocument, RelayLimitation};
+pub use publish::{Publish, PublishState, PublishTracker};
+pub use stats::RelayStats;
+use sync::Sync;
 
 #[derive(Debug)]
 pub enum RelayStatus {
@@ -16,11 +30,71 @@ pub enum RelayStatus {
     Disconnected,
 }
 
+/// NIP-42 authentication state. This is per connection, relays forget
+/// about us when the websocket drops.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RelayAuthStatus {
+    /// The relay hasn't asked us to authenticate
+    None,
+    /// The relay sent a challenge, but we don't have a secret key to
+    /// answer it with
+    Required,
+    /// We answered the challenge and are waiting for the relay's OK
+    Pending(NoteId),
+    Authenticated,
+    /// The relay rejected our AUTH event
+    Failed(String),
+}
+
+/// Whether a relay does NIP-77 negentropy syncs
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum NegentropySupport {
+    /// We haven't tried yet, or it isn't in the relay's NIP-11 document.
+    /// Plenty of relays that sync leave it out.
+    Unknown,
+    Supported,
+    /// It refused a sync with a NEG-ERR or NOTICE, we won't ask again
+    Unsupported,
+}
+
 pub struct Relay {
     pub url: String,
     pub status: RelayStatus,
-    pub sender: WsSender,
+    pub auth: RelayAuthStatus,
+    pub stats: RelayStats,
+    pub sender: RelaySender,
     pub receiver: WsReceiver,
+
+    /// How we connect, see [`ProxyConfig`]
+    pub proxy: ProxyConfig,
+
+    /// Filters for the REQs we have open on this relay, so that we can
+    /// send them again when needed
+    subs: BTreeMap<String, Vec<Filter>>,
+
+    /// Messages waiting for authentication to complete
+    pending: Vec<ClientMessage>,
+
+    /// When the first of them started waiting
+    pending_since: Option<Instant>,
+
+    pub negentropy: NegentropySupport,
+
+    /// The relay's NIP-11 document, once we have fetched it
+    pub info: Option<RelayInfoDocument>,
+
+    /// Negentropy syncs in progress, by subscription id
+    syncs: BTreeMap<String, Sync>,
+
+    /// REQs for the notes our syncs found missing, which we close on EOSE
+    fetching: BTreeSet<String>,
+
+    /// We reconnected, and the relay doesn't know about our subscriptions
+    /// anymore
+    needs_resubscribe: bool,
+
+    /// We got through to the relay at least once
+    ever_connected: bool,
 }
 
 impl fmt::Debug for Relay {
@@ -28,6 +102,7 @@ impl fmt::Debug for Relay {
         f.debug_struct("Relay")
             .field("url", &self.url)
             .field("status", &self.status)
+            .field("auth", &self.auth)
             .finish()
     }
 }
@@ -48,19 +123,80 @@ impl PartialEq for Relay {
 impl Eq for Relay {}
 
 impl Relay {
-    pub fn new(url: String, wakeup: impl Fn() + Send + Sync + 'static) -> Result<Self> {
+    pub fn new(
+        url: String,
+        proxy: ProxyConfig,
+        wakeup: impl Fn() + Send + Sync + 'static,
+    ) -> Result<Self> {
         let status = RelayStatus::Connecting;
-        let (sender, receiver) = ewebsock::connect_with_wakeup(&url, Options::default(), wakeup)?;
+        let (sender, receiver) = connection::connect(&url, &proxy, wakeup)?;
 
         Ok(Self {
             url,
             sender,
             receiver,
+            proxy,
             status,
+            auth: RelayAuthStatus::None,
+            stats: RelayStats {
+                connect_attempts: 1,
+                ..Default::default()
+            },
+            subs: BTreeMap::new(),
+            pending: Vec::new(),
+            pending_since: None,
+            negentropy: NegentropySupport::Unknown,
+            info: None,
+            syncs: BTreeMap::new(),
+            fetching: BTreeSet::new(),
+            needs_resubscribe: false,
+            ever_connected: false,
         })
     }
 
     pub fn send(&mut self, msg: &ClientMessage) {
+        match msg {
+            ClientMessage::Req { sub_id, filters } => {
+                // a relay that doesn't know NIP-50 would match these
+                // without the search, which is just noise
+                if self.supports(50) == Some(false) && filters.iter().any(is_search) {
+                    debug!("{} can't search, leaving that out of {}", self.url, sub_id);
+                    let filters: Vec<Filter> =
+                        filters.iter().filter(|f| !is_search(f)).cloned().collect();
+                    if !filters.is_empty() {
+                        self.send(&ClientMessage::req(sub_id.clone(), filters));
+                    }
+                    return;
+                }
+
+                self.subs.insert(sub_id.clone(), filters.clone());
+
+                // no point in sending this, the relay will just close it
+                if let RelayAuthStatus::Pending(_) = self.auth {
+                    debug!(
+                        "queueing {} on {} until we are authenticated",
+                        sub_id, self.url
+                    );
+                    self.park(msg.clone());
+                    return;
+                }
+            }
+            ClientMessage::Close { sub_id } => {
+                self.subs.remove(sub_id);
+                self.stats.record_close(sub_id);
+                self.pending.retain(|pending| !is_req_for(pending, sub_id));
+            }
+            _ => {}
+        }
+
+        self.send_now(msg);
+    }
+
+    fn send_now(&mut self, msg: &ClientMessage) {
+        if let ClientMessage::Req { sub_id, .. } = msg {
+            self.stats.record_req(sub_id);
+        }
+
         let json = match msg.to_json() {
             Ok(json) => {
                 debug!("sending {} to {}", json, self.url);
@@ -77,11 +213,30 @@ impl Relay {
     }
 
     pub fn connect(&mut self, wakeup: impl Fn() + Send + Sync + 'static) -> Result<()> {
-        let (sender, receiver) =
-            ewebsock::connect_with_wakeup(&self.url, Options::default(), wakeup)?;
+        let (sender, receiver) = connection::connect(&self.url, &self.proxy, wakeup)?;
         self.status = RelayStatus::Connecting;
+        self.stats.connect_attempts += 1;
+        self.auth = RelayAuthStatus::None;
+        self.pending.clear();
+        self.pending_since = None;
+        self.syncs.clear();
+        // the syncs that started these are gone, no need to redo them
+        for sub_id in std::mem::take(&mut self.fetching) {
+            self.subs.remove(&sub_id);
+        }
         self.sender = sender;
         self.receiver = receiver;
+
+        if self.ever_connected {
+            self.needs_resubscribe = true;
+        } else {
+            // the relay never saw what we sent before, and it still gets
+            // everything else a first connection does. The new connection
+            // sends these once it opens.
+            for (sub_id, filters) in self.subs.clone() {
+                self.send_now(&ClientMessage::req(sub_id, filters));
+            }
+        }
         Ok(())
     }
 
@@ -97,4 +252,411 @@ impl Relay {
         );
         self.send(&ClientMessage::req(subid, filters));
     }
+
+    /// Whether we have this subscription open on the relay
+    pub fn has_sub(&self, sub_id: &str) -> bool {
+        self.subs.contains_key(sub_id)
+    }
+
+    pub fn has_subs(&self) -> bool {
+        !self.subs.is_empty()
+    }
+
+    pub fn needs_resubscribe(&self) -> bool {
+        self.needs_resubscribe
+    }
+
+    /// Send all of our subscriptions again, after we reconnected. `update`
+    /// gets to change each subscription's filters first, to add a `since`
+    /// for example.
+    pub fn resubscribe(&mut self, mut update: impl FnMut(&str, Vec<Filter>) -> Vec<Filter>) {
+        self.needs_resubscribe = false;
+
+        let subs = std::mem::take(&mut self.subs);
+        info!("resubscribing to {} subs on {}", subs.len(), self.url);
+        for (sub_id, filters) in subs {
+            let filters = update(&sub_id, filters);
+            self.send(&ClientMessage::req(sub_id, filters));
+        }
+    }
+
+    /// Answer a NIP-42 AUTH challenge. Without a secret key we can only
+    /// remember that the relay wants us to authenticate.
+    pub fn authenticate(&mut self, challenge: &str, keypair: Option<FilledKeypair<'_>>) {
+        let keypair = if let Some(keypair) = keypair {
+            keypair
+        } else {
+            warn!("{} requires auth, but we don't have a secret key", self.url);
+            self.auth = RelayAuthStatus::Required;
+            self.fail_pending("it requires auth and we can't");
+            return;
+        };
+
+        let note = match auth_note(&self.url, challenge, &keypair.secret_key.to_secret_bytes()) {
+            Ok(note) => note,
+            Err(err) => {
+                error!("failed to create auth note for {}: {}", self.url, err);
+                return;
+            }
+        };
+
+        self.send_auth(note);
+    }
+
+    /// Someone else is signing our answer to a challenge, a remote signer
+    /// for example. REQs wait until [`Relay::send_auth`] gets the signed
+    /// answer to the relay and it accepts it.
+    pub fn auth_pending(&mut self, id: NoteId) {
+        self.auth = RelayAuthStatus::Pending(id);
+    }
+
+    /// Our answer to the relay's challenge isn't coming, say because a
+    /// remote signer never signed it. What was waiting on it goes out
+    /// anyway rather than being held forever.
+    pub fn auth_failed(&mut self, reason: &str) {
+        if !matches!(self.auth, RelayAuthStatus::Pending(_)) {
+            return;
+        }
+
+        warn!("giving up on authenticating with {}: {}", self.url, reason);
+        self.auth = RelayAuthStatus::Failed(reason.to_owned());
+        self.release_pending();
+    }
+
+    /// Send our signed answer to the relay's challenge
+    pub fn send_auth(&mut self, note: Note) {
+        info!("authenticating with {}", self.url);
+        self.auth = RelayAuthStatus::Pending(note.id);
+        self.send_now(&ClientMessage::auth(note));
+    }
+
+    /// Handle an OK from the relay. Returns true if it was the answer to
+    /// our AUTH event.
+    pub fn handle_ok(&mut self, event_id: &str, accepted: bool, message: &str) -> bool {
+        let auth_id = if let RelayAuthStatus::Pending(auth_id) = &self.auth {
+            auth_id
+        } else {
+            return false;
+        };
+
+        if auth_id.hex() != event_id {
+            return false;
+        }
+
+        if accepted {
+            info!("authenticated with {}", self.url);
+            self.auth = RelayAuthStatus::Authenticated;
+            self.release_pending();
+        } else {
+            error!("{} rejected our auth: {}", self.url, message);
+            self.auth = RelayAuthStatus::Failed(message.to_owned());
+            self.fail_pending("it rejected our auth");
+        }
+
+        true
+    }
+
+    /// The relay closed one of our subscriptions. If it did so because
+    /// we need to authenticate, hold on to the REQ so we can send it
+    /// again once we are. When we can't authenticate, or the challenge
+    /// doesn't come in time (see [`Relay::expire_pending`]), it's dropped.
+    pub fn handle_closed(&mut self, sub_id: &str, reason: &str) {
+        let filters = if let Some(filters) = self.subs.remove(sub_id) {
+            filters
+        } else {
+            return;
+        };
+
+        if !reason.starts_with("auth-required:") {
+            return;
+        }
+
+        // no challenge is coming, don't hold the REQ for one
+        if self.supports(42) == Some(false) {
+            warn!(
+                "{} wants auth for {}, but says it doesn't do NIP-42",
+                self.url, sub_id
+            );
+            return;
+        }
+
+        match &self.auth {
+            RelayAuthStatus::Authenticated => {
+                // we raced with the relay, just try again
+                self.send(&ClientMessage::req(sub_id.to_owned(), filters));
+            }
+            RelayAuthStatus::Required | RelayAuthStatus::Failed(_) => {
+                warn!(
+                    "dropping {} on {}: it wants auth and we can't",
+                    sub_id, self.url
+                );
+            }
+            RelayAuthStatus::None | RelayAuthStatus::Pending(_) => {
+                debug!("{} needs auth for {}, waiting", self.url, sub_id);
+                self.subs.insert(sub_id.to_owned(), filters.clone());
+                self.pending.retain(|pending| !is_req_for(pending, sub_id));
+                self.park(ClientMessage::req(sub_id.to_owned(), filters));
+            }
+        }
+    }
+
+    /// How long REQs wait for a relay that wants auth to send its
+    /// challenge
+    pub fn auth_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// Hold a message until authentication is done
+    fn park(&mut self, msg: ClientMessage) {
+        if self.pending.is_empty() {
+            self.pending_since = Some(Instant::now());
+        }
+        self.pending.push(msg);
+    }
+
+    /// Send everything that was waiting on authentication
+    fn release_pending(&mut self) {
+        self.pending_since = None;
+        for msg in std::mem::take(&mut self.pending) {
+            self.send_now(&msg);
+        }
+    }
+
+    /// Authentication isn't going to happen, so the REQs waiting on it
+    /// would only be closed again. Drop them.
+    fn fail_pending(&mut self, why: &str) {
+        self.pending_since = None;
+        for msg in std::mem::take(&mut self.pending) {
+            if let ClientMessage::Req { sub_id, .. } = msg {
+                warn!("dropping {} on {}: {}", sub_id, self.url, why);
+                self.subs.remove(&sub_id);
+                self.stats.record_close(&sub_id);
+            }
+        }
+    }
+
+    /// Drop REQs that have waited longer than `timeout` for a challenge
+    /// that never came. Answers we're waiting on a remote signer for
+    /// end with [`Relay::auth_failed`] instead.
+    pub fn expire_pending(&mut self, timeout: Duration) {
+        if self.auth != RelayAuthStatus::None {
+            return;
+        }
+
+        if self
+            .pending_since
+            .is_some_and(|since| since.elapsed() > timeout)
+        {
+            self.fail_pending("it never sent an AUTH challenge");
+        }
+    }
+}
+
+impl Relay {
+    /// Whether the relay says it supports a NIP. None until we have its
+    /// info document.
+    pub fn supports(&self, nip: u32) -> Option<bool> {
+        self.info.as_ref().map(|info| info.supports(nip))
+    }
+
+    pub fn set_info(&mut self, info: RelayInfoDocument) {
+        // a document without 77 isn't a no, we find out when we try
+        if info.supports(77) {
+            self.negentropy = NegentropySupport::Supported;
+        }
+        self.info = Some(info);
+    }
+
+    /// Fetch the notes matching `filter` that we don't have, which are
+    /// any not in `have`. This is done with a NIP-77 negentropy sync, so
+    /// relays that don't support it are left alone. At most `limit`
+    /// notes are fetched.
+    pub fn sync(
+        &mut self,
+        sub_id: String,
+        filter: Filter,
+        have: impl IntoIterator<Item = (u64, Id)>,
+        limit: usize,
+    ) {
+        if self.negentropy == NegentropySupport::Unsupported {
+            return;
+        }
+
+        let neg = Negentropy::new(have);
+        let msg = ClientMessage::neg_open(sub_id.clone(), filter, neg.initiate());
+        debug!("starting negentropy sync {} with {}", sub_id, self.url);
+        self.syncs.insert(sub_id, Sync::new(neg, limit));
+        self.send(&msg);
+    }
+
+    /// Handle a NEG-MSG. Returns false if it wasn't for one of our syncs.
+    pub fn handle_neg_msg(&mut self, sub_id: &str, message: &str) -> bool {
+        let sync = if let Some(sync) = self.syncs.get_mut(sub_id) {
+            sync
+        } else {
+            return false;
+        };
+
+        self.negentropy = NegentropySupport::Supported;
+
+        match sync.neg.reconcile(message) {
+            Ok(Some(next)) => {
+                self.send(&ClientMessage::neg_msg(sub_id.to_owned(), next));
+            }
+
+            Ok(None) => {
+                let filters = sync.missing_filters();
+                info!(
+                    "negentropy sync {} with {} done, missing {} notes",
+                    sub_id,
+                    self.url,
+                    sync.neg.need().len()
+                );
+                self.syncs.remove(sub_id);
+                self.send(&ClientMessage::neg_close(sub_id.to_owned()));
+
+                if !filters.is_empty() {
+                    self.fetching.insert(sub_id.to_owned());
+                    self.send(&ClientMessage::req(sub_id.to_owned(), filters));
+                }
+            }
+
+            Err(err) => {
+                error!(
+                    "negentropy sync {} with {} failed: {}",
+                    sub_id, self.url, err
+                );
+                self.syncs.remove(sub_id);
+                self.send(&ClientMessage::neg_close(sub_id.to_owned()));
+            }
+        }
+
+        true
+    }
+
+    /// The relay gave up on a sync. Returns false if it wasn't ours.
+    pub fn handle_neg_err(&mut self, sub_id: &str, reason: &str) -> bool {
+        if self.syncs.remove(sub_id).is_none() {
+            return false;
+        }
+
+        warn!(
+            "{} stopped negentropy sync {}: {}",
+            self.url, sub_id, reason
+        );
+
+        // a relay that never synced with us is saying it won't
+        if self.negentropy == NegentropySupport::Unknown {
+            info!("{} doesn't support negentropy", self.url);
+            self.negentropy = NegentropySupport::Unsupported;
+        }
+        true
+    }
+
+    /// A NOTICE while we wait on the first answer to a sync is the relay
+    /// not knowing what NEG-OPEN is. Returns true if we took it that way.
+    pub fn handle_notice(&mut self, notice: &str) -> bool {
+        if self.syncs.is_empty() || self.negentropy != NegentropySupport::Unknown {
+            return false;
+        }
+
+        info!(
+            "{} doesn't support negentropy, it said: {}",
+            self.url, notice
+        );
+        self.negentropy = NegentropySupport::Unsupported;
+        // nothing to close, it never opened them
+        self.syncs.clear();
+        true
+    }
+
+    /// Close the REQs we made for missing notes once they are done.
+    /// Returns true if this was one of those.
+    pub fn handle_eose(&mut self, sub_id: &str) -> bool {
+        if !self.fetching.remove(sub_id) {
+            return false;
+        }
+
+        self.send(&ClientMessage::close(sub_id.to_owned()));
+        true
+    }
+
+    /// Give up on syncs the relay never answered
+    pub fn expire_syncs(&mut self) {
+        let expired: Vec<String> = self
+            .syncs
+            .iter()
+            .filter(|(_, sync)| sync.is_expired())
+            .map(|(sub_id, _)| sub_id.clone())
+            .collect();
+
+        if expired.is_empty() {
+            return;
+        }
+
+        for sub_id in expired {
+            self.syncs.remove(&sub_id);
+            self.send(&ClientMessage::neg_close(sub_id));
+        }
+    }
+}
+
+fn is_search(filter: &Filter) -> bool {
+    FilterSpec::from_filter(filter).is_ok_and(|spec| spec.search.is_some())
+}
+
+fn is_req_for(msg: &ClientMessage, sub_id: &str) -> bool {
+    matches!(msg, ClientMessage::Req { sub_id: id, .. } if id == sub_id)
+}
+
+/// An unsigned kind 22242 event answering a relay's challenge
+pub fn auth_note_builder<'a>(relay_url: &str, challenge: &str) -> NoteBuilder<'a> {
+    NoteBuilder::new()
+        .kind(22242)
+        .content("")
+        .start_tag()
+        .tag_str("relay")
+        .tag_str(relay_url)
+        .start_tag()
+        .tag_str("challenge")
+        .tag_str(challenge)
+}
+
+/// Build a signed kind 22242 event answering a relay's challenge
+fn auth_note(relay_url: &str, challenge: &str, seckey: &[u8; 32]) -> Result<Note> {
+    let note = auth_note_builder(relay_url, challenge)
+        .sign(seckey)
+        .build()
+        .ok_or_else(|| crate::Error::Generic("failed to build auth note".to_owned()))?;
+
+    Note::from_json(&note.json()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::FullKeypair;
+
+    #[test]
+    fn test_auth_note() -> Result<()> {
+        let kp = FullKeypair::generate();
+        let note = auth_note(
+            "wss://relay.example.com/",
+            "challenge-string",
+            &kp.secret_key.to_secret_bytes(),
+        )?;
+
+        assert_eq!(note.kind, 22242);
+        assert_eq!(note.pubkey, kp.pubkey);
+        assert_eq!(note.content, "");
+        assert_eq!(
+            note.tags,
+            vec![
+                vec!["relay".to_string(), "wss://relay.example.com/".to_string()],
+                vec!["challenge".to_string(), "challenge-string".to_string()],
+            ]
+        );
+
+        Ok(())
+    }
 }
@@ -1,8 +1,11 @@
-use crate::relay::{Relay, RelayStatus};
-use crate::{ClientMessage, Result};
+use crate::relay::publish::PublishTracker;
+use crate::relay::{Relay, RelayAuthStatus, RelayInfoDocument, RelayStatus};
+use crate::{ClientMessage, FilledKeypair, Note, NoteId, ProxyConfig, Result};
 use nostrdb::Filter;
 
+use std::collections::hash_map::RandomState;
 use std::collections::BTreeSet;
+use std::hash::{BuildHasher, Hasher};
 use std::time::{Duration, Instant};
 
 use url::Url;
@@ -37,27 +40,74 @@ pub struct PoolRelay {
     pub relay: Relay,
     pub last_ping: Instant,
     pub last_connect_attempt: Instant,
+
+    /// How long to wait before reconnecting. This doubles after every
+    /// failed attempt, up to [`PoolRelay::max_reconnect_duration`]
     pub retry_connect_after: Duration,
+
+    /// When we will try to reconnect, this is `retry_connect_after` with
+    /// some jitter so that we don't hammer relays in lockstep
+    pub reconnect_at: Instant,
 }
 
 impl PoolRelay {
     pub fn new(relay: Relay) -> PoolRelay {
+        let now = Instant::now();
         PoolRelay {
             relay,
-            last_ping: Instant::now(),
-            last_connect_attempt: Instant::now(),
+            last_ping: now,
+            last_connect_attempt: now,
             retry_connect_after: Self::initial_reconnect_duration(),
+            reconnect_at: now + Self::initial_reconnect_duration(),
         }
     }
 
     pub fn initial_reconnect_duration() -> Duration {
         Duration::from_secs(5)
     }
+
+    pub fn max_reconnect_duration() -> Duration {
+        Duration::from_secs(5 * 60)
+    }
+
+    /// Schedule the next reconnect after a failed attempt
+    fn backoff(&mut self, now: Instant) {
+        self.last_connect_attempt = now;
+        self.reconnect_at = now + jitter(self.retry_connect_after);
+        self.retry_connect_after = next_backoff(self.retry_connect_after);
+    }
+
+    fn reset_backoff(&mut self) {
+        self.retry_connect_after = Self::initial_reconnect_duration();
+    }
+}
+
+fn next_backoff(current: Duration) -> Duration {
+    (current * 2).min(PoolRelay::max_reconnect_duration())
+}
+
+/// Randomly spread a duration by up to 20% in either direction
+fn jitter(duration: Duration) -> Duration {
+    // a randomly keyed hasher is random enough for this, no need for a
+    // rand dependency
+    let random = RandomState::new().build_hasher().finish();
+    let factor = 0.8 + (random % 1000) as f64 / 1000.0 * 0.4;
+    duration.mul_f64(factor)
 }
 
 pub struct RelayPool {
     pub relays: Vec<PoolRelay>,
     pub ping_rate: Duration,
+    /// How long REQs wait on a relay that wants auth but hasn't sent a
+    /// challenge, see [`Relay::expire_pending`]
+    pub auth_timeout: Duration,
+    /// How long publishes wait on a relay before they time out, see
+    /// [`PublishTracker::expire_queued`]
+    pub queue_timeout: Duration,
+    pub publishes: PublishTracker,
+
+    /// How relays are connected to, new and old
+    proxy: ProxyConfig,
 }
 
 impl Default for RelayPool {
@@ -72,7 +122,30 @@ impl RelayPool {
         RelayPool {
             relays: vec![],
             ping_rate: Duration::from_secs(25),
+            auth_timeout: Relay::auth_timeout(),
+            queue_timeout: PublishTracker::queue_timeout(),
+            publishes: PublishTracker::default(),
+            proxy: ProxyConfig::default(),
+        }
+    }
+
+    pub fn proxy(&self) -> &ProxyConfig {
+        &self.proxy
+    }
+
+    /// Change how we connect to relays. Relays we already have reconnect
+    /// the new way on the next [`RelayPool::keepalive_ping`].
+    pub fn set_proxy(&mut self, proxy: ProxyConfig) {
+        if proxy == self.proxy {
+            return;
+        }
+
+        for relay in &mut self.relays {
+            relay.relay.proxy = proxy.clone();
+            relay.relay.status = RelayStatus::Disconnected;
+            relay.reconnect_at = Instant::now();
         }
+        self.proxy = proxy;
     }
 
     pub fn ping_rate(&mut self, duration: Duration) -> &mut Self {
@@ -115,27 +188,137 @@ impl RelayPool {
         }
     }
 
+    /// Subscribe on a subset of the pool's relays. Relays that aren't in
+    /// the pool are skipped, add them first with [`RelayPool::add_url`].
+    pub fn subscribe_to<'a>(
+        &mut self,
+        subid: String,
+        filter: Vec<Filter>,
+        relay_urls: impl IntoIterator<Item = &'a str>,
+    ) {
+        let targets: BTreeSet<&str> = relay_urls.into_iter().collect();
+        for relay in &mut self.relays {
+            if targets.contains(relay.relay.url.as_str()) {
+                relay.relay.subscribe(subid.clone(), filter.clone());
+            }
+        }
+    }
+
+    /// Send a message to a subset of the pool's relays
+    pub fn send_to_relays<'a>(
+        &mut self,
+        cmd: &ClientMessage,
+        relay_urls: impl IntoIterator<Item = &'a str>,
+    ) {
+        let targets: BTreeSet<&str> = relay_urls.into_iter().collect();
+        for relay in &mut self.relays {
+            if targets.contains(relay.relay.url.as_str()) {
+                relay.relay.send(cmd);
+            }
+        }
+    }
+
+    /// The relays we have a subscription open on
+    pub fn urls_with_sub(&self, subid: &str) -> BTreeSet<String> {
+        self.relays
+            .iter()
+            .filter(|relay| relay.relay.has_sub(subid))
+            .map(|relay| relay.relay.url.clone())
+            .collect()
+    }
+
+    /// Whether we're done with a relay for now: no open subscriptions, and
+    /// no notes it still has to get or answer
+    pub fn is_idle(&self, relay_url: &str) -> bool {
+        self.relays
+            .iter()
+            .find(|relay| relay.relay.url == relay_url)
+            .is_some_and(|relay| {
+                !relay.relay.has_subs() && !self.publishes.is_waiting_on(relay_url)
+            })
+    }
+
+    /// Whether a relay reconnected and needs our subscriptions again, see
+    /// [`RelayPool::resubscribe`]
+    pub fn needs_resubscribe(&self, relay_url: &str) -> bool {
+        self.relays
+            .iter()
+            .any(|relay| relay.relay.url == relay_url && relay.relay.needs_resubscribe())
+    }
+
+    /// Replay a relay's subscriptions after it reconnected
+    pub fn resubscribe(
+        &mut self,
+        relay_url: &str,
+        update: impl FnMut(&str, Vec<Filter>) -> Vec<Filter>,
+    ) {
+        if let Some(relay) = self.get_relay_mut(relay_url) {
+            relay.resubscribe(update);
+        }
+    }
+
+    /// The relays that advertise a NIP in their info document, and the
+    /// ones we don't have a document for yet
+    pub fn urls_supporting(&self, nip: u32) -> BTreeSet<String> {
+        self.relays
+            .iter()
+            .filter(|relay| relay.relay.supports(nip) != Some(false))
+            .map(|relay| relay.relay.url.clone())
+            .collect()
+    }
+
+    pub fn set_info(&mut self, relay_url: &str, info: RelayInfoDocument) {
+        if let Some(relay) = self.get_relay_mut(relay_url) {
+            relay.set_info(info);
+        }
+    }
+
+    /// Publish a note to a subset of the pool's relays and track the
+    /// relays' OK responses in [`RelayPool::publishes`]. Relays that
+    /// aren't connected right now get it when they are.
+    pub fn publish<'a>(
+        &mut self,
+        id: NoteId,
+        msg: ClientMessage,
+        relay_urls: impl IntoIterator<Item = &'a str>,
+    ) {
+        let targets: BTreeSet<&str> = relay_urls.into_iter().collect();
+        let mut relays = Vec::new();
+        for relay in &mut self.relays {
+            let relay = &mut relay.relay;
+            if !targets.contains(relay.url.as_str()) {
+                continue;
+            }
+
+            let connected = matches!(relay.status, RelayStatus::Connected);
+            if connected {
+                relay.send(&msg);
+            }
+            relays.push((relay.url.clone(), connected));
+        }
+
+        self.publishes.track(id, msg, relays);
+    }
+
     /// Keep relay connectiongs alive by pinging relays that haven't been
     /// pinged in awhile. Adjust ping rate with [`ping_rate`].
     pub fn keepalive_ping(&mut self, wakeup: impl Fn() + Send + Sync + Clone + 'static) {
+        self.publishes.expire_queued(self.queue_timeout);
+
         for relay in &mut self.relays {
             let now = std::time::Instant::now();
 
             match relay.relay.status {
                 RelayStatus::Disconnected => {
-                    let reconnect_at = relay.last_connect_attempt + relay.retry_connect_after;
-                    if now > reconnect_at {
-                        relay.last_connect_attempt = now;
-                        let next_duration = Duration::from_millis(
-                            ((relay.retry_connect_after.as_millis() as f64) * 1.5) as u64,
-                        );
+                    if now > relay.reconnect_at {
+                        relay.backoff(now);
                         debug!(
-                            "bumping reconnect duration from {:?} to {:?} and retrying connect",
-                            relay.retry_connect_after, next_duration
+                            "retrying connect to {}, next attempt in {:?}",
+                            relay.relay.url, relay.retry_connect_after
                         );
-                        relay.retry_connect_after = next_duration;
                         if let Err(err) = relay.relay.connect(wakeup.clone()) {
                             error!("error connecting to relay: {}", err);
+                            relay.relay.stats.last_error = Some(err.to_string());
                         }
                     } else {
                         // let's wait a bit before we try again
@@ -143,7 +326,9 @@ impl RelayPool {
                 }
 
                 RelayStatus::Connected => {
-                    relay.retry_connect_after = PoolRelay::initial_reconnect_duration();
+                    relay.reset_backoff();
+                    relay.relay.expire_syncs();
+                    relay.relay.expire_pending(self.auth_timeout);
 
                     let should_ping = now - relay.last_ping > self.ping_rate;
                     if should_ping {
@@ -170,6 +355,120 @@ impl RelayPool {
         }
     }
 
+    fn get_relay_mut(&mut self, relay_url: &str) -> Option<&mut Relay> {
+        self.relays
+            .iter_mut()
+            .map(|pool_relay| &mut pool_relay.relay)
+            .find(|relay| relay.url == relay_url)
+    }
+
+    /// Answer a NIP-42 AUTH challenge from a relay. Read-only accounts
+    /// can't sign, so the relay is just marked as requiring auth.
+    pub fn authenticate(
+        &mut self,
+        relay_url: &str,
+        challenge: &str,
+        keypair: Option<FilledKeypair<'_>>,
+    ) {
+        if let Some(relay) = self.get_relay_mut(relay_url) {
+            relay.authenticate(challenge, keypair);
+        }
+    }
+
+    /// A relay's challenge is being answered by someone else, see
+    /// [`Relay::auth_pending`]
+    pub fn auth_pending(&mut self, relay_url: &str, id: NoteId) {
+        if let Some(relay) = self.get_relay_mut(relay_url) {
+            relay.auth_pending(id);
+        }
+    }
+
+    /// See [`Relay::auth_failed`]
+    pub fn auth_failed(&mut self, relay_url: &str, reason: &str) {
+        if let Some(relay) = self.get_relay_mut(relay_url) {
+            relay.auth_failed(reason);
+        }
+    }
+
+    /// Send a signed answer to a relay's challenge
+    pub fn send_auth(&mut self, relay_url: &str, note: Note) {
+        if let Some(relay) = self.get_relay_mut(relay_url) {
+            relay.send_auth(note);
+        }
+    }
+
+    /// Let the relay look at an OK result. Returns true if this was for
+    /// an authentication attempt or a note we published. Authenticating
+    /// sends any REQs and notes that were waiting on it.
+    pub fn handle_ok(
+        &mut self,
+        relay_url: &str,
+        event_id: &str,
+        accepted: bool,
+        message: &str,
+    ) -> bool {
+        // not get_relay_mut, we need self.publishes below
+        let relay = if let Some(pool_relay) = self
+            .relays
+            .iter_mut()
+            .find(|pool_relay| pool_relay.relay.url == relay_url)
+        {
+            &mut pool_relay.relay
+        } else {
+            return false;
+        };
+
+        if relay.handle_ok(event_id, accepted, message) {
+            if relay.auth == RelayAuthStatus::Authenticated {
+                for msg in self.publishes.take_queued(relay_url) {
+                    relay.send(&msg);
+                }
+            }
+            return true;
+        }
+
+        self.publishes
+            .handle_ok(relay_url, event_id, accepted, message)
+    }
+
+    /// Note how long a subscription took to reach EOSE. Returns true if
+    /// this was for a REQ the pool made itself, like the ones fetching
+    /// notes a negentropy sync found missing.
+    pub fn handle_eose(&mut self, relay_url: &str, sub_id: &str) -> bool {
+        if let Some(relay) = self.get_relay_mut(relay_url) {
+            if let Some(latency) = relay.stats.record_eose(sub_id) {
+                debug!("{} EOSE for {} after {:?}", relay_url, sub_id, latency);
+            }
+            return relay.handle_eose(sub_id);
+        }
+
+        false
+    }
+
+    /// Returns false if this wasn't for one of our negentropy syncs
+    pub fn handle_neg_msg(&mut self, relay_url: &str, sub_id: &str, message: &str) -> bool {
+        self.get_relay_mut(relay_url)
+            .is_some_and(|relay| relay.handle_neg_msg(sub_id, message))
+    }
+
+    pub fn handle_neg_err(&mut self, relay_url: &str, sub_id: &str, reason: &str) -> bool {
+        self.get_relay_mut(relay_url)
+            .is_some_and(|relay| relay.handle_neg_err(sub_id, reason))
+    }
+
+    /// Returns true if the NOTICE was the relay refusing a negentropy
+    /// sync
+    pub fn handle_notice(&mut self, relay_url: &str, notice: &str) -> bool {
+        self.get_relay_mut(relay_url)
+            .is_some_and(|relay| relay.handle_notice(notice))
+    }
+
+    pub fn handle_closed(&mut self, relay_url: &str, sub_id: &str, reason: &str) {
+        if let Some(relay) = self.get_relay_mut(relay_url) {
+            relay.handle_closed(sub_id, reason);
+        }
+    }
+
     // Adds a websocket url to the RelayPool.
     pub fn add_url(
         &mut self,
@@ -181,7 +480,7 @@ impl RelayPool {
         if self.has(&url) {
             return Ok(());
         }
-        let relay = Relay::new(url, wakeup)?;
+        let relay = Relay::new(url, self.proxy.clone(), wakeup)?;
         let pool_relay = PoolRelay::new(relay);
 
         self.relays.push(pool_relay);
@@ -218,21 +517,39 @@ impl RelayPool {
     /// receive a message from each. If a message is received, return it.
     /// If no message is received from any relays, None is returned.
     pub fn try_recv(&mut self) -> Option<PoolEvent<'_>> {
-        for relay in &mut self.relays {
-            let relay = &mut relay.relay;
+        for pool_relay in &mut self.relays {
+            let last_ping = pool_relay.last_ping;
+            let relay = &mut pool_relay.relay;
             if let Some(event) = relay.receiver.try_recv() {
                 match &event {
                     WsEvent::Opened => {
                         relay.status = RelayStatus::Connected;
+                        relay.ever_connected = true;
+                        for msg in self.publishes.take_queued(&relay.url) {
+                            relay.send(&msg);
+                        }
                     }
                     WsEvent::Closed => {
                         relay.status = RelayStatus::Disconnected;
+                        relay.stats.record_disconnect(None);
+                        self.publishes.requeue(&relay.url);
                     }
                     WsEvent::Error(err) => {
                         error!("{:?}", err);
                         relay.status = RelayStatus::Disconnected;
+                        relay.stats.record_disconnect(Some(err.to_owned()));
+                        self.publishes.requeue(&relay.url);
                     }
                     WsEvent::Message(ev) => {
+                        match ev {
+                            WsMessage::Text(txt) => relay.stats.record_message(txt.len()),
+                            WsMessage::Binary(bs) => relay.stats.record_message(bs.len()),
+                            WsMessage::Pong(_) => {
+                                relay.stats.latency = Some(last_ping.elapsed());
+                            }
+                            _ => {}
+                        }
+
                         // let's just handle pongs here.
                         // We only need to do this natively.
                         #[cfg(not(target_arch = "wasm32"))]
@@ -252,3 +569,28 @@ impl RelayPool {
         None
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_capped() {
+        let mut backoff = PoolRelay::initial_reconnect_duration();
+        for _ in 0..20 {
+            let next = next_backoff(backoff);
+            assert!(next >= backoff);
+            backoff = next;
+        }
+        assert_eq!(backoff, PoolRelay::max_reconnect_duration());
+    }
+
+    #[test]
+    fn test_jitter_range() {
+        let base = Duration::from_secs(10);
+        for _ in 0..100 {
+            let d = jitter(base);
+            assert!(d >= Duration::from_secs(8) && d <= Duration::from_secs(12));
+        }
+    }
+}
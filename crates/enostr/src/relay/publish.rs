@@ -0,0 +1,286 @@
+use crate::{ClientMessage, NoteId};
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+
+/// Where a published note is at on a single relay
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum PublishState {
+    /// The relay wasn't connected when we published, we will send it when
+    /// it is
+    Queued,
+    /// Sent, waiting for the relay's OK
+    Sent,
+    Accepted,
+    /// The relay said no, with its reason
+    Rejected(String),
+    /// It was still queued when we gave up on sending it, see
+    /// [`PublishTracker::queue_timeout`]
+    TimedOut,
+}
+
+/// A note we published and what each relay said about it
+#[derive(Debug, Clone)]
+pub struct Publish {
+    msg: ClientMessage,
+    pub relays: BTreeMap<String, PublishState>,
+    pub created: Instant,
+}
+
+impl Publish {
+    pub fn accepted(&self) -> usize {
+        self.relays
+            .values()
+            .filter(|state| **state == PublishState::Accepted)
+            .count()
+    }
+
+    pub fn rejected(&self) -> usize {
+        self.relays
+            .values()
+            .filter(|state| matches!(state, PublishState::Rejected(_)))
+            .count()
+    }
+
+    /// Relays that said no, or that we gave up on
+    pub fn failed(&self) -> usize {
+        self.relays
+            .values()
+            .filter(|state| matches!(state, PublishState::Rejected(_) | PublishState::TimedOut))
+            .count()
+    }
+
+    pub fn total(&self) -> usize {
+        self.relays.len()
+    }
+
+    /// Every relay has answered
+    pub fn is_done(&self) -> bool {
+        self.relays.values().all(|state| {
+            matches!(
+                state,
+                PublishState::Accepted | PublishState::Rejected(_) | PublishState::TimedOut
+            )
+        })
+    }
+}
+
+/// Keeps track of the notes we published, keyed by event id, so that we
+/// can tell when a post didn't make it anywhere
+#[derive(Debug, Default)]
+pub struct PublishTracker {
+    publishes: HashMap<NoteId, Publish>,
+}
+
+impl PublishTracker {
+    /// How long we remember finished publishes
+    pub fn retention() -> Duration {
+        Duration::from_secs(60 * 60)
+    }
+
+    /// How long a note waits for a relay to connect, or to let us
+    /// authenticate, before we stop trying to send it there
+    pub fn queue_timeout() -> Duration {
+        Duration::from_secs(2 * 60)
+    }
+
+    pub fn get(&self, id: &NoteId) -> Option<&Publish> {
+        self.publishes.get(id)
+    }
+
+    /// Start tracking a note. `relays` is each relay we are sending it to
+    /// and whether it was sent right away.
+    pub fn track(
+        &mut self,
+        id: NoteId,
+        msg: ClientMessage,
+        relays: impl IntoIterator<Item = (String, bool)>,
+    ) {
+        self.prune();
+
+        let relays = relays
+            .into_iter()
+            .map(|(url, sent)| {
+                let state = if sent {
+                    PublishState::Sent
+                } else {
+                    PublishState::Queued
+                };
+                (url, state)
+            })
+            .collect();
+
+        self.publishes.insert(
+            id,
+            Publish {
+                msg,
+                relays,
+                created: Instant::now(),
+            },
+        );
+    }
+
+    /// Record a relay's OK for one of our notes. Returns false if this
+    /// wasn't for a note we are tracking.
+    pub fn handle_ok(
+        &mut self,
+        relay: &str,
+        event_id: &str,
+        accepted: bool,
+        message: &str,
+    ) -> bool {
+        let publish = if let Some(publish) = NoteId::from_hex(event_id)
+            .ok()
+            .and_then(|id| self.publishes.get_mut(&id))
+        {
+            publish
+        } else {
+            return false;
+        };
+
+        let state = if let Some(state) = publish.relays.get_mut(relay) {
+            state
+        } else {
+            return false;
+        };
+
+        *state = if accepted {
+            PublishState::Accepted
+        } else if message.starts_with("auth-required:") {
+            // we'll try again once we are authenticated
+            PublishState::Queued
+        } else {
+            PublishState::Rejected(message.to_owned())
+        };
+
+        true
+    }
+
+    /// The relay dropped, anything it didn't answer yet needs to be sent
+    /// again
+    pub fn requeue(&mut self, relay: &str) {
+        for publish in self.publishes.values_mut() {
+            if let Some(state) = publish.relays.get_mut(relay) {
+                if *state == PublishState::Sent {
+                    *state = PublishState::Queued;
+                }
+            }
+        }
+    }
+
+    /// Whether a relay still has to get or answer one of our notes
+    pub fn is_waiting_on(&self, relay: &str) -> bool {
+        self.publishes.values().any(|publish| {
+            matches!(
+                publish.relays.get(relay),
+                Some(PublishState::Queued | PublishState::Sent)
+            )
+        })
+    }
+
+    /// Take the messages waiting on a relay, marking them as sent
+    pub fn take_queued(&mut self, relay: &str) -> Vec<ClientMessage> {
+        let mut msgs = Vec::new();
+        for publish in self.publishes.values_mut() {
+            if let Some(state) = publish.relays.get_mut(relay) {
+                if *state == PublishState::Queued {
+                    *state = PublishState::Sent;
+                    msgs.push(publish.msg.clone());
+                }
+            }
+        }
+        msgs
+    }
+
+    /// Give up on notes that have been queued longer than `timeout`, so
+    /// relays that never take them don't look busy forever
+    pub fn expire_queued(&mut self, timeout: Duration) {
+        for publish in self.publishes.values_mut() {
+            if publish.created.elapsed() < timeout {
+                continue;
+            }
+
+            for state in publish.relays.values_mut() {
+                if *state == PublishState::Queued {
+                    *state = PublishState::TimedOut;
+                }
+            }
+        }
+    }
+
+    fn prune(&mut self) {
+        let retention = Self::retention();
+        self.publishes
+            .retain(|_, publish| !publish.is_done() || publish.created.elapsed() < retention);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_tracking() {
+        let id = NoteId::new([1; 32]);
+        let mut tracker = PublishTracker::default();
+        tracker.track(
+            id,
+            ClientMessage::raw("[]".to_owned()),
+            [
+                ("wss://a/".to_owned(), true),
+                ("wss://b/".to_owned(), true),
+                ("wss://c/".to_owned(), false),
+            ],
+        );
+
+        assert!(tracker.handle_ok("wss://a/", &id.hex(), true, ""));
+        assert!(tracker.handle_ok("wss://b/", &id.hex(), false, "blocked: spam"));
+        assert!(!tracker.handle_ok("wss://d/", &id.hex(), true, ""));
+
+        let publish = tracker.get(&id).unwrap();
+        assert_eq!(publish.accepted(), 1);
+        assert_eq!(publish.rejected(), 1);
+        assert_eq!(publish.total(), 3);
+        assert!(!publish.is_done());
+        assert_eq!(
+            publish.relays["wss://b/"],
+            PublishState::Rejected("blocked: spam".to_owned())
+        );
+
+        assert!(!tracker.is_waiting_on("wss://a/"));
+        assert!(tracker.is_waiting_on("wss://c/"));
+        assert!(!tracker.is_waiting_on("wss://d/"));
+
+        assert_eq!(tracker.take_queued("wss://c/").len(), 1);
+        assert!(tracker.take_queued("wss://c/").is_empty());
+
+        tracker.requeue("wss://c/");
+        assert_eq!(tracker.take_queued("wss://c/").len(), 1);
+    }
+
+    #[test]
+    fn test_queued_publishes_time_out() {
+        let id = NoteId::new([1; 32]);
+        let mut tracker = PublishTracker::default();
+        tracker.track(
+            id,
+            ClientMessage::raw("[]".to_owned()),
+            [
+                ("wss://a/".to_owned(), true),
+                ("wss://b/".to_owned(), false),
+            ],
+        );
+
+        tracker.expire_queued(PublishTracker::queue_timeout());
+        assert!(tracker.is_waiting_on("wss://b/"));
+
+        tracker.expire_queued(Duration::ZERO);
+        assert!(!tracker.is_waiting_on("wss://b/"));
+        assert!(tracker.take_queued("wss://b/").is_empty());
+
+        let publish = tracker.get(&id).unwrap();
+        assert_eq!(publish.relays["wss://a/"], PublishState::Sent);
+        assert_eq!(publish.relays["wss://b/"], PublishState::TimedOut);
+        assert_eq!(publish.failed(), 1);
+        assert_eq!(publish.rejected(), 0);
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Connection health of a relay, so that we can see which relays are slow
+/// or keep dropping us
+#[derive(Debug, Default, Clone)]
+pub struct RelayStats {
+    /// How many times we have tried to open a websocket
+    pub connect_attempts: u64,
+
+    /// How many times an open connection was closed or errored
+    pub disconnects: u64,
+
+    pub last_error: Option<String>,
+
+    /// Round-trip time of our last ping
+    pub latency: Option<Duration>,
+
+    pub messages_received: u64,
+    pub bytes_received: u64,
+
+    /// How long it took from sending a REQ until the relay sent EOSE, for
+    /// the subscriptions that are still open
+    pub eose_latency: HashMap<String, Duration>,
+
+    /// When we sent each REQ that is still waiting for EOSE
+    req_sent: HashMap<String, Instant>,
+}
+
+impl RelayStats {
+    pub fn record_req(&mut self, sub_id: &str) {
+        self.eose_latency.remove(sub_id);
+        self.req_sent.insert(sub_id.to_owned(), Instant::now());
+    }
+
+    pub fn record_eose(&mut self, sub_id: &str) -> Option<Duration> {
+        let latency = self.req_sent.remove(sub_id)?.elapsed();
+        self.eose_latency.insert(sub_id.to_owned(), latency);
+        Some(latency)
+    }
+
+    pub fn record_close(&mut self, sub_id: &str) {
+        self.req_sent.remove(sub_id);
+        self.eose_latency.remove(sub_id);
+    }
+
+    pub fn record_message(&mut self, bytes: usize) {
+        self.messages_received += 1;
+        self.bytes_received += bytes as u64;
+    }
+
+    pub fn record_disconnect(&mut self, error: Option<String>) {
+        self.disconnects += 1;
+        if error.is_some() {
+            self.last_error = error;
+        }
+        // anything in flight died with the connection
+        self.req_sent.clear();
+        self.latency = None;
+    }
+
+    /// The average time to EOSE over our open subscriptions
+    pub fn average_eose_latency(&self) -> Option<Duration> {
+        let n = self.eose_latency.len() as u32;
+        if n == 0 {
+            return None;
+        }
+
+        Some(self.eose_latency.values().sum::<Duration>() / n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eose_latency() {
+        let mut stats = RelayStats::default();
+        assert_eq!(stats.record_eose("unknown"), None);
+
+        stats.record_req("sub");
+        assert!(stats.record_eose("sub").is_some());
+        assert!(stats.average_eose_latency().is_some());
+
+        // only the first EOSE counts
+        assert_eq!(stats.record_eose("sub"), None);
+
+        stats.record_close("sub");
+        assert_eq!(stats.average_eose_latency(), None);
+    }
+}
@@ -0,0 +1,47 @@
+use crate::negentropy::Negentropy;
+use nostrdb::Filter;
+use std::time::{Duration, Instant};
+
+/// How many ids we put in a single filter when fetching missing notes
+const IDS_PER_FILTER: usize = 250;
+
+/// A NIP-77 reconciliation we are running with a relay
+#[derive(Debug)]
+pub struct Sync {
+    pub neg: Negentropy,
+    pub started: Instant,
+
+    /// Most notes we will fetch, it's what a plain REQ would have given
+    /// us
+    pub limit: usize,
+}
+
+impl Sync {
+    pub fn new(neg: Negentropy, limit: usize) -> Self {
+        Sync {
+            neg,
+            started: Instant::now(),
+            limit,
+        }
+    }
+
+    /// Relays that don't know about NIP-77 just ignore us, so this is how
+    /// long we wait before we decide that is what happened
+    pub fn timeout() -> Duration {
+        Duration::from_secs(15)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.started.elapsed() > Self::timeout()
+    }
+
+    /// Filters for the notes the relay has that we don't
+    pub fn missing_filters(&self) -> Vec<Filter> {
+        let need = self.neg.need();
+        let need = &need[..need.len().min(self.limit)];
+
+        need.chunks(IDS_PER_FILTER)
+            .map(|ids| Filter::new().ids(ids.iter()).build())
+            .collect()
+    }
+}
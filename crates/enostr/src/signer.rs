@@ -0,0 +1,147 @@
+use crate::{Error, FilledKeypair, Note, Pubkey, Result, UnsignedNote};
+
+/// What came of asking a [`Signer`] to sign
+#[derive(Debug)]
+pub enum Signature {
+    /// Signed right away
+    Signed(Note),
+    /// Sent to a remote signer, the note comes back later as the answer
+    /// to this request id
+    Requested(String),
+}
+
+/// What came of asking a [`Signer`] to encrypt or decrypt
+#[derive(Debug)]
+pub enum Crypt {
+    /// Done right away
+    Done(String),
+    /// Sent to a remote signer, the text comes back later as the answer
+    /// to this request id
+    Requested(String),
+}
+
+/// The ways a [`Signer`] encrypts to, and decrypts from, other people
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    Nip04,
+    Nip44,
+}
+
+impl Encryption {
+    /// What NIP-46 calls encrypting with it
+    pub fn encrypt_method(&self) -> &'static str {
+        match self {
+            Encryption::Nip04 => "nip04_encrypt",
+            Encryption::Nip44 => "nip44_encrypt",
+        }
+    }
+
+    /// What NIP-46 calls decrypting with it
+    pub fn decrypt_method(&self) -> &'static str {
+        match self {
+            Encryption::Nip04 => "nip04_decrypt",
+            Encryption::Nip44 => "nip44_decrypt",
+        }
+    }
+}
+
+/// Something that signs notes for an account, and encrypts with its key.
+/// That's a secret key we hold, or a NIP-46 remote signer, see
+/// [`crate::nip46::BunkerSession`].
+pub trait Signer {
+    fn sign(&mut self, note: UnsignedNote) -> Result<Signature>;
+
+    /// Encrypt `plaintext` so only `to` and the account can read it
+    fn encrypt(&mut self, encryption: Encryption, to: &Pubkey, plaintext: &str) -> Result<Crypt>;
+
+    /// Decrypt what `from` encrypted to the account, or what the account
+    /// encrypted to `from`
+    fn decrypt(&mut self, encryption: Encryption, from: &Pubkey, ciphertext: &str)
+        -> Result<Crypt>;
+}
+
+impl Signer for FilledKeypair<'_> {
+    fn sign(&mut self, note: UnsignedNote) -> Result<Signature> {
+        if note.pubkey != *self.pubkey {
+            return Err(Error::InvalidPublicKey);
+        }
+
+        Ok(Signature::Signed(note.sign(self.secret_key)?))
+    }
+
+    fn encrypt(&mut self, encryption: Encryption, to: &Pubkey, plaintext: &str) -> Result<Crypt> {
+        let ciphertext = match encryption {
+            Encryption::Nip04 => self.nip04_encrypt(to, plaintext)?,
+            Encryption::Nip44 => self.nip44_encrypt(to, plaintext)?,
+        };
+        Ok(Crypt::Done(ciphertext))
+    }
+
+    fn decrypt(
+        &mut self,
+        encryption: Encryption,
+        from: &Pubkey,
+        ciphertext: &str,
+    ) -> Result<Crypt> {
+        let plaintext = match encryption {
+            Encryption::Nip04 => self.nip04_decrypt(from, ciphertext)?,
+            Encryption::Nip44 => self.nip44_decrypt(from, ciphertext)?,
+        };
+        Ok(Crypt::Done(plaintext))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{nip59, FullKeypair};
+
+    #[test]
+    fn test_local_signer() -> Result<()> {
+        let keypair = FullKeypair::generate();
+        let note = UnsignedNote {
+            pubkey: keypair.pubkey,
+            created_at: 1_700_000_000,
+            kind: 1,
+            tags: vec![vec!["t".to_owned(), "nostr".to_owned()]],
+            content: "hello".to_owned(),
+        };
+        let id = note.id();
+
+        let signed = if let Signature::Signed(signed) = keypair.to_filled().sign(note.clone())? {
+            signed
+        } else {
+            panic!("local keys sign right away");
+        };
+        assert_eq!(signed.id, id);
+        nip59::verify_event(&signed)?;
+
+        // not our note to sign
+        let other = FullKeypair::generate();
+        assert!(other.to_filled().sign(note).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_encryption() -> Result<()> {
+        let alice = FullKeypair::generate();
+        let bob = FullKeypair::generate();
+
+        for encryption in [Encryption::Nip04, Encryption::Nip44] {
+            let ciphertext = match alice.to_filled().encrypt(encryption, &bob.pubkey, "hola")? {
+                Crypt::Done(ciphertext) => ciphertext,
+                Crypt::Requested(_) => panic!("local keys encrypt right away"),
+            };
+
+            let plaintext = match bob
+                .to_filled()
+                .decrypt(encryption, &alice.pubkey, &ciphertext)?
+            {
+                Crypt::Done(plaintext) => plaintext,
+                Crypt::Requested(_) => panic!("local keys decrypt right away"),
+            };
+            assert_eq!(plaintext, "hola");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! A NIP-46 session against a scripted signer on a mock relay
+
+use enostr::nip46::{
+    message, open_message, BunkerEvent, BunkerSession, BunkerUri, Request, Response, SessionStatus,
+    NOSTR_CONNECT_KIND,
+};
+use enostr::{
+    Crypt, Encryption, FullKeypair, Note, ProxyConfig, Pubkey, Signature, Signer, UnsignedNote,
+};
+use mock_relay::MockRelay;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answers whatever the client asks on the relay, signing with `user`
+struct ScriptedSigner {
+    /// What the signer talks with
+    keys: FullKeypair,
+    /// Who it signs for
+    user: FullKeypair,
+    secret: String,
+    answered: HashSet<String>,
+}
+
+impl ScriptedSigner {
+    fn new(secret: &str) -> Self {
+        ScriptedSigner {
+            keys: FullKeypair::generate(),
+            user: FullKeypair::generate(),
+            secret: secret.to_owned(),
+            answered: HashSet::new(),
+        }
+    }
+
+    fn uri(&self, relay: &MockRelay, secret: &str) -> BunkerUri {
+        BunkerUri::parse(&format!(
+            "bunker://{}?relay={}&secret={}",
+            self.keys.pubkey.hex(),
+            relay.url(),
+            secret
+        ))
+        .unwrap()
+    }
+
+    fn answer(&mut self, relay: &MockRelay) {
+        for event in relay.events() {
+            let note = Note::from_json(&event.to_string()).unwrap();
+            if note.kind != NOSTR_CONNECT_KIND
+                || note.pubkey == self.keys.pubkey
+                || !self.answered.insert(note.id.hex())
+            {
+                continue;
+            }
+
+            let json = open_message(self.keys.to_filled(), &note).unwrap();
+            let request: Request = serde_json::from_str(&json).unwrap();
+            let (result, error) = self.handle(&request);
+            let response = Response {
+                id: request.id,
+                result,
+                error,
+            };
+
+            let reply = message(
+                self.keys.to_filled(),
+                &note.pubkey,
+                &serde_json::to_string(&response).unwrap(),
+            )
+            .unwrap();
+            relay.add_event(serde_json::to_value(&reply).unwrap());
+        }
+    }
+
+    fn handle(&self, request: &Request) -> (Option<String>, Option<String>) {
+        match request.method.as_str() {
+            "connect" if request.params.get(1) == Some(&self.secret) => {
+                (Some("ack".to_owned()), None)
+            }
+            "connect" => (None, Some("invalid secret".to_owned())),
+            "get_public_key" => (Some(self.user.pubkey.hex()), None),
+            "sign_event" => {
+                let unsigned: serde_json::Value = serde_json::from_str(&request.params[0]).unwrap();
+                let note = UnsignedNote {
+                    pubkey: self.user.pubkey,
+                    created_at: unsigned["created_at"].as_u64().unwrap(),
+                    kind: unsigned["kind"].as_u64().unwrap(),
+                    tags: serde_json::from_value(unsigned["tags"].clone()).unwrap(),
+                    content: unsigned["content"].as_str().unwrap().to_owned(),
+                }
+                .sign(&self.user.secret_key)
+                .unwrap();
+                (Some(serde_json::to_string(&note).unwrap()), None)
+            }
+            "nip44_encrypt" | "nip44_decrypt" => {
+                let other = Pubkey::from_hex(&request.params[0]).unwrap();
+                let text = &request.params[1];
+                let result = if request.method == "nip44_encrypt" {
+                    self.user.nip44_encrypt(&other, text)
+                } else {
+                    self.user.nip44_decrypt(&other, text)
+                };
+                match result {
+                    Ok(text) => (Some(text), None),
+                    Err(err) => (None, Some(err.to_string())),
+                }
+            }
+            method => (None, Some(format!("{} isn't supported", method))),
+        }
+    }
+}
+
+/// Poll the session with the signer answering, until `done` or the
+/// timeout. Returns everything the session told us.
+fn pump(
+    session: &mut BunkerSession,
+    signer: &mut ScriptedSigner,
+    relay: &MockRelay,
+    mut done: impl FnMut(&[BunkerEvent]) -> bool,
+) -> Vec<BunkerEvent> {
+    let mut events = Vec::new();
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT && !done(&events) {
+        events.extend(session.poll(|| {}));
+        signer.answer(relay);
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    events
+}
+
+#[test]
+fn test_connect_and_sign() {
+    let relay = MockRelay::start().unwrap();
+    let mut signer = ScriptedSigner::new("s3cret");
+    let mut session =
+        BunkerSession::connect(signer.uri(&relay, "s3cret"), &ProxyConfig::default(), || {})
+            .unwrap();
+    assert_eq!(session.status(), &SessionStatus::Connecting);
+
+    let events = pump(&mut session, &mut signer, &relay, |evs| !evs.is_empty());
+    assert_eq!(events, vec![BunkerEvent::Connected(signer.user.pubkey)]);
+    assert_eq!(session.user(), Some(&signer.user.pubkey));
+
+    let unsigned = UnsignedNote {
+        pubkey: signer.user.pubkey,
+        created_at: 1_700_000_000,
+        kind: 1,
+        tags: vec![vec!["t".to_owned(), "nostr".to_owned()]],
+        content: "signed somewhere else".to_owned(),
+    };
+    let id = unsigned.id();
+    let request = match session.sign(unsigned).unwrap() {
+        Signature::Requested(request) => request,
+        Signature::Signed(_) => panic!("the bunker has the key, not us"),
+    };
+
+    let events = pump(&mut session, &mut signer, &relay, |evs| !evs.is_empty());
+    match &events[..] {
+        [BunkerEvent::Signed {
+            request: answer,
+            note,
+        }] => {
+            assert_eq!(answer, &request);
+            assert_eq!(note.id, id);
+            assert_eq!(note.pubkey, signer.user.pubkey);
+        }
+        other => panic!("expected a signed note, got {:?}", other),
+    }
+
+    // only the user's notes get sent off
+    let stranger = UnsignedNote {
+        pubkey: FullKeypair::generate().pubkey,
+        created_at: 1_700_000_000,
+        kind: 1,
+        tags: vec![],
+        content: "not ours".to_owned(),
+    };
+    assert!(session.sign(stranger).is_err());
+}
+
+#[test]
+fn test_wrong_secret() {
+    let relay = MockRelay::start().unwrap();
+    let mut signer = ScriptedSigner::new("s3cret");
+    let mut session =
+        BunkerSession::connect(signer.uri(&relay, "guess"), &ProxyConfig::default(), || {})
+            .unwrap();
+
+    let events = pump(&mut session, &mut signer, &relay, |evs| !evs.is_empty());
+    assert_eq!(
+        events,
+        vec![BunkerEvent::ConnectFailed("invalid secret".to_owned())]
+    );
+    assert!(matches!(session.status(), SessionStatus::Failed(_)));
+    assert!(session.user().is_none());
+}
+
+#[test]
+fn test_remote_encryption() {
+    let relay = MockRelay::start().unwrap();
+    let mut signer = ScriptedSigner::new("s3cret");
+    let mut session =
+        BunkerSession::connect(signer.uri(&relay, "s3cret"), &ProxyConfig::default(), || {})
+            .unwrap();
+    pump(&mut session, &mut signer, &relay, |evs| !evs.is_empty());
+
+    let friend = FullKeypair::generate();
+    let crypted = |events: &[BunkerEvent], request: &str| {
+        events.iter().find_map(|ev| match ev {
+            BunkerEvent::Crypted {
+                request: answer,
+                text,
+            } if answer == request => Some(text.clone()),
+            _ => None,
+        })
+    };
+
+    let request = match session
+        .encrypt(Encryption::Nip44, &friend.pubkey, "just between us")
+        .unwrap()
+    {
+        Crypt::Requested(request) => request,
+        Crypt::Done(_) => panic!("the bunker has the key, not us"),
+    };
+    let events = pump(&mut session, &mut signer, &relay, |evs| {
+        crypted(evs, &request).is_some()
+    });
+    let ciphertext = crypted(&events, &request).expect("encrypted");
+    assert_eq!(
+        friend
+            .nip44_decrypt(&signer.user.pubkey, &ciphertext)
+            .unwrap(),
+        "just between us"
+    );
+
+    let reply = friend.nip44_encrypt(&signer.user.pubkey, "got it").unwrap();
+    let request = match session
+        .decrypt(Encryption::Nip44, &friend.pubkey, &reply)
+        .unwrap()
+    {
+        Crypt::Requested(request) => request,
+        Crypt::Done(_) => panic!("the bunker has the key, not us"),
+    };
+    let events = pump(&mut session, &mut signer, &relay, |evs| {
+        crypted(evs, &request).is_some()
+    });
+    assert_eq!(crypted(&events, &request).as_deref(), Some("got it"));
+}
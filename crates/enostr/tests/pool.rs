@@ -0,0 +1,641 @@
+//! RelayPool against a real websocket, see the mock_relay crate
+
+use enostr::{
+    ClientMessage, Filter, FilterSpec, FullKeypair, NegentropySupport, Note, NoteId, ProxyConfig,
+    PublishState, RelayAuthStatus, RelayEvent, RelayInfoDocument, RelayMessage, RelayPool,
+    RelayStatus,
+};
+use mock_relay::{Faults, MockRelay, SocksProxy};
+use serde_json::{json, Value};
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn event(id: u8, kind: u64, created_at: u64) -> Value {
+    json!({
+        "id": hex::encode([id; 32]),
+        "pubkey": hex::encode([0xaa; 32]),
+        "created_at": created_at,
+        "kind": kind,
+        "tags": [],
+        "content": format!("note {}", id),
+        "sig": hex::encode([0; 64]),
+    })
+}
+
+fn pool_with(relay: &MockRelay) -> RelayPool {
+    let mut pool = RelayPool::new();
+    pool.add_url(relay.url(), || {}).unwrap();
+    pool
+}
+
+/// Something the pool received, kept around so tests can look at it
+#[derive(Debug, Default)]
+struct Received {
+    opened: usize,
+    closed: usize,
+    errors: usize,
+    events: Vec<(String, String)>,
+    eose: Vec<String>,
+    oks: Vec<(String, bool, String)>,
+    closed_subs: Vec<(String, String)>,
+    notices: Vec<String>,
+}
+
+/// Handle pool events the way the app does, until `done` or the timeout.
+/// Returns whether `done` happened.
+fn pump(
+    pool: &mut RelayPool,
+    keypair: Option<&FullKeypair>,
+    received: &mut Received,
+    mut done: impl FnMut(&Received) -> bool,
+) -> bool {
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        let ev = if let Some(ev) = pool.try_recv() {
+            ev.into_owned()
+        } else {
+            if done(received) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(5));
+            continue;
+        };
+
+        match RelayEvent::from(&ev.event) {
+            RelayEvent::Opened => received.opened += 1,
+            RelayEvent::Closed => received.closed += 1,
+            RelayEvent::Error(_) | RelayEvent::Other(_) => received.errors += 1,
+            RelayEvent::Message(msg) => match msg {
+                RelayMessage::Event(sub_id, txt) => {
+                    received.events.push((sub_id.to_string(), txt.to_owned()))
+                }
+                RelayMessage::Eose(sub_id) => {
+                    pool.handle_eose(&ev.relay, &sub_id);
+                    received.eose.push(sub_id.to_string());
+                }
+                RelayMessage::Ok {
+                    event_id,
+                    accepted,
+                    message,
+                } => {
+                    pool.handle_ok(&ev.relay, &event_id, accepted, &message);
+                    received
+                        .oks
+                        .push((event_id.to_string(), accepted, message.to_string()));
+                }
+                RelayMessage::Closed { sub_id, reason } => {
+                    pool.handle_closed(&ev.relay, &sub_id, &reason);
+                    received
+                        .closed_subs
+                        .push((sub_id.to_string(), reason.to_string()));
+                }
+                RelayMessage::Auth { challenge } => {
+                    pool.authenticate(&ev.relay, &challenge, keypair.map(|kp| kp.to_filled()));
+                }
+                RelayMessage::NegMsg { sub_id, message } => {
+                    pool.handle_neg_msg(&ev.relay, &sub_id, &message);
+                }
+                RelayMessage::NegErr { sub_id, reason } => {
+                    pool.handle_neg_err(&ev.relay, &sub_id, &reason);
+                }
+                RelayMessage::Notice(notice) => {
+                    pool.handle_notice(&ev.relay, &notice);
+                    received.notices.push(notice.to_string());
+                }
+                RelayMessage::Count { .. } => {}
+            },
+        }
+
+        if done(received) {
+            return true;
+        }
+    }
+
+    false
+}
+
+#[test]
+fn test_req_gets_stored_events_then_eose() {
+    let relay = MockRelay::start().unwrap();
+    relay.add_event(event(1, 1, 100));
+    relay.add_event(event(2, 1, 200));
+    relay.add_event(event(3, 7, 300));
+
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    pool.subscribe("notes".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| !r.eose.is_empty()));
+    assert_eq!(received.eose, vec!["notes"]);
+    assert_eq!(received.events.len(), 2);
+    assert!(received.events.iter().all(|(sub_id, _)| sub_id == "notes"));
+
+    let stats = &pool.relays[0].relay.stats;
+    assert!(stats.eose_latency.contains_key("notes"));
+    assert!(stats.messages_received >= 3);
+}
+
+#[test]
+fn test_live_events_after_eose() {
+    let relay = MockRelay::start().unwrap();
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    pool.subscribe("live".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| !r.eose.is_empty()));
+    assert!(received.events.is_empty());
+
+    relay.add_event(event(4, 7, 100));
+    relay.add_event(event(5, 1, 100));
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.events.len() == 1));
+    assert_eq!(received.events.len(), 1);
+    assert!(received.events[0].1.contains(&hex::encode([5; 32])));
+}
+
+#[test]
+fn test_publish_is_tracked() {
+    let relay = MockRelay::start().unwrap();
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+
+    // wait for the connection so the publish goes out right away
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 1));
+
+    let note = Note::from_json(&event(6, 1, 100).to_string()).unwrap();
+    let id = NoteId::new([6; 32]);
+    let url = relay.url();
+    pool.publish(id, ClientMessage::event(note), [url.as_str()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| !r.oks.is_empty()));
+    let publish = pool.publishes.get(&id).unwrap();
+    assert_eq!(publish.accepted(), 1);
+    assert!(publish.is_done());
+    assert_eq!(relay.events().len(), 1);
+}
+
+#[test]
+fn test_rejected_publish() {
+    let relay = MockRelay::start().unwrap();
+    relay.set_faults(Faults {
+        reject_events: Some("blocked: no thanks".to_owned()),
+        ..Default::default()
+    });
+
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+
+    // not connected yet, this gets queued until we are
+    let note = Note::from_json(&event(7, 1, 100).to_string()).unwrap();
+    let id = NoteId::new([7; 32]);
+    let url = relay.url();
+    pool.publish(id, ClientMessage::event(note), [url.as_str()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| !r.oks.is_empty()));
+    let publish = pool.publishes.get(&id).unwrap();
+    assert_eq!(publish.rejected(), 1);
+    assert!(relay.events().is_empty());
+}
+
+#[test]
+fn test_publish_queued_behind_auth_times_out() {
+    let relay = MockRelay::start().unwrap();
+    relay.require_auth(true);
+
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 1));
+
+    // we can't authenticate, so the relay never takes it
+    let note = Note::from_json(&event(9, 1, 100).to_string()).unwrap();
+    let id = NoteId::new([9; 32]);
+    let url = relay.url();
+    pool.publish(id, ClientMessage::event(note), [url.as_str()]);
+    assert!(pump(&mut pool, None, &mut received, |r| !r.oks.is_empty()));
+    assert_eq!(
+        pool.publishes.get(&id).unwrap().relays[&url],
+        PublishState::Queued
+    );
+    assert!(!pool.is_idle(&url));
+
+    pool.queue_timeout = Duration::ZERO;
+    pool.keepalive_ping(|| {});
+    let publish = pool.publishes.get(&id).unwrap();
+    assert_eq!(publish.relays[&url], PublishState::TimedOut);
+    assert!(publish.is_done());
+    assert!(pool.is_idle(&url));
+}
+
+#[test]
+fn test_auth_before_req() {
+    let relay = MockRelay::start().unwrap();
+    relay.require_auth(true);
+    relay.add_event(event(8, 1, 100));
+
+    let keypair = FullKeypair::generate();
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    pool.subscribe("private".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, Some(&keypair), &mut received, |r| {
+        !r.eose.is_empty()
+    }));
+    assert_eq!(pool.relays[0].relay.auth, RelayAuthStatus::Authenticated);
+    assert_eq!(received.events.len(), 1);
+
+    // depending on timing the relay might have closed our first REQ
+    for (_, reason) in &received.closed_subs {
+        assert!(reason.starts_with("auth-required:"));
+    }
+}
+
+#[test]
+fn test_auth_without_secret_key() {
+    let relay = MockRelay::start().unwrap();
+    relay.require_auth(true);
+
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 1));
+    pool.subscribe("private".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| {
+        !r.closed_subs.is_empty()
+    }));
+    assert_eq!(pool.relays[0].relay.auth, RelayAuthStatus::Required);
+    assert!(relay.received_of("AUTH").is_empty());
+
+    // we can't authenticate, so it isn't kept around waiting to
+    assert!(pool.urls_with_sub("private").is_empty());
+}
+
+#[test]
+fn test_reqs_dont_wait_forever_for_a_challenge() {
+    let relay = MockRelay::start().unwrap();
+    let mut pool = pool_with(&relay);
+    pool.auth_timeout = Duration::from_millis(100);
+    let mut received = Received::default();
+    pool.subscribe("waiting".to_owned(), vec![Filter::new().kinds([1]).build()]);
+    assert!(pump(&mut pool, None, &mut received, |r| !r.eose.is_empty()));
+
+    // the relay wants auth for it, but never sends a challenge
+    relay.send_raw(r#"["CLOSED","waiting","auth-required: who are you?"]"#);
+    assert!(pump(&mut pool, None, &mut received, |r| {
+        !r.closed_subs.is_empty()
+    }));
+    assert!(!pool.urls_with_sub("waiting").is_empty());
+
+    std::thread::sleep(Duration::from_millis(200));
+    pool.keepalive_ping(|| {});
+    assert!(pool.urls_with_sub("waiting").is_empty());
+}
+
+#[test]
+fn test_auth_failed_releases_reqs() {
+    let relay = MockRelay::start().unwrap();
+    relay.add_event(event(8, 1, 100));
+
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 1));
+
+    // a remote signer is answering the challenge, REQs wait for it
+    let url = pool.relays[0].relay.url.clone();
+    pool.auth_pending(&url, NoteId::new([1; 32]));
+    pool.subscribe("waiting".to_owned(), vec![Filter::new().kinds([1]).build()]);
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(relay.received_of("REQ").is_empty());
+
+    // and never does
+    pool.auth_failed(&url, "the remote signer didn't answer");
+    assert!(pump(&mut pool, None, &mut received, |r| {
+        !r.eose.is_empty()
+    }));
+    assert_eq!(
+        pool.relays[0].relay.auth,
+        RelayAuthStatus::Failed("the remote signer didn't answer".to_owned())
+    );
+    assert_eq!(received.events.len(), 1);
+}
+
+#[test]
+fn test_malformed_messages() {
+    let relay = MockRelay::start().unwrap();
+    relay.set_faults(Faults {
+        malformed: true,
+        ..Default::default()
+    });
+    relay.add_event(event(9, 1, 100));
+
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    pool.subscribe("notes".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| !r.eose.is_empty()));
+    assert!(received.errors > 0);
+    assert_eq!(received.events.len(), 1);
+}
+
+#[test]
+fn test_missing_eose() {
+    let relay = MockRelay::start().unwrap();
+    relay.set_faults(Faults {
+        no_eose: true,
+        ..Default::default()
+    });
+    relay.add_event(event(10, 1, 100));
+
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    pool.subscribe("notes".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.events.len() == 1));
+    let got_eose = pump(&mut pool, None, &mut received, |r| !r.eose.is_empty());
+    assert!(!got_eose);
+    assert!(pool.relays[0].relay.stats.eose_latency.is_empty());
+}
+
+#[test]
+fn test_reconnect_after_drop() {
+    let relay = MockRelay::start().unwrap();
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 1));
+    relay.drop_connections();
+    assert!(pump(&mut pool, None, &mut received, |r| r.closed
+        + r.errors
+        > 0));
+    assert!(matches!(
+        pool.relays[0].relay.status,
+        RelayStatus::Disconnected
+    ));
+    assert_eq!(pool.relays[0].relay.stats.disconnects, 1);
+
+    // don't wait out the backoff
+    pool.relays[0].reconnect_at = Instant::now() - Duration::from_millis(1);
+    pool.keepalive_ping(|| {});
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 2));
+    assert!(matches!(
+        pool.relays[0].relay.status,
+        RelayStatus::Connected
+    ));
+    assert_eq!(relay.total_connections(), 2);
+    assert_eq!(pool.relays[0].relay.stats.connect_attempts, 2);
+}
+
+#[test]
+fn test_drop_after_faults() {
+    let relay = MockRelay::start().unwrap();
+    relay.set_faults(Faults {
+        drop_after: Some(1),
+        ..Default::default()
+    });
+
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    pool.subscribe("a".to_owned(), vec![Filter::new().kinds([1]).build()]);
+    pool.subscribe("b".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.closed
+        + r.errors
+        > 0));
+    assert_eq!(received.eose, vec!["a"]);
+    assert_eq!(relay.received_of("REQ").len(), 2);
+}
+
+#[test]
+fn test_resubscribe_after_reconnect() {
+    let relay = MockRelay::start().unwrap();
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    pool.subscribe("notes".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| !r.eose.is_empty()));
+    assert!(!pool.needs_resubscribe(&relay.url()));
+
+    relay.drop_connections();
+    assert!(pump(&mut pool, None, &mut received, |r| r.closed
+        + r.errors
+        > 0));
+    pool.relays[0].reconnect_at = Instant::now() - Duration::from_millis(1);
+    pool.keepalive_ping(|| {});
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 2));
+    assert!(pool.needs_resubscribe(&relay.url()));
+
+    // catch up from where we were
+    relay.add_event(event(11, 1, 50));
+    relay.add_event(event(12, 1, 150));
+    pool.resubscribe(&relay.url(), |_sub_id, filters| {
+        filters.into_iter().map(|f| f.since_mut(100)).collect()
+    });
+    assert!(!pool.needs_resubscribe(&relay.url()));
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.eose.len() == 2));
+    let reqs = relay.received_of("REQ");
+    assert_eq!(reqs.len(), 2);
+    assert_eq!(reqs[1][1], "notes");
+    assert_eq!(reqs[1][2]["since"], 100);
+    assert_eq!(received.events.len(), 1);
+    assert!(received.events[0].1.contains(&hex::encode([12; 32])));
+}
+
+#[test]
+fn test_negentropy_unknown_until_refused() {
+    let relay = MockRelay::start().unwrap();
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 1));
+
+    // lots of relays that sync don't list 77
+    pool.set_info(&relay.url(), RelayInfoDocument::default());
+    assert_eq!(pool.relays[0].relay.negentropy, NegentropySupport::Unknown);
+
+    // the mock relay doesn't know NEG-OPEN, and says so with a NOTICE
+    pool.relays[0].relay.sync(
+        "sync".to_owned(),
+        Filter::new().kinds([1]).build(),
+        std::iter::empty(),
+        10,
+    );
+    assert!(pump(&mut pool, None, &mut received, |r| !r
+        .notices
+        .is_empty()));
+    assert_eq!(
+        pool.relays[0].relay.negentropy,
+        NegentropySupport::Unsupported
+    );
+    assert_eq!(relay.received_of("NEG-OPEN").len(), 1);
+}
+
+#[test]
+fn test_negentropy_sync_round_trip() {
+    let relay = MockRelay::start().unwrap();
+    relay.support_negentropy(true);
+    relay.add_event(event(1, 1, 100));
+    relay.add_event(event(2, 1, 200));
+    relay.add_event(event(3, 1, 300));
+    relay.add_event(event(4, 7, 400));
+
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 1));
+
+    // we have the first note already
+    pool.relays[0].relay.sync(
+        "sync".to_owned(),
+        Filter::new().kinds([1]).build(),
+        [(100, [1; 32])],
+        10,
+    );
+
+    // the relay tells us what it has, and we fetch what we're missing
+    assert!(pump(&mut pool, None, &mut received, |r| r
+        .eose
+        .iter()
+        .any(|sub_id| sub_id == "sync")));
+    assert_eq!(
+        pool.relays[0].relay.negentropy,
+        NegentropySupport::Supported
+    );
+    assert_eq!(relay.received_of("NEG-OPEN").len(), 1);
+    assert_eq!(relay.received_of("NEG-CLOSE").len(), 1);
+
+    let reqs = relay.received_of("REQ");
+    assert_eq!(reqs.len(), 1);
+    assert_eq!(reqs[0][2]["ids"].as_array().map(Vec::len), Some(2));
+
+    assert_eq!(received.events.len(), 2);
+    for id in [2, 3] {
+        assert!(received
+            .events
+            .iter()
+            .any(|(sub_id, ev)| sub_id == "sync" && ev.contains(&hex::encode([id; 32]))));
+    }
+}
+
+#[test]
+fn test_search_only_to_relays_that_search() {
+    let relay = MockRelay::start().unwrap();
+    let mut pool = pool_with(&relay);
+    let mut received = Received::default();
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 1));
+
+    // NIP-11 says no NIP-50
+    pool.set_info(&relay.url(), RelayInfoDocument::default());
+    let search = FilterSpec::new()
+        .kinds([1])
+        .search("nostr")
+        .build()
+        .unwrap();
+    pool.subscribe("search".to_owned(), vec![search.clone()]);
+    pool.subscribe(
+        "mixed".to_owned(),
+        vec![search, Filter::new().kinds([7]).build()],
+    );
+
+    assert!(pump(&mut pool, None, &mut received, |r| !r.eose.is_empty()));
+    let reqs = relay.received_of("REQ");
+    assert_eq!(reqs.len(), 1);
+    assert_eq!(reqs[0][1], "mixed");
+    assert_eq!(reqs[0].as_array().unwrap().len(), 3);
+    assert_eq!(reqs[0][2]["kinds"][0], 7);
+}
+
+#[test]
+fn test_first_connect_fails_then_retry_succeeds() {
+    // nothing listens here yet
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let url = format!("ws://{}/", addr);
+
+    let mut pool = RelayPool::new();
+    pool.add_url(url.clone(), || {}).unwrap();
+    let mut received = Received::default();
+    pool.subscribe("notes".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.closed
+        + r.errors
+        > 0));
+    assert_eq!(received.opened, 0);
+
+    let relay = MockRelay::start_on(addr).unwrap();
+    relay.add_event(event(1, 1, 100));
+    pool.relays[0].reconnect_at = Instant::now() - Duration::from_millis(1);
+    pool.keepalive_ping(|| {});
+
+    // a relay we never reached gets what a first connection gets, not
+    // a resubscribe
+    assert!(pump(&mut pool, None, &mut received, |r| r.opened == 1));
+    assert!(!pool.needs_resubscribe(&url));
+
+    // and the REQ we made while it was down still goes out
+    assert!(pump(&mut pool, None, &mut received, |r| !r.eose.is_empty()));
+    assert_eq!(received.eose, vec!["notes"]);
+    assert_eq!(received.events.len(), 1);
+}
+
+#[test]
+fn test_through_socks_proxy() {
+    let relay = MockRelay::start().unwrap();
+    relay.add_event(event(1, 1, 100));
+    let proxy = SocksProxy::start().unwrap();
+
+    let mut pool = RelayPool::new();
+    pool.set_proxy(ProxyConfig::socks5(proxy.addr()));
+    pool.add_url(relay.url(), || {}).unwrap();
+    let mut received = Received::default();
+    pool.subscribe("notes".to_owned(), vec![Filter::new().kinds([1]).build()]);
+
+    assert!(pump(&mut pool, None, &mut received, |r| !r.eose.is_empty()));
+    assert_eq!(received.events.len(), 1);
+    assert_eq!(
+        proxy.targets(),
+        vec![relay
+            .url()
+            .trim_start_matches("ws://")
+            .trim_end_matches('/')
+            .to_owned()]
+    );
+}
+
+#[test]
+fn test_proxy_only_without_proxy() {
+    let relay = MockRelay::start().unwrap();
+
+    let mut pool = RelayPool::new();
+    pool.set_proxy(ProxyConfig {
+        socks5: None,
+        proxy_only: true,
+    });
+    pool.add_url(relay.url(), || {}).unwrap();
+    let mut received = Received::default();
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.errors > 0));
+    assert_eq!(received.opened, 0);
+    assert_eq!(relay.total_connections(), 0);
+}
+
+#[test]
+fn test_proxy_down_does_not_connect_directly() {
+    let relay = MockRelay::start().unwrap();
+    let proxy_addr = {
+        let proxy = SocksProxy::start().unwrap();
+        proxy.addr()
+    };
+
+    let mut pool = RelayPool::new();
+    pool.set_proxy(ProxyConfig::socks5(proxy_addr));
+    pool.add_url(relay.url(), || {}).unwrap();
+    let mut received = Received::default();
+
+    assert!(pump(&mut pool, None, &mut received, |r| r.errors > 0));
+    assert_eq!(received.opened, 0);
+    assert_eq!(relay.total_connections(), 0);
+}
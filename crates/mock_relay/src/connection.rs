@@ -0,0 +1,346 @@
+use crate::{filter, negentropy, Broadcast, State};
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+use tungstenite::{Error, Message, WebSocket};
+
+/// How long a read blocks before we go check for broadcasts
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+struct Connection {
+    ws: WebSocket<TcpStream>,
+    state: Arc<Mutex<State>>,
+    broadcasts: Receiver<Broadcast>,
+
+    subs: HashMap<String, Vec<Value>>,
+
+    /// The challenge we sent, if the relay requires auth
+    challenge: Option<String>,
+    authed: bool,
+
+    /// Messages received on this connection, for `Faults::drop_after`
+    received: usize,
+}
+
+pub fn run(stream: TcpStream, state: Arc<Mutex<State>>, shutdown: Arc<AtomicBool>) {
+    let mut conn = match Connection::accept(stream, state.clone()) {
+        Ok(conn) => conn,
+        Err(e) => {
+            warn!("mock relay: handshake failed: {}", e);
+            return;
+        }
+    };
+
+    state.lock().unwrap().open_connections += 1;
+
+    while !shutdown.load(Ordering::SeqCst) {
+        match conn.poll() {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => {
+                debug!("mock relay: connection closed: {}", e);
+                break;
+            }
+        }
+    }
+
+    let _ = conn.ws.close(None);
+    let _ = conn.ws.flush();
+    state.lock().unwrap().open_connections -= 1;
+}
+
+impl Connection {
+    fn accept(stream: TcpStream, state: Arc<Mutex<State>>) -> Result<Self, String> {
+        stream.set_nonblocking(false).map_err(|e| e.to_string())?;
+        let ws = tungstenite::accept(stream).map_err(|e| e.to_string())?;
+        ws.get_ref()
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .map_err(|e| e.to_string())?;
+
+        let (sender, broadcasts) = mpsc::channel();
+        let require_auth = {
+            let mut state = state.lock().unwrap();
+            state.connections.push(sender);
+            state.total_connections += 1;
+            state.require_auth
+        };
+
+        let mut conn = Connection {
+            ws,
+            state,
+            broadcasts,
+            subs: HashMap::new(),
+            challenge: None,
+            authed: false,
+            received: 0,
+        };
+
+        if require_auth {
+            let challenge = new_challenge();
+            conn.send(json!(["AUTH", challenge]))
+                .map_err(|e| e.to_string())?;
+            conn.challenge = Some(challenge);
+        }
+
+        Ok(conn)
+    }
+
+    /// Handle whatever is waiting. Returns false when the connection
+    /// should close.
+    fn poll(&mut self) -> Result<bool, Error> {
+        loop {
+            match self.broadcasts.try_recv() {
+                Ok(Broadcast::Drop) | Err(TryRecvError::Disconnected) => return Ok(false),
+                Ok(Broadcast::Raw(txt)) => self.ws.send(Message::Text(txt))?,
+                Ok(Broadcast::Event(ev)) => self.send_live_event(ev)?,
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+
+        let txt = match self.ws.read() {
+            Ok(Message::Text(txt)) => txt,
+            Ok(Message::Close(_)) => return Ok(false),
+            Ok(_) => return Ok(true),
+            Err(Error::Io(e))
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                return Ok(true)
+            }
+            Err(e) => return Err(e),
+        };
+
+        let (faults, does_negentropy) = {
+            let mut state = self.state.lock().unwrap();
+            state.received.push(txt.clone());
+            (state.faults.clone(), state.negentropy)
+        };
+
+        self.received += 1;
+        if let Some(n) = faults.drop_after {
+            if self.received > n {
+                debug!("mock relay: dropping connection after {} messages", n);
+                return Ok(false);
+            }
+        }
+
+        if !faults.delay.is_zero() {
+            thread::sleep(faults.delay);
+        }
+
+        if faults.malformed {
+            self.ws
+                .send(Message::Text("[\"EVENT\", {not json".to_owned()))?;
+        }
+
+        let msg: Value = match serde_json::from_str(&txt) {
+            Ok(msg) => msg,
+            Err(_) => {
+                self.send(json!(["NOTICE", "could not parse message"]))?;
+                return Ok(true);
+            }
+        };
+
+        self.handle(msg, faults.reject_events, faults.no_eose, does_negentropy)?;
+        Ok(true)
+    }
+
+    fn handle(
+        &mut self,
+        msg: Value,
+        reject_events: Option<String>,
+        no_eose: bool,
+        does_negentropy: bool,
+    ) -> Result<(), Error> {
+        let cmd = msg[0].as_str().unwrap_or_default();
+        match cmd {
+            "REQ" => {
+                let sub_id = msg[1].as_str().unwrap_or_default().to_owned();
+                if !self.is_authed() {
+                    return self.send(json!(["CLOSED", sub_id, "auth-required: please auth"]));
+                }
+
+                let filters = filters(&msg);
+                let events: Vec<Value> = {
+                    let state = self.state.lock().unwrap();
+                    filter::query(&state.events, &filters)
+                        .into_iter()
+                        .cloned()
+                        .collect()
+                };
+
+                for ev in events {
+                    self.send(json!(["EVENT", sub_id, ev]))?;
+                }
+
+                if !no_eose {
+                    self.send(json!(["EOSE", sub_id]))?;
+                }
+
+                self.subs.insert(sub_id, filters);
+            }
+
+            "CLOSE" => {
+                if let Some(sub_id) = msg[1].as_str() {
+                    self.subs.remove(sub_id);
+                }
+            }
+
+            "EVENT" => {
+                let ev = msg[1].clone();
+                let id = ev["id"].as_str().unwrap_or_default().to_owned();
+
+                if !self.is_authed() {
+                    return self.send(json!(["OK", id, false, "auth-required: please auth"]));
+                }
+
+                if let Some(reason) = reject_events {
+                    return self.send(json!(["OK", id, false, reason]));
+                }
+
+                let duplicate = {
+                    let mut state = self.state.lock().unwrap();
+                    let duplicate = state.events.iter().any(|e| e["id"] == ev["id"]);
+                    if !duplicate {
+                        state.events.push(ev.clone());
+                        state.broadcast(Broadcast::Event(ev));
+                    }
+                    duplicate
+                };
+
+                let message = if duplicate {
+                    "duplicate: already have it"
+                } else {
+                    ""
+                };
+                self.send(json!(["OK", id, true, message]))?;
+            }
+
+            "COUNT" => {
+                let sub_id = msg[1].as_str().unwrap_or_default().to_owned();
+                if !self.is_authed() {
+                    return self.send(json!(["CLOSED", sub_id, "auth-required: please auth"]));
+                }
+
+                let filters = filters(&msg);
+                let count = {
+                    let state = self.state.lock().unwrap();
+                    filter::query(&state.events, &filters).len()
+                };
+
+                self.send(json!(["COUNT", sub_id, { "count": count }]))?;
+            }
+
+            "NEG-OPEN" if does_negentropy => {
+                let sub_id = msg[1].as_str().unwrap_or_default().to_owned();
+                if !self.is_authed() {
+                    return self.send(json!(["NEG-ERR", sub_id, "auth-required: please auth"]));
+                }
+
+                let initial = msg[3].as_str().unwrap_or_default();
+                if !initial.starts_with(negentropy::PROTOCOL_VERSION) {
+                    return self.send(json!([
+                        "NEG-ERR",
+                        sub_id,
+                        "invalid: unsupported protocol version"
+                    ]));
+                }
+
+                let ids: Vec<String> = {
+                    let state = self.state.lock().unwrap();
+                    filter::query(&state.events, &[msg[2].clone()])
+                        .into_iter()
+                        .filter_map(|ev| ev["id"].as_str().map(str::to_owned))
+                        .collect()
+                };
+
+                self.send(json!(["NEG-MSG", sub_id, negentropy::id_list(&ids)]))?;
+            }
+
+            // we answered everything in one go, there's nothing to keep
+            "NEG-MSG" | "NEG-CLOSE" if does_negentropy => {}
+
+            "AUTH" => {
+                let ev = &msg[1];
+                let id = ev["id"].as_str().unwrap_or_default().to_owned();
+                let answered = ev["kind"].as_u64() == Some(22242)
+                    && self.challenge.as_deref().is_some_and(|challenge| {
+                        ev["tags"].as_array().is_some_and(|tags| {
+                            tags.iter().any(|tag| {
+                                tag[0].as_str() == Some("challenge")
+                                    && tag[1].as_str() == Some(challenge)
+                            })
+                        })
+                    });
+
+                if answered {
+                    self.authed = true;
+                    self.send(json!(["OK", id, true, ""]))?;
+                } else {
+                    self.send(json!(["OK", id, false, "auth-required: bad challenge"]))?;
+                }
+            }
+
+            _ => {
+                self.send(json!(["NOTICE", format!("unknown command: {}", cmd)]))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_authed(&self) -> bool {
+        self.challenge.is_none() || self.authed
+    }
+
+    /// Send a newly stored event to the subscriptions it matches
+    fn send_live_event(&mut self, ev: Value) -> Result<(), Error> {
+        if !self.is_authed() {
+            return Ok(());
+        }
+
+        let matching: Vec<String> = self
+            .subs
+            .iter()
+            .filter(|(_, filters)| filters.iter().any(|f| filter::matches(f, &ev)))
+            .map(|(sub_id, _)| sub_id.clone())
+            .collect();
+
+        for sub_id in matching {
+            self.send(json!(["EVENT", sub_id, ev]))?;
+        }
+
+        Ok(())
+    }
+
+    fn send(&mut self, msg: Value) -> Result<(), Error> {
+        self.ws.send(Message::Text(msg.to_string()))
+    }
+}
+
+/// The filters of a REQ or COUNT, everything after the subscription id
+fn filters(msg: &Value) -> Vec<Value> {
+    msg.as_array()
+        .and_then(|msg| msg.get(2..))
+        .map(|filters| filters.to_vec())
+        .unwrap_or_default()
+}
+
+/// Good enough to tell connections apart, these aren't secret
+fn new_challenge() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    format!("{:x}{:?}", nanos, thread::current().id())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect()
+}
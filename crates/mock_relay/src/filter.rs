@@ -0,0 +1,144 @@
+use serde_json::Value;
+
+/// Does a NIP-01 filter match an event? Unknown filter fields are ignored.
+pub fn matches(filter: &Value, event: &Value) -> bool {
+    let filter = if let Some(filter) = filter.as_object() {
+        filter
+    } else {
+        return false;
+    };
+
+    for (key, value) in filter {
+        let matched = match key.as_str() {
+            "ids" => any_prefix(value, &event["id"]),
+            "authors" => any_prefix(value, &event["pubkey"]),
+            "kinds" => contains(value, &event["kind"]),
+            "since" => compare(&event["created_at"], value, |created, since| {
+                created >= since
+            }),
+            "until" => compare(&event["created_at"], value, |created, until| {
+                created <= until
+            }),
+            "limit" | "search" => true,
+            tag if tag.starts_with('#') && tag.len() == 2 => has_tag(event, &tag[1..], value),
+            _ => true,
+        };
+
+        if !matched {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// The events matching any of the filters, newest first. A filter's
+/// limit only applies to the events it matched.
+pub fn query<'a>(events: &'a [Value], filters: &[Value]) -> Vec<&'a Value> {
+    let mut newest_first: Vec<&Value> = events.iter().collect();
+    newest_first.sort_by_key(|ev| std::cmp::Reverse(ev["created_at"].as_u64().unwrap_or(0)));
+
+    let mut results: Vec<&Value> = Vec::new();
+    for filter in filters {
+        let limit = filter["limit"].as_u64().map(|l| l as usize);
+        let matching = newest_first
+            .iter()
+            .filter(|ev| matches(filter, ev))
+            .take(limit.unwrap_or(usize::MAX));
+
+        for ev in matching {
+            if !results.iter().any(|r| r["id"] == ev["id"]) {
+                results.push(ev);
+            }
+        }
+    }
+
+    results.sort_by_key(|ev| std::cmp::Reverse(ev["created_at"].as_u64().unwrap_or(0)));
+    results
+}
+
+fn any_prefix(values: &Value, field: &Value) -> bool {
+    let field = if let Some(field) = field.as_str() {
+        field
+    } else {
+        return false;
+    };
+
+    values.as_array().is_some_and(|values| {
+        values
+            .iter()
+            .filter_map(|v| v.as_str())
+            .any(|v| field.starts_with(v))
+    })
+}
+
+fn contains(values: &Value, field: &Value) -> bool {
+    values
+        .as_array()
+        .is_some_and(|values| values.iter().any(|v| v == field))
+}
+
+fn compare(field: &Value, value: &Value, cmp: impl Fn(u64, u64) -> bool) -> bool {
+    match (field.as_u64(), value.as_u64()) {
+        (Some(field), Some(value)) => cmp(field, value),
+        _ => false,
+    }
+}
+
+fn has_tag(event: &Value, name: &str, values: &Value) -> bool {
+    let tags = if let Some(tags) = event["tags"].as_array() {
+        tags
+    } else {
+        return false;
+    };
+
+    tags.iter()
+        .any(|tag| tag[0].as_str() == Some(name) && contains(values, &tag[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn event(id: &str, kind: u64, created_at: u64) -> Value {
+        json!({
+            "id": id,
+            "pubkey": "aa",
+            "kind": kind,
+            "created_at": created_at,
+            "tags": [["e", "root"]],
+            "content": "",
+            "sig": "",
+        })
+    }
+
+    #[test]
+    fn test_matches() {
+        let ev = event("01", 1, 100);
+        assert!(matches(&json!({}), &ev));
+        assert!(matches(&json!({"kinds": [1, 7]}), &ev));
+        assert!(!matches(&json!({"kinds": [3]}), &ev));
+        assert!(matches(&json!({"authors": ["a"]}), &ev));
+        assert!(matches(&json!({"#e": ["root"]}), &ev));
+        assert!(!matches(&json!({"#e": ["other"]}), &ev));
+        assert!(!matches(&json!({"#p": ["root"]}), &ev));
+        assert!(matches(&json!({"since": 100, "until": 100}), &ev));
+        assert!(!matches(&json!({"since": 101}), &ev));
+    }
+
+    #[test]
+    fn test_query_limit() {
+        let events = vec![
+            event("01", 1, 100),
+            event("02", 1, 300),
+            event("03", 1, 200),
+        ];
+        let results = query(&events, &[json!({"kinds": [1], "limit": 2})]);
+        let ids: Vec<&str> = results
+            .iter()
+            .map(|ev| ev["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, vec!["02", "03"]);
+    }
+}
@@ -0,0 +1,243 @@
+//! A small nostr relay that runs in-process, for integration tests that
+//! need something to talk to over a real websocket. It keeps events in
+//! memory, answers REQ, CLOSE, EVENT, COUNT and AUTH, NIP-77 syncs when
+//! asked to, and can be told to misbehave with [`Faults`].
+//!
+//! Signatures are not checked. Anything that looks like an event is
+//! accepted, which lets tests hand it whatever they want.
+
+mod connection;
+mod filter;
+mod negentropy;
+mod socks;
+
+pub use filter::{matches, query};
+pub use socks::SocksProxy;
+
+use serde_json::Value;
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, error};
+
+/// Ways the relay can misbehave. Change them at any time with
+/// [`MockRelay::set_faults`], open connections pick them up right away.
+#[derive(Debug, Clone, Default)]
+pub struct Faults {
+    /// Wait this long before handling each message
+    pub delay: Duration,
+
+    /// Drop the connection after receiving this many messages on it
+    pub drop_after: Option<usize>,
+
+    /// Send a garbage text frame before every response
+    pub malformed: bool,
+
+    /// Reject every EVENT with this reason instead of storing it
+    pub reject_events: Option<String>,
+
+    /// Never send EOSE
+    pub no_eose: bool,
+}
+
+/// Things connections have to be told about
+#[derive(Debug, Clone)]
+enum Broadcast {
+    /// A new event was stored, send it to matching subscriptions
+    Event(Value),
+    /// Send this text to everyone
+    Raw(String),
+    /// Close the connection
+    Drop,
+}
+
+#[derive(Default)]
+struct State {
+    events: Vec<Value>,
+    faults: Faults,
+
+    /// When set, clients have to AUTH before REQ or EVENT
+    require_auth: bool,
+
+    /// Whether we answer NEG-OPEN, otherwise it's an unknown command
+    negentropy: bool,
+
+    /// Every text message we received, in order
+    received: Vec<String>,
+
+    connections: Vec<Sender<Broadcast>>,
+    open_connections: usize,
+    total_connections: usize,
+}
+
+impl State {
+    fn broadcast(&mut self, msg: Broadcast) {
+        self.connections
+            .retain(|conn| conn.send(msg.clone()).is_ok());
+    }
+}
+
+/// A relay listening on a random localhost port. It stops when dropped.
+pub struct MockRelay {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl MockRelay {
+    pub fn start() -> io::Result<Self> {
+        Self::start_on(([127, 0, 0, 1], 0).into())
+    }
+
+    /// Listen on a given address, for a relay that comes up late
+    pub fn start_on(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let state = Arc::new(Mutex::new(State::default()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread_state = state.clone();
+        let thread_shutdown = shutdown.clone();
+        let handle = thread::spawn(move || {
+            accept_loop(listener, thread_state, thread_shutdown);
+        });
+
+        debug!("mock relay listening on {}", addr);
+
+        Ok(MockRelay {
+            addr,
+            state,
+            shutdown,
+            listener: Some(handle),
+        })
+    }
+
+    /// The websocket url, with the trailing slash the pool adds
+    pub fn url(&self) -> String {
+        format!("ws://{}/", self.addr)
+    }
+
+    /// Store an event, as if someone had published it
+    pub fn add_event(&self, event: Value) {
+        let mut state = self.state.lock().unwrap();
+        state.events.push(event.clone());
+        state.broadcast(Broadcast::Event(event));
+    }
+
+    /// Store an event from its json
+    pub fn add_event_json(&self, json: &str) -> serde_json::Result<()> {
+        self.add_event(serde_json::from_str(json)?);
+        Ok(())
+    }
+
+    pub fn events(&self) -> Vec<Value> {
+        self.state.lock().unwrap().events.clone()
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        self.state.lock().unwrap().faults = faults;
+    }
+
+    /// Make clients answer an AUTH challenge before anything else works.
+    /// Only new connections get a challenge.
+    pub fn require_auth(&self, required: bool) {
+        self.state.lock().unwrap().require_auth = required;
+    }
+
+    /// Answer NIP-77 negentropy syncs, which we don't by default
+    pub fn support_negentropy(&self, supported: bool) {
+        self.state.lock().unwrap().negentropy = supported;
+    }
+
+    /// Send a NOTICE to everyone connected
+    pub fn notice(&self, msg: &str) {
+        self.send_raw(&serde_json::json!(["NOTICE", msg]).to_string());
+    }
+
+    /// Send some text to everyone connected, valid nostr or not
+    pub fn send_raw(&self, txt: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .broadcast(Broadcast::Raw(txt.to_owned()));
+    }
+
+    /// Close every open connection
+    pub fn drop_connections(&self) {
+        self.state.lock().unwrap().broadcast(Broadcast::Drop);
+    }
+
+    /// Every text message the relay received so far
+    pub fn received(&self) -> Vec<String> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// The received messages of a type, parsed. `"REQ"` gives all of the
+    /// REQs for example.
+    pub fn received_of(&self, cmd: &str) -> Vec<Value> {
+        self.received()
+            .iter()
+            .filter_map(|msg| serde_json::from_str::<Value>(msg).ok())
+            .filter(|msg| msg[0].as_str() == Some(cmd))
+            .collect()
+    }
+
+    /// How many connections are open right now
+    pub fn connections(&self) -> usize {
+        self.state.lock().unwrap().open_connections
+    }
+
+    /// How many connections were ever made
+    pub fn total_connections(&self) -> usize {
+        self.state.lock().unwrap().total_connections
+    }
+
+    /// Wait until `cond` is true, or give up after `timeout`
+    pub fn wait_for(&self, timeout: Duration, mut cond: impl FnMut(&MockRelay) -> bool) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            if cond(self) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        cond(self)
+    }
+}
+
+impl Drop for MockRelay {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        self.drop_connections();
+        if let Some(handle) = self.listener.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn accept_loop(listener: TcpListener, state: Arc<Mutex<State>>, shutdown: Arc<AtomicBool>) {
+    while !shutdown.load(Ordering::SeqCst) {
+        match listener.accept() {
+            Ok((stream, addr)) => {
+                debug!("mock relay: connection from {}", addr);
+                let state = state.clone();
+                let shutdown = shutdown.clone();
+                thread::spawn(move || connection::run(stream, state, shutdown));
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(5));
+            }
+            Err(e) => {
+                error!("mock relay: accept failed: {}", e);
+                return;
+            }
+        }
+    }
+}
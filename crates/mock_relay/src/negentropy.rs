@@ -0,0 +1,46 @@
+//! Just enough NIP-77 to answer a sync. We skip the fingerprint rounds
+//! and send every id we have for the filter at once, as a single IdList
+//! range. That's a valid answer, real relays only narrow things down
+//! first to save bandwidth.
+
+/// The first byte of every message, as hex
+pub const PROTOCOL_VERSION: &str = "61";
+
+/// A message listing `ids`, hex encoded, for the whole range
+pub fn id_list(ids: &[String]) -> String {
+    // the bound at infinity is timestamp 0 with no id prefix, then the
+    // IdList mode
+    let mut msg = format!("{}000002", PROTOCOL_VERSION);
+    msg.push_str(&varint(ids.len()));
+    for id in ids {
+        msg.push_str(id);
+    }
+    msg
+}
+
+/// Base 128, most significant group first, as hex
+fn varint(mut n: usize) -> String {
+    let mut bytes = vec![(n & 0x7f) as u8];
+    n >>= 7;
+    while n > 0 {
+        bytes.push((n & 0x7f) as u8 | 0x80);
+        n >>= 7;
+    }
+    bytes.iter().rev().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_list() {
+        assert_eq!(id_list(&[]), "6100000200");
+
+        let id = "ab".repeat(32);
+        assert_eq!(id_list(&[id.clone()]), format!("6100000201{}", id));
+
+        let ids = vec![id; 200];
+        assert!(id_list(&ids).starts_with("610000028148"));
+    }
+}
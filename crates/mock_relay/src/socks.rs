@@ -0,0 +1,146 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{debug, error};
+
+/// A SOCKS5 proxy on a random localhost port, without authentication. It
+/// remembers where it was asked to connect to, so tests can check that
+/// traffic went through it. It stops when dropped.
+pub struct SocksProxy {
+    addr: SocketAddr,
+    targets: Arc<Mutex<Vec<String>>>,
+    shutdown: Arc<AtomicBool>,
+    listener: Option<JoinHandle<()>>,
+}
+
+impl SocksProxy {
+    pub fn start() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+
+        let targets = Arc::new(Mutex::new(Vec::new()));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread_targets = targets.clone();
+        let thread_shutdown = shutdown.clone();
+        let handle = thread::spawn(move || {
+            while !thread_shutdown.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let targets = thread_targets.clone();
+                        thread::spawn(move || {
+                            if let Err(err) = handle_client(stream, targets) {
+                                debug!("socks proxy: {}", err);
+                            }
+                        });
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(5));
+                    }
+                    Err(e) => {
+                        error!("socks proxy: accept failed: {}", e);
+                        return;
+                    }
+                }
+            }
+        });
+
+        debug!("socks proxy listening on {}", addr);
+
+        Ok(SocksProxy {
+            addr,
+            targets,
+            shutdown,
+            listener: Some(handle),
+        })
+    }
+
+    /// `host:port`, the way proxy settings want it
+    pub fn addr(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// Every `host:port` we were asked to connect to
+    pub fn targets(&self) -> Vec<String> {
+        self.targets.lock().unwrap().clone()
+    }
+}
+
+impl Drop for SocksProxy {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(handle) = self.listener.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn handle_client(mut client: TcpStream, targets: Arc<Mutex<Vec<String>>>) -> io::Result<()> {
+    client.set_nonblocking(false)?;
+
+    // version and auth methods, we only do "none"
+    let mut header = [0u8; 2];
+    client.read_exact(&mut header)?;
+    let mut methods = vec![0u8; header[1] as usize];
+    client.read_exact(&mut methods)?;
+    if header[0] != 5 || !methods.contains(&0) {
+        client.write_all(&[5, 0xff])?;
+        return Ok(());
+    }
+    client.write_all(&[5, 0])?;
+
+    let mut req = [0u8; 4];
+    client.read_exact(&mut req)?;
+    let host = match req[3] {
+        1 => {
+            let mut ip = [0u8; 4];
+            client.read_exact(&mut ip)?;
+            std::net::Ipv4Addr::from(ip).to_string()
+        }
+        3 => {
+            let mut len = [0u8; 1];
+            client.read_exact(&mut len)?;
+            let mut name = vec![0u8; len[0] as usize];
+            client.read_exact(&mut name)?;
+            String::from_utf8_lossy(&name).into_owned()
+        }
+        4 => {
+            let mut ip = [0u8; 16];
+            client.read_exact(&mut ip)?;
+            format!("[{}]", std::net::Ipv6Addr::from(ip))
+        }
+        _ => return Ok(()),
+    };
+    let mut port = [0u8; 2];
+    client.read_exact(&mut port)?;
+    let target = format!("{}:{}", host, u16::from_be_bytes(port));
+    targets.lock().unwrap().push(target.clone());
+
+    let upstream = match TcpStream::connect(&target) {
+        Ok(upstream) => upstream,
+        Err(err) => {
+            // connection refused
+            client.write_all(&[5, 5, 0, 1, 0, 0, 0, 0, 0, 0])?;
+            return Err(err);
+        }
+    };
+    client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])?;
+
+    let mut client_read = client.try_clone()?;
+    let mut upstream_write = upstream.try_clone()?;
+    let to_upstream = thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut upstream_write);
+        let _ = upstream_write.shutdown(Shutdown::Write);
+    });
+
+    let mut upstream_read = upstream;
+    let _ = io::copy(&mut upstream_read, &mut client);
+    let _ = client.shutdown(Shutdown::Write);
+    let _ = to_upstream.join();
+
+    Ok(())
+}
@@ -1,12 +1,21 @@
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
+use crate::muted::decrypt_private_tags;
+use crate::note::tag_strings;
+use crate::signing::{Answer, Bunkers, Destination, SignError};
 use crate::{
-    KeyStorageResponse, KeyStorageType, Muted, SingleUnkIdAction, UnknownIds, UserAccount,
+    outbox, Contacts, Directory, KeyStorageResponse, KeyStorageType, KeyStoreUpdate, Mute, MuteFun,
+    Muted, NoteCache, RelaySpec, SingleUnkIdAction, UnknownIds, UserAccount,
+};
+use enostr::nip46::BunkerSession;
+use enostr::{
+    auth_note_builder, ClientMessage, Crypt, Encryption, FilledKeypair, Keypair, NoteId, Pubkey,
+    RelayPool, Signature, Signer, UnsignedNote,
 };
-use enostr::{ClientMessage, FilledKeypair, Keypair, RelayPool};
 use nostrdb::{Filter, Ndb, Note, NoteKey, Subscription, Transaction};
+use poll_promise::Promise;
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use url::Url;
 use uuid::Uuid;
 
@@ -23,8 +32,12 @@ pub struct AccountRelayData {
     filter: Filter,
     subid: String,
     sub: Option<Subscription>,
-    local: BTreeSet<String>,      // used locally but not advertised
-    advertised: BTreeSet<String>, // advertised via NIP-65
+    local: BTreeSet<String>,         // used locally but not advertised
+    advertised: BTreeSet<RelaySpec>, // advertised via NIP-65
+    /// Set once a relay has sent us its newest list or told us it has
+    /// none. Until then the list in ndb may be stale or missing, and
+    /// publishing over it would lose relays.
+    fetched: bool,
 }
 
 #[derive(Default)]
@@ -81,6 +94,7 @@ impl AccountRelayData {
             sub: Some(ndbsub),
             local: BTreeSet::new(),
             advertised: relays.into_iter().collect(),
+            fetched: false,
         }
     }
 
@@ -92,28 +106,11 @@ impl AccountRelayData {
         }
     }
 
-    fn harvest_nip65_relays(ndb: &Ndb, txn: &Transaction, nks: &[NoteKey]) -> Vec<String> {
+    fn harvest_nip65_relays(ndb: &Ndb, txn: &Transaction, nks: &[NoteKey]) -> Vec<RelaySpec> {
         let mut relays = Vec::new();
         for nk in nks.iter() {
             if let Ok(note) = ndb.get_note_by_key(txn, *nk) {
-                for tag in note.tags() {
-                    match tag.get(0).and_then(|t| t.variant().str()) {
-                        Some("r") => {
-                            if let Some(url) = tag.get(1).and_then(|f| f.variant().str()) {
-                                relays.push(Self::canonicalize_url(url));
-                            }
-                        }
-                        Some("alt") => {
-                            // ignore for now
-                        }
-                        Some(x) => {
-                            error!("harvest_nip65_relays: unexpected tag type: {}", x);
-                        }
-                        None => {
-                            error!("harvest_nip65_relays: invalid tag");
-                        }
-                    }
-                }
+                relays.extend(outbox::nip65_relays(&note));
             }
         }
         relays
@@ -125,10 +122,22 @@ pub struct AccountMutedData {
     subid: String,
     sub: Option<Subscription>,
     muted: Arc<Muted>,
+    /// When the newest list we've seen was made, None if we haven't seen one
+    created_at: Option<u64>,
+    /// Set once a relay has sent us its newest list or told us it has
+    /// none. Until then the list in ndb may be stale, and publishing over
+    /// it would lose mutes.
+    fetched: bool,
 }
 
 impl AccountMutedData {
-    pub fn new(ndb: &Ndb, pool: &mut RelayPool, pubkey: &[u8; 32]) -> Self {
+    /// With a secret key we can read the private part of the list too
+    pub fn new(
+        ndb: &Ndb,
+        pool: &mut RelayPool,
+        pubkey: &[u8; 32],
+        keypair: Option<FilledKeypair>,
+    ) -> Self {
         // Construct a filter for the user's NIP-51 muted list
         let filter = Filter::new()
             .authors([pubkey])
@@ -150,7 +159,10 @@ impl AccountMutedData {
             .iter()
             .map(|qr| qr.note_key)
             .collect::<Vec<NoteKey>>();
-        let muted = Self::harvest_nip51_muted(ndb, &txn, &nks);
+        let (created_at, muted) = match Self::harvest_nip51_muted(ndb, &txn, &nks, keypair, None) {
+            Some((created_at, muted)) => (Some(created_at), muted),
+            None => (None, Muted::default()),
+        };
         debug!("pubkey {}: initial muted {:?}", hex::encode(pubkey), muted);
 
         // Id for future remote relay subscriptions
@@ -164,54 +176,154 @@ impl AccountMutedData {
             subid,
             sub: Some(ndbsub),
             muted: Arc::new(muted),
+            created_at,
+            fetched: false,
         }
     }
 
-    fn harvest_nip51_muted(ndb: &Ndb, txn: &Transaction, nks: &[NoteKey]) -> Muted {
-        let mut muted = Muted::default();
+    /// Read the list again, say now that we can decrypt its private part
+    fn refresh(&mut self, ndb: &Ndb, keypair: Option<FilledKeypair>) {
+        let txn = Transaction::new(ndb).expect("transaction");
+        let lim = self
+            .filter
+            .limit()
+            .unwrap_or(crate::filter::default_limit()) as i32;
+        let nks = ndb
+            .query(&txn, &[self.filter.clone()], lim)
+            .expect("query user muted results")
+            .iter()
+            .map(|qr| qr.note_key)
+            .collect::<Vec<NoteKey>>();
+        if let Some((created_at, muted)) = Self::harvest_nip51_muted(ndb, &txn, &nks, keypair, None)
+        {
+            self.created_at = Some(created_at);
+            self.muted = Arc::new(muted);
+        }
+    }
+
+    /// The newest list out of `nks`, if it's newer than the one made at
+    /// `since`, along with when it was made
+    fn harvest_nip51_muted(
+        ndb: &Ndb,
+        txn: &Transaction,
+        nks: &[NoteKey],
+        keypair: Option<FilledKeypair>,
+        since: Option<u64>,
+    ) -> Option<(u64, Muted)> {
+        let mut latest: Option<Note> = None;
         for nk in nks.iter() {
             if let Ok(note) = ndb.get_note_by_key(txn, *nk) {
-                for tag in note.tags() {
-                    match tag.get(0).and_then(|t| t.variant().str()) {
-                        Some("p") => {
-                            if let Some(id) = tag.get(1).and_then(|f| f.variant().id()) {
-                                muted.pubkeys.insert(*id);
-                            }
-                        }
-                        Some("t") => {
-                            if let Some(str) = tag.get(1).and_then(|f| f.variant().str()) {
-                                muted.hashtags.insert(str.to_string());
-                            }
-                        }
-                        Some("word") => {
-                            if let Some(str) = tag.get(1).and_then(|f| f.variant().str()) {
-                                muted.words.insert(str.to_string());
-                            }
-                        }
-                        Some("e") => {
-                            if let Some(id) = tag.get(1).and_then(|f| f.variant().id()) {
-                                muted.threads.insert(*id);
-                            }
-                        }
-                        Some("alt") => {
-                            // maybe we can ignore these?
-                        }
-                        Some(x) => error!("query_nip51_muted: unexpected tag: {}", x),
-                        None => error!(
-                            "query_nip51_muted: bad tag value: {:?}",
-                            tag.get_unchecked(0).variant()
-                        ),
-                    }
+                let newest = latest
+                    .as_ref()
+                    .map_or(since, |latest| Some(latest.created_at()));
+                if newest.map_or(true, |created_at| note.created_at() > created_at) {
+                    latest = Some(note);
                 }
             }
         }
-        muted
+        let note = latest?;
+
+        let public_tags = note.tags().iter().map(|tag| tag_strings(&tag)).collect();
+        let content = note.content();
+        let (private_tags, sealed) =
+            if let Some(tags) = keypair.and_then(|kp| decrypt_private_tags(kp, content)) {
+                (tags, None)
+            } else if !content.is_empty() {
+                // keep what we can't read so editing the list doesn't drop it
+                (Vec::new(), Some(content.to_owned()))
+            } else {
+                (Vec::new(), None)
+            };
+
+        let muted = Muted::from_tags(public_tags, private_tags);
+        let muted = if let Some(content) = sealed {
+            muted.with_sealed(content)
+        } else {
+            muted
+        };
+        Some((note.created_at(), muted))
+    }
+}
+
+pub struct AccountContactsData {
+    filter: Filter,
+    subid: String,
+    sub: Option<Subscription>,
+    /// The newest list we've seen, None if we haven't seen one
+    contacts: Option<Contacts>,
+    /// Set once a relay has sent us its newest list or told us it has
+    /// none. Until then the list in ndb may be stale, and publishing over
+    /// it would lose follows.
+    fetched: bool,
+}
+
+impl AccountContactsData {
+    pub fn new(ndb: &Ndb, pool: &mut RelayPool, pubkey: &[u8; 32]) -> Self {
+        // Construct a filter for the user's kind 3 contact list
+        let filter = Filter::new().authors([pubkey]).kinds([3]).limit(1).build();
+
+        // Local ndb subscription
+        let ndbsub = ndb
+            .subscribe(&[filter.clone()])
+            .expect("ndb contacts subscription");
+
+        // Query the ndb immediately to see if the user's contact list is already there
+        let txn = Transaction::new(ndb).expect("transaction");
+        let lim = filter.limit().unwrap_or(crate::filter::default_limit()) as i32;
+        let nks = ndb
+            .query(&txn, &[filter.clone()], lim)
+            .expect("query user contacts results")
+            .iter()
+            .map(|qr| qr.note_key)
+            .collect::<Vec<NoteKey>>();
+        let contacts = Self::harvest_contacts(ndb, &txn, &nks, None);
+        debug!(
+            "pubkey {}: initial contacts {:?}",
+            hex::encode(pubkey),
+            contacts.as_ref().map(|c| c.len())
+        );
+
+        // Id for future remote relay subscriptions
+        let subid = Uuid::new_v4().to_string();
+
+        // Add remote subscription to existing relays
+        pool.subscribe(subid.clone(), vec![filter.clone()]);
+
+        AccountContactsData {
+            filter,
+            subid,
+            sub: Some(ndbsub),
+            contacts,
+            fetched: false,
+        }
+    }
+
+    /// The newest list out of `nks` and the one we already have
+    fn harvest_contacts(
+        ndb: &Ndb,
+        txn: &Transaction,
+        nks: &[NoteKey],
+        current: Option<Contacts>,
+    ) -> Option<Contacts> {
+        let mut latest = current;
+        for nk in nks.iter() {
+            if let Ok(note) = ndb.get_note_by_key(txn, *nk) {
+                if latest
+                    .as_ref()
+                    .map_or(true, |c| note.created_at() > c.created_at)
+                {
+                    latest = Some(Contacts::from_note(&note));
+                }
+            }
+        }
+        latest
     }
 }
 
 pub struct AccountData {
     relay: AccountRelayData,
     muted: AccountMutedData,
+    contacts: AccountContactsData,
 }
 
 /// The interface for managing the user's accounts.
@@ -223,7 +335,15 @@ pub struct Accounts {
     account_data: BTreeMap<[u8; 32], AccountData>,
     forced_relays: BTreeSet<String>,
     bootstrap_relays: BTreeSet<String>,
+    outbox_relays: BTreeSet<String>,
     needs_relay_config: bool,
+    /// Where each account's local relays are saved, if anywhere
+    relay_storage: Option<Directory>,
+    /// Remote signers for the accounts we don't have keys for
+    bunkers: Bunkers,
+    /// Mute lists waiting on a remote signer to encrypt their private
+    /// mutes, by request id
+    muted_edits: HashMap<String, (Pubkey, Muted)>,
 }
 
 impl Accounts {
@@ -259,7 +379,84 @@ impl Accounts {
             account_data,
             forced_relays,
             bootstrap_relays,
+            outbox_relays: BTreeSet::new(),
             needs_relay_config: true,
+            relay_storage: None,
+            bunkers: Bunkers::default(),
+            muted_edits: HashMap::new(),
+        }
+    }
+
+    /// Save local relays in `directory`, one file per account
+    pub fn set_relay_storage(&mut self, directory: Directory) {
+        self.relay_storage = Some(directory);
+    }
+
+    /// Save remote signer sessions in `directory`, one file per account
+    pub fn set_bunker_storage(&mut self, directory: Directory) {
+        self.bunkers.set_storage(directory);
+    }
+
+    /// Whether the stored secret keys are encrypted and we don't have the
+    /// passphrase yet. Until then, those accounts can't sign.
+    pub fn is_locked(&self) -> bool {
+        self.key_store.is_locked()
+    }
+
+    /// Whether the stored secret keys can be encrypted with a passphrase
+    pub fn can_encrypt_keys(&self) -> bool {
+        self.key_store.can_encrypt()
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.key_store.is_encrypted()
+    }
+
+    /// Unlock the stored secret keys with `passphrase`, on another thread.
+    /// Hand the result to [`Accounts::key_store_updated`] once it's ready.
+    pub fn unlock(&self, passphrase: &str) -> Promise<crate::Result<KeyStoreUpdate>> {
+        self.key_store.unlock(passphrase)
+    }
+
+    /// Encrypt the stored secret keys with `passphrase` from now on, on
+    /// another thread like [`Accounts::unlock`]
+    pub fn change_passphrase(&self, passphrase: &str) -> Promise<crate::Result<KeyStoreUpdate>> {
+        self.key_store.change_passphrase(passphrase)
+    }
+
+    /// Take on the unlocked or re-encrypted key storage, and give the
+    /// secret keys back to the accounts that were loaded without them
+    pub fn key_store_updated(&mut self, ndb: &Ndb, update: KeyStoreUpdate) {
+        for key in self.key_store.apply(update) {
+            if key.secret_key.is_none() {
+                continue;
+            }
+
+            let account = if let Some(account) = self
+                .accounts
+                .iter_mut()
+                .find(|acc| acc.pubkey == key.pubkey && acc.secret_key.is_none())
+            {
+                account
+            } else {
+                continue;
+            };
+
+            info!("unlocked secret key for {}", key.pubkey);
+            *account = key;
+            if let Some(data) = self.account_data.get_mut(account.pubkey.bytes()) {
+                data.muted.refresh(ndb, account.to_full());
+            }
+        }
+
+        // remote signer client keys are encrypted with the same passphrase
+        if let Some(cipher) = self.key_store.key_cipher() {
+            self.bunkers.save_all(&cipher);
+            for account in &self.accounts {
+                if account.secret_key.is_none() {
+                    self.bunkers.restore(&account.pubkey, &cipher);
+                }
+            }
         }
     }
 
@@ -324,7 +521,11 @@ impl Accounts {
                     "user provided nsec, but we already have npub {}. Upgrading to nsec",
                     pubkey
                 );
-                let _ = self.key_store.add_key(&account);
+                if let KeyStorageResponse::ReceivedResult(Err(err)) =
+                    self.key_store.add_key(&account)
+                {
+                    error!("could not store the secret key: {}", err);
+                }
 
                 self.accounts[contains_acc.index] = account;
             } else {
@@ -333,7 +534,9 @@ impl Accounts {
             contains_acc.index
         } else {
             info!("adding new account {}", pubkey);
-            let _ = self.key_store.add_key(&account);
+            if let KeyStorageResponse::ReceivedResult(Err(err)) = self.key_store.add_key(&account) {
+                error!("could not store account: {}", err);
+            }
             self.accounts.push(account);
             self.accounts.len() - 1
         };
@@ -344,6 +547,16 @@ impl Accounts {
         }
     }
 
+    /// Add the account a connected remote signer signs for. Its notes
+    /// are signed by the signer from now on.
+    #[must_use = "UnknownIdAction's must be handled. Use .process_unknown_id_action()"]
+    pub fn add_bunker_account(&mut self, session: BunkerSession) -> AddAccountAction {
+        let pubkey = *session.user().expect("only connected signers log in");
+        let cipher = self.key_store.key_cipher();
+        self.bunkers.add(session, cipher.as_ref());
+        self.add_account(Keypair::only_pubkey(pubkey))
+    }
+
     pub fn num_accounts(&self) -> usize {
         self.accounts.len()
     }
@@ -358,6 +571,220 @@ impl Accounts {
             .or_else(|| self.accounts.iter().find_map(|a| a.to_full()))
     }
 
+    /// Whether we can sign for `pubkey`, with its secret key or a remote
+    /// signer
+    pub fn can_sign(&self, pubkey: &Pubkey) -> bool {
+        let has_nsec = self
+            .find_account(pubkey.bytes())
+            .is_some_and(|acc| acc.secret_key.is_some());
+        has_nsec || self.bunkers.has(pubkey)
+    }
+
+    /// The selected account, if we can sign for it
+    pub fn selected_signer(&self) -> Option<Pubkey> {
+        self.get_selected_account()
+            .map(|acc| acc.pubkey)
+            .filter(|pk| self.can_sign(pk))
+    }
+
+    /// Like [`Accounts::selected_or_first_nsec`], but remote signers count
+    pub fn selected_or_first_signer(&self) -> Option<Pubkey> {
+        self.selected_signer().or_else(|| {
+            self.accounts
+                .iter()
+                .map(|acc| acc.pubkey)
+                .find(|pk| self.can_sign(pk))
+        })
+    }
+
+    /// Sign `note` as its author and send it to `destination`. Notes we
+    /// have the key for go out right away. The others are sent once
+    /// their remote signer answers, see [`Accounts::update`].
+    pub fn sign_and_send(
+        &mut self,
+        ndb: &Ndb,
+        pool: &mut RelayPool,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+        note: UnsignedNote,
+        destination: Destination,
+    ) -> Result<(), SignError> {
+        if let Some(note) = self.sign(note, &destination)? {
+            self.send_signed(ndb, pool, wakeup, note, destination);
+        }
+        Ok(())
+    }
+
+    /// Signed right away, or None if a remote signer got the request
+    fn sign(
+        &mut self,
+        note: UnsignedNote,
+        destination: &Destination,
+    ) -> Result<Option<enostr::Note>, SignError> {
+        let id = note.id();
+        let author = note.pubkey;
+        match self.with_signer(&author, |signer| signer.sign(note))? {
+            Signature::Signed(note) => Ok(Some(note)),
+            Signature::Requested(request) => {
+                info!("asked the remote signer to sign {}", id.hex());
+                self.bunkers.requested(request, destination.clone());
+                Ok(None)
+            }
+        }
+    }
+
+    /// Sign `note` as its author without sending it anywhere. A remote
+    /// signer's answer is picked up with [`Accounts::take_answer`].
+    pub fn sign_unsent(&mut self, note: UnsignedNote) -> Result<Signature, SignError> {
+        let author = note.pubkey;
+        let signature = self.with_signer(&author, |signer| signer.sign(note))?;
+        if let Signature::Requested(request) = &signature {
+            self.bunkers.awaited(request.clone());
+        }
+        Ok(signature)
+    }
+
+    /// Encrypt `plaintext` as `account` so only `to` and they can read
+    /// it. A remote signer's answer is picked up with
+    /// [`Accounts::take_answer`].
+    pub fn encrypt(
+        &mut self,
+        account: &Pubkey,
+        encryption: Encryption,
+        to: &Pubkey,
+        plaintext: &str,
+    ) -> Result<Crypt, SignError> {
+        let crypt =
+            self.with_signer(account, |signer| signer.encrypt(encryption, to, plaintext))?;
+        self.await_crypt(&crypt);
+        Ok(crypt)
+    }
+
+    /// Decrypt what `from` and `account` sent each other, like
+    /// [`Accounts::encrypt`]
+    pub fn decrypt(
+        &mut self,
+        account: &Pubkey,
+        encryption: Encryption,
+        from: &Pubkey,
+        ciphertext: &str,
+    ) -> Result<Crypt, SignError> {
+        let crypt = self.with_signer(account, |signer| {
+            signer.decrypt(encryption, from, ciphertext)
+        })?;
+        self.await_crypt(&crypt);
+        Ok(crypt)
+    }
+
+    fn await_crypt(&mut self, crypt: &Crypt) {
+        if let Crypt::Requested(request) = crypt {
+            self.bunkers.awaited(request.clone());
+        }
+    }
+
+    /// The remote signer's answer to a request from
+    /// [`Accounts::sign_unsent`], [`Accounts::encrypt`] or
+    /// [`Accounts::decrypt`], once it's here
+    pub fn take_answer(&mut self, request: &str) -> Option<Answer> {
+        self.bunkers.take_answer(request)
+    }
+
+    /// Where the user approves what their remote signers are holding
+    /// back. Nothing gets signed until they do.
+    pub fn signer_approval_urls(&self) -> Vec<String> {
+        self.bunkers
+            .approval_urls()
+            .into_iter()
+            .map(str::to_owned)
+            .collect()
+    }
+
+    /// Hand `account`'s signer to `f`: its secret key if we have it,
+    /// otherwise its remote signer
+    fn with_signer<R>(
+        &mut self,
+        account: &Pubkey,
+        f: impl FnOnce(&mut dyn Signer) -> enostr::Result<R>,
+    ) -> Result<R, SignError> {
+        let result = if let Some(mut keypair) = self
+            .accounts
+            .iter()
+            .find(|acc| acc.pubkey == *account)
+            .and_then(|acc| acc.to_full())
+        {
+            f(&mut keypair)
+        } else if let Some(session) = self.bunkers.get_mut(account) {
+            f(session)
+        } else {
+            return Err(SignError::NoSigner);
+        };
+
+        result.map_err(|err| SignError::Sign(err.to_string()))
+    }
+
+    fn send_signed(
+        &mut self,
+        ndb: &Ndb,
+        pool: &mut RelayPool,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+        note: enostr::Note,
+        destination: Destination,
+    ) {
+        let targets = match destination {
+            Destination::Auth(relay) => {
+                pool.send_auth(&relay, note);
+                return;
+            }
+            Destination::Relays(targets) => targets,
+            Destination::Outbox => {
+                let tagged: Vec<Pubkey> = note
+                    .tags
+                    .iter()
+                    .filter(|tag| tag.len() >= 2 && tag[0] == "p")
+                    .filter_map(|tag| Pubkey::from_hex(&tag[1]).ok())
+                    .collect();
+                let txn = Transaction::new(ndb).expect("txn");
+                let inboxes = outbox::inbox_relays(ndb, &txn, tagged.iter().map(|pk| pk.bytes()));
+                self.add_outbox_relays(pool, inboxes.iter().cloned(), wakeup);
+
+                let mut targets: BTreeSet<String> = self.list_targets(pool).into_iter().collect();
+                targets.extend(inboxes);
+                targets.into_iter().collect()
+            }
+        };
+
+        debug!("publishing {} to {:?}", note.id.hex(), targets);
+        let json = serde_json::to_string(&note).expect("note json");
+        store_and_publish(ndb, pool, &targets, note.id, &json);
+    }
+
+    /// Answer `relay`'s auth challenge as the selected account, through
+    /// its remote signer if that's what it has
+    pub fn authenticate(&mut self, pool: &mut RelayPool, relay: &str, challenge: &str) {
+        let pubkey = if let Some(pubkey) = self.selected_signer() {
+            pubkey
+        } else {
+            pool.authenticate(relay, challenge, None);
+            return;
+        };
+
+        let note = match UnsignedNote::from_builder(pubkey, auth_note_builder(relay, challenge)) {
+            Ok(note) => note,
+            Err(err) => {
+                error!("could not build auth note for {}: {}", relay, err);
+                return;
+            }
+        };
+        let id = note.id();
+
+        let destination = Destination::Auth(relay.to_owned());
+        match self.sign(note, &destination) {
+            Ok(Some(note)) => pool.send_auth(relay, note),
+            // hold back our REQs until the signer answers
+            Ok(None) => pool.auth_pending(relay, id),
+            Err(err) => error!("could not authenticate with {}: {}", relay, err),
+        }
+    }
+
     pub fn get_selected_account(&self) -> Option<&UserAccount> {
         if let Some(account_index) = self.currently_selected_account {
             if let Some(account) = self.get_account(account_index) {
@@ -382,17 +809,479 @@ impl Accounts {
         self.key_store.select_key(None);
     }
 
-    pub fn mutefun(&self) -> Box<dyn Fn(&Note) -> bool> {
+    /// The NIP-65 relays of the selected account
+    pub fn get_selected_account_relays(&self) -> Option<&BTreeSet<RelaySpec>> {
+        let account = self.get_selected_account()?;
+        self.account_data
+            .get(account.pubkey.bytes())
+            .map(|data| &data.relay.advertised)
+    }
+
+    /// Whether a relay has sent us the selected account's NIP-65 list, or
+    /// told us it has none. Until then the list can't be edited.
+    pub fn selected_account_relays_fetched(&self) -> bool {
+        self.get_selected_account()
+            .and_then(|account| self.account_data.get(account.pubkey.bytes()))
+            .is_some_and(|data| data.relay.fetched)
+    }
+
+    /// The selected account's relays that aren't in its NIP-65 list
+    pub fn get_selected_account_local_relays(&self) -> Option<&BTreeSet<String>> {
+        let account = self.get_selected_account()?;
+        self.account_data
+            .get(account.pubkey.bytes())
+            .map(|data| &data.relay.local)
+    }
+
+    /// Add `spec` to the selected account's NIP-65 list, or change its
+    /// markers if it's already there, and publish the new list
+    pub fn advertise_relay(&mut self, ndb: &Ndb, pool: &mut RelayPool, spec: RelaySpec) -> bool {
+        self.edit_advertised(ndb, pool, |relays| {
+            relays.replace(spec);
+        })
+    }
+
+    /// Remove `url` from the selected account's NIP-65 list and publish
+    /// the new list
+    pub fn unadvertise_relay(&mut self, ndb: &Ndb, pool: &mut RelayPool, url: &str) -> bool {
+        self.edit_advertised(ndb, pool, |relays| {
+            relays.retain(|spec| spec.url != url);
+        })
+    }
+
+    fn edit_advertised(
+        &mut self,
+        ndb: &Ndb,
+        pool: &mut RelayPool,
+        edit: impl FnOnce(&mut BTreeSet<RelaySpec>),
+    ) -> bool {
+        let pubkey = if let Some(pubkey) = self.selected_signer() {
+            pubkey
+        } else {
+            return false;
+        };
+        let targets = self.list_targets(pool);
+        let data = if let Some(data) = self.account_data.get(pubkey.bytes()) {
+            data
+        } else {
+            return false;
+        };
+        if !data.relay.fetched {
+            warn!("can't edit the relay list before a relay has sent it");
+            return false;
+        }
+
+        // The relays told us there's no list and we've been using the
+        // bootstrap relays, start from those rather than publishing a
+        // list with a single relay
+        let mut advertised = if data.relay.advertised.is_empty() {
+            self.bootstrap_relays
+                .iter()
+                .map(|url| RelaySpec::new(url.clone(), false, false))
+                .collect()
+        } else {
+            data.relay.advertised.clone()
+        };
+        edit(&mut advertised);
+
+        let note = match UnsignedNote::from_builder(pubkey, outbox::nip65_note(&advertised)) {
+            Ok(note) => note,
+            Err(err) => {
+                error!("could not build relay list: {}", err);
+                return false;
+            }
+        };
+
+        info!("publishing relay list {}", note.id().hex());
+        if let Err(err) = self.sign_and_send(ndb, pool, || {}, note, Destination::Relays(targets)) {
+            error!("could not publish relay list: {}", err);
+            return false;
+        }
+
+        if let Some(data) = self.account_data.get_mut(pubkey.bytes()) {
+            data.relay.advertised = advertised;
+        }
+        self.needs_relay_config = true;
+        true
+    }
+
+    /// Use `url` for the selected account without advertising it
+    pub fn add_local_relay(&mut self, url: String) -> bool {
+        self.edit_local(|local| local.insert(url))
+    }
+
+    pub fn remove_local_relay(&mut self, url: &str) -> bool {
+        self.edit_local(|local| local.remove(url))
+    }
+
+    fn edit_local(&mut self, edit: impl FnOnce(&mut BTreeSet<String>) -> bool) -> bool {
+        let pubkey = if let Some(account) = self.get_selected_account() {
+            *account.pubkey.bytes()
+        } else {
+            return false;
+        };
+        let data = if let Some(data) = self.account_data.get_mut(&pubkey) {
+            data
+        } else {
+            return false;
+        };
+
+        if !edit(&mut data.relay.local) {
+            return false;
+        }
+
+        if let Some(storage) = &self.relay_storage {
+            if let Err(err) = save_local_relays(storage, &pubkey, &data.relay.local) {
+                error!("could not save local relays: {}", err);
+            }
+        }
+
+        self.needs_relay_config = true;
+        true
+    }
+
+    /// Add relays that we only connect to for reading other people's
+    /// notes or delivering to their inboxes. These are connected right
+    /// away and stay in the pool along with the account relays. When
+    /// relays are forced on the command line, we stick to those.
+    pub fn add_outbox_relays(
+        &mut self,
+        pool: &mut RelayPool,
+        urls: impl IntoIterator<Item = String>,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    ) {
+        if !self.forced_relays.is_empty() {
+            return;
+        }
+
+        let mut added = BTreeSet::new();
+        for url in urls {
+            let url = AccountRelayData::canonicalize_url(&url);
+            if self.outbox_relays.insert(url.clone()) && !pool.has(&url) {
+                added.insert(url);
+            }
+        }
+
+        if !added.is_empty() {
+            debug!("adding outbox relays: {:?}", added);
+            let _ = pool.add_urls(added, wakeup);
+        }
+    }
+
+    /// Is this a relay we only use for the outbox model? These don't get
+    /// our account or full timeline subscriptions.
+    pub fn is_outbox_relay(&self, url: &str) -> bool {
+        self.outbox_relays.contains(url) && !self.is_account_relay(url)
+    }
+
+    /// Drop the outbox relays we're done with, so their connections don't
+    /// pile up as timelines come and go
+    pub fn prune_outbox_relays(&mut self, pool: &mut RelayPool) {
+        let idle: BTreeSet<String> = self
+            .outbox_relays
+            .iter()
+            .filter(|url| !self.is_account_relay(url) && pool.is_idle(url))
+            .cloned()
+            .collect();
+
+        if idle.is_empty() {
+            return;
+        }
+
+        debug!("removing idle outbox relays: {:?}", idle);
+        pool.remove_urls(&idle);
+        self.outbox_relays.retain(|url| !idle.contains(url));
+    }
+
+    /// Whether `url` is in [`Accounts::account_relays`], without building
+    /// the set. This gets called a lot.
+    fn is_account_relay(&self, url: &str) -> bool {
+        if !self.forced_relays.is_empty() {
+            return self.forced_relays.contains(url);
+        }
+
+        let mut has_relays = false;
+        for data in self.account_data.values() {
+            if data.relay.local.contains(url)
+                || data.relay.advertised.iter().any(|spec| spec.url == url)
+            {
+                return true;
+            }
+            has_relays =
+                has_relays || !data.relay.local.is_empty() || !data.relay.advertised.is_empty();
+        }
+
+        !has_relays && self.bootstrap_relays.contains(url)
+    }
+
+    /// The relays we would use without the outbox model
+    fn account_relays(&self) -> BTreeSet<String> {
+        // If forced relays are set use them only
+        let mut desired_relays = self.forced_relays.clone();
+
+        // Compose the desired relay lists from the accounts
+        if desired_relays.is_empty() {
+            for data in self.account_data.values() {
+                desired_relays.extend(data.relay.local.iter().cloned());
+                desired_relays.extend(data.relay.advertised.iter().map(|spec| spec.url.clone()));
+            }
+        }
+
+        // If no relays are specified at this point use the bootstrap list
+        if desired_relays.is_empty() {
+            desired_relays = self.bootstrap_relays.clone();
+        }
+
+        desired_relays
+    }
+
+    pub fn mutefun(&self) -> Box<MuteFun> {
         if let Some(index) = self.currently_selected_account {
             if let Some(account) = self.accounts.get(index) {
                 let pubkey = account.pubkey.bytes();
                 if let Some(account_data) = self.account_data.get(pubkey) {
                     let muted = Arc::clone(&account_data.muted.muted);
-                    return Box::new(move |note: &Note| muted.is_muted(note));
+                    return Box::new(move |note: &Note, ndb: &Ndb, note_cache: &mut NoteCache| {
+                        muted.is_muted(note, ndb, note_cache)
+                    });
                 }
             }
         }
-        Box::new(|_: &Note| false)
+        Box::new(|_: &Note, _: &Ndb, _: &mut NoteCache| false)
+    }
+
+    /// The selected account's mute list
+    pub fn get_selected_account_muted(&self) -> Option<Arc<Muted>> {
+        let account = self.get_selected_account()?;
+        self.account_data
+            .get(account.pubkey.bytes())
+            .map(|data| Arc::clone(&data.muted.muted))
+    }
+
+    /// Whether a relay has answered with the selected account's mute
+    /// list, so it can be edited
+    pub fn selected_account_muted_fetched(&self) -> bool {
+        self.get_selected_account()
+            .and_then(|account| self.account_data.get(account.pubkey.bytes()))
+            .is_some_and(|data| data.muted.fetched)
+    }
+
+    /// Add `mute` to the selected account's mute list, privately if
+    /// `private` is set, and publish the new list. Returns false if there
+    /// is no selected account we can sign for, if its list hasn't been
+    /// fetched yet, or if we'd have to change private mutes we can't
+    /// decrypt.
+    pub fn mute(&mut self, ndb: &Ndb, pool: &mut RelayPool, mute: Mute, private: bool) -> bool {
+        let sealed = self
+            .get_selected_account_muted()
+            .is_some_and(|muted| !muted.can_edit_private());
+        if private && sealed {
+            warn!("can't add a private mute, the private mutes couldn't be decrypted");
+            return false;
+        }
+
+        self.edit_muted(ndb, pool, |muted| muted.with(mute, private))
+    }
+
+    /// Remove `mute` from the selected account's mute list and publish the
+    /// new list
+    pub fn unmute(&mut self, ndb: &Ndb, pool: &mut RelayPool, mute: &Mute) -> bool {
+        self.edit_muted(ndb, pool, |muted| muted.without(mute))
+    }
+
+    fn edit_muted(
+        &mut self,
+        ndb: &Ndb,
+        pool: &mut RelayPool,
+        edit: impl FnOnce(&Muted) -> Muted,
+    ) -> bool {
+        let pubkey = if let Some(pubkey) = self.selected_signer() {
+            pubkey
+        } else {
+            return false;
+        };
+        let data = if let Some(data) = self.account_data.get(pubkey.bytes()) {
+            data
+        } else {
+            return false;
+        };
+
+        // a stale or empty list here would unmute things
+        if !data.muted.fetched {
+            warn!("not editing the mute list before it's fetched");
+            return false;
+        }
+        let muted = edit(&data.muted.muted);
+
+        let content = if let Some(content) = muted.unencrypted_content() {
+            content
+        } else {
+            match self.encrypt(&pubkey, Encryption::Nip44, &pubkey, &muted.private_json()) {
+                Ok(Crypt::Done(content)) => content,
+                Ok(Crypt::Requested(request)) => {
+                    info!("asked the remote signer to encrypt the private mutes");
+                    self.muted_edits.insert(request, (pubkey, muted.clone()));
+                    self.set_muted(&pubkey, muted);
+                    return true;
+                }
+                Err(err) => {
+                    error!("could not encrypt private mutes: {}", err);
+                    return false;
+                }
+            }
+        };
+
+        self.publish_muted(ndb, pool, || {}, pubkey, muted, &content)
+    }
+
+    fn publish_muted(
+        &mut self,
+        ndb: &Ndb,
+        pool: &mut RelayPool,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+        pubkey: Pubkey,
+        muted: Muted,
+        content: &str,
+    ) -> bool {
+        let note = match UnsignedNote::from_builder(pubkey, muted.to_note(content)) {
+            Ok(note) => note,
+            Err(err) => {
+                error!("could not build mute list: {}", err);
+                return false;
+            }
+        };
+
+        info!("publishing mute list {}", note.id().hex());
+        let created_at = note.created_at;
+        let targets = self.list_targets(pool);
+        if let Err(err) = self.sign_and_send(ndb, pool, wakeup, note, Destination::Relays(targets))
+        {
+            error!("could not sign mute list: {}", err);
+            return false;
+        }
+
+        self.set_muted(&pubkey, muted);
+        if let Some(data) = self.account_data.get_mut(pubkey.bytes()) {
+            data.muted.created_at = Some(created_at);
+        }
+        true
+    }
+
+    /// Use `muted` right away, we don't wait for relays to echo it back
+    fn set_muted(&mut self, pubkey: &Pubkey, muted: Muted) {
+        if let Some(data) = self.account_data.get_mut(pubkey.bytes()) {
+            data.muted.muted = Arc::new(muted);
+        }
+    }
+
+    /// The selected account's contact list, None if we haven't seen it yet
+    pub fn get_selected_account_contacts(&self) -> Option<&Contacts> {
+        let account = self.get_selected_account()?;
+        self.account_data
+            .get(account.pubkey.bytes())?
+            .contacts
+            .contacts
+            .as_ref()
+    }
+
+    /// Whether a relay has answered with the selected account's contact
+    /// list, so it can be edited
+    pub fn selected_account_contacts_fetched(&self) -> bool {
+        self.get_selected_account()
+            .and_then(|account| self.account_data.get(account.pubkey.bytes()))
+            .is_some_and(|data| data.contacts.fetched)
+    }
+
+    /// Follow `pubkey` from the selected account and publish the new
+    /// contact list
+    pub fn follow(
+        &mut self,
+        ndb: &Ndb,
+        pool: &mut RelayPool,
+        pubkey: &[u8; 32],
+    ) -> Result<(), ContactsError> {
+        self.edit_contacts(ndb, pool, |contacts| contacts.follow(pubkey))
+    }
+
+    /// Unfollow `pubkey` from the selected account and publish the new
+    /// contact list
+    pub fn unfollow(
+        &mut self,
+        ndb: &Ndb,
+        pool: &mut RelayPool,
+        pubkey: &[u8; 32],
+    ) -> Result<(), ContactsError> {
+        self.edit_contacts(ndb, pool, |contacts| contacts.unfollow(pubkey))
+    }
+
+    fn edit_contacts(
+        &mut self,
+        ndb: &Ndb,
+        pool: &mut RelayPool,
+        edit: impl FnOnce(&Contacts) -> Contacts,
+    ) -> Result<(), ContactsError> {
+        let pubkey = if let Some(pubkey) = self.selected_signer() {
+            pubkey
+        } else {
+            return Err(ContactsError::NoSecretKey);
+        };
+        let targets = self.list_targets(pool);
+        let data = if let Some(data) = self.account_data.get(pubkey.bytes()) {
+            data
+        } else {
+            return Err(ContactsError::NotFetched);
+        };
+
+        // a stale or empty list here would unfollow people
+        if !data.contacts.fetched {
+            return Err(ContactsError::NotFetched);
+        }
+        let contacts = edit(&data.contacts.contacts.clone().unwrap_or_default());
+
+        let note = UnsignedNote::from_builder(pubkey, contacts.to_note())
+            .map_err(|_| ContactsError::Sign)?;
+        let created_at = note.created_at;
+
+        info!("publishing contact list {}", note.id().hex());
+        self.sign_and_send(ndb, pool, || {}, note, Destination::Relays(targets))
+            .map_err(|_| ContactsError::Sign)?;
+
+        if let Some(data) = self.account_data.get_mut(pubkey.bytes()) {
+            data.contacts.contacts = Some(Contacts {
+                created_at,
+                ..contacts
+            });
+        }
+        Ok(())
+    }
+
+    /// Where we publish the selected account's lists: every relay we're
+    /// connected to except ones we only joined to read someone's outbox
+    fn list_targets(&self, pool: &RelayPool) -> Vec<String> {
+        pool.urls()
+            .into_iter()
+            .filter(|url| !self.is_outbox_relay(url))
+            .collect()
+    }
+
+    /// Note that a relay has sent everything it has for one of our
+    /// account subscriptions. Returns false if `subid` isn't one of ours.
+    pub fn handle_eose(&mut self, subid: &str) -> bool {
+        for data in self.account_data.values_mut() {
+            if data.contacts.subid == subid {
+                data.contacts.fetched = true;
+                return true;
+            }
+            if data.relay.subid == subid {
+                data.relay.fetched = true;
+                return true;
+            }
+            if data.muted.subid == subid {
+                data.muted.fetched = true;
+                return true;
+            }
+        }
+        false
     }
 
     pub fn send_initial_filters(&mut self, pool: &mut RelayPool, relay_url: &str) {
@@ -405,6 +1294,13 @@ impl Accounts {
                 &ClientMessage::req(data.muted.subid.clone(), vec![data.muted.filter.clone()]),
                 relay_url,
             );
+            pool.send_to(
+                &ClientMessage::req(
+                    data.contacts.subid.clone(),
+                    vec![data.contacts.filter.clone()],
+                ),
+                relay_url,
+            );
         }
     }
 
@@ -427,11 +1323,22 @@ impl Accounts {
 
     fn handle_added_account(&mut self, ndb: &Ndb, pool: &mut RelayPool, pubkey: &[u8; 32]) {
         debug!("handle_added_account {}", hex::encode(pubkey));
+        let keypair = self
+            .accounts
+            .iter()
+            .find(|acc| acc.pubkey.bytes() == pubkey)
+            .and_then(|acc| acc.to_full());
+
+        let mut relay = AccountRelayData::new(ndb, pool, pubkey);
+        if let Some(storage) = &self.relay_storage {
+            relay.local = load_local_relays(storage, pubkey);
+        }
 
         // Create the user account data
         let new_account_data = AccountData {
-            relay: AccountRelayData::new(ndb, pool, pubkey),
-            muted: AccountMutedData::new(ndb, pool, pubkey),
+            relay,
+            muted: AccountMutedData::new(ndb, pool, pubkey, keypair),
+            contacts: AccountContactsData::new(ndb, pool, pubkey),
         };
         self.account_data.insert(*pubkey, new_account_data);
     }
@@ -440,6 +1347,7 @@ impl Accounts {
         debug!("handle_removed_account {}", hex::encode(pubkey));
         // FIXME - we need to unsubscribe here
         self.account_data.remove(pubkey);
+        self.bunkers.remove(&Pubkey::new(*pubkey));
     }
 
     fn poll_for_updates(&mut self, ndb: &Ndb) -> bool {
@@ -456,6 +1364,8 @@ impl Accounts {
                         relays
                     );
                     data.relay.advertised = relays.into_iter().collect();
+                    // a newer list came in, from a relay or from us
+                    data.relay.fetched = true;
                     changed = true;
                 }
             }
@@ -463,10 +1373,46 @@ impl Accounts {
                 let nks = ndb.poll_for_notes(sub, 1);
                 if !nks.is_empty() {
                     let txn = Transaction::new(ndb).expect("txn");
-                    let muted = AccountMutedData::harvest_nip51_muted(ndb, &txn, &nks);
-                    debug!("pubkey {}: updated muted {:?}", hex::encode(pubkey), muted);
-                    data.muted.muted = Arc::new(muted);
-                    changed = true;
+                    let keypair = self
+                        .accounts
+                        .iter()
+                        .find(|acc| acc.pubkey.bytes() == pubkey)
+                        .and_then(|acc| acc.to_full());
+                    let newer = AccountMutedData::harvest_nip51_muted(
+                        ndb,
+                        &txn,
+                        &nks,
+                        keypair,
+                        data.muted.created_at,
+                    );
+                    // an older list is a relay echoing something stale
+                    if let Some((created_at, muted)) = newer {
+                        debug!("pubkey {}: updated muted {:?}", hex::encode(pubkey), muted);
+                        data.muted.muted = Arc::new(muted);
+                        data.muted.created_at = Some(created_at);
+                        // a newer list came in, from a relay or from us
+                        data.muted.fetched = true;
+                        changed = true;
+                    }
+                }
+            }
+            if let Some(sub) = data.contacts.sub {
+                let nks = ndb.poll_for_notes(sub, 1);
+                if !nks.is_empty() {
+                    let txn = Transaction::new(ndb).expect("txn");
+                    let current = data.contacts.contacts.take();
+                    let before = current.as_ref().map(|c| c.created_at);
+                    data.contacts.contacts =
+                        AccountContactsData::harvest_contacts(ndb, &txn, &nks, current);
+                    // a newer list came in, from a relay or from us
+                    if data.contacts.contacts.as_ref().map(|c| c.created_at) != before {
+                        data.contacts.fetched = true;
+                    }
+                    debug!(
+                        "pubkey {}: updated contacts {:?}",
+                        hex::encode(pubkey),
+                        data.contacts.contacts.as_ref().map(|c| c.len())
+                    );
                 }
             }
         }
@@ -478,21 +1424,8 @@ impl Accounts {
         pool: &mut RelayPool,
         wakeup: impl Fn() + Send + Sync + Clone + 'static,
     ) {
-        // If forced relays are set use them only
-        let mut desired_relays = self.forced_relays.clone();
-
-        // Compose the desired relay lists from the accounts
-        if desired_relays.is_empty() {
-            for data in self.account_data.values() {
-                desired_relays.extend(data.relay.local.iter().cloned());
-                desired_relays.extend(data.relay.advertised.iter().cloned());
-            }
-        }
-
-        // If no relays are specified at this point use the bootstrap list
-        if desired_relays.is_empty() {
-            desired_relays = self.bootstrap_relays.clone();
-        }
+        let mut desired_relays = self.account_relays();
+        desired_relays.extend(self.outbox_relays.iter().cloned());
 
         debug!("current relays: {:?}", pool.urls());
         debug!("desired relays: {:?}", desired_relays);
@@ -527,6 +1460,10 @@ impl Accounts {
         let (added, removed) = self.delta_accounts();
         for pk in added {
             self.handle_added_account(ndb, pool, &pk);
+            // while the keys are locked, this waits until they aren't
+            if let Some(cipher) = self.key_store.key_cipher() {
+                self.bunkers.restore(&Pubkey::new(pk), &cipher);
+            }
             relays_changed = true;
         }
         for pk in removed {
@@ -537,6 +1474,40 @@ impl Accounts {
         // Did any accounts receive updates (ie NIP-65 relay lists)
         relays_changed = self.poll_for_updates(ndb) || relays_changed;
 
+        // Send whatever the remote signers signed for us
+        let proxy = pool.proxy().clone();
+        for (note, destination) in self.bunkers.poll(&proxy, wakeup.clone()) {
+            self.send_signed(ndb, pool, wakeup.clone(), note, destination);
+        }
+
+        // Stop holding back REQs for auth the signers won't sign
+        for relay in self.bunkers.expire() {
+            pool.auth_failed(&relay, "the remote signer didn't answer");
+        }
+
+        // and the mute lists they encrypted
+        let requests: Vec<String> = self.muted_edits.keys().cloned().collect();
+        for request in requests {
+            let answer = if let Some(answer) = self.bunkers.take_answer(&request) {
+                answer
+            } else {
+                continue;
+            };
+
+            let (pubkey, muted) = if let Some(edit) = self.muted_edits.remove(&request) {
+                edit
+            } else {
+                continue;
+            };
+
+            match answer {
+                Answer::Crypted(content) => {
+                    self.publish_muted(ndb, pool, wakeup.clone(), pubkey, muted, &content);
+                }
+                _ => error!("remote signer didn't encrypt the private mutes"),
+            }
+        }
+
         // If needed, update the relay configuration
         if relays_changed {
             self.update_relay_configuration(pool, wakeup);
@@ -545,6 +1516,73 @@ impl Accounts {
     }
 }
 
+/// Why a contact list edit wasn't published
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ContactsError {
+    /// The selected account is watch only
+    NoSecretKey,
+    /// No relay has sent us the account's contact list, or told us it
+    /// has none, yet
+    NotFetched,
+    Sign,
+}
+
+impl std::fmt::Display for ContactsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContactsError::NoSecretKey => write!(f, "no secret key for the selected account"),
+            ContactsError::NotFetched => write!(f, "contact list hasn't been fetched yet"),
+            ContactsError::Sign => write!(f, "could not sign the contact list"),
+        }
+    }
+}
+
+/// Store one of our own notes right away, so we see it (or don't read an
+/// old list back) before the relays echo it, then publish it
+fn store_and_publish(ndb: &Ndb, pool: &mut RelayPool, targets: &[String], id: NoteId, json: &str) {
+    if let Err(err) = ndb.process_event(&format!("[\"EVENT\",\"ours\",{}]", json)) {
+        error!("could not store our note {}: {:?}", id.hex(), err);
+    }
+
+    pool.publish(
+        id,
+        ClientMessage::raw(format!("[\"EVENT\",{}]", json)),
+        targets.iter().map(|url| url.as_str()),
+    );
+}
+
+fn local_relays_file_name(pubkey: &[u8; 32]) -> String {
+    hex::encode(pubkey)
+}
+
+fn load_local_relays(storage: &Directory, pubkey: &[u8; 32]) -> BTreeSet<String> {
+    let json = if let Ok(json) = storage.get_file(local_relays_file_name(pubkey)) {
+        json
+    } else {
+        return BTreeSet::new();
+    };
+
+    match serde_json::from_str::<BTreeSet<String>>(&json) {
+        Ok(relays) => relays,
+        Err(err) => {
+            error!("could not parse local relays: {}", err);
+            BTreeSet::new()
+        }
+    }
+}
+
+fn save_local_relays(
+    storage: &Directory,
+    pubkey: &[u8; 32],
+    relays: &BTreeSet<String>,
+) -> crate::Result<()> {
+    crate::storage::write_file(
+        &storage.file_path,
+        local_relays_file_name(pubkey),
+        &serde_json::to_string(relays)?,
+    )
+}
+
 fn get_selected_index(accounts: &[UserAccount], keystore: &KeyStorageType) -> Option<usize> {
     match keystore.get_selected_key() {
         KeyStorageResponse::ReceivedResult(Ok(Some(pubkey))) => {
@@ -567,3 +1605,109 @@ impl AddAccountAction {
         self.unk_id_action.process_action(ids, ndb, txn);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_relays_roundtrip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let storage = Directory::new(dir.path().to_path_buf());
+        let pubkey = [7; 32];
+
+        assert!(load_local_relays(&storage, &pubkey).is_empty());
+
+        let relays: BTreeSet<String> = ["ws://localhost:8080/", "wss://relay.example.com/"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        save_local_relays(&storage, &pubkey, &relays).unwrap();
+
+        assert_eq!(load_local_relays(&storage, &pubkey), relays);
+        assert!(load_local_relays(&storage, &[8; 32]).is_empty());
+    }
+
+    /// Accounts with a single account we can sign for, selected
+    fn signing_account(ndb: &Ndb, pool: &mut RelayPool) -> (Accounts, Pubkey) {
+        let mut accounts = Accounts::new(KeyStorageType::None, vec![]);
+        let keypair = enostr::FullKeypair::generate();
+        let pubkey = keypair.pubkey;
+        accounts.add_account(keypair.to_keypair()).process_action(
+            &mut UnknownIds::default(),
+            ndb,
+            &Transaction::new(ndb).unwrap(),
+        );
+        accounts.select_account(0);
+        accounts.update(ndb, pool, &egui::Context::default());
+
+        (accounts, pubkey)
+    }
+
+    #[test]
+    fn test_contacts_not_editable_until_eose() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ndb = Ndb::new(tmp.path().to_str().unwrap(), &nostrdb::Config::new()).unwrap();
+        let mut pool = RelayPool::new();
+        let (mut accounts, pubkey) = signing_account(&ndb, &mut pool);
+
+        let followed = [9; 32];
+        assert_eq!(
+            accounts.follow(&ndb, &mut pool, &followed),
+            Err(ContactsError::NotFetched)
+        );
+
+        let subid = accounts.account_data[pubkey.bytes()].contacts.subid.clone();
+        assert!(accounts.handle_eose(&subid));
+        assert!(!accounts.handle_eose("someone else's"));
+
+        // the relays have no list, so we start a new one
+        accounts.follow(&ndb, &mut pool, &followed).unwrap();
+        let contacts = accounts.get_selected_account_contacts().unwrap();
+        assert!(contacts.is_following(&followed));
+    }
+
+    #[test]
+    fn test_mutes_not_editable_until_eose() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ndb = Ndb::new(tmp.path().to_str().unwrap(), &nostrdb::Config::new()).unwrap();
+        let mut pool = RelayPool::new();
+        let (mut accounts, pubkey) = signing_account(&ndb, &mut pool);
+
+        let mute = Mute::Pubkey([9; 32]);
+        assert!(!accounts.selected_account_muted_fetched());
+        assert!(!accounts.mute(&ndb, &mut pool, mute.clone(), true));
+
+        let subid = accounts.account_data[pubkey.bytes()].muted.subid.clone();
+        assert!(accounts.handle_eose(&subid));
+        assert!(accounts.selected_account_muted_fetched());
+
+        // the relays have no list, so we start a new one
+        assert!(accounts.mute(&ndb, &mut pool, mute.clone(), true));
+        let muted = accounts.get_selected_account_muted().unwrap();
+        assert!(muted.contains(&mute));
+        assert!(muted.is_private(&mute));
+    }
+
+    #[test]
+    fn test_relay_list_not_editable_until_eose() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ndb = Ndb::new(tmp.path().to_str().unwrap(), &nostrdb::Config::new()).unwrap();
+        let mut pool = RelayPool::new();
+        let (mut accounts, pubkey) = signing_account(&ndb, &mut pool);
+
+        let spec = RelaySpec::new("wss://relay.example.com/".to_owned(), false, false);
+        assert!(!accounts.selected_account_relays_fetched());
+        assert!(!accounts.advertise_relay(&ndb, &mut pool, spec.clone()));
+
+        let subid = accounts.account_data[pubkey.bytes()].relay.subid.clone();
+        assert!(accounts.handle_eose(&subid));
+        assert!(accounts.selected_account_relays_fetched());
+
+        // there's no list, so the new one starts from the bootstrap relays
+        assert!(accounts.advertise_relay(&ndb, &mut pool, spec.clone()));
+        let advertised = accounts.get_selected_account_relays().unwrap();
+        assert!(advertised.contains(&spec));
+        assert_eq!(advertised.len(), accounts.bootstrap_relays.len() + 1);
+    }
+}
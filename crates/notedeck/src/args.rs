@@ -1,4 +1,4 @@
-use enostr::{Keypair, Pubkey, SecretKey};
+use enostr::{Keypair, ProxyConfig, Pubkey, SecretKey};
 use tracing::error;
 
 pub struct Args {
@@ -14,6 +14,13 @@ pub struct Args {
     pub use_keystore: bool,
     pub dbpath: Option<String>,
     pub datapath: Option<String>,
+
+    /// Set from `--socks5` and `--proxy-only`, and merged with the saved
+    /// proxy settings on startup
+    pub proxy: ProxyConfig,
+
+    /// `--no-proxy`: forget the saved proxy settings and connect directly
+    pub no_proxy: bool,
 }
 
 impl Args {
@@ -28,6 +35,8 @@ impl Args {
             use_keystore: true,
             dbpath: None,
             datapath: None,
+            proxy: ProxyConfig::default(),
+            no_proxy: false,
         };
 
         let mut i = 0;
@@ -106,6 +115,19 @@ impl Args {
                     continue;
                 };
                 res.relays.push(relay.clone());
+            } else if arg == "--socks5" || arg == "--proxy" {
+                i += 1;
+                let addr = if let Some(next_arg) = args.get(i) {
+                    next_arg
+                } else {
+                    error!("proxy argument missing?");
+                    continue;
+                };
+                res.proxy.socks5 = Some(addr.clone());
+            } else if arg == "--proxy-only" {
+                res.proxy.proxy_only = true;
+            } else if arg == "--no-proxy" || arg == "--direct" {
+                res.no_proxy = true;
             } else if arg == "--no-keystore" {
                 res.use_keystore = false;
             }
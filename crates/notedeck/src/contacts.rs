@@ -0,0 +1,132 @@
+use nostrdb::{Note, NoteBuilder};
+
+use crate::note::tag_strings;
+
+/// A kind 3 contact list, kept as it was published so we can add or
+/// remove a follow without losing relay hints, petnames, other tags or
+/// the legacy relay json in the content
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Contacts {
+    pub created_at: u64,
+    pub tags: Vec<Vec<String>>,
+    pub content: String,
+}
+
+impl Contacts {
+    pub fn from_note(note: &Note) -> Self {
+        Contacts {
+            created_at: note.created_at(),
+            tags: note.tags().iter().map(|tag| tag_strings(&tag)).collect(),
+            content: note.content().to_owned(),
+        }
+    }
+
+    pub fn is_following(&self, pubkey: &[u8; 32]) -> bool {
+        let hex = hex::encode(pubkey);
+        self.tags.iter().any(|tag| is_p_tag_for(tag, &hex))
+    }
+
+    /// Number of followed pubkeys
+    pub fn len(&self) -> usize {
+        self.tags
+            .iter()
+            .filter(|tag| tag.first().map(String::as_str) == Some("p"))
+            .count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// A copy of the list following `pubkey` too
+    pub fn follow(&self, pubkey: &[u8; 32]) -> Contacts {
+        let mut contacts = self.clone();
+        if !self.is_following(pubkey) {
+            contacts
+                .tags
+                .push(vec!["p".to_owned(), hex::encode(pubkey)]);
+        }
+        contacts
+    }
+
+    /// A copy of the list without `pubkey`
+    pub fn unfollow(&self, pubkey: &[u8; 32]) -> Contacts {
+        let hex = hex::encode(pubkey);
+        let mut contacts = self.clone();
+        contacts.tags.retain(|tag| !is_p_tag_for(tag, &hex));
+        contacts
+    }
+
+    /// An unsigned kind 3 for this list
+    pub fn to_note(&self) -> NoteBuilder<'_> {
+        let mut builder = NoteBuilder::new().kind(3).content(&self.content);
+        for tag in &self.tags {
+            builder = builder.start_tag();
+            for value in tag {
+                builder = builder.tag_str(value);
+            }
+        }
+
+        builder
+    }
+}
+
+fn is_p_tag_for(tag: &[String], hex: &str) -> bool {
+    tag.len() >= 2 && tag[0] == "p" && tag[1].eq_ignore_ascii_case(hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enostr::FullKeypair;
+
+    fn tag(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn contacts() -> Contacts {
+        Contacts {
+            created_at: 1,
+            tags: vec![
+                tag(&["p", &hex::encode([1; 32]), "wss://relay.one", "alice"]),
+                tag(&["p", &hex::encode([2; 32])]),
+                tag(&["t", "nostr"]),
+            ],
+            content: "{\"wss://relay.one\":{\"read\":true,\"write\":true}}".to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_follow_keeps_existing_entries() {
+        let followed = contacts().follow(&[3; 32]);
+        assert!(followed.is_following(&[3; 32]));
+        assert_eq!(followed.len(), 3);
+        assert_eq!(followed.tags[..3], contacts().tags[..]);
+        assert_eq!(followed.content, contacts().content);
+
+        // following twice doesn't add another tag
+        assert_eq!(followed.follow(&[3; 32]), followed);
+    }
+
+    #[test]
+    fn test_unfollow_only_removes_that_pubkey() {
+        let unfollowed = contacts().unfollow(&[1; 32]);
+        assert!(!unfollowed.is_following(&[1; 32]));
+        assert!(unfollowed.is_following(&[2; 32]));
+        assert_eq!(unfollowed.tags.len(), 2);
+        assert_eq!(unfollowed.content, contacts().content);
+    }
+
+    #[test]
+    fn test_note_round_trip() {
+        let keypair = FullKeypair::generate();
+        let seckey = keypair.secret_key.to_secret_bytes();
+        let contacts = contacts().follow(&[3; 32]);
+        let note = contacts.to_note().sign(&seckey).build().unwrap();
+        assert_eq!(note.kind(), 3);
+
+        let decoded = Contacts::from_note(&note);
+        assert_eq!(decoded.tags, contacts.tags);
+        assert_eq!(decoded.content, contacts.content);
+    }
+}
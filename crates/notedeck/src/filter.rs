@@ -161,6 +161,11 @@ pub fn default_remote_limit() -> u64 {
     250
 }
 
+/// Text notes, and kind 6 and 16 reposts of them
+pub fn note_kinds() -> [u64; 3] {
+    [1, 6, 16]
+}
+
 pub struct FilteredTags {
     pub authors: Option<FilterBuilder>,
     pub hashtags: Option<FilterBuilder>,
@@ -168,7 +173,7 @@ pub struct FilteredTags {
 
 impl FilteredTags {
     pub fn into_follow_filter(self) -> Vec<Filter> {
-        self.into_filter([1], default_limit())
+        self.into_filter(note_kinds(), default_limit())
     }
 
     // TODO: make this more general
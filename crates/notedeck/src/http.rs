@@ -0,0 +1,64 @@
+use enostr::ProxyConfig;
+use std::collections::BTreeMap;
+use std::io::Read;
+
+/// Like [`ehttp::fetch`], but honors the proxy settings. ehttp can't be
+/// pointed at a proxy, so proxied requests go through ureq on a thread
+/// of their own.
+pub fn fetch(
+    proxy: &ProxyConfig,
+    request: ehttp::Request,
+    on_done: impl 'static + Send + FnOnce(Result<ehttp::Response, String>),
+) {
+    if let Some(proxy_url) = proxy.url() {
+        std::thread::spawn(move || on_done(fetch_proxied(&proxy_url, request)));
+    } else if proxy.allows_direct() {
+        ehttp::fetch(request, on_done);
+    } else {
+        on_done(Err(format!(
+            "not fetching {}: direct connections are disabled and there is no proxy",
+            request.url
+        )));
+    }
+}
+
+fn fetch_proxied(proxy_url: &str, request: ehttp::Request) -> Result<ehttp::Response, String> {
+    let proxy = ureq::Proxy::new(proxy_url).map_err(|e| e.to_string())?;
+    let agent = ureq::AgentBuilder::new().proxy(proxy).build();
+
+    let mut req = agent.request(&request.method, &request.url);
+    for (name, value) in &request.headers {
+        req = req.set(name, value);
+    }
+
+    let resp = match req.send_bytes(&request.body) {
+        Ok(resp) => resp,
+        // non-2xx statuses are still responses as far as ehttp is concerned
+        Err(ureq::Error::Status(_, resp)) => resp,
+        Err(e) => return Err(e.to_string()),
+    };
+
+    let url = resp.get_url().to_owned();
+    let status = resp.status();
+    let status_text = resp.status_text().to_owned();
+    let mut headers = BTreeMap::new();
+    for name in resp.headers_names() {
+        if let Some(value) = resp.header(&name) {
+            headers.insert(name.to_ascii_lowercase(), value.to_owned());
+        }
+    }
+
+    let mut bytes = vec![];
+    resp.into_reader()
+        .read_to_end(&mut bytes)
+        .map_err(|e| e.to_string())?;
+
+    Ok(ehttp::Response {
+        url,
+        ok: (200..300).contains(&status),
+        status,
+        status_text,
+        bytes,
+        headers,
+    })
+}
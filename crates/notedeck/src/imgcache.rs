@@ -1,5 +1,6 @@
 use crate::Result;
 use egui::TextureHandle;
+use enostr::ProxyConfig;
 use poll_promise::Promise;
 
 use egui::ColorImage;
@@ -14,6 +15,9 @@ pub type ImageCacheMap = HashMap<String, ImageCacheValue>;
 
 pub struct ImageCache {
     pub cache_dir: path::PathBuf,
+
+    /// Images are fetched through this
+    pub proxy: ProxyConfig,
     url_imgs: ImageCacheMap,
 }
 
@@ -21,6 +25,7 @@ impl ImageCache {
     pub fn new(cache_dir: path::PathBuf) -> Self {
         Self {
             cache_dir,
+            proxy: ProxyConfig::default(),
             url_imgs: HashMap::new(),
         }
     }
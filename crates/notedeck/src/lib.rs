@@ -1,15 +1,21 @@
 mod accounts;
 mod app;
 mod args;
+mod contacts;
 mod context;
 mod error;
 pub mod filter;
 pub mod fonts;
+pub mod http;
 mod imgcache;
 mod muted;
 pub mod note;
 mod notecache;
+pub mod outbox;
+mod proxy_handler;
+mod relayspec;
 mod result;
+mod signing;
 pub mod storage;
 mod style;
 pub mod theme;
@@ -20,20 +26,25 @@ pub mod ui;
 mod unknowns;
 mod user_account;
 
-pub use accounts::{AccountData, Accounts, AccountsAction, AddAccountAction};
+pub use accounts::{AccountData, Accounts, AccountsAction, AddAccountAction, ContactsError};
 pub use app::App;
 pub use args::Args;
+pub use contacts::Contacts;
 pub use context::AppContext;
 pub use error::{Error, FilterError};
 pub use filter::{FilterState, FilterStates, UnifiedSubscription};
 pub use fonts::NamedFontFamily;
 pub use imgcache::ImageCache;
-pub use muted::{MuteFun, Muted};
+pub use muted::{Mute, MuteFun, Muted};
 pub use note::NoteRef;
 pub use notecache::{CachedNote, NoteCache};
+pub use proxy_handler::ProxyHandler;
+pub use relayspec::RelaySpec;
 pub use result::Result;
+pub use signing::{Answer, Destination, SignError};
 pub use storage::{
-    DataPath, DataPathType, Directory, FileKeyStorage, KeyStorageResponse, KeyStorageType,
+    DataPath, DataPathType, Directory, FileKeyStorage, KeyCipher, KeyStorageResponse,
+    KeyStorageType, KeyStoreUpdate,
 };
 pub use style::NotedeckTextStyle;
 pub use theme::ColorTheme;
@@ -1,17 +1,115 @@
-use nostrdb::Note;
-use std::collections::BTreeSet;
+use enostr::FilledKeypair;
+use nostrdb::{BlockType, Ndb, Note, NoteBuilder, NoteReply};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use unicode_segmentation::UnicodeSegmentation;
 
-use tracing::debug;
+use crate::NoteCache;
+use tracing::{debug, error};
 
-pub type MuteFun = dyn Fn(&Note) -> bool;
+pub type MuteFun = dyn Fn(&Note, &Ndb, &mut NoteCache) -> bool;
 
-#[derive(Default)]
+/// A single entry in a NIP-51 mute list
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Mute {
+    Pubkey([u8; 32]),
+    Hashtag(String),
+    Word(String),
+    Thread([u8; 32]),
+}
+
+impl Mute {
+    pub fn from_tag(tag: &[String]) -> Option<Mute> {
+        let value = tag.get(1)?;
+        match tag.first()?.as_str() {
+            "p" => Some(Mute::Pubkey(hex_id(value)?)),
+            "t" => Some(Mute::Hashtag(value.to_owned())),
+            "word" => Some(Mute::Word(value.to_owned())),
+            "e" => Some(Mute::Thread(hex_id(value)?)),
+            _ => None,
+        }
+    }
+
+    pub fn to_tag(&self) -> Vec<String> {
+        match self {
+            Mute::Pubkey(pk) => vec!["p".to_owned(), hex::encode(pk)],
+            Mute::Hashtag(tag) => vec!["t".to_owned(), tag.to_owned()],
+            Mute::Word(word) => vec!["word".to_owned(), word.to_owned()],
+            Mute::Thread(id) => vec!["e".to_owned(), hex::encode(id)],
+        }
+    }
+}
+
+fn hex_id(s: &str) -> Option<[u8; 32]> {
+    hex::decode(s).ok()?.try_into().ok()
+}
+
+#[derive(Default, Clone)]
 pub struct Muted {
-    // TODO - implement private mutes
     pub pubkeys: BTreeSet<[u8; 32]>,
     pub hashtags: BTreeSet<String>,
     pub words: BTreeSet<String>,
     pub threads: BTreeSet<[u8; 32]>,
+
+    /// The public tags of the list, including ones we don't understand,
+    /// so we can publish it again without losing anything
+    pub public_tags: Vec<Vec<String>>,
+
+    /// The tags from the list's encrypted content
+    pub private_tags: Vec<Vec<String>>,
+
+    /// The list's encrypted content when we couldn't read it, say with
+    /// the wrong key. It's published again as it was so the private mutes
+    /// in it survive our edits.
+    pub sealed: Option<String>,
+
+    /// Lowercased hashtags, for case insensitive matching
+    hashtag_matcher: HashSet<String>,
+    word_matcher: WordMatcher,
+}
+
+/// Muted words and phrases, split into lowercase words when the list
+/// changes so that matching a note is a single pass over its words.
+/// Words are matched whole, "cat" doesn't mute "catalog".
+#[derive(Default, Clone)]
+struct WordMatcher {
+    /// Phrases keyed by their first word
+    phrases: HashMap<String, Vec<Vec<String>>>,
+}
+
+impl WordMatcher {
+    fn new<'a>(words: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut phrases: HashMap<String, Vec<Vec<String>>> = HashMap::new();
+        for word in words {
+            let phrase: Vec<String> = word.unicode_words().map(str::to_lowercase).collect();
+            if let Some(first) = phrase.first() {
+                phrases.entry(first.clone()).or_default().push(phrase);
+            }
+        }
+        WordMatcher { phrases }
+    }
+
+    fn find(&self, text: &str) -> Option<&[String]> {
+        if self.phrases.is_empty() {
+            return None;
+        }
+
+        let words: Vec<String> = text.unicode_words().map(str::to_lowercase).collect();
+        for (i, word) in words.iter().enumerate() {
+            let candidates = if let Some(candidates) = self.phrases.get(word) {
+                candidates
+            } else {
+                continue;
+            };
+
+            for phrase in candidates {
+                if words[i..].starts_with(phrase) {
+                    return Some(phrase);
+                }
+            }
+        }
+
+        None
+    }
 }
 
 impl std::fmt::Debug for Muted {
@@ -27,12 +125,164 @@ impl std::fmt::Debug for Muted {
                 "threads",
                 &self.threads.iter().map(hex::encode).collect::<Vec<_>>(),
             )
+            .field("private", &self.private_tags.len())
+            .field("sealed", &self.sealed.is_some())
             .finish()
     }
 }
 
 impl Muted {
-    pub fn is_muted(&self, note: &Note) -> bool {
+    /// Build the mute sets from a list's public and decrypted private tags
+    pub fn from_tags(public_tags: Vec<Vec<String>>, private_tags: Vec<Vec<String>>) -> Self {
+        let mut muted = Muted {
+            public_tags,
+            private_tags,
+            ..Default::default()
+        };
+
+        for tag in muted.public_tags.iter().chain(muted.private_tags.iter()) {
+            match Mute::from_tag(tag) {
+                Some(Mute::Pubkey(pk)) => {
+                    muted.pubkeys.insert(pk);
+                }
+                Some(Mute::Hashtag(hashtag)) => {
+                    muted.hashtags.insert(hashtag);
+                }
+                Some(Mute::Word(word)) => {
+                    muted.words.insert(word);
+                }
+                Some(Mute::Thread(id)) => {
+                    muted.threads.insert(id);
+                }
+                None => {}
+            }
+        }
+
+        muted.hashtag_matcher = muted.hashtags.iter().map(|t| t.to_lowercase()).collect();
+        muted.word_matcher = WordMatcher::new(&muted.words);
+
+        muted
+    }
+
+    /// The list with private content we couldn't decrypt
+    pub fn with_sealed(mut self, content: String) -> Self {
+        self.sealed = Some(content);
+        self
+    }
+
+    /// Whether we can read, and so change, the private part of the list
+    pub fn can_edit_private(&self) -> bool {
+        self.sealed.is_none()
+    }
+
+    /// Every mute in the list, and whether it's private
+    pub fn entries(&self) -> Vec<(Mute, bool)> {
+        let public = self.public_tags.iter().map(|tag| (tag, false));
+        let private = self.private_tags.iter().map(|tag| (tag, true));
+        public
+            .chain(private)
+            .filter_map(|(tag, private)| Mute::from_tag(tag).map(|mute| (mute, private)))
+            .collect()
+    }
+
+    pub fn contains(&self, mute: &Mute) -> bool {
+        match mute {
+            Mute::Pubkey(pk) => self.pubkeys.contains(pk),
+            Mute::Hashtag(hashtag) => self.hashtags.contains(hashtag),
+            Mute::Word(word) => self.words.contains(word),
+            Mute::Thread(id) => self.threads.contains(id),
+        }
+    }
+
+    pub fn is_private(&self, mute: &Mute) -> bool {
+        self.private_tags
+            .iter()
+            .any(|tag| Mute::from_tag(tag).as_ref() == Some(mute))
+    }
+
+    /// A copy of the list with `mute` added, in the private section if
+    /// `private` is set. A mute that's already there moves sections. See
+    /// [`Muted::can_edit_private`] before adding private mutes.
+    pub fn with(&self, mute: Mute, private: bool) -> Muted {
+        let without = self.without(&mute);
+        let mut public_tags = without.public_tags;
+        let mut private_tags = without.private_tags;
+        if private {
+            private_tags.push(mute.to_tag());
+        } else {
+            public_tags.push(mute.to_tag());
+        }
+
+        Muted {
+            sealed: without.sealed,
+            ..Muted::from_tags(public_tags, private_tags)
+        }
+    }
+
+    /// A copy of the list without `mute`
+    pub fn without(&self, mute: &Mute) -> Muted {
+        let keep = |tag: &&Vec<String>| Mute::from_tag(tag).as_ref() != Some(mute);
+        Muted {
+            sealed: self.sealed.clone(),
+            ..Muted::from_tags(
+                self.public_tags.iter().filter(keep).cloned().collect(),
+                self.private_tags.iter().filter(keep).cloned().collect(),
+            )
+        }
+    }
+
+    /// The private tags, NIP-44 encrypted to ourselves for the list's
+    /// content, or the content we couldn't decrypt as it was
+    pub fn encrypt_private(&self, keypair: FilledKeypair) -> Option<String> {
+        if let Some(content) = self.unencrypted_content() {
+            return Some(content);
+        }
+
+        match keypair.nip44_encrypt(keypair.pubkey, &self.private_json()) {
+            Ok(content) => Some(content),
+            Err(err) => {
+                error!("could not encrypt private mutes: {}", err);
+                None
+            }
+        }
+    }
+
+    /// The list's content when there's nothing to encrypt: the content we
+    /// couldn't decrypt as it was, or nothing without private mutes
+    pub fn unencrypted_content(&self) -> Option<String> {
+        if let Some(sealed) = &self.sealed {
+            Some(sealed.clone())
+        } else if self.private_tags.is_empty() {
+            Some(String::new())
+        } else {
+            None
+        }
+    }
+
+    /// The private tags, for the account's signer to NIP-44 encrypt to
+    /// itself as the list's content
+    pub fn private_json(&self) -> String {
+        serde_json::to_string(&self.private_tags).expect("tags json")
+    }
+
+    /// A kind 10000 for this list. Its `content` is the encrypted
+    /// [`Muted::private_json`], or [`Muted::unencrypted_content`].
+    pub fn to_note<'a>(&'a self, content: &'a str) -> NoteBuilder<'a> {
+        let mut builder = NoteBuilder::new().kind(10000).content(content);
+        for tag in &self.public_tags {
+            builder = builder.start_tag();
+            for value in tag {
+                builder = builder.tag_str(value);
+            }
+        }
+
+        builder
+    }
+
+    /// Whether to hide `note`. This runs on every note we put in a
+    /// timeline, so everything it matches against is prepared in
+    /// [`Muted::from_tags`].
+    pub fn is_muted(&self, note: &Note, ndb: &Ndb, note_cache: &mut NoteCache) -> bool {
         if self.pubkeys.contains(note.pubkey()) {
             debug!(
                 "{}: MUTED pubkey: {}",
@@ -41,21 +291,220 @@ impl Muted {
             );
             return true;
         }
-        // FIXME - Implement hashtag muting here
-
-        // TODO - let's not add this for now, we will likely need to
-        // have an optimized data structure in nostrdb to properly
-        // mute words. this mutes substrings which is not ideal.
-        //
-        // let content = note.content().to_lowercase();
-        // for word in &self.words {
-        //     if content.contains(&word.to_lowercase()) {
-        //         debug!("{}: MUTED word: {}", hex::encode(note.id()), word);
-        //         return true;
-        //     }
-        // }
-
-        // FIXME - Implement thread muting here
+
+        if let Some(hashtag) = self.muted_hashtag(note, ndb) {
+            debug!("{}: MUTED hashtag: {}", hex::encode(note.id()), hashtag);
+            return true;
+        }
+
+        if let Some(phrase) = self.word_matcher.find(note.content()) {
+            debug!(
+                "{}: MUTED word: {}",
+                hex::encode(note.id()),
+                phrase.join(" ")
+            );
+            return true;
+        }
+
+        if !self.threads.is_empty() {
+            let root = if let Some(key) = note.key() {
+                let cached = note_cache.cached_note_or_insert(key, note);
+                cached.reply.borrow(note.tags()).root().map(|r| *r.id)
+            } else {
+                NoteReply::new(note.tags()).root().map(|r| *r.id)
+            };
+
+            let thread = root.unwrap_or(*note.id());
+            if self.threads.contains(&thread) {
+                debug!(
+                    "{}: MUTED thread: {}",
+                    hex::encode(note.id()),
+                    hex::encode(thread)
+                );
+                return true;
+            }
+        }
+
         false
     }
+
+    fn muted_hashtag(&self, note: &Note, ndb: &Ndb) -> Option<String> {
+        if self.hashtag_matcher.is_empty() {
+            return None;
+        }
+
+        for tag in note.tags() {
+            if tag.count() < 2 || tag.get_unchecked(0).variant().str() != Some("t") {
+                continue;
+            }
+
+            if let Some(hashtag) = tag.get_unchecked(1).variant().str() {
+                let hashtag = hashtag.to_lowercase();
+                if self.hashtag_matcher.contains(&hashtag) {
+                    return Some(hashtag);
+                }
+            }
+        }
+
+        // plenty of notes have hashtags in their content but no t tags
+        let (txn, key) = match (note.txn(), note.key()) {
+            (Some(txn), Some(key)) => (txn, key),
+            _ => return None,
+        };
+        let blocks = ndb.get_blocks_by_key(txn, key).ok()?;
+        for block in blocks.iter(note) {
+            if block.blocktype() != BlockType::Hashtag {
+                continue;
+            }
+
+            let hashtag = block.as_str().to_lowercase();
+            if self.hashtag_matcher.contains(&hashtag) {
+                return Some(hashtag);
+            }
+        }
+
+        None
+    }
+}
+
+/// Decrypt the private section of a mute list. Older clients used NIP-04,
+/// newer ones NIP-44.
+pub fn decrypt_private_tags(keypair: FilledKeypair, content: &str) -> Option<Vec<Vec<String>>> {
+    if content.is_empty() {
+        return Some(vec![]);
+    }
+
+    let decrypted = if content.contains("?iv=") {
+        keypair.nip04_decrypt(keypair.pubkey, content)
+    } else {
+        keypair.nip44_decrypt(keypair.pubkey, content)
+    };
+
+    match decrypted {
+        Ok(json) => match serde_json::from_str(&json) {
+            Ok(tags) => Some(tags),
+            Err(err) => {
+                error!("private mutes aren't a list of tags: {}", err);
+                None
+            }
+        },
+        Err(err) => {
+            error!("could not decrypt private mutes: {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enostr::FullKeypair;
+
+    fn tag(parts: &[&str]) -> Vec<String> {
+        parts.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_private_mutes_round_trip() {
+        let keypair = FullKeypair::generate();
+        let muted = Muted::default()
+            .with(Mute::Pubkey([1; 32]), false)
+            .with(Mute::Word("spoilers".to_owned()), true)
+            .with(Mute::Hashtag("nsfw".to_owned()), true);
+
+        let content = muted.encrypt_private(keypair.to_filled()).unwrap();
+        let seckey = keypair.secret_key.to_secret_bytes();
+        let note = muted.to_note(&content).sign(&seckey).build().unwrap();
+        assert_eq!(note.kind(), 10000);
+        assert!(!note.content().contains("spoilers"));
+
+        let private = decrypt_private_tags(keypair.to_filled(), note.content()).unwrap();
+        let decoded = Muted::from_tags(muted.public_tags.clone(), private);
+        assert!(decoded.pubkeys.contains(&[1; 32]));
+        assert!(decoded.words.contains("spoilers"));
+        assert!(decoded.hashtags.contains("nsfw"));
+        assert!(decoded.is_private(&Mute::Word("spoilers".to_owned())));
+        assert!(!decoded.is_private(&Mute::Pubkey([1; 32])));
+    }
+
+    #[test]
+    fn test_nip04_private_mutes() {
+        let keypair = FullKeypair::generate();
+        let content = keypair
+            .nip04_encrypt(&keypair.pubkey, r#"[["word","nip04"]]"#)
+            .unwrap();
+
+        let private = decrypt_private_tags(keypair.to_filled(), &content).unwrap();
+        assert_eq!(private, vec![tag(&["word", "nip04"])]);
+    }
+
+    #[test]
+    fn test_undecryptable_private_mutes_survive_edits() {
+        let owner = FullKeypair::generate();
+        let content = Muted::default()
+            .with(Mute::Word("secret".to_owned()), true)
+            .encrypt_private(owner.to_filled())
+            .unwrap();
+
+        // someone else's key can't read it
+        let other = FullKeypair::generate();
+        assert!(decrypt_private_tags(other.to_filled(), &content).is_none());
+        let muted = Muted::from_tags(vec![tag(&["t", "foo"])], vec![]).with_sealed(content.clone());
+        assert!(!muted.can_edit_private());
+
+        // muting and unmuting publicly publishes the content unchanged
+        let muted = muted
+            .with(Mute::Pubkey([1; 32]), false)
+            .without(&Mute::Hashtag("foo".to_owned()));
+        assert!(muted.pubkeys.contains(&[1; 32]));
+        let republished = muted.encrypt_private(other.to_filled()).unwrap();
+        assert_eq!(republished, content);
+
+        let private = decrypt_private_tags(owner.to_filled(), &republished).unwrap();
+        assert_eq!(private, vec![tag(&["word", "secret"])]);
+    }
+
+    #[test]
+    fn test_with_and_without_keep_unknown_tags() {
+        let muted = Muted::from_tags(
+            vec![tag(&["alt", "mute list"]), tag(&["t", "foo"])],
+            vec![tag(&["custom", "x"])],
+        );
+
+        // muting privately what was public moves it over
+        let muted = muted.with(Mute::Hashtag("foo".to_owned()), true);
+        assert!(muted.is_private(&Mute::Hashtag("foo".to_owned())));
+        assert_eq!(muted.public_tags, vec![tag(&["alt", "mute list"])]);
+
+        let muted = muted.without(&Mute::Hashtag("foo".to_owned()));
+        assert!(!muted.contains(&Mute::Hashtag("foo".to_owned())));
+        assert_eq!(muted.private_tags, vec![tag(&["custom", "x"])]);
+        assert_eq!(muted.public_tags, vec![tag(&["alt", "mute list"])]);
+    }
+
+    #[test]
+    fn test_word_matching() {
+        let words = vec!["cat".to_owned(), "Déjà Vu".to_owned(), "日本".to_owned()];
+        let matcher = WordMatcher::new(&words);
+
+        assert!(matcher.find("my CAT is great").is_some());
+        assert!(matcher.find("cat!").is_some());
+        assert!(matcher.find("a catalog of things").is_none());
+        assert!(matcher.find("concatenate").is_none());
+
+        // phrases match as a whole, case and unicode aware
+        assert!(matcher.find("what a déjà vu moment").is_some());
+        assert!(matcher.find("déjà, vu").is_some());
+        assert!(matcher.find("déjà entendu").is_none());
+
+        assert!(matcher.find("日本 語").is_some());
+        assert!(WordMatcher::default().find("cat").is_none());
+    }
+
+    #[test]
+    fn test_hashtags_are_case_insensitive() {
+        let muted = Muted::from_tags(vec![tag(&["t", "Bitcoin"])], vec![]);
+        assert!(muted.hashtag_matcher.contains("bitcoin"));
+        assert!(muted.hashtags.contains("Bitcoin"));
+    }
 }
@@ -1,5 +1,5 @@
 use crate::notecache::NoteCache;
-use nostrdb::{Ndb, Note, NoteKey, QueryResult, Transaction};
+use nostrdb::{Ndb, Note, NoteKey, QueryResult, Tag, Transaction};
 use std::cmp::Ordering;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -71,3 +71,17 @@ pub fn root_note_id_from_selected_id<'a>(
         .root()
         .map_or_else(|| selected_note_id, |nr| nr.id)
 }
+
+/// A tag's values as strings, ids as hex
+pub fn tag_strings(tag: &Tag) -> Vec<String> {
+    let mut strings = Vec::new();
+    for i in 0..tag.count() {
+        let variant = tag.get_unchecked(i).variant();
+        if let Some(s) = variant.str() {
+            strings.push(s.to_owned());
+        } else if let Some(id) = variant.id() {
+            strings.push(hex::encode(id));
+        }
+    }
+    strings
+}
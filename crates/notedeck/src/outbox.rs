@@ -0,0 +1,324 @@
+//! Outbox model relay selection. Users advertise where they publish
+//! (write relays) and where they want to be reached (read relays) in
+//! their NIP-65 kind 10002 lists. We read their notes from the former
+//! and deliver mentions to the latter.
+
+use crate::accounts::AccountRelayData;
+use crate::RelaySpec;
+use enostr::{Filter, Pubkey};
+use nostrdb::{Ndb, Note, NoteBuilder, Transaction};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use tracing::error;
+
+/// How many write relays we pick for each author. Most people list
+/// several relays that carry the same notes, we don't need all of them.
+pub const DEFAULT_RELAYS_PER_AUTHOR: usize = 2;
+
+/// Parse the relays out of a NIP-65 relay list note. A url that is
+/// listed twice with different markers is merged into one entry.
+pub fn nip65_relays(note: &Note) -> Vec<RelaySpec> {
+    let mut relays: BTreeMap<String, RelaySpec> = BTreeMap::new();
+
+    for tag in note.tags() {
+        match tag.get(0).and_then(|t| t.variant().str()) {
+            Some("r") => {
+                let url = if let Some(url) = tag.get(1).and_then(|f| f.variant().str()) {
+                    AccountRelayData::canonicalize_url(url)
+                } else {
+                    continue;
+                };
+                let marker = tag.get(2).and_then(|f| f.variant().str());
+                let spec = RelaySpec::from_marker(url.clone(), marker);
+
+                relays
+                    .entry(url)
+                    .and_modify(|existing| {
+                        // read + write is the same as no marker
+                        if existing.has_read_marker != spec.has_read_marker {
+                            existing.has_read_marker = false;
+                            existing.has_write_marker = false;
+                        }
+                    })
+                    .or_insert(spec);
+            }
+            Some("alt") => {
+                // ignore for now
+            }
+            Some(x) => {
+                error!("nip65_relays: unexpected tag type: {}", x);
+            }
+            None => {
+                error!("nip65_relays: invalid tag");
+            }
+        }
+    }
+
+    relays.into_values().collect()
+}
+
+/// An unsigned NIP-65 relay list advertising `relays`
+pub fn nip65_note<'a>(relays: impl IntoIterator<Item = &'a RelaySpec>) -> NoteBuilder<'a> {
+    let mut builder = NoteBuilder::new().kind(10002).content("");
+    for spec in relays {
+        builder = builder.start_tag().tag_str("r").tag_str(&spec.url);
+        if let Some(marker) = spec.marker() {
+            builder = builder.tag_str(marker);
+        }
+    }
+
+    builder
+}
+
+/// The filter for a set of users' NIP-65 relay lists
+pub fn relay_lists_filter<'a>(pubkeys: impl IntoIterator<Item = &'a [u8; 32]>) -> Filter {
+    Filter::new().authors(pubkeys).kinds([10002]).build()
+}
+
+/// The latest NIP-65 relay list we have for a user, if any
+pub fn relay_list(ndb: &Ndb, txn: &Transaction, pubkey: &[u8; 32]) -> Option<Vec<RelaySpec>> {
+    let filter = Filter::new()
+        .authors([pubkey])
+        .kinds([10002])
+        .limit(1)
+        .build();
+
+    let results = ndb.query(txn, &[filter], 1).ok()?;
+    results.first().map(|qr| nip65_relays(&qr.note))
+}
+
+/// The relays a set of users want to be reached at. Replies and mentions
+/// should be delivered here so that the people involved see them.
+pub fn inbox_relays<'a>(
+    ndb: &Ndb,
+    txn: &Transaction,
+    pubkeys: impl IntoIterator<Item = &'a [u8; 32]>,
+) -> BTreeSet<String> {
+    let mut relays = BTreeSet::new();
+    for pubkey in pubkeys {
+        if let Some(list) = relay_list(ndb, txn, pubkey) {
+            relays.extend(
+                list.into_iter()
+                    .filter(|spec| spec.is_readable())
+                    .map(|spec| spec.url),
+            );
+        }
+    }
+    relays
+}
+
+/// NIP-17 lists of the relays users want their direct messages sent to
+pub const DM_RELAYS_KIND: u32 = 10050;
+
+/// The filter for a set of users' DM relay lists
+pub fn dm_relay_lists_filter<'a>(pubkeys: impl IntoIterator<Item = &'a [u8; 32]>) -> Filter {
+    Filter::new()
+        .authors(pubkeys)
+        .kinds([DM_RELAYS_KIND as u64])
+        .build()
+}
+
+/// Parse the `relay` tags out of a DM relay list note
+pub fn parse_dm_relays(note: &Note) -> BTreeSet<String> {
+    let mut relays = BTreeSet::new();
+    for tag in note.tags() {
+        if tag.get(0).and_then(|t| t.variant().str()) != Some("relay") {
+            continue;
+        }
+
+        if let Some(url) = tag
+            .get(1)
+            .and_then(|f| f.variant().str())
+            .and_then(RelaySpec::parse_url)
+        {
+            relays.insert(url);
+        }
+    }
+    relays
+}
+
+/// The relays a user wants their direct messages sent to. None if we
+/// don't have their list, in which case we shouldn't message them.
+pub fn dm_relays(ndb: &Ndb, txn: &Transaction, pubkey: &[u8; 32]) -> Option<BTreeSet<String>> {
+    let filter = Filter::new()
+        .authors([pubkey])
+        .kinds([DM_RELAYS_KIND as u64])
+        .limit(1)
+        .build();
+
+    let results = ndb.query(txn, &[filter], 1).ok()?;
+    results.first().map(|qr| parse_dm_relays(&qr.note))
+}
+
+/// Where to read notes from a set of authors
+#[derive(Debug, Default)]
+pub struct OutboxRelays {
+    /// The authors we will find on each relay
+    pub relays: BTreeMap<String, BTreeSet<Pubkey>>,
+
+    /// Authors we don't have a relay list for yet
+    pub missing: BTreeSet<Pubkey>,
+}
+
+impl OutboxRelays {
+    /// Pick up to `per_author` write relays for every author. Relays that
+    /// many of the authors share are preferred, so that we end up
+    /// connecting to as few relays as possible.
+    pub fn for_authors<'a>(
+        ndb: &Ndb,
+        txn: &Transaction,
+        authors: impl IntoIterator<Item = &'a [u8; 32]>,
+        per_author: usize,
+    ) -> Self {
+        let mut outbox = OutboxRelays::default();
+        let mut lists: Vec<(Pubkey, Vec<String>)> = Vec::new();
+
+        for author in authors {
+            let pk = Pubkey::new(*author);
+            match relay_list(ndb, txn, author) {
+                Some(list) => {
+                    let write: Vec<String> = list
+                        .into_iter()
+                        .filter(|spec| spec.is_writable())
+                        .map(|spec| spec.url)
+                        .collect();
+                    lists.push((pk, write));
+                }
+                None => {
+                    outbox.missing.insert(pk);
+                }
+            }
+        }
+
+        outbox.assign(lists, per_author);
+        outbox
+    }
+
+    fn assign(&mut self, lists: Vec<(Pubkey, Vec<String>)>, per_author: usize) {
+        let mut popularity: HashMap<&str, usize> = HashMap::new();
+        for (_, relays) in &lists {
+            for relay in relays {
+                *popularity.entry(relay).or_default() += 1;
+            }
+        }
+
+        for (pk, relays) in &lists {
+            let mut ranked: Vec<&String> = relays.iter().collect();
+            // most popular first, then by url so that we're deterministic
+            ranked.sort_by(|a, b| {
+                popularity[b.as_str()]
+                    .cmp(&popularity[a.as_str()])
+                    .then_with(|| a.cmp(b))
+            });
+
+            for relay in ranked.into_iter().take(per_author) {
+                self.relays.entry(relay.clone()).or_default().insert(*pk);
+            }
+        }
+    }
+
+    pub fn urls(&self) -> BTreeSet<String> {
+        self.relays.keys().cloned().collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.relays.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pk(b: u8) -> Pubkey {
+        Pubkey::new([b; 32])
+    }
+
+    #[test]
+    fn test_nip65_note_roundtrip() {
+        let keypair = enostr::FullKeypair::generate();
+        let seckey = keypair.secret_key.to_secret_bytes();
+        let relays = vec![
+            RelaySpec::new("wss://both.example.com/", false, false),
+            RelaySpec::new("wss://read.example.com/", true, false),
+            RelaySpec::new("wss://write.example.com/", false, true),
+        ];
+
+        let note = nip65_note(&relays).sign(&seckey).build().unwrap();
+        assert_eq!(note.kind(), 10002);
+
+        let parsed = nip65_relays(&note);
+        assert_eq!(parsed.len(), 3);
+        for (a, b) in relays.iter().zip(parsed.iter()) {
+            assert_eq!(a.url, b.url);
+            assert_eq!(a.marker(), b.marker());
+        }
+    }
+
+    #[test]
+    fn test_parse_dm_relays() {
+        let keypair = enostr::FullKeypair::generate();
+        let seckey = keypair.secret_key.to_secret_bytes();
+        let note = NoteBuilder::new()
+            .kind(DM_RELAYS_KIND)
+            .content("")
+            .start_tag()
+            .tag_str("relay")
+            .tag_str("wss://inbox.example.com")
+            .start_tag()
+            .tag_str("relay")
+            .tag_str("https://not-a-relay.example.com")
+            .start_tag()
+            .tag_str("r")
+            .tag_str("wss://nip65.example.com")
+            .sign(&seckey)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            parse_dm_relays(&note),
+            ["wss://inbox.example.com/".to_string()]
+                .into_iter()
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_assign_prefers_shared_relays() {
+        let mut outbox = OutboxRelays::default();
+        outbox.assign(
+            vec![
+                (
+                    pk(1),
+                    vec!["wss://a/".to_string(), "wss://shared/".to_string()],
+                ),
+                (
+                    pk(2),
+                    vec!["wss://b/".to_string(), "wss://shared/".to_string()],
+                ),
+                (pk(3), vec!["wss://c/".to_string()]),
+            ],
+            1,
+        );
+
+        assert_eq!(
+            outbox.urls(),
+            ["wss://c/", "wss://shared/"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect()
+        );
+        assert_eq!(outbox.relays["wss://shared/"].len(), 2);
+    }
+
+    #[test]
+    fn test_relay_spec_markers() {
+        let read = RelaySpec::from_marker("wss://r/", Some("read"));
+        let write = RelaySpec::from_marker("wss://w/", Some("write"));
+        let both = RelaySpec::from_marker("wss://b/", None);
+
+        assert!(read.is_readable() && !read.is_writable());
+        assert!(!write.is_readable() && write.is_writable());
+        assert!(both.is_readable() && both.is_writable());
+        assert_eq!(both.marker(), None);
+    }
+}
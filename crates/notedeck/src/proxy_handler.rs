@@ -0,0 +1,127 @@
+use enostr::ProxyConfig;
+use tracing::{error, info};
+
+use crate::{storage, DataPath, DataPathType, Directory};
+
+/// Loads and saves the proxy settings. Command line arguments win over
+/// what's saved here, and are saved in turn.
+pub struct ProxyHandler {
+    directory: Directory,
+}
+
+const PROXY_FILE: &str = "proxy.json";
+
+impl ProxyHandler {
+    pub fn new(path: &DataPath) -> Self {
+        let directory = Directory::new(path.path(DataPathType::Setting));
+        Self { directory }
+    }
+
+    pub fn load(&self) -> ProxyConfig {
+        let contents = match self.directory.get_file(PROXY_FILE.to_owned()) {
+            Ok(contents) => contents,
+            Err(e) => {
+                info!("No saved proxy settings ({:?}), connecting directly", e);
+                return ProxyConfig::default();
+            }
+        };
+
+        match serde_json::from_str(&contents) {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Could not deserialize proxy settings: {}", e);
+                ProxyConfig::default()
+            }
+        }
+    }
+
+    /// The saved settings with the command line's on top. When that
+    /// changes anything it's saved, so it sticks after a restart without
+    /// the arguments. `no_proxy` drops the saved settings first, which is
+    /// the only way to turn a saved proxy back off.
+    pub fn load_with_args(&self, args: &ProxyConfig, no_proxy: bool) -> ProxyConfig {
+        let saved = self.load();
+        let mut proxy = if no_proxy {
+            ProxyConfig::default()
+        } else {
+            saved.clone()
+        };
+        if args.socks5.is_some() {
+            proxy.socks5 = args.socks5.clone();
+        }
+        proxy.proxy_only |= args.proxy_only;
+
+        if proxy != saved {
+            self.save(&proxy);
+        }
+        proxy
+    }
+
+    pub fn save(&self, config: &ProxyConfig) {
+        let json = match serde_json::to_string(config) {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Could not serialize proxy settings: {}", e);
+                return;
+            }
+        };
+
+        match storage::write_file(&self.directory.file_path, PROXY_FILE.to_owned(), &json) {
+            Ok(_) => info!("Saved proxy settings to {}", PROXY_FILE),
+            Err(_) => error!("Could not save proxy settings to {}", PROXY_FILE),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_settings_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = DataPath::new(dir.path());
+        let handler = ProxyHandler::new(&path);
+        assert_eq!(handler.load(), ProxyConfig::default());
+
+        let config = ProxyConfig {
+            socks5: Some("127.0.0.1:9050".to_owned()),
+            proxy_only: true,
+        };
+        handler.save(&config);
+        assert_eq!(ProxyHandler::new(&path).load(), config);
+    }
+
+    #[test]
+    fn test_command_line_proxy_is_saved() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = DataPath::new(dir.path());
+
+        let args = ProxyConfig::socks5("127.0.0.1:9050");
+        assert_eq!(ProxyHandler::new(&path).load_with_args(&args, false), args);
+
+        // started again without the arguments
+        let proxy = ProxyHandler::new(&path).load_with_args(&ProxyConfig::default(), false);
+        assert_eq!(proxy, args);
+    }
+
+    #[test]
+    fn test_no_proxy_clears_saved_proxy() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = DataPath::new(dir.path());
+
+        let args = ProxyConfig {
+            socks5: Some("127.0.0.1:9050".to_owned()),
+            proxy_only: true,
+        };
+        assert_eq!(ProxyHandler::new(&path).load_with_args(&args, false), args);
+
+        let proxy = ProxyHandler::new(&path).load_with_args(&ProxyConfig::default(), true);
+        assert_eq!(proxy, ProxyConfig::default());
+        assert!(proxy.allows_direct());
+
+        // and it stays cleared on the next start
+        let proxy = ProxyHandler::new(&path).load_with_args(&ProxyConfig::default(), false);
+        assert_eq!(proxy, ProxyConfig::default());
+    }
+}
@@ -0,0 +1,120 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+/// A relay from a NIP-65 list, along with its read/write markers. A
+/// relay without any marker is used for both reading and writing.
+#[derive(Debug, Clone)]
+pub struct RelaySpec {
+    pub url: String,
+    pub has_read_marker: bool,
+    pub has_write_marker: bool,
+}
+
+impl RelaySpec {
+    pub fn new(url: impl Into<String>, has_read_marker: bool, has_write_marker: bool) -> Self {
+        RelaySpec {
+            url: url.into(),
+            has_read_marker,
+            has_write_marker,
+        }
+    }
+
+    /// Parse the marker from an `["r", <url>, <marker>]` tag
+    pub fn from_marker(url: impl Into<String>, marker: Option<&str>) -> Self {
+        match marker {
+            Some("read") => RelaySpec::new(url, true, false),
+            Some("write") => RelaySpec::new(url, false, true),
+            _ => RelaySpec::new(url, false, false),
+        }
+    }
+
+    /// The user reads from this relay, others should deliver mentions here
+    pub fn is_readable(&self) -> bool {
+        !self.has_write_marker
+    }
+
+    /// The user publishes their notes to this relay
+    pub fn is_writable(&self) -> bool {
+        !self.has_read_marker
+    }
+
+    /// A websocket relay url typed in by the user, in canonical form.
+    /// `wss://` is assumed when there's no scheme.
+    pub fn parse_url(input: &str) -> Option<String> {
+        let input = input.trim();
+        let with_scheme = if input.contains("://") {
+            input.to_owned()
+        } else {
+            format!("wss://{}", input)
+        };
+
+        let url = url::Url::parse(&with_scheme).ok()?;
+        if !matches!(url.scheme(), "ws" | "wss") || url.host_str().map_or(true, str::is_empty) {
+            return None;
+        }
+
+        Some(url.to_string())
+    }
+
+    pub fn marker(&self) -> Option<&'static str> {
+        match (self.has_read_marker, self.has_write_marker) {
+            (true, false) => Some("read"),
+            (false, true) => Some("write"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for RelaySpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.marker() {
+            Some(marker) => write!(f, "{} [{}]", self.url, marker),
+            None => write!(f, "{}", self.url),
+        }
+    }
+}
+
+// Relay specs are keyed by url, so a set can only contain a url once
+impl PartialEq for RelaySpec {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url
+    }
+}
+
+impl Eq for RelaySpec {}
+
+impl Ord for RelaySpec {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.url.cmp(&other.url)
+    }
+}
+
+impl PartialOrd for RelaySpec {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_url() {
+        assert_eq!(
+            RelaySpec::parse_url("wss://relay.damus.io"),
+            Some("wss://relay.damus.io/".to_owned())
+        );
+        assert_eq!(
+            RelaySpec::parse_url(" nos.lol "),
+            Some("wss://nos.lol/".to_owned())
+        );
+        assert_eq!(
+            RelaySpec::parse_url("ws://localhost:8080"),
+            Some("ws://localhost:8080/".to_owned())
+        );
+        assert_eq!(RelaySpec::parse_url("https://relay.damus.io"), None);
+        assert_eq!(RelaySpec::parse_url("wss://"), None);
+        assert_eq!(RelaySpec::parse_url(""), None);
+    }
+}
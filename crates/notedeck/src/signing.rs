@@ -0,0 +1,369 @@
+use crate::storage::{delete_file, write_file};
+use crate::{Directory, KeyCipher};
+use enostr::nip46::{BunkerEvent, BunkerSession, BunkerUri};
+use enostr::{FullKeypair, Note, ProxyConfig, Pubkey, SerializableKeypair};
+use poll_promise::Promise;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+
+/// Where a note goes once it's signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    /// Our relays, plus the read relays of everyone the note tags
+    Outbox,
+    /// Only these relays
+    Relays(Vec<String>),
+    /// It answers this relay's auth challenge
+    Auth(String),
+}
+
+/// Why a note couldn't be signed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignError {
+    /// We have neither the account's secret key nor a remote signer for it
+    NoSigner,
+    /// The signer couldn't sign, encrypt or decrypt
+    Sign(String),
+}
+
+impl std::fmt::Display for SignError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignError::NoSigner => write!(f, "no way to sign for this account"),
+            SignError::Sign(err) => write!(f, "signer failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SignError {}
+
+/// A remote signer's answer to a request whose caller waits on it
+/// itself, see [`crate::Accounts::take_answer`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Answer {
+    Signed(Note),
+    /// Encrypted or decrypted text
+    Crypted(String),
+    Failed(String),
+}
+
+/// What we keep around to pick a remote signing session back up
+#[derive(Serialize, Deserialize)]
+struct SavedSession {
+    uri: BunkerUri,
+    client: SerializableKeypair,
+    user: Pubkey,
+}
+
+/// NIP-46 sessions for the accounts whose keys live in a remote signer,
+/// and the notes we're waiting on them to sign
+#[derive(Default)]
+pub struct Bunkers {
+    sessions: HashMap<Pubkey, BunkerSession>,
+    /// Saved sessions whose client key is being decrypted
+    restoring: HashMap<Pubkey, Promise<Option<(SavedSession, FullKeypair)>>>,
+    /// Where each requested note goes, by request id
+    pending: HashMap<String, Destination>,
+    /// Requests whose callers pick up the answer, and the answers that
+    /// came back so far
+    awaited: HashMap<String, Option<Answer>>,
+    /// Requests the signer holds until the user approves them at a url
+    approvals: HashMap<String, String>,
+    /// When each request we're waiting on went out
+    sent: HashMap<String, Instant>,
+    /// Relays whose auth challenge the signer refused to answer
+    refused_auths: Vec<String>,
+    /// Where sessions are saved, if anywhere
+    storage: Option<Directory>,
+}
+
+impl Bunkers {
+    /// How long we wait for a signer to answer, including the time it
+    /// takes the user to approve the request
+    fn request_timeout() -> Duration {
+        Duration::from_secs(120)
+    }
+
+    pub fn set_storage(&mut self, directory: Directory) {
+        self.storage = Some(directory);
+    }
+
+    /// Sign for `session`'s user with it from now on. It's saved encrypted
+    /// with `cipher`, or once the keys are unlocked if there's none yet.
+    pub fn add(&mut self, session: BunkerSession, cipher: Option<&KeyCipher>) {
+        let user = if let Some(user) = session.user() {
+            *user
+        } else {
+            error!("not adding a remote signer that isn't connected");
+            return;
+        };
+
+        if let Some(cipher) = cipher {
+            self.save(&user, &session, cipher);
+        }
+
+        info!("signing for {} with a remote signer", user);
+        self.sessions.insert(user, session);
+    }
+
+    /// Save every session again encrypted with `cipher`, after the keys
+    /// were unlocked or got a new passphrase
+    pub fn save_all(&self, cipher: &KeyCipher) {
+        for (user, session) in &self.sessions {
+            self.save(user, session, cipher);
+        }
+    }
+
+    /// Pick up a saved session for `user`, if there is one. Its client key
+    /// is decrypted on another thread, and [`Bunkers::poll`] reconnects
+    /// once it's ready.
+    pub fn restore(&mut self, user: &Pubkey, cipher: &KeyCipher) {
+        if self.sessions.contains_key(user) || self.restoring.contains_key(user) {
+            return;
+        }
+
+        let saved = if let Some(saved) = self.storage.as_ref().and_then(|s| load_session(s, user)) {
+            saved
+        } else {
+            return;
+        };
+
+        let (sender, promise) = Promise::new();
+        let cipher = cipher.clone();
+        std::thread::spawn(move || {
+            let client = cipher
+                .decrypt(&saved.client)
+                .ok()
+                .and_then(|client| client.to_full().map(|client| client.to_full()));
+            if client.is_none() {
+                error!(
+                    "could not decrypt the client key of {}'s remote signer session",
+                    saved.user
+                );
+            }
+            sender.send(client.map(|client| (saved, client)));
+        });
+        self.restoring.insert(*user, promise);
+    }
+
+    /// Forget `user`'s session, including the saved one
+    pub fn remove(&mut self, user: &Pubkey) {
+        let restoring = self.restoring.remove(user).is_some();
+        if self.sessions.remove(user).is_none() && !restoring {
+            return;
+        }
+
+        if let Some(storage) = &self.storage {
+            if let Err(err) = delete_file(&storage.file_path, session_file_name(user)) {
+                error!("could not delete remote signer session: {}", err);
+            }
+        }
+    }
+
+    pub fn has(&self, user: &Pubkey) -> bool {
+        self.sessions.contains_key(user)
+    }
+
+    pub fn get_mut(&mut self, user: &Pubkey) -> Option<&mut BunkerSession> {
+        self.sessions.get_mut(user)
+    }
+
+    /// `request` will come back signed, send it to `destination` then
+    pub fn requested(&mut self, request: String, destination: Destination) {
+        self.sent.insert(request.clone(), Instant::now());
+        self.pending.insert(request, destination);
+    }
+
+    /// Keep the answer to `request` until it's taken
+    pub fn awaited(&mut self, request: String) {
+        self.sent.insert(request.clone(), Instant::now());
+        self.awaited.insert(request, None);
+    }
+
+    /// The answer to an awaited request, once it's here
+    pub fn take_answer(&mut self, request: &str) -> Option<Answer> {
+        self.awaited.get(request)?.as_ref()?;
+        self.awaited.remove(request).flatten()
+    }
+
+    /// Where the user approves the requests their signers are holding
+    pub fn approval_urls(&self) -> BTreeSet<&str> {
+        self.approvals.values().map(String::as_str).collect()
+    }
+
+    /// Give up on the requests the signers didn't answer in time.
+    /// Returns the relays whose auth challenges won't be answered, these
+    /// or ones the signers refused.
+    pub fn expire(&mut self) -> Vec<String> {
+        let expired: Vec<String> = self
+            .sent
+            .iter()
+            .filter(|(_, sent)| sent.elapsed() > Self::request_timeout())
+            .map(|(request, _)| request.clone())
+            .collect();
+
+        let mut auth_relays = std::mem::take(&mut self.refused_auths);
+        for request in expired {
+            warn!("remote signer didn't answer request {} in time", request);
+            for session in self.sessions.values_mut() {
+                session.forget(&request);
+            }
+
+            if let Some(Destination::Auth(relay)) = self.pending.remove(&request) {
+                auth_relays.push(relay);
+            }
+            self.answered(
+                &request,
+                Answer::Failed("the remote signer didn't answer".to_owned()),
+            );
+        }
+        auth_relays
+    }
+
+    /// Reconnect the saved sessions whose client key got decrypted
+    fn finish_restoring(
+        &mut self,
+        proxy: &ProxyConfig,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    ) {
+        let ready: Vec<Pubkey> = self
+            .restoring
+            .iter()
+            .filter(|(_, promise)| promise.ready().is_some())
+            .map(|(user, _)| *user)
+            .collect();
+
+        for user in ready {
+            let restored = self
+                .restoring
+                .remove(&user)
+                .and_then(Promise::block_and_take);
+            let (saved, client) = if let Some(restored) = restored {
+                restored
+            } else {
+                continue;
+            };
+
+            match BunkerSession::restore(saved.uri, client, saved.user, proxy, wakeup.clone()) {
+                Ok(session) => {
+                    info!("restored remote signer session for {}", user);
+                    self.sessions.insert(user, session);
+                }
+                Err(err) => error!("could not restore remote signer session: {}", err),
+            }
+        }
+    }
+
+    /// Save `session` on another thread, encrypting its client key is slow
+    fn save(&self, user: &Pubkey, session: &BunkerSession, cipher: &KeyCipher) {
+        let storage = if let Some(storage) = &self.storage {
+            storage.clone()
+        } else {
+            return;
+        };
+
+        let uri = session.uri().clone();
+        let client = session.client().clone().to_keypair();
+        let user = *user;
+        let cipher = cipher.clone();
+        std::thread::spawn(move || {
+            let saved = SavedSession {
+                uri,
+                client: cipher.encrypt(&client),
+                user,
+            };
+            if let Err(err) = save_session(&storage, &saved) {
+                error!("could not save remote signer session: {}", err);
+            }
+        });
+    }
+
+    fn answered(&mut self, request: &str, answer: Answer) {
+        self.sent.remove(request);
+        self.approvals.remove(request);
+        if let Some(slot) = self.awaited.get_mut(request) {
+            *slot = Some(answer);
+        }
+    }
+
+    /// Hear back from the signers, whose relays go through `proxy` like
+    /// ours. Returns the notes they signed, along with where they go.
+    pub fn poll(
+        &mut self,
+        proxy: &ProxyConfig,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    ) -> Vec<(Note, Destination)> {
+        self.finish_restoring(proxy, wakeup.clone());
+
+        let mut events = Vec::new();
+        for (user, session) in &mut self.sessions {
+            session.set_proxy(proxy);
+            events.extend(
+                session
+                    .poll(wakeup.clone())
+                    .into_iter()
+                    .map(|event| (*user, event)),
+            );
+        }
+
+        let mut signed = Vec::new();
+        for (user, event) in events {
+            match event {
+                BunkerEvent::Signed { request, note } => {
+                    if let Some(destination) = self.pending.remove(&request) {
+                        self.sent.remove(&request);
+                        self.approvals.remove(&request);
+                        signed.push((note, destination));
+                    } else {
+                        self.answered(&request, Answer::Signed(note));
+                    }
+                }
+                BunkerEvent::SignFailed { request, error } => {
+                    if let Some(Destination::Auth(relay)) = self.pending.remove(&request) {
+                        self.refused_auths.push(relay);
+                    }
+                    error!("remote signer for {} didn't sign: {}", user, error);
+                    self.answered(&request, Answer::Failed(error));
+                }
+                BunkerEvent::Crypted { request, text } => {
+                    self.answered(&request, Answer::Crypted(text));
+                }
+                BunkerEvent::CryptFailed { request, error } => {
+                    error!("remote signer for {} didn't answer: {}", user, error);
+                    self.answered(&request, Answer::Failed(error));
+                }
+                BunkerEvent::AuthUrl { request, url } => {
+                    warn!("remote signer for {} wants approval at {}", user, url);
+                    self.approvals.insert(request, url);
+                }
+                BunkerEvent::Connected(_) | BunkerEvent::ConnectFailed(_) => {}
+            }
+        }
+        signed
+    }
+}
+
+fn session_file_name(user: &Pubkey) -> String {
+    user.hex()
+}
+
+fn load_session(storage: &Directory, user: &Pubkey) -> Option<SavedSession> {
+    let json = storage.get_file(session_file_name(user)).ok()?;
+    match serde_json::from_str(&json) {
+        Ok(saved) => Some(saved),
+        Err(err) => {
+            error!("could not parse remote signer session: {}", err);
+            None
+        }
+    }
+}
+
+fn save_session(storage: &Directory, saved: &SavedSession) -> crate::Result<()> {
+    write_file(
+        &storage.file_path,
+        session_file_name(&saved.user),
+        &serde_json::to_string(saved)?,
+    )
+}
@@ -1,5 +1,7 @@
-use crate::Result;
-use enostr::{Keypair, Pubkey, SerializableKeypair};
+use crate::{Error, Result};
+use enostr::{FullKeypair, Keypair, Pubkey, SerializableKeypair};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
 
 use super::{
     file_storage::{delete_file, write_file, Directory},
@@ -8,38 +10,279 @@ use super::{
 
 static SELECTED_PUBKEY_FILE_NAME: &str = "selected_pubkey";
 
+/// Sits next to the keys once they're encrypted, so we can tell a wrong
+/// passphrase even without any secret keys stored
+static PASSPHRASE_CHECK_FILE_NAME: &str = "passphrase_check";
+
+/// Key files being rewritten are written under this suffix first, and
+/// renamed over the old ones once they all are
+static TEMP_FILE_SUFFIX: &str = ".tmp";
+
+/// NIP-49 scrypt cost for keys encrypted with the user's passphrase.
+/// Without one they're encrypted with an empty passphrase, cheaply.
+const ENCRYPTED_LOG_N: u8 = 16;
+const UNENCRYPTED_LOG_N: u8 = 7;
+
 /// An OS agnostic file key storage implementation
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct FileKeyStorage {
     keys_directory: Directory,
     selected_key_directory: Directory,
+    /// What the secret keys are encrypted with, once unlocked
+    passphrase: Option<Passphrase>,
+    /// Whether there's a passphrase check stored, so we don't look on
+    /// every frame
+    encrypted: bool,
+    log_n: u8,
+}
+
+#[derive(Clone, PartialEq)]
+struct Passphrase(String);
+
+impl std::fmt::Debug for Passphrase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Passphrase(<hidden>)")
+    }
+}
+
+/// Encrypts secret keys kept outside the key storage, like remote signer
+/// client keys, the same way the storage encrypts its own
+#[derive(Clone)]
+pub struct KeyCipher {
+    passphrase: Option<Passphrase>,
+    log_n: u8,
+}
+
+impl KeyCipher {
+    /// For key storages without a passphrase of ours
+    pub fn unencrypted() -> Self {
+        Self {
+            passphrase: None,
+            log_n: UNENCRYPTED_LOG_N,
+        }
+    }
+
+    pub fn encrypt(&self, key: &Keypair) -> SerializableKeypair {
+        match &self.passphrase {
+            Some(Passphrase(passphrase)) => {
+                SerializableKeypair::from_keypair(key, passphrase, self.log_n)
+            }
+            None => SerializableKeypair::from_keypair(key, "", UNENCRYPTED_LOG_N),
+        }
+    }
+
+    /// Keys stored before there was a passphrase decrypt too
+    pub fn decrypt(&self, stored: &SerializableKeypair) -> Result<Keypair> {
+        let decrypted = match &self.passphrase {
+            Some(Passphrase(passphrase)) => {
+                stored.decrypt(passphrase).or_else(|_| stored.decrypt(""))
+            }
+            None => stored.decrypt(""),
+        };
+        decrypted.map_err(|err| Error::Generic(err.to_string()))
+    }
+}
+
+/// A throwaway key encrypted with the passphrase
+#[derive(Serialize, Deserialize)]
+struct PassphraseCheck {
+    ncryptsec: String,
 }
 
 impl FileKeyStorage {
     pub fn new(keys_directory: Directory, selected_key_directory: Directory) -> Self {
-        Self {
+        Self::with_log_n(keys_directory, selected_key_directory, ENCRYPTED_LOG_N)
+    }
+
+    fn with_log_n(keys_directory: Directory, selected_key_directory: Directory, log_n: u8) -> Self {
+        let mut storage = Self {
             keys_directory,
             selected_key_directory,
+            passphrase: None,
+            encrypted: false,
+            log_n,
+        };
+        storage.encrypted = storage.passphrase_check().is_some();
+        storage
+    }
+
+    /// Whether the secret keys are encrypted with a passphrase
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// Whether the secret keys are encrypted and we don't have the
+    /// passphrase yet. Until unlocked, keys load without their secrets.
+    pub fn is_locked(&self) -> bool {
+        self.passphrase.is_none() && self.is_encrypted()
+    }
+
+    /// Check `passphrase` and use it for the keys from now on. Keys still
+    /// stored unencrypted get encrypted with it.
+    pub fn unlock(&mut self, passphrase: &str) -> Result<()> {
+        let check = if let Some(check) = self.passphrase_check() {
+            check
+        } else {
+            return Err(Error::Generic("the keys aren't encrypted".to_owned()));
+        };
+
+        if FullKeypair::from_ncryptsec(&check.ncryptsec, passphrase).is_err() {
+            return Err(Error::Generic("wrong passphrase".to_owned()));
+        }
+
+        self.passphrase = Some(Passphrase(passphrase.to_owned()));
+        self.migrate()
+    }
+
+    /// Encrypt the keys with `passphrase` instead, or for the first time
+    pub fn change_passphrase(&mut self, passphrase: &str) -> Result<()> {
+        if passphrase.is_empty() {
+            return Err(Error::Generic("the passphrase can't be empty".to_owned()));
+        }
+        if self.is_locked() {
+            return Err(Error::Generic("unlock the keys first".to_owned()));
+        }
+
+        let (keys, undecryptable) = self.decrypt_keys()?;
+        if !undecryptable.is_empty() {
+            // rewriting them would lose their secret keys for good
+            return Err(Error::Generic(format!(
+                "couldn't decrypt the keys for {}",
+                pubkey_list(&undecryptable)
+            )));
+        }
+
+        let mut files = Vec::new();
+        for key in &keys {
+            let serializable = SerializableKeypair::from_keypair(key, passphrase, self.log_n);
+            files.push((key.pubkey.hex(), serde_json::to_string(&serializable)?));
+        }
+        let check = PassphraseCheck {
+            ncryptsec: FullKeypair::generate()
+                .to_ncryptsec(passphrase, self.log_n)
+                .map_err(|err| Error::Generic(err.to_string()))?,
+        };
+        files.push((
+            PASSPHRASE_CHECK_FILE_NAME.to_owned(),
+            serde_json::to_string(&check)?,
+        ));
+
+        // nothing is replaced until everything is written, so a failure
+        // part way leaves the old passphrase working
+        let dir = &self.keys_directory.file_path;
+        for (name, json) in &files {
+            write_file(dir, format!("{}{}", name, TEMP_FILE_SUFFIX), json)?;
+        }
+        // the check goes last, it's what decides the passphrase
+        for (name, _) in &files {
+            std::fs::rename(
+                dir.join(format!("{}{}", name, TEMP_FILE_SUFFIX)),
+                dir.join(name),
+            )?;
+        }
+
+        self.passphrase = Some(Passphrase(passphrase.to_owned()));
+        self.encrypted = true;
+        Ok(())
+    }
+
+    /// What the keys are encrypted with, unless they're locked
+    pub fn cipher(&self) -> Option<KeyCipher> {
+        if self.is_locked() {
+            return None;
+        }
+
+        Some(KeyCipher {
+            passphrase: self.passphrase.clone(),
+            log_n: self.log_n,
+        })
+    }
+
+    fn passphrase_check(&self) -> Option<PassphraseCheck> {
+        let json = self
+            .keys_directory
+            .get_file(PASSPHRASE_CHECK_FILE_NAME.to_owned())
+            .ok()?;
+        serde_json::from_str(&json).ok()
+    }
+
+    /// Encrypt the keys written before there was a passphrase
+    fn migrate(&self) -> Result<()> {
+        for stored in self.stored_keys()? {
+            if stored.encrypted_secret_key.is_none() {
+                continue;
+            }
+
+            if let Ok(key) = stored.decrypt("") {
+                info!("encrypting stored key for {}", key.pubkey);
+                self.add_key_internal(&key)?;
+            }
         }
+        Ok(())
+    }
+
+    fn stored_keys(&self) -> Result<Vec<SerializableKeypair>> {
+        let keys = self
+            .keys_directory
+            .get_files()?
+            .into_iter()
+            .filter(|(name, _)| {
+                name != PASSPHRASE_CHECK_FILE_NAME && !name.ends_with(TEMP_FILE_SUFFIX)
+            })
+            .filter_map(|(_, str_key)| serde_json::from_str::<SerializableKeypair>(&str_key).ok())
+            .collect();
+        Ok(keys)
     }
 
     fn add_key_internal(&self, key: &Keypair) -> Result<()> {
+        let serializable = if let Some(cipher) = self.cipher() {
+            cipher.encrypt(key)
+        } else if key.secret_key.is_some() {
+            return Err(Error::Generic(
+                "unlock the keys before storing a secret key".to_owned(),
+            ));
+        } else {
+            KeyCipher::unencrypted().encrypt(key)
+        };
+
         write_file(
             &self.keys_directory.file_path,
             key.pubkey.hex(),
-            &serde_json::to_string(&SerializableKeypair::from_keypair(key, "", 7))?,
+            &serde_json::to_string(&serializable)?,
         )
     }
 
     fn get_keys_internal(&self) -> Result<Vec<Keypair>> {
-        let keys = self
-            .keys_directory
-            .get_files()?
-            .values()
-            .filter_map(|str_key| serde_json::from_str::<SerializableKeypair>(str_key).ok())
-            .map(|serializable_keypair| serializable_keypair.to_keypair(""))
-            .collect();
-        Ok(keys)
+        Ok(self.decrypt_keys()?.0)
+    }
+
+    /// The stored keys, and the pubkeys of those whose secret key didn't
+    /// decrypt. Those are loaded without it.
+    pub(super) fn decrypt_keys(&self) -> Result<(Vec<Keypair>, Vec<Pubkey>)> {
+        let mut keys = Vec::new();
+        let mut undecryptable = Vec::new();
+
+        for stored in self.stored_keys()? {
+            let decrypted = if let Some(cipher) = self.cipher() {
+                cipher.decrypt(&stored)
+            } else {
+                Ok(Keypair::only_pubkey(stored.pubkey))
+            };
+
+            match decrypted {
+                Ok(key) => keys.push(key),
+                Err(err) => {
+                    error!(
+                        "could not decrypt the stored key for {}: {}",
+                        stored.pubkey, err
+                    );
+                    undecryptable.push(stored.pubkey);
+                    keys.push(Keypair::only_pubkey(stored.pubkey));
+                }
+            }
+        }
+
+        Ok((keys, undecryptable))
     }
 
     fn remove_key_internal(&self, key: &Keypair) -> Result<()> {
@@ -77,6 +320,14 @@ impl FileKeyStorage {
     }
 }
 
+fn pubkey_list(pubkeys: &[Pubkey]) -> String {
+    pubkeys
+        .iter()
+        .map(|pubkey| pubkey.hex())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 impl FileKeyStorage {
     pub fn get_keys(&self) -> KeyStorageResponse<Vec<enostr::Keypair>> {
         KeyStorageResponse::ReceivedResult(self.get_keys_internal())
@@ -112,10 +363,28 @@ mod tests {
 
     impl FileKeyStorage {
         fn mock() -> Result<Self> {
-            Ok(Self {
-                keys_directory: Directory::new(CREATE_TMP_DIR()?),
-                selected_key_directory: Directory::new(CREATE_TMP_DIR()?),
-            })
+            Ok(Self::with_log_n(
+                Directory::new(CREATE_TMP_DIR()?),
+                Directory::new(CREATE_TMP_DIR()?),
+                // cheap, these are only tests
+                UNENCRYPTED_LOG_N,
+            ))
+        }
+
+        /// The same directories, as if the app started over
+        fn reopen(&self) -> Self {
+            Self::with_log_n(
+                Directory::new(self.keys_directory.file_path.clone()),
+                Directory::new(self.selected_key_directory.file_path.clone()),
+                self.log_n,
+            )
+        }
+    }
+
+    fn get_keys(storage: &FileKeyStorage) -> Vec<Keypair> {
+        match storage.get_keys() {
+            KeyStorageResponse::ReceivedResult(Ok(keys)) => keys,
+            _ => panic!("could not get keys"),
         }
     }
 
@@ -164,4 +433,158 @@ mod tests {
 
         assert!(resp.is_ok());
     }
+
+    #[test]
+    fn test_encrypt_existing_keys() {
+        let full = enostr::FullKeypair::generate();
+        let kp = full.clone().to_keypair();
+        let mut storage = FileKeyStorage::mock().unwrap();
+        let _ = storage.add_key(&kp);
+        assert!(!storage.is_encrypted());
+
+        storage.change_passphrase("hunter2").unwrap();
+        assert!(storage.is_encrypted());
+        assert!(!storage.is_locked());
+        assert_eq!(get_keys(&storage), vec![full.clone().to_keypair()]);
+
+        // after a restart the secrets stay locked up
+        let mut storage = storage.reopen();
+        assert!(storage.is_locked());
+        assert_eq!(get_keys(&storage), vec![Keypair::only_pubkey(kp.pubkey)]);
+        assert!(storage.unlock("hunter3").is_err());
+        assert!(storage.is_locked());
+
+        storage.unlock("hunter2").unwrap();
+        assert_eq!(get_keys(&storage), vec![full.clone().to_keypair()]);
+    }
+
+    #[test]
+    fn test_no_secrets_while_locked() {
+        let mut storage = FileKeyStorage::mock().unwrap();
+        storage.change_passphrase("hunter2").unwrap();
+
+        let storage = storage.reopen();
+        let full = enostr::FullKeypair::generate();
+        let kp = full.clone().to_keypair();
+        assert!(storage.add_key_internal(&kp).is_err());
+        assert!(storage
+            .add_key_internal(&Keypair::only_pubkey(kp.pubkey))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_migrate_unencrypted_keys() {
+        let mut storage = FileKeyStorage::mock().unwrap();
+        storage.change_passphrase("hunter2").unwrap();
+
+        // a key file from before there was a passphrase
+        let full = enostr::FullKeypair::generate();
+        let kp = full.clone().to_keypair();
+        write_file(
+            &storage.keys_directory.file_path,
+            kp.pubkey.hex(),
+            &serde_json::to_string(&SerializableKeypair::from_keypair(&kp, "", 7)).unwrap(),
+        )
+        .unwrap();
+
+        let mut storage = storage.reopen();
+        storage.unlock("hunter2").unwrap();
+
+        let stored = storage.stored_keys().unwrap();
+        assert_eq!(stored.len(), 1);
+        assert!(stored[0].decrypt("").is_err());
+        assert_eq!(
+            stored[0].decrypt("hunter2").unwrap(),
+            full.clone().to_keypair()
+        );
+    }
+
+    #[test]
+    fn test_change_passphrase() {
+        let full = enostr::FullKeypair::generate();
+        let kp = full.clone().to_keypair();
+        let mut storage = FileKeyStorage::mock().unwrap();
+        storage.change_passphrase("hunter2").unwrap();
+        let _ = storage.add_key(&kp);
+
+        storage.change_passphrase("correct horse").unwrap();
+        assert!(storage.change_passphrase("").is_err());
+
+        let mut storage = storage.reopen();
+        assert!(storage.unlock("hunter2").is_err());
+        storage.unlock("correct horse").unwrap();
+        assert_eq!(get_keys(&storage), vec![full.clone().to_keypair()]);
+    }
+
+    #[test]
+    fn test_undecryptable_key() {
+        let mut storage = FileKeyStorage::mock().unwrap();
+        storage.change_passphrase("hunter2").unwrap();
+
+        // a key left encrypted with some other passphrase
+        let full = enostr::FullKeypair::generate();
+        let kp = full.clone().to_keypair();
+        write_file(
+            &storage.keys_directory.file_path,
+            kp.pubkey.hex(),
+            &serde_json::to_string(&SerializableKeypair::from_keypair(&kp, "hunter3", 7)).unwrap(),
+        )
+        .unwrap();
+
+        let mut storage = storage.reopen();
+        storage.unlock("hunter2").unwrap();
+        let (keys, undecryptable) = storage.decrypt_keys().unwrap();
+        assert_eq!(keys, vec![Keypair::only_pubkey(kp.pubkey)]);
+        assert_eq!(undecryptable, vec![kp.pubkey]);
+
+        // re-encrypting would lose it, so nothing changes
+        assert!(storage.change_passphrase("correct horse").is_err());
+        let mut storage = storage.reopen();
+        storage.unlock("hunter2").unwrap();
+        assert_eq!(
+            storage.stored_keys().unwrap()[0]
+                .decrypt("hunter3")
+                .unwrap(),
+            full.to_keypair()
+        );
+    }
+
+    #[test]
+    fn test_leftover_temp_files() {
+        let mut storage = FileKeyStorage::mock().unwrap();
+        let kp = enostr::FullKeypair::generate().to_keypair();
+        let _ = storage.add_key(&kp);
+        storage.change_passphrase("hunter2").unwrap();
+
+        // as if a passphrase change died before renaming
+        let other = enostr::FullKeypair::generate().to_keypair();
+        write_file(
+            &storage.keys_directory.file_path,
+            format!("{}{}", other.pubkey.hex(), TEMP_FILE_SUFFIX),
+            &serde_json::to_string(&SerializableKeypair::from_keypair(&other, "", 7)).unwrap(),
+        )
+        .unwrap();
+
+        let stored = storage.stored_keys().unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].pubkey, kp.pubkey);
+    }
+
+    #[test]
+    fn test_key_cipher() {
+        let kp = enostr::FullKeypair::generate().to_keypair();
+        let mut storage = FileKeyStorage::mock().unwrap();
+        let unencrypted = storage.cipher().unwrap().encrypt(&kp);
+        storage.change_passphrase("hunter2").unwrap();
+
+        let cipher = storage.cipher().unwrap();
+        let stored = cipher.encrypt(&kp);
+        assert!(stored.decrypt("").is_err());
+        assert_eq!(cipher.decrypt(&stored).unwrap(), kp);
+        // saved before there was a passphrase
+        assert_eq!(cipher.decrypt(&unencrypted).unwrap(), kp);
+
+        let storage = storage.reopen();
+        assert!(storage.cipher().is_none());
+    }
 }
@@ -29,6 +29,8 @@ pub enum DataPathType {
     Setting,
     Keys,
     SelectedKey,
+    Relays,
+    Bunkers,
     Db,
     Cache,
 }
@@ -40,6 +42,8 @@ impl DataPath {
             DataPathType::Setting => PathBuf::from("settings"),
             DataPathType::Keys => PathBuf::from("storage").join("accounts"),
             DataPathType::SelectedKey => PathBuf::from("storage").join("selected_account"),
+            DataPathType::Relays => PathBuf::from("storage").join("relays"),
+            DataPathType::Bunkers => PathBuf::from("storage").join("bunkers"),
             DataPathType::Db => PathBuf::from("db"),
             DataPathType::Cache => PathBuf::from("cache"),
         }
@@ -50,7 +54,7 @@ impl DataPath {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Directory {
     pub file_path: PathBuf,
 }
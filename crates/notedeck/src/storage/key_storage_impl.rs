@@ -1,6 +1,7 @@
 use enostr::{Keypair, Pubkey};
+use poll_promise::Promise;
 
-use super::file_key_storage::FileKeyStorage;
+use super::file_key_storage::{FileKeyStorage, KeyCipher};
 use crate::Result;
 
 #[cfg(target_os = "macos")]
@@ -14,6 +15,21 @@ pub enum KeyStorageType {
     SecurityFramework(SecurityFrameworkKeyStorage),
 }
 
+/// The file storage after a background job unlocked it or changed its
+/// passphrase, with the keys it loaded
+pub struct KeyStoreUpdate {
+    storage: FileKeyStorage,
+    keys: Vec<Keypair>,
+    undecryptable: Vec<Pubkey>,
+}
+
+impl KeyStoreUpdate {
+    /// The accounts whose secret key didn't decrypt with the passphrase
+    pub fn undecryptable(&self) -> &[Pubkey] {
+        &self.undecryptable
+    }
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub enum KeyStorageResponse<R> {
@@ -85,4 +101,83 @@ impl KeyStorageType {
             Self::SecurityFramework(_) => unimplemented!(),
         }
     }
+
+    /// Whether the secret keys are encrypted and waiting on a passphrase
+    pub fn is_locked(&self) -> bool {
+        match self {
+            Self::FileSystem(f) => f.is_locked(),
+            _ => false,
+        }
+    }
+
+    /// Whether the secret keys can be encrypted with a passphrase. The
+    /// OS keychains have their own protection.
+    pub fn can_encrypt(&self) -> bool {
+        matches!(self, Self::FileSystem(_))
+    }
+
+    /// Whether the secret keys are encrypted with a passphrase of ours
+    pub fn is_encrypted(&self) -> bool {
+        match self {
+            Self::FileSystem(f) => f.is_encrypted(),
+            _ => false,
+        }
+    }
+
+    /// What secret keys kept outside the storage get encrypted with, or
+    /// `None` while it's locked
+    pub fn key_cipher(&self) -> Option<KeyCipher> {
+        match self {
+            Self::FileSystem(f) => f.cipher(),
+            _ => Some(KeyCipher::unencrypted()),
+        }
+    }
+
+    /// Check `passphrase` and unlock the keys with it, on another thread
+    /// since scrypt is slow. Hand the result to [`KeyStorageType::apply`].
+    pub fn unlock(&self, passphrase: &str) -> Promise<Result<KeyStoreUpdate>> {
+        let passphrase = passphrase.to_owned();
+        self.in_background(move |storage| storage.unlock(&passphrase))
+    }
+
+    /// Encrypt the keys with `passphrase` instead, on another thread
+    pub fn change_passphrase(&self, passphrase: &str) -> Promise<Result<KeyStoreUpdate>> {
+        let passphrase = passphrase.to_owned();
+        self.in_background(move |storage| storage.change_passphrase(&passphrase))
+    }
+
+    /// Take on the storage a background job left behind, giving back the
+    /// keys it loaded
+    pub fn apply(&mut self, update: KeyStoreUpdate) -> Vec<Keypair> {
+        *self = Self::FileSystem(update.storage);
+        update.keys
+    }
+
+    /// Run `job` on a copy of the file storage, then load the keys with it
+    fn in_background(
+        &self,
+        job: impl FnOnce(&mut FileKeyStorage) -> Result<()> + Send + 'static,
+    ) -> Promise<Result<KeyStoreUpdate>> {
+        let (sender, promise) = Promise::new();
+
+        let mut storage = if let Self::FileSystem(f) = self {
+            f.clone()
+        } else {
+            sender.send(Err(crate::Error::Generic(
+                "this key storage doesn't use a passphrase".to_owned(),
+            )));
+            return promise;
+        };
+
+        std::thread::spawn(move || {
+            let result = job(&mut storage).and_then(|()| storage.decrypt_keys());
+            sender.send(result.map(|(keys, undecryptable)| KeyStoreUpdate {
+                storage,
+                keys,
+                undecryptable,
+            }));
+        });
+
+        promise
+    }
 }
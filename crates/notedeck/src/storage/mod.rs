@@ -1,11 +1,11 @@
 mod file_key_storage;
 mod file_storage;
 
-pub use file_key_storage::FileKeyStorage;
+pub use file_key_storage::{FileKeyStorage, KeyCipher};
 pub use file_storage::{delete_file, write_file, DataPath, DataPathType, Directory};
 
 #[cfg(target_os = "macos")]
 mod security_framework_key_storage;
 
 pub mod key_storage_impl;
-pub use key_storage_impl::{KeyStorageResponse, KeyStorageType};
+pub use key_storage_impl::{KeyStorageResponse, KeyStorageType, KeyStoreUpdate};
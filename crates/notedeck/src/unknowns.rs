@@ -255,6 +255,12 @@ pub fn get_unknown_note_ids<'a>(
     #[cfg(feature = "profiling")]
     puffin::profile_function!();
 
+    // gift wraps are signed by throwaway keys and their contents are
+    // encrypted, there is nothing here to look up
+    if note.kind() == 1059 {
+        return Ok(());
+    }
+
     // the author pubkey
     if ndb.get_profile_by_pubkey(txn, note.pubkey()).is_err() {
         ids.insert(UnknownId::Pubkey(Pubkey::new(*note.pubkey())));
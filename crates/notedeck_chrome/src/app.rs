@@ -2,7 +2,7 @@ use crate::{app_size::AppSizeHandler, setup::setup_cc, theme};
 
 use notedeck::{
     Accounts, AppContext, Args, DataPath, DataPathType, Directory, FileKeyStorage, ImageCache,
-    KeyStorageType, NoteCache, ThemeHandler, UnknownIds,
+    KeyStorageType, NoteCache, ProxyHandler, ThemeHandler, UnknownIds,
 };
 
 use enostr::RelayPool;
@@ -113,6 +113,10 @@ impl Notedeck {
         };
 
         let mut accounts = Accounts::new(keystore, parsed_args.relays);
+        if parsed_args.use_keystore {
+            accounts.set_relay_storage(Directory::new(path.path(DataPathType::Relays)));
+            accounts.set_bunker_storage(Directory::new(path.path(DataPathType::Bunkers)));
+        }
 
         let num_keys = parsed_args.keys.len();
 
@@ -133,15 +137,25 @@ impl Notedeck {
             accounts.select_account(0);
         }
 
+        // command line proxy settings win over saved ones
+        let proxy =
+            ProxyHandler::new(&path).load_with_args(&parsed_args.proxy, parsed_args.no_proxy);
+        if let Some(addr) = &proxy.socks5 {
+            info!("connecting through socks5 proxy {}", addr);
+        }
+
         // AccountManager will setup the pool on first update
-        let pool = RelayPool::new();
+        let mut pool = RelayPool::new();
+        pool.set_proxy(proxy.clone());
 
-        let img_cache = ImageCache::new(imgcache_dir);
+        let mut img_cache = ImageCache::new(imgcache_dir);
+        img_cache.proxy = proxy.clone();
         let note_cache = NoteCache::default();
         let unknown_ids = UnknownIds::default();
         let egui = ctx.clone();
         let tabs = Tabs::new(None);
-        let parsed_args = Args::parse(args);
+        let mut parsed_args = Args::parse(args);
+        parsed_args.proxy = proxy;
         let app_rect_handler = AppSizeHandler::new(&path);
 
         Self {
@@ -1,4 +1,4 @@
-use enostr::FullKeypair;
+use enostr::{FullKeypair, ProxyConfig};
 use nostrdb::Ndb;
 
 use notedeck::{Accounts, AccountsAction, AddAccountAction, ImageCache, SingleUnkIdAction};
@@ -11,6 +11,7 @@ use crate::{
     ui::{
         account_login_view::{AccountLoginResponse, AccountLoginView},
         accounts::{AccountsView, AccountsViewResponse},
+        passphrase::PassphraseViewState,
     },
 };
 use tracing::info;
@@ -29,6 +30,8 @@ pub fn render_accounts_route(
     accounts: &mut Accounts,
     decks: &mut DecksCache,
     login_state: &mut AcquireKeyState,
+    passphrase_state: &mut PassphraseViewState,
+    proxy: &ProxyConfig,
     route: AccountsRoute,
 ) -> AddAccountAction {
     let resp = match route {
@@ -37,7 +40,12 @@ pub fn render_accounts_route(
             .inner
             .map(AccountsRouteResponse::Accounts),
 
-        AccountsRoute::AddAccount => AccountLoginView::new(login_state)
+        AccountsRoute::AddAccount => AccountLoginView::new(login_state, proxy)
+            .exportable(
+                accounts
+                    .get_selected_account()
+                    .and_then(|acc| acc.to_full()),
+            )
             .ui(ui)
             .inner
             .map(AccountsRouteResponse::AddAccount),
@@ -46,7 +54,13 @@ pub fn render_accounts_route(
     if let Some(resp) = resp {
         match resp {
             AccountsRouteResponse::Accounts(response) => {
-                let action = process_accounts_view_response(accounts, decks, col, response);
+                let action = process_accounts_view_response(
+                    accounts,
+                    decks,
+                    passphrase_state,
+                    col,
+                    response,
+                );
                 AddAccountAction {
                     accounts_action: action,
                     unk_id_action: SingleUnkIdAction::no_action(),
@@ -73,6 +87,7 @@ pub fn render_accounts_route(
 pub fn process_accounts_view_response(
     accounts: &mut Accounts,
     decks: &mut DecksCache,
+    passphrase_state: &mut PassphraseViewState,
     col: usize,
     response: AccountsViewResponse,
 ) -> Option<AccountsAction> {
@@ -94,6 +109,9 @@ pub fn process_accounts_view_response(
         AccountsViewResponse::RouteToLogin => {
             router.route_to(Route::add_account());
         }
+        AccountsViewResponse::ChangePassphrase => {
+            passphrase_state.open_change();
+        }
     }
 
     selection
@@ -114,6 +132,9 @@ pub fn process_login_view_response(
             let pubkey = keypair.pubkey;
             (manager.add_account(keypair), pubkey)
         }
+        AccountLoginResponse::LoginWithBunker(pubkey, session) => {
+            (manager.add_bunker_account(session), pubkey)
+        }
     };
 
     decks.add_deck_default(pubkey);
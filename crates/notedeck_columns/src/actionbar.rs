@@ -1,21 +1,78 @@
 use crate::{
     column::Columns,
+    deletions::{self, Deletions},
     notes_holder::{NotesHolder, NotesHolderStorage},
     profile::Profile,
+    reactions::{self, Reaction, Reactions},
+    repost,
     route::{Route, Router},
     thread::Thread,
 };
 
 use enostr::{NoteId, Pubkey, RelayPool};
 use nostrdb::{Ndb, Transaction};
-use notedeck::{note::root_note_id_from_selected_id, MuteFun, NoteCache, NoteRef};
+use notedeck::{note::root_note_id_from_selected_id, Accounts, Mute, MuteFun, NoteCache, NoteRef};
+use tracing::warn;
 
-#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Clone)]
 pub enum NoteAction {
     Reply(NoteId),
     Quote(NoteId),
+    Repost(NoteId),
+    Delete(NoteId),
     OpenThread(NoteId),
     OpenProfile(Pubkey),
+    EditProfile,
+    Mute(MuteAction),
+    Contact(ContactAction),
+    React { note_id: NoteId, reaction: Reaction },
+}
+
+/// An edit to the selected account's mute list
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum MuteAction {
+    /// Mute something, in the encrypted part of the list if `private`
+    Mute {
+        mute: Mute,
+        private: bool,
+    },
+    Unmute(Mute),
+}
+
+/// An edit to the selected account's contact list
+#[derive(Debug, Eq, PartialEq, Clone, Copy)]
+pub enum ContactAction {
+    Follow(Pubkey),
+    Unfollow(Pubkey),
+}
+
+impl ContactAction {
+    /// Publish the selected account's contact list with this change
+    pub fn process(&self, ndb: &Ndb, accounts: &mut Accounts, pool: &mut RelayPool) {
+        let result = match self {
+            ContactAction::Follow(pubkey) => accounts.follow(ndb, pool, pubkey.bytes()),
+            ContactAction::Unfollow(pubkey) => accounts.unfollow(ndb, pool, pubkey.bytes()),
+        };
+
+        if let Err(err) = result {
+            warn!("could not update contact list: {}", err);
+        }
+    }
+}
+
+impl MuteAction {
+    /// Apply the edit and publish the new list. The account's mutes are
+    /// updated right away, we don't wait for relays to echo it back.
+    pub fn process(&self, ndb: &Ndb, accounts: &mut Accounts, pool: &mut RelayPool) {
+        let published = match self {
+            MuteAction::Mute { mute, private } => accounts.mute(ndb, pool, mute.clone(), *private),
+            MuteAction::Unmute(mute) => accounts.unmute(ndb, pool, mute),
+        };
+
+        if !published {
+            warn!("could not update mute list");
+        }
+    }
 }
 
 pub struct NewNotes {
@@ -59,6 +116,10 @@ impl NoteAction {
         profiles: &mut NotesHolderStorage<Profile>,
         note_cache: &mut NoteCache,
         pool: &mut RelayPool,
+        accounts: &mut Accounts,
+        reactions: &mut Reactions,
+        deletions: &mut Deletions,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
         txn: &Transaction,
         is_muted: &MuteFun,
     ) -> Option<NotesHolderResult> {
@@ -96,6 +157,40 @@ impl NoteAction {
                 router.route_to(Route::quote(note_id));
                 None
             }
+
+            NoteAction::EditProfile => {
+                router.route_to(Route::edit_profile());
+                None
+            }
+
+            NoteAction::Repost(note_id) => {
+                repost::repost(ndb, txn, accounts, pool, wakeup, &note_id);
+                None
+            }
+
+            NoteAction::Delete(note_id) => {
+                if deletions::delete(ndb, txn, accounts, pool, wakeup, &note_id) {
+                    deletions.deleted(&note_id);
+                }
+                None
+            }
+
+            NoteAction::Mute(mute_action) => {
+                mute_action.process(ndb, accounts, pool);
+                None
+            }
+
+            NoteAction::Contact(contact_action) => {
+                contact_action.process(ndb, accounts, pool);
+                None
+            }
+
+            NoteAction::React { note_id, reaction } => {
+                if reactions::react(ndb, txn, accounts, pool, wakeup, &note_id, &reaction) {
+                    reactions.reacted(&note_id, &reaction);
+                }
+                None
+            }
         }
     }
 
@@ -110,12 +205,17 @@ impl NoteAction {
         profiles: &mut NotesHolderStorage<Profile>,
         note_cache: &mut NoteCache,
         pool: &mut RelayPool,
+        accounts: &mut Accounts,
+        reactions: &mut Reactions,
+        deletions: &mut Deletions,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
         txn: &Transaction,
         is_muted: &MuteFun,
     ) {
         let router = columns.column_mut(col).router_mut();
         if let Some(br) = self.execute(
-            ndb, router, threads, profiles, note_cache, pool, txn, is_muted,
+            ndb, router, threads, profiles, note_cache, pool, accounts, reactions, deletions,
+            wakeup, txn, is_muted,
         ) {
             br.process(ndb, note_cache, txn, threads, is_muted);
         }
@@ -1,17 +1,23 @@
 use crate::{
     args::ColumnsArgs,
     column::Columns,
+    counts::Counts,
     decks::{Decks, DecksCache, FALLBACK_PUBKEY},
+    deletions::Deletions,
+    dms::DirectMessages,
     draft::Drafts,
     nav,
     notes_holder::NotesHolderStorage,
+    outbox,
     profile::Profile,
-    storage,
+    reactions::Reactions,
+    relay_info::RelayInfos,
+    relay_pool_manager, storage,
     subscriptions::{SubKind, Subscriptions},
     support::Support,
     thread::Thread,
     timeline::{self, Timeline},
-    ui::{self, DesktopSidePanel},
+    ui::{self, DesktopSidePanel, PassphraseView, SignerApprovalView},
     unknowns,
     view_state::ViewState,
     Result,
@@ -47,6 +53,11 @@ pub struct Damus {
     pub threads: NotesHolderStorage<Thread>,
     pub profiles: NotesHolderStorage<Profile>,
     pub subscriptions: Subscriptions,
+    pub counts: Counts,
+    pub reactions: Reactions,
+    pub deletions: Deletions,
+    pub dms: DirectMessages,
+    pub relay_infos: RelayInfos,
     pub support: Support,
 
     //frame_history: crate::frame_history::FrameHistory,
@@ -96,7 +107,7 @@ fn try_process_event(
         ctx2.request_repaint();
     };
 
-    app_ctx.pool.keepalive_ping(wakeup);
+    app_ctx.pool.keepalive_ping(wakeup.clone());
 
     // NOTE: we don't use the while let loop due to borrow issues
     #[allow(clippy::while_let_loop)]
@@ -108,6 +119,20 @@ fn try_process_event(
         };
 
         match (&ev.event).into() {
+            // the relay forgot about us when it dropped the connection
+            RelayEvent::Opened if app_ctx.pool.needs_resubscribe(&ev.relay) => {
+                info!("{} reconnected, resubscribing", &ev.relay);
+                timeline::resubscribe(
+                    app_ctx.pool,
+                    &ev.relay,
+                    &damus.subscriptions,
+                    get_active_columns(app_ctx.accounts, &damus.decks_cache),
+                );
+            }
+            // outbox relays only get the subscriptions routed to them
+            RelayEvent::Opened if app_ctx.accounts.is_outbox_relay(&ev.relay) => {
+                info!("outbox relay {} connected", &ev.relay)
+            }
             RelayEvent::Opened => {
                 app_ctx
                     .accounts
@@ -122,7 +147,6 @@ fn try_process_event(
                     &ev.relay,
                 );
             }
-            // TODO: handle reconnects
             RelayEvent::Closed => warn!("{} connection closed", &ev.relay),
             RelayEvent::Error(e) => error!("{}: {}", &ev.relay, e),
             RelayEvent::Other(msg) => trace!("other event {:?}", &msg),
@@ -138,6 +162,7 @@ fn try_process_event(
             timeline::is_timeline_ready(
                 app_ctx.ndb,
                 app_ctx.pool,
+                &mut damus.subscriptions,
                 app_ctx.note_cache,
                 timeline,
                 &app_ctx.accounts.mutefun(),
@@ -145,6 +170,20 @@ fn try_process_event(
         };
 
         if is_ready {
+            let timeline = &current_columns.timelines[timeline_ind];
+            if !damus.subscriptions.outbox.contains_key(&timeline.id) {
+                outbox::subscribe_timeline(
+                    app_ctx.ndb,
+                    app_ctx.accounts,
+                    app_ctx.pool,
+                    &mut damus.subscriptions,
+                    wakeup.clone(),
+                    damus.since_optimize,
+                    timeline,
+                    true,
+                );
+            }
+
             let txn = Transaction::new(app_ctx.ndb).expect("txn");
 
             if let Err(err) = Timeline::poll_notes_into_view(
@@ -158,6 +197,19 @@ fn try_process_event(
             ) {
                 error!("poll_notes_into_view: {err}");
             }
+
+            let timeline = &current_columns.timelines[timeline_ind];
+            if timeline.kind.is_messages() {
+                damus.dms.update(
+                    app_ctx.ndb,
+                    app_ctx.accounts,
+                    app_ctx.pool,
+                    &mut damus.subscriptions,
+                    app_ctx.unknown_ids,
+                    wakeup.clone(),
+                    timeline,
+                );
+            }
         } else {
             // TODO: show loading?
         }
@@ -167,6 +219,17 @@ fn try_process_event(
         unknown_id_send(app_ctx.unknown_ids, app_ctx.pool);
     }
 
+    damus.dms.poll(
+        app_ctx.ndb,
+        app_ctx.accounts,
+        app_ctx.pool,
+        app_ctx.unknown_ids,
+        wakeup.clone(),
+    );
+    damus.relay_infos.update(app_ctx.pool, ctx);
+    damus.counts.send_requests(app_ctx.pool);
+    damus.deletions.send_requests(app_ctx.pool);
+
     Ok(())
 }
 
@@ -315,17 +378,107 @@ fn handle_eose(
                 .filter
                 .set_relay_state(relay_url.to_string(), FilterState::got_remote(local_sub));
         }
+
+        SubKind::FetchingRelayLists(timeline_uid) => {
+            let msg = ClientMessage::close(subid.to_string());
+            ctx.pool.send_to(&msg, relay_url);
+
+            // forget the fetch once every relay we asked is done with it
+            if ctx.pool.urls_with_sub(subid).is_empty() {
+                damus.subscriptions.subs.remove(subid);
+            }
+
+            let timeline = if let Some(tl) =
+                get_active_columns(ctx.accounts, &damus.decks_cache).find_timeline(timeline_uid)
+            {
+                tl
+            } else {
+                error!(
+                    "timeline uid:{} not found for FetchingRelayLists",
+                    timeline_uid
+                );
+                return Ok(());
+            };
+
+            info!(
+                "got relay lists from {}, rerouting timeline {}",
+                relay_url, timeline_uid
+            );
+
+            // we don't fetch missing lists again, some people just don't
+            // have one
+            let wakeup = relay_pool_manager::create_wakeup(ctx.egui);
+            outbox::subscribe_timeline(
+                ctx.ndb,
+                ctx.accounts,
+                ctx.pool,
+                &mut damus.subscriptions,
+                wakeup,
+                damus.since_optimize,
+                timeline,
+                false,
+            );
+        }
     }
 
     Ok(())
 }
 
-fn process_message(damus: &mut Damus, ctx: &mut AppContext<'_>, relay: &str, msg: &RelayMessage) {
+pub(crate) fn process_message(
+    damus: &mut Damus,
+    ctx: &mut AppContext<'_>,
+    relay: &str,
+    msg: &RelayMessage,
+) {
     match msg {
         RelayMessage::Event(subid, ev) => process_event(ctx.ndb, subid, ev),
-        RelayMessage::Notice(msg) => warn!("Notice from {}: {}", relay, msg),
-        RelayMessage::OK(cr) => info!("OK {:?}", cr),
+        RelayMessage::Notice(msg) => {
+            warn!("Notice from {}: {}", relay, msg);
+            ctx.pool.handle_notice(relay, msg);
+        }
+        RelayMessage::Ok {
+            event_id,
+            accepted,
+            message,
+        } => {
+            if !ctx.pool.handle_ok(relay, event_id, *accepted, message) {
+                info!("OK {} from {}: {} {}", event_id, relay, accepted, message)
+            }
+        }
+        RelayMessage::Closed { sub_id, reason } => {
+            warn!("{} closed subscription {}: {}", relay, sub_id, reason);
+            ctx.pool.handle_closed(relay, sub_id, reason);
+        }
+        RelayMessage::Auth { challenge } => {
+            ctx.accounts.authenticate(ctx.pool, relay, challenge);
+        }
+        RelayMessage::Count { sub_id, count } => {
+            if !damus.counts.handle_count(ctx.pool, relay, sub_id, *count) {
+                info!("{} counted {} for {}", relay, count, sub_id)
+            }
+        }
+        RelayMessage::NegMsg { sub_id, message } => {
+            if !ctx.pool.handle_neg_msg(relay, sub_id, message) {
+                warn!("{} sent NEG-MSG for unknown sync {}", relay, sub_id);
+            }
+        }
+        RelayMessage::NegErr { sub_id, reason } => {
+            if !ctx.pool.handle_neg_err(relay, sub_id, reason) {
+                warn!(
+                    "{} sent NEG-ERR for unknown sync {}: {}",
+                    relay, sub_id, reason
+                );
+            }
+        }
         RelayMessage::Eose(sid) => {
+            // the pool closes its own subscriptions
+            if ctx.pool.handle_eose(relay, sid)
+                || ctx.accounts.handle_eose(sid)
+                || damus.counts.handle_eose(ctx.pool, relay, sid)
+                || damus.deletions.handle_eose(ctx.pool, relay, sid)
+            {
+                return;
+            }
             if let Err(err) = handle_eose(damus, ctx, sid, relay) {
                 error!("error handling eose: {}", err);
             }
@@ -340,6 +493,24 @@ fn render_damus(damus: &mut Damus, app_ctx: &mut AppContext<'_>) {
         render_damus_desktop(damus, app_ctx);
     }
 
+    // Ask for the passphrase on startup, if the keys are encrypted
+    damus
+        .view_state
+        .passphrase
+        .poll(app_ctx.ndb, app_ctx.accounts);
+    if let Some(action) =
+        PassphraseView::new(&mut damus.view_state.passphrase, app_ctx.accounts).show(app_ctx.egui)
+    {
+        action.process(app_ctx.accounts, &mut damus.view_state.passphrase);
+    }
+
+    // Remote signers that want the user to approve something first
+    SignerApprovalView::new(
+        &mut damus.view_state.signer_approval,
+        app_ctx.accounts.signer_approval_urls(),
+    )
+    .show(app_ctx.egui);
+
     // We use this for keeping timestamps and things up to date
     app_ctx.egui.request_repaint_after(Duration::from_secs(1));
 
@@ -413,9 +584,15 @@ impl Damus {
 
         let debug = ctx.args.debug;
         let support = Support::new(ctx.path);
+        let relay_infos = RelayInfos::new(ctx.path);
 
         Self {
             subscriptions: Subscriptions::default(),
+            counts: Counts::default(),
+            reactions: Reactions::default(),
+            deletions: Deletions::default(),
+            dms: DirectMessages::default(),
+            relay_infos,
             since_optimize: parsed_args.since_optimize,
             threads: NotesHolderStorage::default(),
             profiles: NotesHolderStorage::default(),
@@ -455,10 +632,16 @@ impl Damus {
         let debug = true;
 
         let support = Support::new(&path);
+        let relay_infos = RelayInfos::new(&path);
 
         Self {
             debug,
             subscriptions: Subscriptions::default(),
+            counts: Counts::default(),
+            reactions: Reactions::default(),
+            deletions: Deletions::default(),
+            dms: DirectMessages::default(),
+            relay_infos,
             since_optimize: true,
             threads: NotesHolderStorage::default(),
             profiles: NotesHolderStorage::default(),
@@ -575,6 +758,7 @@ fn timelines_view(ui: &mut egui::Ui, sizes: Size, app: &mut Damus, ctx: &mut App
                     ctx.accounts.get_selected_account(),
                     &app.decks_cache,
                 )
+                .can_compose(ctx.accounts.selected_signer().is_some())
                 .show(ui);
 
                 if side_panel.response.clicked() || side_panel.response.secondary_clicked() {
@@ -1,7 +1,7 @@
 use notedeck::FilterState;
 
 use crate::timeline::{PubkeySource, Timeline, TimelineKind};
-use enostr::{Filter, Pubkey};
+use enostr::{Filter, FilterSpec, Pubkey};
 use nostrdb::Ndb;
 use tracing::{debug, error, info};
 
@@ -37,10 +37,10 @@ impl ColumnsArgs {
                     continue;
                 };
 
-                if let Ok(filter) = Filter::from_json(filter) {
-                    res.columns.push(ArgColumn::Generic(vec![filter]));
-                } else {
-                    error!("failed to parse filter '{}'", filter);
+                match FilterSpec::from_json(filter).map(|spec| spec.build()) {
+                    Ok(Ok(filter)) => res.columns.push(ArgColumn::Generic(vec![filter])),
+                    Ok(Err(err)) => error!("nostrdb rejected filter '{}': {}", filter, err),
+                    Err(err) => error!("invalid filter '{}': {}", filter, err),
                 }
             } else if arg == "--column" || arg == "-c" {
                 i += 1;
@@ -120,13 +120,15 @@ impl ColumnsArgs {
                     continue;
                 };
 
-                if let Some(filter) = std::str::from_utf8(&data)
-                    .ok()
-                    .and_then(|s| Filter::from_json(s).ok())
-                {
-                    res.columns.push(ArgColumn::Generic(vec![filter]));
-                } else {
-                    error!("failed to parse filter in '{}'", filter_file);
+                let spec = std::str::from_utf8(&data)
+                    .map_err(|_| "not utf8".to_owned())
+                    .and_then(|s| FilterSpec::from_json(s).map_err(|e| e.to_string()));
+                match spec.map(|spec| spec.build()) {
+                    Ok(Ok(filter)) => res.columns.push(ArgColumn::Generic(vec![filter])),
+                    Ok(Err(err)) => {
+                        error!("nostrdb rejected filter in '{}': {}", filter_file, err)
+                    }
+                    Err(err) => error!("invalid filter in '{}': {}", filter_file, err),
                 }
             }
 
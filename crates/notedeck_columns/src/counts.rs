@@ -0,0 +1,512 @@
+use crate::subscriptions;
+
+use enostr::{ClientMessage, Filter, NoteId, Pubkey, RelayPool};
+use nostrdb::{Ndb, NoteReply, Transaction};
+use notedeck::TimeCached;
+use std::cell::RefCell;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use tracing::{debug, error};
+
+/// What a COUNT we sent is counting
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CountOf {
+    Followers(Pubkey),
+    Replies(NoteId),
+    Reactions(NoteId),
+}
+
+impl CountOf {
+    fn filter(&self) -> Filter {
+        match self {
+            CountOf::Followers(pk) => Filter::new().pubkeys([pk.bytes()]).kinds([3]).build(),
+            CountOf::Replies(id) => Filter::new().kinds([1]).event(id.bytes()).build(),
+            CountOf::Reactions(id) => Filter::new().kinds([7]).event(id.bytes()).build(),
+        }
+    }
+}
+
+struct CachedCount {
+    /// The counts each relay gave us. Relays only know about the events
+    /// they have, so the largest one is our best guess.
+    by_relay: Rc<RefCell<HashMap<String, u64>>>,
+
+    /// The best guess, which we ask relays for again when it expires
+    count: TimeCached<Option<u64>>,
+
+    used: Instant,
+}
+
+impl CachedCount {
+    fn new() -> Self {
+        let by_relay: Rc<RefCell<HashMap<String, u64>>> = Rc::new(RefCell::new(HashMap::new()));
+        let counts = by_relay.clone();
+        let count = TimeCached::new(Counts::expires_in(), move || {
+            counts.borrow().values().max().copied()
+        });
+
+        CachedCount {
+            by_relay,
+            count,
+            used: Instant::now(),
+        }
+    }
+}
+
+struct CachedNote {
+    /// The replies we have locally
+    count: u64,
+    /// When we last counted the replies we have
+    counted: Instant,
+    /// What relays with NIP-45 counted. Their reply counts include
+    /// mentions and quotes, so they're only a rough guess.
+    relay_replies: CachedCount,
+    relay_reactions: CachedCount,
+    /// When we last asked relays for replies and reactions
+    fetched: Instant,
+    used: Instant,
+}
+
+/// A subscription we're waiting on relays to finish
+struct PendingSub<T> {
+    what: T,
+    sent: Instant,
+}
+
+/// Follower, reply and reaction counts, from relays with NIP-45, and
+/// reply counts from the replies we have locally. Views ask with
+/// [`Counts::followers`], [`Counts::replies`] and [`Counts::reactions`],
+/// which return whatever we have and note what needs asking for. The
+/// requests go out in [`Counts::send_requests`]: COUNTs per profile and
+/// note to the relays that can count, and a single REQ for the replies
+/// and reactions of every note shown since the last call to the relays
+/// that can't.
+///
+/// Counts nobody asked for in a while are dropped.
+#[derive(Default)]
+pub struct Counts {
+    followers: HashMap<Pubkey, CachedCount>,
+    notes: HashMap<NoteId, CachedNote>,
+    wanted_followers: HashSet<Pubkey>,
+    wanted_notes: HashSet<NoteId>,
+    /// COUNTs, closed on each relay once it answers
+    count_subs: HashMap<String, PendingSub<CountOf>>,
+    /// REQs for replies and reactions, closed on each relay on EOSE
+    fetch_subs: HashMap<String, PendingSub<usize>>,
+}
+
+impl Counts {
+    pub fn expires_in() -> Duration {
+        Duration::from_secs(5 * 60)
+    }
+
+    /// How often we count the replies we have again, while a note is
+    /// on screen
+    fn recount_in() -> Duration {
+        Duration::from_secs(3)
+    }
+
+    /// How long relays get to answer before we close the subscription
+    /// on them
+    fn answer_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// The most replies we look at for a single note, and the most
+    /// events we ask relays without NIP-45 for at a time
+    fn limit() -> u64 {
+        500
+    }
+
+    /// The number of contact lists following `pk`, if any relay told us
+    /// yet. We ask relays again when the cached count is stale.
+    pub fn followers(&mut self, pk: &Pubkey) -> Option<u64> {
+        let cached = self.followers.entry(*pk).or_insert_with(CachedCount::new);
+        cached.used = Instant::now();
+        if cached.count.needs_update() {
+            self.wanted_followers.insert(*pk);
+            cached.count.update();
+        }
+
+        cached.count.get().copied().flatten()
+    }
+
+    /// The number of replies to a note. That's the NIP-10 replies we
+    /// have locally, where mentions and quotes don't count, unless a
+    /// relay counted more. Relays are asked again now and then.
+    pub fn replies(&mut self, ndb: &Ndb, txn: &Transaction, note_id: &NoteId) -> u64 {
+        let now = Instant::now();
+        let cached = if let Some(cached) = self.notes.get_mut(note_id) {
+            cached
+        } else {
+            self.wanted_notes.insert(*note_id);
+            self.notes.entry(*note_id).or_insert(CachedNote {
+                count: count_replies(ndb, txn, note_id),
+                counted: now,
+                relay_replies: CachedCount::new(),
+                relay_reactions: CachedCount::new(),
+                fetched: now,
+                used: now,
+            })
+        };
+
+        cached.used = now;
+        if now - cached.fetched > Self::expires_in() {
+            self.wanted_notes.insert(*note_id);
+            cached.fetched = now;
+        }
+        if now - cached.counted > Self::recount_in() {
+            cached.count = count_replies(ndb, txn, note_id);
+            cached.counted = now;
+        }
+
+        cached
+            .relay_replies
+            .count
+            .get()
+            .copied()
+            .flatten()
+            .map_or(cached.count, |relay_count| relay_count.max(cached.count))
+    }
+
+    /// The number of reactions to a note a relay counted, if any did.
+    /// Ask for [`Counts::replies`] first, which is what asks relays.
+    pub fn reactions(&self, note_id: &NoteId) -> Option<u64> {
+        let cached = self.notes.get(note_id)?;
+        cached.relay_reactions.count.get().copied().flatten()
+    }
+
+    /// Ask relays for everything that was wanted since the last call,
+    /// and forget what nobody looked at in a while
+    pub fn send_requests(&mut self, pool: &mut RelayPool) {
+        self.prune(pool);
+
+        // no point asking relays that told us they can't count
+        let counting = pool.urls_supporting(45);
+
+        if !self.wanted_notes.is_empty() {
+            let ids: Vec<NoteId> = self.wanted_notes.drain().collect();
+            for id in &ids {
+                self.send_count(pool, &counting, CountOf::Replies(*id));
+                self.send_count(pool, &counting, CountOf::Reactions(*id));
+            }
+
+            // the rest send us the events, and we count what we have
+            let others: Vec<String> = pool
+                .urls()
+                .into_iter()
+                .filter(|url| !counting.contains(url))
+                .collect();
+            if !others.is_empty() {
+                let filter = Filter::new()
+                    .kinds([1, 7])
+                    .events(ids.iter().map(NoteId::bytes))
+                    .limit(Self::limit())
+                    .build();
+
+                let sub_id = subscriptions::new_sub_id();
+                debug!(
+                    "requesting replies and reactions to {} notes in {} from {} relays",
+                    ids.len(),
+                    sub_id,
+                    others.len()
+                );
+                pool.send_to_relays(
+                    &ClientMessage::req(sub_id.clone(), vec![filter]),
+                    others.iter().map(String::as_str),
+                );
+                self.fetch_subs.insert(
+                    sub_id,
+                    PendingSub {
+                        what: ids.len(),
+                        sent: Instant::now(),
+                    },
+                );
+            }
+        }
+
+        let pks: Vec<Pubkey> = self.wanted_followers.drain().collect();
+        for pk in pks {
+            self.send_count(pool, &counting, CountOf::Followers(pk));
+        }
+    }
+
+    fn send_count(&mut self, pool: &mut RelayPool, urls: &BTreeSet<String>, what: CountOf) {
+        if urls.is_empty() {
+            return;
+        }
+
+        // older requests for this have been answered by now
+        self.count_subs.retain(|_, sub| sub.what != what);
+
+        let sub_id = subscriptions::new_sub_id();
+        debug!("requesting count {} from {} relays", sub_id, urls.len());
+        pool.send_to_relays(
+            &ClientMessage::count(sub_id.clone(), vec![what.filter()]),
+            urls.iter().map(String::as_str),
+        );
+        self.count_subs.insert(
+            sub_id,
+            PendingSub {
+                what,
+                sent: Instant::now(),
+            },
+        );
+    }
+
+    /// Record a relay's COUNT response and close the COUNT on it.
+    /// Returns false if this wasn't one of ours.
+    pub fn handle_count(
+        &mut self,
+        pool: &mut RelayPool,
+        relay: &str,
+        sub_id: &str,
+        count: u64,
+    ) -> bool {
+        let what = if let Some(sub) = self.count_subs.get(sub_id) {
+            sub.what
+        } else {
+            return false;
+        };
+
+        pool.send_to(&ClientMessage::close(sub_id.to_owned()), relay);
+        let cached = match what {
+            CountOf::Followers(pk) => self.followers.get_mut(&pk),
+            CountOf::Replies(id) => self.notes.get_mut(&id).map(|c| &mut c.relay_replies),
+            CountOf::Reactions(id) => self.notes.get_mut(&id).map(|c| &mut c.relay_reactions),
+        };
+        if let Some(cached) = cached {
+            cached.by_relay.borrow_mut().insert(relay.to_owned(), count);
+            cached.count.update();
+        }
+
+        true
+    }
+
+    /// Close a reply and reaction REQ on a relay that sent everything it
+    /// has. Returns false if this wasn't one of ours.
+    pub fn handle_eose(&mut self, pool: &mut RelayPool, relay: &str, sub_id: &str) -> bool {
+        if !self.fetch_subs.contains_key(sub_id) {
+            return false;
+        }
+
+        pool.send_to(&ClientMessage::close(sub_id.to_owned()), relay);
+        true
+    }
+
+    fn prune(&mut self, pool: &mut RelayPool) {
+        // relays that didn't answer by now aren't going to
+        let timeout = Self::answer_timeout();
+        let stale: Vec<String> = self
+            .count_subs
+            .iter()
+            .filter(|(_, sub)| sub.sent.elapsed() > timeout)
+            .map(|(sub_id, _)| sub_id.to_owned())
+            .chain(
+                self.fetch_subs
+                    .iter()
+                    .filter(|(_, sub)| sub.sent.elapsed() > timeout)
+                    .map(|(sub_id, _)| sub_id.to_owned()),
+            )
+            .collect();
+        for sub_id in stale {
+            self.count_subs.remove(&sub_id);
+            self.fetch_subs.remove(&sub_id);
+            pool.unsubscribe(sub_id);
+        }
+
+        let expires_in = Self::expires_in();
+        self.followers
+            .retain(|_, cached| cached.used.elapsed() < expires_in);
+        self.notes
+            .retain(|_, cached| cached.used.elapsed() < expires_in);
+    }
+}
+
+/// Replies to `note_id` we have, going by NIP-10 markers or positions
+fn count_replies(ndb: &Ndb, txn: &Transaction, note_id: &NoteId) -> u64 {
+    let filter = Filter::new()
+        .kinds([1])
+        .event(note_id.bytes())
+        .limit(Counts::limit())
+        .build();
+
+    let results = match ndb.query(txn, &[filter], Counts::limit() as i32) {
+        Ok(results) => results,
+        Err(err) => {
+            error!("replies query failed: {}", err);
+            return 0;
+        }
+    };
+
+    results
+        .iter()
+        .filter(|qr| is_reply_to(&NoteReply::new(qr.note.tags()), note_id))
+        .count() as u64
+}
+
+/// Whether a note with these NIP-10 tags replies to `note_id`, rather
+/// than mentioning or quoting it, or replying further down its thread
+fn is_reply_to(note_reply: &NoteReply, note_id: &NoteId) -> bool {
+    // a reply straight to the root only has the root tag
+    note_reply
+        .reply()
+        .or(note_reply.root())
+        .is_some_and(|reply| reply.id == note_id.bytes())
+}
+
+/// "1.2k" and so on, for tight spaces
+pub fn format_count(count: u64) -> String {
+    if count < 1000 {
+        count.to_string()
+    } else if count < 1_000_000 {
+        format!("{:.1}k", count as f64 / 1000.0)
+    } else {
+        format!("{:.1}M", count as f64 / 1_000_000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enostr::RelayInfoDocument;
+
+    #[test]
+    fn test_count_uses_largest_relay_count() {
+        let pk = Pubkey::new([1; 32]);
+        let mut counts = Counts::default();
+        let mut pool = RelayPool::new();
+        assert_eq!(counts.followers(&pk), None);
+
+        let sub_id = "sub".to_string();
+        counts.count_subs.insert(
+            sub_id.clone(),
+            PendingSub {
+                what: CountOf::Followers(pk),
+                sent: Instant::now(),
+            },
+        );
+        assert!(counts.handle_count(&mut pool, "wss://a/", &sub_id, 3));
+        assert!(counts.handle_count(&mut pool, "wss://b/", &sub_id, 7));
+        assert!(!counts.handle_count(&mut pool, "wss://b/", "other", 7));
+
+        assert_eq!(counts.followers(&pk), Some(7));
+    }
+
+    #[test]
+    fn test_replies_are_batched_and_pruned() {
+        let ndb_dir = tempfile::tempdir().unwrap();
+        let ndb = Ndb::new(ndb_dir.path().to_str().unwrap(), &nostrdb::Config::new()).unwrap();
+        let txn = Transaction::new(&ndb).unwrap();
+        let mut counts = Counts::default();
+        let mut pool = RelayPool::new();
+        // one relay that can't count, and one we don't know about yet
+        pool.add_url("ws://127.0.0.1:1".to_string(), || {}).unwrap();
+        pool.add_url("ws://127.0.0.1:2".to_string(), || {}).unwrap();
+        pool.set_info(
+            "ws://127.0.0.1:1/",
+            RelayInfoDocument {
+                supported_nips: vec![1, 11],
+                ..Default::default()
+            },
+        );
+
+        let a = NoteId::new([1; 32]);
+        let b = NoteId::new([2; 32]);
+        assert_eq!(counts.replies(&ndb, &txn, &a), 0);
+        assert_eq!(counts.replies(&ndb, &txn, &b), 0);
+        assert_eq!(counts.wanted_notes.len(), 2);
+
+        // a COUNT each for replies and reactions, and both notes go out
+        // in the same REQ to the relay that can't count
+        counts.send_requests(&mut pool);
+        assert!(counts.wanted_notes.is_empty());
+        assert_eq!(counts.count_subs.len(), 4);
+        assert_eq!(counts.fetch_subs.len(), 1);
+        let sub_id = counts.fetch_subs.keys().next().unwrap().clone();
+        assert!(counts.handle_eose(&mut pool, "wss://a/", &sub_id));
+        assert!(!counts.handle_eose(&mut pool, "wss://a/", "other"));
+
+        // nobody looked at these in a while
+        let long_ago = Instant::now() - Counts::expires_in() - Duration::from_secs(1);
+        for cached in counts.notes.values_mut() {
+            cached.used = long_ago;
+        }
+        for sub in counts.fetch_subs.values_mut() {
+            sub.sent = long_ago;
+        }
+        for sub in counts.count_subs.values_mut() {
+            sub.sent = long_ago;
+        }
+        counts.send_requests(&mut pool);
+        assert!(counts.notes.is_empty());
+        assert!(counts.fetch_subs.is_empty());
+        assert!(counts.count_subs.is_empty());
+    }
+
+    #[test]
+    fn test_relay_counts_for_notes() {
+        let ndb_dir = tempfile::tempdir().unwrap();
+        let ndb = Ndb::new(ndb_dir.path().to_str().unwrap(), &nostrdb::Config::new()).unwrap();
+        let txn = Transaction::new(&ndb).unwrap();
+        let mut counts = Counts::default();
+        let mut pool = RelayPool::new();
+
+        let note = NoteId::new([1; 32]);
+        assert_eq!(counts.replies(&ndb, &txn, &note), 0);
+        assert_eq!(counts.reactions(&note), None);
+
+        for (sub_id, what) in [
+            ("replies", CountOf::Replies(note)),
+            ("reactions", CountOf::Reactions(note)),
+        ] {
+            counts.count_subs.insert(
+                sub_id.to_string(),
+                PendingSub {
+                    what,
+                    sent: Instant::now(),
+                },
+            );
+        }
+        assert!(counts.handle_count(&mut pool, "wss://a/", "replies", 12));
+        assert!(counts.handle_count(&mut pool, "wss://a/", "reactions", 40));
+
+        assert_eq!(counts.replies(&ndb, &txn, &note), 12);
+        assert_eq!(counts.reactions(&note), Some(40));
+    }
+
+    #[test]
+    fn test_only_nip10_replies_count() {
+        let root = [1; 32];
+        let other = [2; 32];
+        let root_id = NoteId::new(root);
+
+        let tagged = |tags: &[(&[u8; 32], Option<&str>)]| {
+            let mut builder = nostrdb::NoteBuilder::new().kind(1).content("hi");
+            for (id, marker) in tags {
+                builder = builder.start_tag().tag_str("e").tag_str(&hex::encode(id));
+                if let Some(marker) = marker {
+                    builder = builder.tag_str("").tag_str(marker);
+                }
+            }
+            builder.sign(&[3; 32]).build().unwrap()
+        };
+
+        let reply = tagged(&[(&root, Some("root"))]);
+        assert!(is_reply_to(&NoteReply::new(reply.tags()), &root_id));
+
+        let deeper = tagged(&[(&root, Some("root")), (&other, Some("reply"))]);
+        assert!(!is_reply_to(&NoteReply::new(deeper.tags()), &root_id));
+
+        let mention = tagged(&[(&other, Some("root")), (&root, Some("mention"))]);
+        assert!(!is_reply_to(&NoteReply::new(mention.tags()), &root_id));
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(1234), "1.2k");
+        assert_eq!(format_count(2_500_000), "2.5M");
+    }
+}
@@ -0,0 +1,319 @@
+use crate::subscriptions;
+
+use enostr::{ClientMessage, NoteId, Pubkey, RelayPool, UnsignedNote};
+use nostrdb::{Filter, Ndb, Note, NoteBuilder, Transaction};
+use notedeck::{Accounts, Destination};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info};
+
+/// An unsigned NIP-09 deletion request for `deleting`
+pub fn to_deletion<'a>(deleting: &Note) -> NoteBuilder<'a> {
+    NoteBuilder::new()
+        .kind(5)
+        .content("")
+        .start_tag()
+        .tag_str("e")
+        .tag_str(&hex::encode(deleting.id()))
+        .start_tag()
+        .tag_str("k")
+        .tag_str(&deleting.kind().to_string())
+}
+
+/// Ask relays to delete one of the selected account's notes. Returns false
+/// if the deletion couldn't be signed or sent.
+pub fn delete(
+    ndb: &Ndb,
+    txn: &Transaction,
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    note_id: &NoteId,
+) -> bool {
+    let pubkey = if let Some(pubkey) = accounts.selected_signer() {
+        pubkey
+    } else {
+        error!("can't delete without a signer");
+        return false;
+    };
+
+    let deleting = if let Ok(note) = ndb.get_note_by_id(txn, note_id.bytes()) {
+        note
+    } else {
+        error!("can't delete unknown note {}", note_id.hex());
+        return false;
+    };
+
+    if deleting.pubkey() != pubkey.bytes() {
+        error!("can't delete someone else's note {}", note_id.hex());
+        return false;
+    }
+
+    let note = match UnsignedNote::from_builder(pubkey, to_deletion(&deleting)) {
+        Ok(note) => note,
+        Err(err) => {
+            error!("could not build deletion: {}", err);
+            return false;
+        }
+    };
+
+    info!("deleting {}", note_id.hex());
+    if let Err(err) = accounts.sign_and_send(ndb, pool, wakeup, note, Destination::Outbox) {
+        error!("could not delete {}: {}", note_id.hex(), err);
+        return false;
+    }
+    true
+}
+
+enum Deleted {
+    Yes,
+    /// Not as of when we last looked
+    No(Instant),
+}
+
+/// A kind 5 REQ we're waiting on relays to finish
+struct PendingSub {
+    ids: Vec<NoteId>,
+    sent: Instant,
+}
+
+/// Which notes have been deleted by their authors, from local nostrdb
+/// queries. A kind 5 only counts if it's by the note's own author.
+///
+/// Relays are asked for deletions of the notes we show in
+/// [`Deletions::send_requests`], a single REQ for every note shown since
+/// the last call.
+#[derive(Default)]
+pub struct Deletions {
+    account: Option<Pubkey>,
+    cache: HashMap<NoteId, Deleted>,
+    /// Notes to ask relays about, and their authors
+    wanted: HashMap<NoteId, Pubkey>,
+    /// When we last asked relays about each note
+    requested: HashMap<NoteId, Instant>,
+    /// REQs for deletions, closed on each relay on EOSE
+    fetch_subs: HashMap<String, PendingSub>,
+}
+
+impl Deletions {
+    fn expires_in() -> Duration {
+        Duration::from_secs(5)
+    }
+
+    /// How often we ask relays again about a note that's still shown
+    fn refetch_in() -> Duration {
+        Duration::from_secs(5 * 60)
+    }
+
+    /// How long relays get to answer before we close the subscription
+    /// on them
+    fn answer_timeout() -> Duration {
+        Duration::from_secs(30)
+    }
+
+    /// The account that can delete its own notes
+    pub fn set_account(&mut self, account: Option<Pubkey>) {
+        self.account = account;
+    }
+
+    pub fn can_delete(&self, note: &Note) -> bool {
+        self.account
+            .is_some_and(|account| account.bytes() == note.pubkey())
+    }
+
+    pub fn is_deleted(&mut self, ndb: &Ndb, txn: &Transaction, note: &Note) -> bool {
+        let note_id = NoteId::new(*note.id());
+        let fresh = self
+            .requested
+            .get(&note_id)
+            .is_some_and(|requested| requested.elapsed() < Self::refetch_in());
+        if !fresh {
+            self.wanted.insert(note_id, Pubkey::new(*note.pubkey()));
+            self.requested.insert(note_id, Instant::now());
+        }
+
+        match self.cache.get(&note_id) {
+            Some(Deleted::Yes) => return true,
+            Some(Deleted::No(queried)) if queried.elapsed() < Self::expires_in() => return false,
+            _ => {}
+        }
+
+        let filter = enostr::Filter::new()
+            .kinds([5])
+            .authors([note.pubkey()])
+            .event(note.id())
+            .limit(1)
+            .build();
+
+        let deleted = match ndb.query(txn, &[filter], 1) {
+            Ok(results) => !results.is_empty(),
+            Err(err) => {
+                error!("deletion query failed: {}", err);
+                false
+            }
+        };
+
+        let state = if deleted {
+            Deleted::Yes
+        } else {
+            Deleted::No(Instant::now())
+        };
+        self.cache.insert(note_id, state);
+
+        deleted
+    }
+
+    /// Hide a note we just deleted before nostrdb has the deletion
+    pub fn deleted(&mut self, note_id: &NoteId) {
+        self.cache.insert(*note_id, Deleted::Yes);
+    }
+
+    /// Ask relays for deletions of the notes shown since the last call,
+    /// and forget what nobody looked at in a while
+    pub fn send_requests(&mut self, pool: &mut RelayPool) {
+        self.prune(pool);
+
+        if self.wanted.is_empty() {
+            return;
+        }
+
+        let (ids, authors): (Vec<NoteId>, BTreeSet<Pubkey>) = self.wanted.drain().unzip();
+        let filter = enostr::Filter::new()
+            .kinds([5])
+            .authors(authors.iter().map(Pubkey::bytes))
+            .events(ids.iter().map(NoteId::bytes))
+            .build();
+
+        let sub_id = subscriptions::new_sub_id();
+        debug!("requesting deletions of {} notes in {}", ids.len(), sub_id);
+        pool.send(&ClientMessage::req(sub_id.clone(), vec![filter]));
+        self.fetch_subs.insert(
+            sub_id,
+            PendingSub {
+                ids,
+                sent: Instant::now(),
+            },
+        );
+    }
+
+    /// Close a deletion REQ on a relay that sent everything it has, and
+    /// look at its notes again. Returns false if this wasn't one of ours.
+    pub fn handle_eose(&mut self, pool: &mut RelayPool, relay: &str, sub_id: &str) -> bool {
+        let sub = if let Some(sub) = self.fetch_subs.get(sub_id) {
+            sub
+        } else {
+            return false;
+        };
+
+        pool.send_to(&ClientMessage::close(sub_id.to_owned()), relay);
+        for id in &sub.ids {
+            if let Some(Deleted::No(_)) = self.cache.get(id) {
+                self.cache.remove(id);
+            }
+        }
+        true
+    }
+
+    fn prune(&mut self, pool: &mut RelayPool) {
+        // relays that didn't answer by now aren't going to
+        let timeout = Self::answer_timeout();
+        let stale: Vec<String> = self
+            .fetch_subs
+            .iter()
+            .filter(|(_, sub)| sub.sent.elapsed() > timeout)
+            .map(|(sub_id, _)| sub_id.to_owned())
+            .collect();
+        for sub_id in stale {
+            self.fetch_subs.remove(&sub_id);
+            pool.unsubscribe(sub_id);
+        }
+
+        let refetch_in = Self::refetch_in();
+        self.requested
+            .retain(|_, requested| requested.elapsed() < refetch_in);
+        let requested: HashSet<&NoteId> = self.requested.keys().collect();
+        self.cache.retain(|id, _| requested.contains(id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enostr::FullKeypair;
+
+    #[test]
+    fn test_deletion_tags() {
+        let keypair = FullKeypair::generate();
+        let seckey = keypair.secret_key.to_secret_bytes();
+        let note = NoteBuilder::new()
+            .kind(1)
+            .content("oops")
+            .sign(&seckey)
+            .build()
+            .unwrap();
+
+        let deletion = to_deletion(&note).sign(&seckey).build().unwrap();
+        assert_eq!(deletion.kind(), 5);
+        assert_eq!(deletion.pubkey(), note.pubkey());
+
+        let json: serde_json::Value = serde_json::from_str(&deletion.json().unwrap()).unwrap();
+        let tags = json["tags"].as_array().unwrap();
+        assert_eq!(tags[0][0], "e");
+        assert_eq!(tags[0][1], hex::encode(note.id()));
+        assert_eq!(tags[1][0], "k");
+        assert_eq!(tags[1][1], "1");
+    }
+
+    #[test]
+    fn test_asks_relays_about_shown_notes() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ndb = Ndb::new(tmp.path().to_str().unwrap(), &nostrdb::Config::new()).unwrap();
+        let txn = Transaction::new(&ndb).unwrap();
+        let mut pool = RelayPool::new();
+        let keypair = FullKeypair::generate();
+        let note = NoteBuilder::new()
+            .kind(1)
+            .content("shown")
+            .sign(&keypair.secret_key.to_secret_bytes())
+            .build()
+            .unwrap();
+        let id = NoteId::new(*note.id());
+
+        let mut deletions = Deletions::default();
+        assert!(!deletions.is_deleted(&ndb, &txn, &note));
+        deletions.send_requests(&mut pool);
+        assert!(deletions.wanted.is_empty());
+        let sub_id = deletions.fetch_subs.keys().next().unwrap().clone();
+        assert_eq!(deletions.fetch_subs[&sub_id].ids, vec![id]);
+
+        // we don't ask again while the note is still shown
+        assert!(!deletions.is_deleted(&ndb, &txn, &note));
+        assert!(deletions.wanted.is_empty());
+
+        // once a relay is done, the note is looked at again
+        assert!(deletions.handle_eose(&mut pool, "wss://relay.example.com/", &sub_id));
+        assert!(!deletions.cache.contains_key(&id));
+        assert!(!deletions.handle_eose(&mut pool, "wss://relay.example.com/", "other"));
+    }
+
+    #[test]
+    fn test_can_delete_own_notes() {
+        let keypair = FullKeypair::generate();
+        let seckey = keypair.secret_key.to_secret_bytes();
+        let note = NoteBuilder::new()
+            .kind(1)
+            .content("mine")
+            .sign(&seckey)
+            .build()
+            .unwrap();
+
+        let mut deletions = Deletions::default();
+        assert!(!deletions.can_delete(&note));
+
+        deletions.set_account(Some(keypair.pubkey));
+        assert!(deletions.can_delete(&note));
+
+        deletions.set_account(Some(Pubkey::new([1; 32])));
+        assert!(!deletions.can_delete(&note));
+    }
+}
@@ -0,0 +1,896 @@
+//! NIP-17 private direct messages. Chat messages (kind 14) arrive gift
+//! wrapped, so relays can't see who is talking to whom. We open the
+//! wraps that show up in messages timelines and group what's inside into
+//! conversations by who is in them. Legacy NIP-04 kind 4 messages are
+//! shown too, but we never send them.
+
+use crate::{
+    outbox,
+    subscriptions::{self, SubKind, Subscriptions},
+    timeline::{PubkeySource, Timeline, TimelineKind, ViewFilter},
+};
+
+use enostr::{
+    nip59::{self, Rumor},
+    ClientMessage, Crypt, Encryption, Note as WrapNote, NoteId, Pubkey, RelayPool, Signature,
+};
+use nostrdb::{Filter, Ndb, Note, NoteKey, Transaction};
+use notedeck::{filter, outbox as relays, Accounts, Answer, NoteRef, UnknownIds};
+use std::collections::{hash_map::DefaultHasher, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{debug, error, info};
+
+pub const CHAT_KIND: u64 = 14;
+const GIFT_WRAP_KIND: u32 = nip59::GIFT_WRAP_KIND as u32;
+const LEGACY_DM_KIND: u32 = 4;
+
+/// What a messages timeline asks for: gift wraps for `pk`, and kind 4s
+/// to or from them
+pub fn filters(pk: &[u8; 32]) -> Vec<Filter> {
+    vec![
+        Filter::new()
+            .kinds([GIFT_WRAP_KIND as u64])
+            .pubkeys([pk])
+            .limit(filter::default_limit())
+            .build(),
+        Filter::new()
+            .kinds([LEGACY_DM_KIND as u64])
+            .pubkeys([pk])
+            .limit(filter::default_limit())
+            .build(),
+        Filter::new()
+            .kinds([LEGACY_DM_KIND as u64])
+            .authors([pk])
+            .limit(filter::default_limit())
+            .build(),
+    ]
+}
+
+/// A conversation is everyone in it, so the same people always end up in
+/// the same conversation no matter who wrote the message
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct ConversationId(u64);
+
+impl ConversationId {
+    pub fn new(participants: &BTreeSet<Pubkey>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        participants.hash(&mut hasher);
+        ConversationId(hasher.finish())
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DirectMessage {
+    /// The rumor's id, or the note id of a legacy message
+    pub id: NoteId,
+    pub author: Pubkey,
+    pub created_at: u64,
+    pub content: String,
+    /// A NIP-04 kind 4. Anyone can see who these are between.
+    pub legacy: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Conversation {
+    pub id: ConversationId,
+    /// Everyone in the conversation, including us
+    pub participants: BTreeSet<Pubkey>,
+    /// Oldest first
+    pub messages: Vec<DirectMessage>,
+}
+
+impl Conversation {
+    fn new(participants: BTreeSet<Pubkey>) -> Self {
+        Conversation {
+            id: ConversationId::new(&participants),
+            participants,
+            messages: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, message: DirectMessage) {
+        if self.messages.iter().any(|m| m.id == message.id) {
+            return;
+        }
+
+        let pos = self
+            .messages
+            .partition_point(|m| m.created_at <= message.created_at);
+        self.messages.insert(pos, message);
+    }
+
+    /// Everyone but `us`. Empty for notes to self.
+    pub fn others<'a>(&'a self, us: &'a Pubkey) -> impl Iterator<Item = &'a Pubkey> {
+        self.participants.iter().filter(move |pk| *pk != us)
+    }
+
+    pub fn last_message(&self) -> Option<&DirectMessage> {
+        self.messages.last()
+    }
+
+    pub fn has_legacy(&self) -> bool {
+        self.messages.iter().any(|m| m.legacy)
+    }
+}
+
+/// Everyone in the conversation a rumor belongs to: its author and
+/// everyone it tags
+fn rumor_participants(rumor: &Rumor) -> BTreeSet<Pubkey> {
+    let mut participants: BTreeSet<Pubkey> = rumor.tagged_pubkeys().into_iter().collect();
+    participants.insert(rumor.pubkey);
+    participants
+}
+
+fn first_p_tag(note: &Note) -> Option<Pubkey> {
+    for tag in note.tags() {
+        if tag.count() < 2 || tag.get_unchecked(0).variant().str() != Some("p") {
+            continue;
+        }
+
+        if let Some(pk) = tag.get_unchecked(1).variant().id() {
+            return Some(Pubkey::new(*pk));
+        }
+    }
+
+    None
+}
+
+/// A gift wrap or kind 4 part way open. Each layer is decrypted by the
+/// account's signer, and a remote signer takes a while to answer.
+#[derive(Debug, Clone)]
+enum Opening {
+    /// The seal in a gift wrap
+    Wrap,
+    /// The rumor in this seal
+    Seal(WrapNote),
+    /// A kind 4's content
+    Legacy {
+        id: NoteId,
+        author: Pubkey,
+        recipient: Pubkey,
+        created_at: u64,
+    },
+}
+
+/// What to decrypt next to carry on opening a note
+struct Decrypt {
+    opening: Opening,
+    encryption: Encryption,
+    from: Pubkey,
+    ciphertext: String,
+}
+
+enum Step {
+    Decrypt(Decrypt),
+    /// The conversation and message the note held
+    Opened(BTreeSet<Pubkey>, DirectMessage),
+}
+
+/// What to decrypt first to open a gift wrap or kind 4 sent to or by `us`
+fn start_opening(us: &Pubkey, note: &Note) -> Option<Decrypt> {
+    match note.kind() {
+        GIFT_WRAP_KIND => Some(Decrypt {
+            opening: Opening::Wrap,
+            encryption: Encryption::Nip44,
+            from: Pubkey::new(*note.pubkey()),
+            ciphertext: note.content().to_owned(),
+        }),
+
+        LEGACY_DM_KIND => {
+            let author = Pubkey::new(*note.pubkey());
+            let recipient = first_p_tag(note)?;
+            let other = if author == *us {
+                recipient
+            } else if recipient == *us {
+                author
+            } else {
+                return None;
+            };
+
+            Some(Decrypt {
+                opening: Opening::Legacy {
+                    id: NoteId::new(*note.id()),
+                    author,
+                    recipient,
+                    created_at: note.created_at(),
+                },
+                encryption: Encryption::Nip04,
+                from: other,
+                ciphertext: note.content().to_owned(),
+            })
+        }
+
+        _ => None,
+    }
+}
+
+/// Carry on opening a note for `us` with what its last layer decrypted to
+fn carry_on(us: &Pubkey, opening: Opening, plaintext: String) -> Option<Step> {
+    match opening {
+        Opening::Wrap => {
+            let seal = match nip59::open_seal(&plaintext) {
+                Ok(seal) => seal,
+                Err(err) => {
+                    debug!("could not open gift wrap: {}", err);
+                    return None;
+                }
+            };
+
+            Some(Step::Decrypt(Decrypt {
+                encryption: Encryption::Nip44,
+                from: seal.pubkey,
+                ciphertext: seal.content.clone(),
+                opening: Opening::Seal(seal),
+            }))
+        }
+
+        Opening::Seal(seal) => {
+            let rumor = match nip59::open_rumor(&seal, &plaintext) {
+                Ok(rumor) => rumor,
+                Err(err) => {
+                    debug!("could not open seal {}: {}", seal.id.hex(), err);
+                    return None;
+                }
+            };
+
+            if rumor.kind != CHAT_KIND {
+                return None;
+            }
+
+            let mut participants = rumor_participants(&rumor);
+            participants.insert(*us);
+            let message = DirectMessage {
+                id: rumor.id,
+                author: rumor.pubkey,
+                created_at: rumor.created_at,
+                content: rumor.content,
+                legacy: false,
+            };
+
+            Some(Step::Opened(participants, message))
+        }
+
+        Opening::Legacy {
+            id,
+            author,
+            recipient,
+            created_at,
+        } => {
+            let message = DirectMessage {
+                id,
+                author,
+                created_at,
+                content: plaintext,
+                legacy: true,
+            };
+
+            Some(Step::Opened(
+                [author, recipient].into_iter().collect(),
+                message,
+            ))
+        }
+    }
+}
+
+#[derive(Default)]
+struct Inbox {
+    /// Gift wraps and kind 4s we've already opened
+    seen: HashSet<NoteKey>,
+    /// What we're waiting on the remote signer to decrypt, by request id
+    opening: HashMap<String, Opening>,
+    conversations: HashMap<ConversationId, Conversation>,
+
+    /// Our DM relays that we're listening for gift wraps on
+    relays: BTreeSet<String>,
+    subid: Option<String>,
+    checked: Option<Instant>,
+    requested_list: bool,
+}
+
+impl Inbox {
+    /// Decrypt layer after layer until the message is open, or until a
+    /// remote signer has to answer first
+    fn open(
+        &mut self,
+        ndb: &Ndb,
+        txn: &Transaction,
+        unknown_ids: &mut UnknownIds,
+        accounts: &mut Accounts,
+        account: &Pubkey,
+        mut step: Option<Step>,
+    ) {
+        loop {
+            let decrypt = match step {
+                Some(Step::Decrypt(decrypt)) => decrypt,
+                Some(Step::Opened(participants, message)) => {
+                    self.insert(ndb, txn, unknown_ids, participants, message);
+                    return;
+                }
+                None => return,
+            };
+
+            let plaintext = match accounts.decrypt(
+                account,
+                decrypt.encryption,
+                &decrypt.from,
+                &decrypt.ciphertext,
+            ) {
+                Ok(Crypt::Done(plaintext)) => plaintext,
+                Ok(Crypt::Requested(request)) => {
+                    self.opening.insert(request, decrypt.opening);
+                    return;
+                }
+                Err(err) => {
+                    debug!("could not decrypt message: {}", err);
+                    return;
+                }
+            };
+
+            step = carry_on(account, decrypt.opening, plaintext);
+        }
+    }
+
+    /// Carry on opening whatever the remote signer decrypted for us
+    fn answered(
+        &mut self,
+        ndb: &Ndb,
+        txn: &Transaction,
+        unknown_ids: &mut UnknownIds,
+        accounts: &mut Accounts,
+        account: &Pubkey,
+    ) {
+        let requests: Vec<String> = self.opening.keys().cloned().collect();
+        for request in requests {
+            let answer = if let Some(answer) = accounts.take_answer(&request) {
+                answer
+            } else {
+                continue;
+            };
+
+            let opening = if let Some(opening) = self.opening.remove(&request) {
+                opening
+            } else {
+                continue;
+            };
+
+            if let Answer::Crypted(plaintext) = answer {
+                let step = carry_on(account, opening, plaintext);
+                self.open(ndb, txn, unknown_ids, accounts, account, step);
+            }
+        }
+    }
+
+    fn insert(
+        &mut self,
+        ndb: &Ndb,
+        txn: &Transaction,
+        unknown_ids: &mut UnknownIds,
+        participants: BTreeSet<Pubkey>,
+        message: DirectMessage,
+    ) {
+        let id = ConversationId::new(&participants);
+        self.conversations
+            .entry(id)
+            .or_insert_with(|| {
+                for pk in &participants {
+                    unknown_ids.add_pubkey_if_missing(ndb, txn, pk);
+                }
+                Conversation::new(participants)
+            })
+            .insert(message);
+    }
+}
+
+/// A gift wrap on its way to `recipient`. Its seal is encrypted and then
+/// signed by the account's signer before we can wrap it.
+struct Sending {
+    account: Pubkey,
+    recipient: Pubkey,
+    dm_relays: BTreeSet<String>,
+}
+
+/// The decrypted direct messages of each account
+#[derive(Default)]
+pub struct DirectMessages {
+    inboxes: HashMap<Pubkey, Inbox>,
+    /// Messages waiting on a remote signer, by request id
+    sending: HashMap<String, Sending>,
+}
+
+impl DirectMessages {
+    fn check_interval() -> Duration {
+        Duration::from_secs(5)
+    }
+
+    /// Open any notes from a messages timeline we haven't seen yet
+    fn ingest(
+        &mut self,
+        ndb: &Ndb,
+        txn: &Transaction,
+        unknown_ids: &mut UnknownIds,
+        accounts: &mut Accounts,
+        account: &Pubkey,
+        notes: &[NoteRef],
+    ) {
+        let inbox = self.inboxes.entry(*account).or_default();
+
+        for note_ref in notes {
+            if !inbox.seen.insert(note_ref.key) {
+                continue;
+            }
+
+            let note = if let Ok(note) = ndb.get_note_by_key(txn, note_ref.key) {
+                note
+            } else {
+                inbox.seen.remove(&note_ref.key);
+                continue;
+            };
+
+            let step = start_opening(account, &note).map(Step::Decrypt);
+            inbox.open(ndb, txn, unknown_ids, accounts, account, step);
+        }
+    }
+
+    /// Keep up with a messages timeline: open its new notes and listen
+    /// on its account's DM relays
+    #[allow(clippy::too_many_arguments)]
+    pub fn update(
+        &mut self,
+        ndb: &Ndb,
+        accounts: &mut Accounts,
+        pool: &mut RelayPool,
+        subs: &mut Subscriptions,
+        unknown_ids: &mut UnknownIds,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+        timeline: &Timeline,
+    ) {
+        let account = if let Some(account) = messages_account(accounts, &timeline.kind) {
+            account
+        } else {
+            return;
+        };
+
+        if let Ok(txn) = Transaction::new(ndb) {
+            if accounts.can_sign(&account) {
+                let notes = timeline.notes(ViewFilter::NotesAndReplies);
+                self.ingest(ndb, &txn, unknown_ids, accounts, &account, notes);
+            }
+        }
+
+        self.subscribe_inbox(ndb, accounts, pool, subs, wakeup, &account);
+    }
+
+    /// Carry on with whatever remote signers answered: open the messages
+    /// they decrypted, and send the ones they sealed
+    pub fn poll(
+        &mut self,
+        ndb: &Ndb,
+        accounts: &mut Accounts,
+        pool: &mut RelayPool,
+        unknown_ids: &mut UnknownIds,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    ) {
+        if let Ok(txn) = Transaction::new(ndb) {
+            for (account, inbox) in &mut self.inboxes {
+                inbox.answered(ndb, &txn, unknown_ids, accounts, account);
+            }
+        }
+
+        let requests: Vec<String> = self.sending.keys().cloned().collect();
+        for request in requests {
+            let answer = if let Some(answer) = accounts.take_answer(&request) {
+                answer
+            } else {
+                continue;
+            };
+
+            let sending = if let Some(sending) = self.sending.remove(&request) {
+                sending
+            } else {
+                continue;
+            };
+
+            let sent = match answer {
+                Answer::Crypted(content) => {
+                    self.sign_seal(ndb, accounts, pool, wakeup.clone(), sending, content)
+                }
+                Answer::Signed(seal) => {
+                    deliver(ndb, accounts, pool, wakeup.clone(), &sending, &seal)
+                }
+                Answer::Failed(err) => Err(SendError::Wrap(err)),
+            };
+
+            if let Err(err) = sent {
+                error!("sending message: {}", err);
+            }
+        }
+    }
+
+    /// `account`'s conversations, most recently active first
+    pub fn conversations(&self, account: &Pubkey) -> Vec<&Conversation> {
+        let mut conversations: Vec<&Conversation> = if let Some(inbox) = self.inboxes.get(account) {
+            inbox.conversations.values().collect()
+        } else {
+            return vec![];
+        };
+
+        conversations.sort_by_key(|c| std::cmp::Reverse(c.last_message().map(|m| m.created_at)));
+        conversations
+    }
+
+    pub fn conversation(&self, account: &Pubkey, id: ConversationId) -> Option<&Conversation> {
+        self.inboxes.get(account)?.conversations.get(&id)
+    }
+
+    /// Listen for gift wraps on `account`'s DM relays, which may not be
+    /// relays we otherwise use. We look every few seconds since the list
+    /// can show up or change at any time.
+    #[allow(clippy::too_many_arguments)]
+    pub fn subscribe_inbox(
+        &mut self,
+        ndb: &Ndb,
+        accounts: &mut Accounts,
+        pool: &mut RelayPool,
+        subs: &mut Subscriptions,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+        account: &Pubkey,
+    ) {
+        let inbox = self.inboxes.entry(*account).or_default();
+        if inbox
+            .checked
+            .is_some_and(|checked| checked.elapsed() < Self::check_interval())
+        {
+            return;
+        }
+        inbox.checked = Some(Instant::now());
+
+        let txn = if let Ok(txn) = Transaction::new(ndb) {
+            txn
+        } else {
+            return;
+        };
+
+        let dm_relays = if let Some(dm_relays) = relays::dm_relays(ndb, &txn, account.bytes()) {
+            dm_relays
+        } else {
+            if !inbox.requested_list {
+                inbox.requested_list = true;
+                request_dm_relays(ndb, accounts, pool, subs, wakeup, [account]);
+            }
+            return;
+        };
+
+        let new: Vec<String> = dm_relays.difference(&inbox.relays).cloned().collect();
+        if new.is_empty() {
+            return;
+        }
+
+        info!("listening for direct messages on {:?}", new);
+        accounts.add_outbox_relays(pool, new.iter().cloned(), wakeup);
+
+        let subid = inbox
+            .subid
+            .get_or_insert_with(subscriptions::new_sub_id)
+            .clone();
+        subs.subs.insert(
+            subid.clone(),
+            SubKind::Timeline(TimelineKind::messages(PubkeySource::Explicit(*account))),
+        );
+
+        let wraps = Filter::new()
+            .kinds([GIFT_WRAP_KIND as u64])
+            .pubkeys([account.bytes()])
+            .limit(filter::default_remote_limit())
+            .build();
+        pool.subscribe_to(subid, vec![wraps], new.iter().map(String::as_str));
+        inbox.relays.extend(new);
+    }
+}
+
+/// Whose messages a timeline shows, if it's a messages timeline
+pub fn messages_account(accounts: &Accounts, kind: &TimelineKind) -> Option<Pubkey> {
+    if let TimelineKind::Messages(pk_src) = kind {
+        let deck_author = accounts.get_selected_account()?.pubkey;
+        Some(*pk_src.to_pubkey(&deck_author))
+    } else {
+        None
+    }
+}
+
+/// Look for people's DM relay lists, on our relays and on their outbox
+/// relays. We can't message anyone until we have theirs.
+pub fn request_dm_relays<'a>(
+    ndb: &Ndb,
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    subs: &mut Subscriptions,
+    wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    pubkeys: impl IntoIterator<Item = &'a Pubkey>,
+) {
+    let filters = vec![relays::dm_relay_lists_filter(
+        pubkeys.into_iter().map(|pk| pk.bytes()),
+    )];
+
+    let sub_id = subscriptions::new_sub_id();
+    subs.subs.insert(sub_id.clone(), SubKind::OneShot);
+    outbox::subscribe(ndb, accounts, pool, wakeup, &sub_id, &filters);
+    pool.subscribe(sub_id, filters);
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SendError {
+    /// We have neither the account's secret key nor a remote signer
+    NoSigner,
+    /// This person hasn't said where they want to get messages
+    NoDmRelays(Pubkey),
+    Wrap(String),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::NoSigner => write!(
+                f,
+                "Add this account's secret key or remote signer to send messages"
+            ),
+            SendError::NoDmRelays(_) => {
+                write!(f, "They haven't published any relays to message them on")
+            }
+            SendError::Wrap(err) => write!(f, "Could not encrypt message: {}", err),
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl DirectMessages {
+    /// Send a kind 14 to `recipients`. Each of them gets their own gift
+    /// wrap on their DM relays, and so do we, so the message shows up on
+    /// our other devices too. Nothing is sent unless everyone has DM
+    /// relays. With a remote signer the wraps go out once it has sealed
+    /// them, see [`DirectMessages::poll`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn send(
+        &mut self,
+        ndb: &Ndb,
+        txn: &Transaction,
+        accounts: &mut Accounts,
+        pool: &mut RelayPool,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+        account: &Pubkey,
+        recipients: &[Pubkey],
+        content: &str,
+    ) -> Result<(), SendError> {
+        if !accounts.can_sign(account) {
+            return Err(SendError::NoSigner);
+        }
+
+        let mut deliveries: Vec<(Pubkey, BTreeSet<String>)> =
+            Vec::with_capacity(recipients.len() + 1);
+        for recipient in recipients {
+            let dm_relays = relays::dm_relays(ndb, txn, recipient.bytes())
+                .filter(|dm_relays| !dm_relays.is_empty())
+                .ok_or(SendError::NoDmRelays(*recipient))?;
+            deliveries.push((*recipient, dm_relays));
+        }
+
+        // our own copy goes to our DM relays, or our usual ones if we don't
+        // have any yet
+        let ours = relays::dm_relays(ndb, txn, account.bytes())
+            .filter(|dm_relays| !dm_relays.is_empty())
+            .unwrap_or_else(|| {
+                pool.urls()
+                    .into_iter()
+                    .filter(|url| !accounts.is_outbox_relay(url))
+                    .collect()
+            });
+        deliveries.push((*account, ours));
+
+        let tags = recipients
+            .iter()
+            .map(|pk| vec!["p".to_owned(), pk.hex()])
+            .collect();
+        let rumor = Rumor::new(*account, now(), CHAT_KIND, tags, content.to_owned())
+            .to_json()
+            .map_err(|err| SendError::Wrap(err.to_string()))?;
+
+        for (recipient, dm_relays) in deliveries {
+            let sending = Sending {
+                account: *account,
+                recipient,
+                dm_relays,
+            };
+
+            let sealed = accounts
+                .encrypt(account, Encryption::Nip44, &recipient, &rumor)
+                .map_err(|err| SendError::Wrap(err.to_string()))?;
+            match sealed {
+                Crypt::Done(content) => {
+                    self.sign_seal(ndb, accounts, pool, wakeup.clone(), sending, content)?
+                }
+                Crypt::Requested(request) => {
+                    self.sending.insert(request, sending);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Sign the seal around a rumor the account encrypted to `content`,
+    /// and send it off once it's signed
+    fn sign_seal(
+        &mut self,
+        ndb: &Ndb,
+        accounts: &mut Accounts,
+        pool: &mut RelayPool,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+        sending: Sending,
+        content: String,
+    ) -> Result<(), SendError> {
+        let seal = nip59::unsigned_seal(&sending.account, content);
+        match accounts
+            .sign_unsent(seal)
+            .map_err(|err| SendError::Wrap(err.to_string()))?
+        {
+            Signature::Signed(seal) => deliver(ndb, accounts, pool, wakeup, &sending, &seal),
+            Signature::Requested(request) => {
+                self.sending.insert(request, sending);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Wrap `seal` for its recipient with a throwaway key, and send it to
+/// their DM relays
+fn deliver(
+    ndb: &Ndb,
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    sending: &Sending,
+    seal: &WrapNote,
+) -> Result<(), SendError> {
+    let wrap = nip59::gift_wrap(&sending.recipient, seal)
+        .map_err(|err| SendError::Wrap(err.to_string()))?;
+    let json = serde_json::to_string(&wrap).expect("note json");
+
+    if sending.recipient == sending.account {
+        if let Err(err) = ndb.process_event(&format!("[\"EVENT\",\"dm\",{}]", json)) {
+            error!("could not store our message: {:?}", err);
+        }
+    }
+
+    debug!("sending message to {:?}", sending.dm_relays);
+    accounts.add_outbox_relays(pool, sending.dm_relays.iter().cloned(), wakeup);
+    pool.publish(
+        wrap.id,
+        ClientMessage::raw(format!("[\"EVENT\",{}]", json)),
+        sending.dm_relays.iter().map(String::as_str),
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enostr::FullKeypair;
+
+    fn message(id: u8, created_at: u64) -> DirectMessage {
+        DirectMessage {
+            id: NoteId::new([id; 32]),
+            author: Pubkey::new([1; 32]),
+            created_at,
+            content: format!("message {}", id),
+            legacy: false,
+        }
+    }
+
+    #[test]
+    fn test_conversation_id_ignores_order() {
+        let alice = Pubkey::new([1; 32]);
+        let bob = Pubkey::new([2; 32]);
+        let carol = Pubkey::new([3; 32]);
+
+        let a: BTreeSet<Pubkey> = [alice, bob].into_iter().collect();
+        let b: BTreeSet<Pubkey> = [bob, alice].into_iter().collect();
+        let c: BTreeSet<Pubkey> = [alice, bob, carol].into_iter().collect();
+
+        assert_eq!(ConversationId::new(&a), ConversationId::new(&b));
+        assert_ne!(ConversationId::new(&a), ConversationId::new(&c));
+    }
+
+    #[test]
+    fn test_messages_sorted_and_deduped() {
+        let mut conversation = Conversation::new(BTreeSet::new());
+        conversation.insert(message(1, 20));
+        conversation.insert(message(2, 10));
+        conversation.insert(message(3, 30));
+        conversation.insert(message(1, 20));
+
+        let order: Vec<u64> = conversation.messages.iter().map(|m| m.created_at).collect();
+        assert_eq!(order, vec![10, 20, 30]);
+        assert_eq!(
+            conversation.last_message().unwrap().id,
+            NoteId::new([3; 32])
+        );
+    }
+
+    #[test]
+    fn test_rumor_participants() {
+        let alice = FullKeypair::generate();
+        let bob = Pubkey::new([2; 32]);
+        let carol = Pubkey::new([3; 32]);
+        let rumor = Rumor::new(
+            alice.pubkey,
+            0,
+            CHAT_KIND,
+            vec![
+                vec!["p".to_owned(), bob.hex()],
+                vec!["p".to_owned(), carol.hex()],
+                vec!["subject".to_owned(), "release".to_owned()],
+            ],
+            "hi".to_owned(),
+        );
+
+        let participants = rumor_participants(&rumor);
+        assert_eq!(
+            participants,
+            [alice.pubkey, bob, carol].into_iter().collect()
+        );
+
+        let conversation = Conversation::new(participants);
+        let others: BTreeSet<Pubkey> = conversation.others(&alice.pubkey).copied().collect();
+        assert_eq!(others, [bob, carol].into_iter().collect());
+    }
+
+    #[test]
+    fn test_open_gift_wrap_step_by_step() {
+        let alice = FullKeypair::generate();
+        let bob = FullKeypair::generate();
+        let rumor = Rumor::new(
+            alice.pubkey,
+            1_700_000_000,
+            CHAT_KIND,
+            vec![vec!["p".to_owned(), bob.pubkey.hex()]],
+            "hola".to_owned(),
+        );
+        let wrap = nip59::wrap(alice.to_filled(), &bob.pubkey, &rumor).unwrap();
+
+        // what bob's signer would be asked to decrypt, one layer at a time
+        let seal_json = bob.nip44_decrypt(&wrap.pubkey, &wrap.content).unwrap();
+        let decrypt = match carry_on(&bob.pubkey, Opening::Wrap, seal_json) {
+            Some(Step::Decrypt(decrypt)) => decrypt,
+            _ => panic!("the seal needs decrypting next"),
+        };
+        assert_eq!(decrypt.encryption, Encryption::Nip44);
+        assert_eq!(decrypt.from, alice.pubkey);
+
+        let rumor_json = bob
+            .nip44_decrypt(&decrypt.from, &decrypt.ciphertext)
+            .unwrap();
+        let (participants, message) = match carry_on(&bob.pubkey, decrypt.opening, rumor_json) {
+            Some(Step::Opened(participants, message)) => (participants, message),
+            _ => panic!("the rumor is the last layer"),
+        };
+        assert_eq!(
+            participants,
+            [alice.pubkey, bob.pubkey].into_iter().collect()
+        );
+        assert_eq!(message.id, rumor.id);
+        assert_eq!(message.content, "hola");
+        assert!(!message.legacy);
+
+        // a wrap that doesn't hold a seal goes nowhere
+        assert!(carry_on(&bob.pubkey, Opening::Wrap, "{}".to_owned()).is_none());
+    }
+}
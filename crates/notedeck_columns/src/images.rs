@@ -1,4 +1,5 @@
 use egui::{pos2, Color32, ColorImage, Rect, Sense, SizeHint, TextureHandle};
+use enostr::ProxyConfig;
 use image::imageops::FilterType;
 use notedeck::ImageCache;
 use notedeck::Result;
@@ -219,7 +220,7 @@ pub fn fetch_img(
     if path.exists() {
         fetch_img_from_disk(ctx, url, &path)
     } else {
-        fetch_img_from_net(&img_cache.cache_dir, ctx, url, imgtyp)
+        fetch_img_from_net(&img_cache.cache_dir, &img_cache.proxy, ctx, url, imgtyp)
     }
 
     // TODO: fetch image from local cache
@@ -227,6 +228,7 @@ pub fn fetch_img(
 
 fn fetch_img_from_net(
     cache_path: &path::Path,
+    proxy: &ProxyConfig,
     ctx: &egui::Context,
     url: &str,
     imgtyp: ImageType,
@@ -236,7 +238,7 @@ fn fetch_img_from_net(
     let ctx = ctx.clone();
     let cloned_url = url.to_owned();
     let cache_path = cache_path.to_owned();
-    ehttp::fetch(request, move |response| {
+    notedeck::http::fetch(proxy, request, move |response| {
         let handle = response
             .map_err(notedeck::Error::Generic)
             .and_then(|resp| parse_img_response(resp, imgtyp))
@@ -255,3 +257,31 @@ fn fetch_img_from_net(
 
     promise
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proxy_only_images_are_not_fetched_directly() {
+        let mut img_cache = ImageCache::new(std::env::temp_dir().join("notedeck-proxy-only-test"));
+        img_cache.proxy = ProxyConfig {
+            socks5: None,
+            proxy_only: true,
+        };
+        let ctx = egui::Context::default();
+
+        let promise = fetch_img(
+            &img_cache,
+            &ctx,
+            "http://127.0.0.1:1/banner.png",
+            ImageType::Content(1000, 1000),
+        );
+
+        // refused before any connection is made, so it's already resolved
+        let res = promise
+            .ready()
+            .expect("resolved without touching the network");
+        assert!(res.is_err());
+    }
+}
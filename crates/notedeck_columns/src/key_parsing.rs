@@ -3,7 +3,7 @@ use std::str::FromStr;
 
 use crate::Error;
 use ehttp::{Request, Response};
-use enostr::{Keypair, Pubkey, SecretKey};
+use enostr::{FullKeypair, Keypair, ProxyConfig, Pubkey, SecretKey};
 use poll_promise::Promise;
 use serde::{Deserialize, Serialize};
 
@@ -11,6 +11,10 @@ use serde::{Deserialize, Serialize};
 pub enum AcquireKeyError {
     InvalidKey,
     Nip05Failed(String),
+    /// The remote signer didn't let us in
+    Bunker(String),
+    /// The `ncryptsec1` key didn't decrypt with the passphrase
+    WrongPassphrase,
 }
 
 impl std::fmt::Display for AcquireKeyError {
@@ -20,6 +24,8 @@ impl std::fmt::Display for AcquireKeyError {
             AcquireKeyError::Nip05Failed(e) => {
                 write!(f, "Failed to get pubkey from Nip05 address: {e}")
             }
+            AcquireKeyError::Bunker(e) => write!(f, "Remote signer login failed: {e}"),
+            AcquireKeyError::WrongPassphrase => write!(f, "Wrong passphrase for this key."),
         }
     }
 }
@@ -46,7 +52,7 @@ fn get_pubkey_from_result(result: Nip05Result, user: String) -> Result<Pubkey, E
     }
 }
 
-fn get_nip05_pubkey(id: &str) -> Promise<Result<Pubkey, Error>> {
+fn get_nip05_pubkey(id: &str, proxy: &ProxyConfig) -> Promise<Result<Pubkey, Error>> {
     let (sender, promise) = Promise::new();
     let mut parts = id.split('@');
 
@@ -80,7 +86,7 @@ fn get_nip05_pubkey(id: &str) -> Promise<Result<Pubkey, Error>> {
     let request = Request::get(url);
 
     let cloned_user = user.to_string();
-    ehttp::fetch(request, move |response: Result<Response, String>| {
+    notedeck::http::fetch(proxy, request, move |response: Result<Response, String>| {
         let result = match response {
             Ok(resp) => parse_nip05_response(resp)
                 .and_then(move |result| get_pubkey_from_result(result, cloned_user)),
@@ -96,9 +102,12 @@ fn retrieving_nip05_pubkey(key: &str) -> bool {
     key.contains('@')
 }
 
-fn nip05_promise_wrapper(id: &str) -> Promise<Result<Keypair, AcquireKeyError>> {
+fn nip05_promise_wrapper(
+    id: &str,
+    proxy: &ProxyConfig,
+) -> Promise<Result<Keypair, AcquireKeyError>> {
     let (sender, promise) = Promise::new();
-    let original_promise = get_nip05_pubkey(id);
+    let original_promise = get_nip05_pubkey(id, proxy);
 
     std::thread::spawn(move || {
         let result = original_promise.block_and_take();
@@ -120,7 +129,11 @@ fn nip05_promise_wrapper(id: &str) -> Promise<Result<Keypair, AcquireKeyError>>
 /// - Private hex key: "5dab..."
 /// - NIP-05 address: "example@nostr.com"
 ///
-pub fn perform_key_retrieval(key: &str) -> Promise<Result<Keypair, AcquireKeyError>> {
+/// NIP-05 lookups go through `proxy`.
+pub fn perform_key_retrieval(
+    key: &str,
+    proxy: &ProxyConfig,
+) -> Promise<Result<Keypair, AcquireKeyError>> {
     let tmp_key: &str = if let Some(stripped) = key.strip_prefix('@') {
         stripped
     } else {
@@ -128,7 +141,7 @@ pub fn perform_key_retrieval(key: &str) -> Promise<Result<Keypair, AcquireKeyErr
     };
 
     if retrieving_nip05_pubkey(tmp_key) {
-        nip05_promise_wrapper(tmp_key)
+        nip05_promise_wrapper(tmp_key, proxy)
     } else {
         let res = if let Ok(pubkey) = Pubkey::try_from_bech32_string(tmp_key, true) {
             Ok(Keypair::only_pubkey(pubkey))
@@ -144,6 +157,52 @@ pub fn perform_key_retrieval(key: &str) -> Promise<Result<Keypair, AcquireKeyErr
     }
 }
 
+/// Whether `key` is a NIP-49 encrypted secret key, which needs a
+/// passphrase
+pub fn is_ncryptsec(key: &str) -> bool {
+    key.trim().starts_with("ncryptsec1")
+}
+
+/// Decrypt a NIP-49 `ncryptsec1` key with `passphrase`. It's slow on
+/// purpose, so not on the UI thread.
+pub fn decrypt_ncryptsec(key: &str, passphrase: &str) -> Promise<Result<Keypair, AcquireKeyError>> {
+    let (sender, promise) = Promise::new();
+    let key = key.trim().to_owned();
+    let passphrase = passphrase.to_owned();
+
+    std::thread::spawn(move || {
+        let result = match FullKeypair::from_ncryptsec(&key, &passphrase) {
+            Ok(keypair) => Ok(keypair.to_keypair()),
+            Err(enostr::Error::DecryptFailed(_)) => Err(AcquireKeyError::WrongPassphrase),
+            Err(_) => Err(AcquireKeyError::InvalidKey),
+        };
+        sender.send(result);
+    });
+
+    promise
+}
+
+/// Encrypt `keypair`'s secret key with `passphrase` as a NIP-49
+/// `ncryptsec1` string. Slow like decrypting, so not on the UI thread
+/// either.
+pub fn encrypt_ncryptsec(
+    keypair: FullKeypair,
+    passphrase: &str,
+    log_n: u8,
+) -> Promise<Result<String, String>> {
+    let (sender, promise) = Promise::new();
+    let passphrase = passphrase.to_owned();
+
+    std::thread::spawn(move || {
+        let result = keypair
+            .to_ncryptsec(&passphrase, log_n)
+            .map_err(|e| e.to_string());
+        sender.send(result);
+    });
+
+    promise
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -154,7 +213,7 @@ mod tests {
         let pubkey_str = "npub1xtscya34g58tk0z605fvr788k263gsu6cy9x0mhnm87echrgufzsevkk5s";
         let expected_pubkey =
             Pubkey::try_from_bech32_string(pubkey_str, false).expect("Should not have errored.");
-        let login_key_result = perform_key_retrieval(pubkey_str);
+        let login_key_result = perform_key_retrieval(pubkey_str, &ProxyConfig::default());
 
         promise_assert!(
             assert_eq,
@@ -167,7 +226,7 @@ mod tests {
     fn test_hex_pubkey() {
         let pubkey_str = "32e1827635450ebb3c5a7d12c1f8e7b2b514439ac10a67eef3d9fd9c5c68e245";
         let expected_pubkey = Pubkey::from_hex(pubkey_str).expect("Should not have errored.");
-        let login_key_result = perform_key_retrieval(pubkey_str);
+        let login_key_result = perform_key_retrieval(pubkey_str, &ProxyConfig::default());
 
         promise_assert!(
             assert_eq,
@@ -180,7 +239,7 @@ mod tests {
     fn test_privkey() {
         let privkey_str = "nsec1g8wt3hlwjpa4827xylr3r0lccufxltyekhraexes8lqmpp2hensq5aujhs";
         let expected_privkey = SecretKey::from_str(privkey_str).expect("Should not have errored.");
-        let login_key_result = perform_key_retrieval(privkey_str);
+        let login_key_result = perform_key_retrieval(privkey_str, &ProxyConfig::default());
 
         promise_assert!(
             assert_eq,
@@ -193,7 +252,7 @@ mod tests {
     fn test_hex_privkey() {
         let privkey_str = "41dcb8dfee907b53abc627c711bff8c7126fac99b5c7dc9b303fc1b08557cce0";
         let expected_privkey = SecretKey::from_str(privkey_str).expect("Should not have errored.");
-        let login_key_result = perform_key_retrieval(privkey_str);
+        let login_key_result = perform_key_retrieval(privkey_str, &ProxyConfig::default());
 
         promise_assert!(
             assert_eq,
@@ -202,6 +261,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_ncryptsec() {
+        let keypair = FullKeypair::generate();
+        let ncryptsec = keypair.to_ncryptsec("hunter2", 7).unwrap();
+        assert!(is_ncryptsec(&ncryptsec));
+
+        let login_key_result = decrypt_ncryptsec(&ncryptsec, "hunter2");
+        promise_assert!(assert_eq, Ok(keypair.to_keypair()), &login_key_result);
+
+        let wrong_passphrase_result = decrypt_ncryptsec(&ncryptsec, "hunter3");
+        promise_assert!(
+            assert_eq,
+            Err(AcquireKeyError::WrongPassphrase),
+            &wrong_passphrase_result
+        );
+    }
+
     #[test]
     fn test_nip05() {
         let nip05_str = "damus@damus.io";
@@ -210,7 +286,7 @@ mod tests {
             false,
         )
         .expect("Should not have errored.");
-        let login_key_result = perform_key_retrieval(nip05_str);
+        let login_key_result = perform_key_retrieval(nip05_str, &ProxyConfig::default());
 
         promise_assert!(
             assert_eq,
@@ -11,8 +11,11 @@ mod app_style;
 mod args;
 mod colors;
 mod column;
+mod counts;
 mod deck_state;
 mod decks;
+mod deletions;
+mod dms;
 mod draft;
 mod frame_history;
 mod images;
@@ -21,9 +24,14 @@ pub mod login_manager;
 mod multi_subscriber;
 mod nav;
 mod notes_holder;
+mod outbox;
 mod post;
 mod profile;
+mod profile_state;
+mod reactions;
+mod relay_info;
 pub mod relay_pool_manager;
+mod repost;
 mod route;
 mod subscriptions;
 mod support;
@@ -1,8 +1,15 @@
-use crate::key_parsing::perform_key_retrieval;
 use crate::key_parsing::AcquireKeyError;
+use crate::key_parsing::{
+    decrypt_ncryptsec, encrypt_ncryptsec, is_ncryptsec, perform_key_retrieval,
+};
 use egui::{TextBuffer, TextEdit};
-use enostr::Keypair;
+use enostr::nip46::{BunkerEvent, BunkerSession, BunkerUri, SessionStatus};
+use enostr::{FilledKeypair, Keypair, ProxyConfig, Pubkey};
 use poll_promise::Promise;
+use tracing::error;
+
+/// NIP-49 scrypt cost for exported keys
+const EXPORT_LOG_N: u8 = 16;
 
 /// The state data for acquiring a nostr key
 #[derive(Default)]
@@ -12,6 +19,17 @@ pub struct AcquireKeyState {
     error: Option<AcquireKeyError>,
     key_on_error: Option<String>,
     should_create_new: bool,
+    /// The remote signer we're logging in with, and the url it came from
+    bunker: Option<(String, BunkerSession)>,
+    /// Where the user approves the login, if the signer asked for that
+    bunker_auth_url: Option<String>,
+    /// What an `ncryptsec1` key decrypts with
+    passphrase: String,
+    /// What to encrypt the exported key with, typed twice, and the result
+    export_passphrase: String,
+    export_confirm: String,
+    export_promise: Option<Promise<Result<String, String>>>,
+    exported: Option<String>,
 }
 
 impl<'a> AcquireKeyState {
@@ -27,17 +45,53 @@ impl<'a> AcquireKeyState {
         textedit_closure(&mut self.desired_key)
     }
 
+    pub fn get_passphrase_textedit(
+        &'a mut self,
+        textedit_closure: fn(&'a mut dyn TextBuffer) -> TextEdit<'a>,
+    ) -> TextEdit<'a> {
+        textedit_closure(&mut self.passphrase)
+    }
+
+    pub fn get_export_textedit(
+        &'a mut self,
+        textedit_closure: fn(&'a mut dyn TextBuffer) -> TextEdit<'a>,
+    ) -> TextEdit<'a> {
+        textedit_closure(&mut self.export_passphrase)
+    }
+
+    pub fn get_export_confirm_textedit(
+        &'a mut self,
+        textedit_closure: fn(&'a mut dyn TextBuffer) -> TextEdit<'a>,
+    ) -> TextEdit<'a> {
+        textedit_closure(&mut self.export_confirm)
+    }
+
+    /// Whether the key field has an encrypted key in it, which needs the
+    /// passphrase too
+    pub fn is_ncryptsec(&self) -> bool {
+        is_ncryptsec(&self.desired_key)
+    }
+
     /// User pressed the 'acquire' button
-    pub fn apply_acquire(&'a mut self) {
+    pub fn apply_acquire(&'a mut self, proxy: &ProxyConfig) {
+        if self.is_ncryptsec() {
+            // the same key with another passphrase is worth another try
+            self.error = None;
+            self.key_on_error = None;
+            let promise = decrypt_ncryptsec(&self.desired_key, &self.passphrase);
+            self.promise_query = Some((self.desired_key.clone(), promise));
+            return;
+        }
+
         let new_promise = match &self.promise_query {
             Some((query, _)) => {
                 if query != &self.desired_key {
-                    Some(perform_key_retrieval(&self.desired_key))
+                    Some(perform_key_retrieval(&self.desired_key, proxy))
                 } else {
                     None
                 }
             }
-            None => Some(perform_key_retrieval(&self.desired_key)),
+            None => Some(perform_key_retrieval(&self.desired_key, proxy)),
         };
 
         if let Some(new_promise) = new_promise {
@@ -45,8 +99,48 @@ impl<'a> AcquireKeyState {
         }
     }
 
+    /// User pressed 'login with bunker://'. We connect to the remote
+    /// signer in the key field.
+    pub fn apply_bunker(
+        &mut self,
+        proxy: &ProxyConfig,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    ) {
+        if self
+            .bunker
+            .as_ref()
+            .is_some_and(|(uri, _)| *uri == self.desired_key)
+        {
+            return;
+        }
+
+        self.bunker = None;
+        self.bunker_auth_url = None;
+        let connected = BunkerUri::parse(&self.desired_key)
+            .and_then(|uri| BunkerSession::connect(uri, proxy, wakeup));
+        match connected {
+            Ok(session) => self.bunker = Some((self.desired_key.clone(), session)),
+            Err(err) => self.set_error(AcquireKeyError::Bunker(err.to_string())),
+        }
+    }
+
+    /// Whether the key field has a remote signer's url in it
+    pub fn is_bunker_uri(&self) -> bool {
+        self.desired_key.trim().starts_with("bunker://")
+    }
+
+    /// Where the remote signer wants the user to approve the login
+    pub fn bunker_auth_url(&self) -> Option<&str> {
+        self.bunker_auth_url.as_deref()
+    }
+
     pub fn is_awaiting_network(&self) -> bool {
-        self.promise_query.is_some()
+        self.promise_query.is_some() || self.bunker.is_some()
+    }
+
+    fn set_error(&mut self, error: AcquireKeyError) {
+        self.error = Some(error);
+        self.key_on_error = Some(self.desired_key.clone());
     }
 
     /// Whether to indicate to the user that a login error occured
@@ -70,10 +164,7 @@ impl<'a> AcquireKeyState {
                         Ok(key) => {
                             return Some(key);
                         }
-                        Err(e) => {
-                            self.error = Some(e);
-                            self.key_on_error = Some(self.desired_key.clone());
-                        }
+                        Err(e) => self.set_error(e),
                     };
                 }
             }
@@ -81,6 +172,90 @@ impl<'a> AcquireKeyState {
         None
     }
 
+    /// Who the remote signer signs for and its session, once it has let
+    /// us in
+    pub fn check_for_bunker_login(
+        &mut self,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    ) -> Option<(Pubkey, BunkerSession)> {
+        let (_, session) = self.bunker.as_mut()?;
+        for event in session.poll(wakeup) {
+            if let BunkerEvent::AuthUrl { url, .. } = event {
+                self.bunker_auth_url = Some(url);
+            }
+        }
+
+        match session.status().clone() {
+            SessionStatus::Connecting => None,
+            SessionStatus::Connected(user) => {
+                self.bunker_auth_url = None;
+                self.bunker.take().map(|(_, session)| (user, session))
+            }
+            SessionStatus::Failed(err) => {
+                self.bunker = None;
+                self.bunker_auth_url = None;
+                self.set_error(AcquireKeyError::Bunker(err));
+                None
+            }
+        }
+    }
+
+    /// User pressed 'export': encrypt `keypair` with the export
+    /// passphrase, as an `ncryptsec1` string
+    pub fn apply_export(&mut self, keypair: FilledKeypair) {
+        self.start_export(keypair, EXPORT_LOG_N);
+    }
+
+    fn start_export(&mut self, keypair: FilledKeypair, log_n: u8) {
+        if !self.can_export() {
+            return;
+        }
+
+        self.exported = None;
+        self.export_promise = Some(encrypt_ncryptsec(
+            keypair.to_full(),
+            &self.export_passphrase,
+            log_n,
+        ));
+        self.export_passphrase.clear();
+        self.export_confirm.clear();
+    }
+
+    /// Whether there's an export passphrase, typed the same twice, and
+    /// we aren't already encrypting
+    pub fn can_export(&self) -> bool {
+        !self.export_passphrase.is_empty()
+            && self.export_passphrase == self.export_confirm
+            && self.export_promise.is_none()
+    }
+
+    /// Whether the confirmation has been typed and doesn't match
+    pub fn export_mismatch(&self) -> bool {
+        !self.export_confirm.is_empty() && self.export_passphrase != self.export_confirm
+    }
+
+    pub fn is_exporting(&self) -> bool {
+        self.export_promise.is_some()
+    }
+
+    /// The exported key, once it's encrypted
+    pub fn exported(&mut self) -> Option<&str> {
+        if self
+            .export_promise
+            .as_ref()
+            .is_some_and(|promise| promise.ready().is_some())
+        {
+            if let Some(promise) = self.export_promise.take() {
+                match promise.block_and_take() {
+                    Ok(ncryptsec) => self.exported = Some(ncryptsec),
+                    Err(err) => error!("could not export key: {}", err),
+                }
+            }
+        }
+
+        self.exported.as_deref()
+    }
+
     pub fn should_create_new(&mut self) {
         self.should_create_new = true;
     }
@@ -114,14 +289,14 @@ mod tests {
                     text.insert_text("test", 0);
                     egui::TextEdit::singleline(text)
                 });
-                manager.apply_acquire();
+                manager.apply_acquire(&ProxyConfig::default());
             } else if cur_time < Duration::from_millis(30u64) {
                 let _ = manager.get_acquire_textedit(|text| {
                     text.clear();
                     text.insert_text("test2", 0);
                     egui::TextEdit::singleline(text)
                 });
-                manager.apply_acquire();
+                manager.apply_acquire(&ProxyConfig::default());
             } else {
                 let _ = manager.get_acquire_textedit(|text| {
                     text.clear();
@@ -131,7 +306,7 @@ mod tests {
                     );
                     egui::TextEdit::singleline(text)
                 });
-                manager.apply_acquire();
+                manager.apply_acquire(&ProxyConfig::default());
             }
 
             if let Some(key) = manager.check_for_successful_login() {
@@ -142,4 +317,87 @@ mod tests {
 
         panic!();
     }
+
+    #[test]
+    fn test_ncryptsec_login() {
+        let keypair = enostr::FullKeypair::generate();
+        let ncryptsec = keypair.to_ncryptsec("hunter2", 7).unwrap();
+
+        let mut manager = AcquireKeyState::new();
+        manager.desired_key = ncryptsec;
+        let _ = manager.get_passphrase_textedit(|text| {
+            text.insert_text("hunter2", 0);
+            egui::TextEdit::singleline(text)
+        });
+        assert!(manager.is_ncryptsec());
+        manager.apply_acquire(&ProxyConfig::default());
+
+        let start_time = Instant::now();
+        while start_time.elapsed() < Duration::from_secs(5) {
+            if let Some(key) = manager.check_for_successful_login() {
+                assert_eq!(key, keypair.to_keypair());
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("the key didn't decrypt");
+    }
+
+    #[test]
+    fn test_export_needs_confirmed_passphrase() {
+        let keypair = enostr::FullKeypair::generate();
+
+        let mut manager = AcquireKeyState::new();
+        let _ = manager.get_export_textedit(|text| {
+            text.insert_text("hunter2", 0);
+            egui::TextEdit::singleline(text)
+        });
+        let _ = manager.get_export_confirm_textedit(|text| {
+            text.insert_text("hunter3", 0);
+            egui::TextEdit::singleline(text)
+        });
+        assert!(manager.export_mismatch());
+        assert!(!manager.can_export());
+        manager.start_export(keypair.to_filled(), 7);
+        assert!(!manager.is_exporting());
+
+        let _ = manager.get_export_confirm_textedit(|text| {
+            text.clear();
+            text.insert_text("hunter2", 0);
+            egui::TextEdit::singleline(text)
+        });
+        assert!(manager.can_export());
+        manager.start_export(keypair.to_filled(), 7);
+        assert!(manager.is_exporting());
+
+        let start_time = Instant::now();
+        while start_time.elapsed() < Duration::from_secs(5) {
+            if let Some(ncryptsec) = manager.exported() {
+                let decrypted = enostr::FullKeypair::from_ncryptsec(ncryptsec, "hunter2").unwrap();
+                assert_eq!(decrypted.to_keypair(), keypair.to_keypair());
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        panic!("the key didn't encrypt");
+    }
+
+    #[test]
+    fn test_bad_bunker_uri() {
+        let mut manager = AcquireKeyState::new();
+        let _ = manager.get_acquire_textedit(|text| {
+            text.insert_text("bunker://nope?relay=wss://relay.example.com", 0);
+            egui::TextEdit::singleline(text)
+        });
+        assert!(manager.is_bunker_uri());
+
+        manager.apply_bunker(&ProxyConfig::default(), || {});
+        assert!(!manager.is_awaiting_network());
+        assert!(matches!(
+            manager.check_for_error(),
+            Some(AcquireKeyError::Bunker(_))
+        ));
+    }
 }
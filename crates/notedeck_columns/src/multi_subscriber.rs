@@ -4,7 +4,7 @@ use tracing::{debug, error, info};
 use uuid::Uuid;
 
 use crate::Error;
-use notedeck::{MuteFun, NoteRef, UnifiedSubscription};
+use notedeck::{MuteFun, NoteCache, NoteRef, UnifiedSubscription};
 
 pub struct MultiSubscriber {
     filters: Vec<Filter>,
@@ -37,6 +37,11 @@ impl MultiSubscriber {
         })
     }
 
+    /// The id of our relay subscription, if we are subscribed
+    pub fn remote_subid(&self) -> Option<&str> {
+        self.sub.as_ref().map(|sub| sub.remote.as_str())
+    }
+
     pub fn unsubscribe(&mut self, ndb: &Ndb, pool: &mut RelayPool) {
         if self.subscribers == 0 {
             error!("No subscribers to unsubscribe from");
@@ -110,6 +115,7 @@ impl MultiSubscriber {
         &mut self,
         ndb: &Ndb,
         txn: &Transaction,
+        note_cache: &mut NoteCache,
         is_muted: &MuteFun,
     ) -> Result<Vec<NoteRef>, Error> {
         let sub = self.sub.as_ref().ok_or(notedeck::Error::no_active_sub())?;
@@ -129,7 +135,7 @@ impl MultiSubscriber {
                 continue;
             };
 
-            if is_muted(&note) {
+            if is_muted(&note, ndb, note_cache) {
                 continue;
             }
 
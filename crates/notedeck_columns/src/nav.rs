@@ -5,9 +5,12 @@ use crate::{
     column::ColumnsAction,
     deck_state::DeckState,
     decks::{Deck, DecksAction},
+    dms::{self, SendError},
     notes_holder::NotesHolder,
+    outbox,
     profile::Profile,
-    relay_pool_manager::RelayPoolManager,
+    profile_state::{self, ProfileState},
+    relay_pool_manager::{self, RelayPoolManager},
     route::Route,
     thread::Thread,
     timeline::{
@@ -20,9 +23,11 @@ use crate::{
         column::NavTitle,
         configure_deck::ConfigureDeckView,
         edit_deck::{EditDeckResponse, EditDeckView},
-        note::{PostAction, PostType},
+        note::{NoteContext, PostAction, PostType},
+        profile::EditProfileView,
+        relay::AccountRelays,
         support::SupportView,
-        RelayView, View,
+        ConversationView, ConversationsView, MutedView, RelayView, View,
     },
     Damus,
 };
@@ -30,6 +35,7 @@ use crate::{
 use notedeck::{AccountsAction, AppContext};
 
 use egui_nav::{Nav, NavAction, NavResponse, NavUiType};
+use enostr::Pubkey;
 use nostrdb::{Ndb, Transaction};
 use tracing::{error, info};
 
@@ -70,6 +76,14 @@ impl SwitchingAction {
                 }
             },
         }
+
+        // timelines may have gone away with their column or deck
+        outbox::prune(
+            ctx.accounts,
+            ctx.pool,
+            &mut app.subscriptions,
+            &app.decks_cache,
+        );
         true
     }
 }
@@ -128,12 +142,25 @@ impl RenderNavResponse {
                     }
 
                     app.columns_mut(ctx.accounts).delete_column(col);
+                    outbox::prune(
+                        ctx.accounts,
+                        ctx.pool,
+                        &mut app.subscriptions,
+                        &app.decks_cache,
+                    );
                     switching_occured = true;
                 }
 
                 RenderNavAction::PostAction(post_action) => {
                     let txn = Transaction::new(ctx.ndb).expect("txn");
-                    let _ = post_action.execute(ctx.ndb, &txn, ctx.pool, &mut app.drafts);
+                    let _ = post_action.execute(
+                        ctx.ndb,
+                        &txn,
+                        ctx.accounts,
+                        ctx.pool,
+                        relay_pool_manager::create_wakeup(ctx.egui),
+                        &mut app.drafts,
+                    );
                     get_active_columns_mut(ctx.accounts, &mut app.decks_cache)
                         .column_mut(col)
                         .router_mut()
@@ -143,7 +170,9 @@ impl RenderNavResponse {
                 RenderNavAction::NoteAction(note_action) => {
                     let txn = Transaction::new(ctx.ndb).expect("txn");
 
-                    note_action.execute_and_process_result(
+                    let is_muted = ctx.accounts.mutefun();
+
+                    note_action.clone().execute_and_process_result(
                         ctx.ndb,
                         get_active_columns_mut(ctx.accounts, &mut app.decks_cache),
                         col,
@@ -151,9 +180,25 @@ impl RenderNavResponse {
                         &mut app.profiles,
                         ctx.note_cache,
                         ctx.pool,
+                        ctx.accounts,
+                        &mut app.reactions,
+                        &mut app.deletions,
+                        relay_pool_manager::create_wakeup(ctx.egui),
                         &txn,
-                        &ctx.accounts.mutefun(),
+                        &is_muted,
                     );
+
+                    if let NoteAction::OpenProfile(pubkey) = note_action {
+                        outbox::subscribe_profile(
+                            ctx.ndb,
+                            ctx.accounts,
+                            ctx.pool,
+                            &mut app.subscriptions,
+                            relay_pool_manager::create_wakeup(ctx.egui),
+                            &mut app.profiles,
+                            pubkey.bytes(),
+                        );
+                    }
                 }
 
                 RenderNavAction::SwitchingAction(switching_action) => {
@@ -234,21 +279,78 @@ fn render_nav_body(
     col: usize,
 ) -> Option<RenderNavAction> {
     match top {
-        Route::Timeline(tlr) => render_timeline_route(
-            ctx.ndb,
-            get_active_columns_mut(ctx.accounts, &mut app.decks_cache),
-            &mut app.drafts,
-            ctx.img_cache,
-            ctx.unknown_ids,
-            ctx.note_cache,
-            &mut app.threads,
-            &mut app.profiles,
-            ctx.accounts,
-            *tlr,
-            col,
-            app.textmode,
-            ui,
-        ),
+        Route::Timeline(TimelineRoute::Timeline(id))
+            if get_active_columns(ctx.accounts, &app.decks_cache)
+                .find_timeline(*id)
+                .is_some_and(|tl| tl.kind.is_messages()) =>
+        {
+            let kind = &get_active_columns(ctx.accounts, &app.decks_cache)
+                .find_timeline(*id)?
+                .kind;
+            let account = if let Some(account) = dms::messages_account(ctx.accounts, kind) {
+                account
+            } else {
+                ui.label("Add an account to see its messages");
+                return None;
+            };
+            let readable = ctx.accounts.can_sign(&account);
+
+            let txn = Transaction::new(ctx.ndb).expect("txn");
+            let conversations = app.dms.conversations(&account);
+            let opened =
+                ConversationsView::new(ctx.ndb, &txn, ctx.img_cache, &account, &conversations)
+                    .readable(readable)
+                    .ui(ui)?;
+
+            // we'll need everyone's DM relays to reply
+            if let Some(conversation) = app.dms.conversation(&account, opened) {
+                dms::request_dm_relays(
+                    ctx.ndb,
+                    ctx.accounts,
+                    ctx.pool,
+                    &mut app.subscriptions,
+                    relay_pool_manager::create_wakeup(ctx.egui),
+                    conversation.others(&account),
+                );
+            }
+
+            get_active_columns_mut(ctx.accounts, &mut app.decks_cache)
+                .column_mut(col)
+                .router_mut()
+                .route_to(Route::conversation(opened));
+            None
+        }
+        Route::Timeline(tlr) => {
+            let selected = ctx.accounts.get_selected_account().map(|acc| acc.pubkey);
+            app.reactions.set_account(selected);
+            app.deletions.set_account(selected);
+
+            let note_context = NoteContext {
+                publishes: Some(&ctx.pool.publishes),
+                counts: Some(&mut app.counts),
+                reactions: Some(&mut app.reactions),
+                deletions: Some(&mut app.deletions),
+                contacts: None,
+                muted: None,
+            };
+
+            render_timeline_route(
+                ctx.ndb,
+                get_active_columns_mut(ctx.accounts, &mut app.decks_cache),
+                &mut app.drafts,
+                ctx.img_cache,
+                ctx.unknown_ids,
+                ctx.note_cache,
+                &mut app.threads,
+                &mut app.profiles,
+                ctx.accounts,
+                note_context,
+                *tlr,
+                col,
+                app.textmode,
+                ui,
+            )
+        }
         Route::Accounts(amr) => {
             let mut action = render_accounts_route(
                 ui,
@@ -258,6 +360,8 @@ fn render_nav_body(
                 ctx.accounts,
                 &mut app.decks_cache,
                 &mut app.view_state.login,
+                &mut app.view_state.passphrase,
+                &ctx.args.proxy,
                 *amr,
             );
             let txn = Transaction::new(ctx.ndb).expect("txn");
@@ -267,12 +371,28 @@ fn render_nav_body(
                 .map(|f| RenderNavAction::SwitchingAction(SwitchingAction::Accounts(f)))
         }
         Route::Relays => {
-            let manager = RelayPoolManager::new(ctx.pool);
-            RelayView::new(manager).ui(ui);
+            let account = ctx.accounts.get_selected_account();
+            let editable = ctx.accounts.selected_signer().is_some();
+            let mut view = RelayView::new(RelayPoolManager::new(ctx.pool)).img_cache(ctx.img_cache);
+            if account.is_some() {
+                view = view.account_relays(
+                    AccountRelays {
+                        advertised: ctx.accounts.get_selected_account_relays(),
+                        local: ctx.accounts.get_selected_account_local_relays(),
+                        editable,
+                        fetched: ctx.accounts.selected_account_relays_fetched(),
+                    },
+                    &mut app.view_state.relays,
+                );
+            }
+
+            if let Some(action) = view.show(ui) {
+                action.process(ctx.ndb, ctx.accounts, ctx.pool);
+            }
             None
         }
         Route::ComposeNote => {
-            let kp = ctx.accounts.get_selected_account()?.to_full()?;
+            let poster = ctx.accounts.selected_signer()?;
             let draft = app.drafts.compose_mut();
 
             let txn = Transaction::new(ctx.ndb).expect("txn");
@@ -282,7 +402,7 @@ fn render_nav_body(
                 PostType::New,
                 ctx.img_cache,
                 ctx.note_cache,
-                kp,
+                poster,
             )
             .ui(&txn, ui);
 
@@ -297,6 +417,110 @@ fn render_nav_body(
             SupportView::new(&mut app.support).show(ui);
             None
         }
+        Route::Muted => {
+            let muted = if let Some(muted) = ctx.accounts.get_selected_account_muted() {
+                muted
+            } else {
+                ui.label("Add an account to manage what you've muted");
+                return None;
+            };
+            let editable = ctx.accounts.selected_signer().is_some();
+
+            let action = MutedView::new(ctx.ndb, &muted, &mut app.view_state.muted)
+                .editable(editable)
+                .fetched(ctx.accounts.selected_account_muted_fetched())
+                .ui(ui);
+            if let Some(action) = action {
+                action.process(ctx.ndb, ctx.accounts, ctx.pool);
+            }
+            None
+        }
+        Route::EditProfile => {
+            let pubkey = if let Some(pubkey) = ctx.accounts.selected_signer() {
+                pubkey
+            } else {
+                ui.label("Add this account's secret key or remote signer to edit its profile");
+                return None;
+            };
+
+            let txn = Transaction::new(ctx.ndb).expect("txn");
+            let state = app
+                .view_state
+                .edit_profile
+                .entry(pubkey)
+                .or_insert_with(|| ProfileState::from_ndb(ctx.ndb, &txn, &pubkey));
+
+            if !EditProfileView::new(state, ctx.img_cache).ui(ui) {
+                return None;
+            }
+
+            if let Some(state) = app.view_state.edit_profile.remove(&pubkey) {
+                profile_state::publish(
+                    ctx.ndb,
+                    ctx.accounts,
+                    ctx.pool,
+                    relay_pool_manager::create_wakeup(ctx.egui),
+                    &state,
+                );
+            }
+
+            Some(RenderNavAction::Back)
+        }
+        Route::Conversation(id) => {
+            let account = get_active_columns(ctx.accounts, &app.decks_cache)
+                .find_timeline_for_column_index(col)
+                .and_then(|tl| dms::messages_account(ctx.accounts, &tl.kind))?;
+            let conversation = if let Some(conversation) = app.dms.conversation(&account, *id) {
+                conversation
+            } else {
+                ui.label("Conversation not found");
+                return None;
+            };
+            let can_send = ctx.accounts.can_sign(&account);
+
+            let txn = Transaction::new(ctx.ndb).expect("txn");
+            let state = app.view_state.conversations.entry(*id).or_default();
+            let send =
+                ConversationView::new(ctx.ndb, &txn, ctx.img_cache, &account, conversation, state)
+                    .can_send(can_send)
+                    .ui(ui);
+            if !send {
+                return None;
+            }
+
+            let recipients: Vec<Pubkey> = conversation.others(&account).copied().collect();
+            let wakeup = relay_pool_manager::create_wakeup(ctx.egui);
+            match app.dms.send(
+                ctx.ndb,
+                &txn,
+                ctx.accounts,
+                ctx.pool,
+                wakeup.clone(),
+                &account,
+                &recipients,
+                state.draft.trim(),
+            ) {
+                Ok(()) => {
+                    state.draft.clear();
+                    state.error = None;
+                }
+                Err(err) => {
+                    error!("sending message: {err}");
+                    state.error = Some(err.to_string());
+                    if let SendError::NoDmRelays(pk) = err {
+                        dms::request_dm_relays(
+                            ctx.ndb,
+                            ctx.accounts,
+                            ctx.pool,
+                            &mut app.subscriptions,
+                            wakeup,
+                            [&pk],
+                        );
+                    }
+                }
+            }
+            None
+        }
         Route::NewDeck => {
             let id = ui.id().with("new-deck");
             let new_deck_state = app.view_state.id_to_deck_state.entry(id).or_default();
@@ -0,0 +1,302 @@
+//! Routing subscriptions with the outbox model. Authors' notes are read
+//! from their NIP-65 write relays. Our notes are delivered to the read
+//! relays of the people they mention when they're sent, see
+//! [`notedeck::Accounts::sign_and_send`].
+
+use crate::{
+    decks::DecksCache,
+    notes_holder::{NotesHolder, NotesHolderStorage},
+    profile::Profile,
+    subscriptions::{self, SubKind, Subscriptions},
+    timeline::{self, Timeline, TimelineId},
+};
+
+use enostr::{ClientMessage, Filter, Pubkey, RelayPool};
+use nostrdb::{Ndb, Transaction};
+use notedeck::outbox::{self, OutboxRelays, DEFAULT_RELAYS_PER_AUTHOR};
+use notedeck::Accounts;
+use std::collections::{BTreeSet, HashSet};
+use tracing::{debug, error, info};
+
+/// Route a ready timeline to the outbox relays of its authors. The
+/// timeline keeps the same outbox subscription id, so calling this again
+/// (ie. once we have more relay lists) replaces the old subscriptions.
+/// When `fetch_missing` is set, we ask our relays for the relay lists of
+/// any authors we couldn't route yet.
+#[allow(clippy::too_many_arguments)]
+pub fn subscribe_timeline(
+    ndb: &Ndb,
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    subs: &mut Subscriptions,
+    wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    since_optimize: bool,
+    timeline: &Timeline,
+    fetch_missing: bool,
+) {
+    let filters = if let Some(filters) = timeline.filter.get_any_ready() {
+        timeline::remote_timeline_filters(since_optimize, timeline, filters)
+    } else {
+        return;
+    };
+
+    let subid = subs
+        .outbox
+        .entry(timeline.id)
+        .or_insert_with(subscriptions::new_sub_id)
+        .clone();
+    subs.subs
+        .insert(subid.clone(), SubKind::Timeline(timeline.kind.clone()));
+    subs.timelines.insert(subid.clone(), timeline.id);
+
+    let missing = subscribe(ndb, accounts, pool, wakeup, &subid, &filters);
+    if !fetch_missing || missing.is_empty() {
+        return;
+    }
+
+    info!(
+        "fetching {} relay lists for timeline {:?}",
+        missing.len(),
+        timeline.id
+    );
+    let sub_id = subscriptions::new_sub_id();
+    subs.subs
+        .insert(sub_id.clone(), SubKind::FetchingRelayLists(timeline.id));
+    pool.subscribe(
+        sub_id,
+        vec![outbox::relay_lists_filter(
+            missing.iter().map(|pk| pk.bytes()),
+        )],
+    );
+}
+
+/// Send the author parts of `filters` to the write relays of those
+/// authors, using `subid` so that closing the subscription closes them
+/// too. Filters without authors are left alone, the account relays
+/// already have those. Returns the authors we don't have relay lists for.
+pub fn subscribe(
+    ndb: &Ndb,
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    subid: &str,
+    filters: &[Filter],
+) -> BTreeSet<Pubkey> {
+    let authors = filter_authors(filters);
+    if authors.is_empty() {
+        close_stale(accounts, pool, subid, &BTreeSet::new());
+        return BTreeSet::new();
+    }
+
+    let txn = if let Ok(txn) = Transaction::new(ndb) {
+        txn
+    } else {
+        error!("outbox::subscribe: could not open transaction");
+        return BTreeSet::new();
+    };
+
+    let mut plan = OutboxRelays::for_authors(
+        ndb,
+        &txn,
+        authors.iter().map(|pk| pk.bytes()),
+        DEFAULT_RELAYS_PER_AUTHOR,
+    );
+
+    // account relays get the full filters already
+    plan.relays
+        .retain(|url, _| !pool.has(url) || accounts.is_outbox_relay(url));
+
+    // authors that moved, or aren't in the filters anymore
+    close_stale(
+        accounts,
+        pool,
+        subid,
+        &plan.relays.keys().cloned().collect(),
+    );
+    accounts.add_outbox_relays(pool, plan.urls(), wakeup);
+
+    for (relay, relay_authors) in &plan.relays {
+        let relay_filters: Vec<Filter> = filters
+            .iter()
+            .filter_map(|filter| restrict_authors(filter, relay_authors))
+            .collect();
+
+        if relay_filters.is_empty() {
+            continue;
+        }
+
+        debug!(
+            "outbox: {} authors from {} for {}",
+            relay_authors.len(),
+            relay,
+            subid
+        );
+        pool.subscribe_to(subid.to_owned(), relay_filters, [relay.as_str()]);
+    }
+
+    accounts.prune_outbox_relays(pool);
+    plan.missing
+}
+
+/// Close `subid` on the outbox relays it was routed to before that
+/// aren't in `keep` anymore
+fn close_stale(accounts: &Accounts, pool: &mut RelayPool, subid: &str, keep: &BTreeSet<String>) {
+    let stale: Vec<String> = pool
+        .urls_with_sub(subid)
+        .into_iter()
+        .filter(|url| !keep.contains(url) && accounts.is_outbox_relay(url))
+        .collect();
+
+    if stale.is_empty() {
+        return;
+    }
+
+    debug!("outbox: closing {} on {:?}", subid, stale);
+    pool.send_to_relays(
+        &ClientMessage::close(subid.to_owned()),
+        stale.iter().map(|url| url.as_str()),
+    );
+}
+
+/// Close the subscriptions of timelines that are gone, say their column
+/// or deck was removed, and drop the relays nobody needs now
+pub fn prune(
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    subs: &mut Subscriptions,
+    decks: &DecksCache,
+) {
+    let live: HashSet<TimelineId> = decks
+        .get_mapping()
+        .values()
+        .flat_map(|decks| decks.decks())
+        .flat_map(|deck| deck.columns().timelines())
+        .map(|timeline| timeline.id)
+        .collect();
+
+    subs.outbox.retain(|id, _| live.contains(id));
+
+    // remote timeline subscriptions, on account or outbox relays, and the
+    // contact or relay lists we were still fetching for them
+    let gone: BTreeSet<String> = subs
+        .timelines
+        .iter()
+        .filter(|(_, id)| !live.contains(id))
+        .map(|(subid, _)| subid.clone())
+        .chain(
+            subs.subs
+                .iter()
+                .filter(|(_, kind)| match kind {
+                    SubKind::FetchingRelayLists(id) | SubKind::FetchingContactList(id) => {
+                        !live.contains(id)
+                    }
+                    _ => false,
+                })
+                .map(|(subid, _)| subid.clone()),
+        )
+        .collect();
+    if gone.is_empty() {
+        return;
+    }
+
+    for subid in gone {
+        info!("closing subscription {} of a removed timeline", subid);
+        pool.unsubscribe(subid.clone());
+        subs.subs.remove(&subid);
+        subs.timelines.remove(&subid);
+    }
+
+    accounts.prune_outbox_relays(pool);
+}
+
+/// Also look for a profile's notes on the user's outbox relays. Call this
+/// after the profile has been opened, we reuse its subscription id.
+pub fn subscribe_profile(
+    ndb: &Ndb,
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    subs: &mut Subscriptions,
+    wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    profiles: &mut NotesHolderStorage<Profile>,
+    pubkey: &[u8; 32],
+) {
+    let subid = if let Some(subid) = profiles
+        .notes_holder_expected_mut(pubkey)
+        .get_multi_subscriber()
+        .and_then(|ms| ms.remote_subid())
+    {
+        subid.to_owned()
+    } else {
+        return;
+    };
+
+    let missing = subscribe(
+        ndb,
+        accounts,
+        pool,
+        wakeup,
+        &subid,
+        &Profile::filters(pubkey),
+    );
+    if missing.is_empty() {
+        return;
+    }
+
+    // we'll have it for next time
+    let sub_id = subscriptions::new_sub_id();
+    subs.subs.insert(sub_id.clone(), SubKind::OneShot);
+    pool.subscribe(
+        sub_id,
+        vec![outbox::relay_lists_filter(
+            missing.iter().map(|pk| pk.bytes()),
+        )],
+    );
+}
+
+fn filter_json(filter: &Filter) -> Option<serde_json::Map<String, serde_json::Value>> {
+    let json = filter.json().ok()?;
+    match serde_json::from_str(&json) {
+        Ok(serde_json::Value::Object(obj)) => Some(obj),
+        _ => None,
+    }
+}
+
+fn json_authors(obj: &serde_json::Map<String, serde_json::Value>) -> Vec<Pubkey> {
+    obj.get("authors")
+        .and_then(|authors| authors.as_array())
+        .map(|authors| {
+            authors
+                .iter()
+                .filter_map(|author| author.as_str())
+                .filter_map(|author| Pubkey::from_hex(author).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// All of the authors mentioned in a set of filters
+fn filter_authors(filters: &[Filter]) -> BTreeSet<Pubkey> {
+    filters
+        .iter()
+        .filter_map(filter_json)
+        .flat_map(|obj| json_authors(&obj))
+        .collect()
+}
+
+/// The same filter, but only for the given authors. None if the filter
+/// isn't about any of them.
+fn restrict_authors(filter: &Filter, authors: &BTreeSet<Pubkey>) -> Option<Filter> {
+    let mut obj = filter_json(filter)?;
+    let wanted: Vec<serde_json::Value> = json_authors(&obj)
+        .into_iter()
+        .filter(|pk| authors.contains(pk))
+        .map(|pk| pk.hex().into())
+        .collect();
+
+    if wanted.is_empty() {
+        return None;
+    }
+
+    obj.insert("authors".to_owned(), wanted.into());
+    Filter::from_json(&serde_json::Value::Object(obj).to_string()).ok()
+}
@@ -1,10 +1,10 @@
-use enostr::FullKeypair;
+use enostr::Pubkey;
 use nostrdb::{Note, NoteBuilder, NoteReply};
 use std::collections::HashSet;
 
 pub struct NewPost {
     pub content: String,
-    pub account: FullKeypair,
+    pub account: Pubkey,
 }
 
 fn add_client_tag(builder: NoteBuilder<'_>) -> NoteBuilder<'_> {
@@ -15,20 +15,17 @@ fn add_client_tag(builder: NoteBuilder<'_>) -> NoteBuilder<'_> {
 }
 
 impl NewPost {
-    pub fn new(content: String, account: FullKeypair) -> Self {
+    pub fn new(content: String, account: Pubkey) -> Self {
         NewPost { content, account }
     }
 
-    pub fn to_note(&self, seckey: &[u8; 32]) -> Note {
+    pub fn to_note<'a>(&self) -> NoteBuilder<'a> {
         add_client_tag(NoteBuilder::new())
             .kind(1)
             .content(&self.content)
-            .sign(seckey)
-            .build()
-            .expect("note should be ok")
     }
 
-    pub fn to_reply(&self, seckey: &[u8; 32], replying_to: &Note) -> Note {
+    pub fn to_reply<'a>(&self, replying_to: &Note) -> NoteBuilder<'a> {
         let builder = add_client_tag(NoteBuilder::new())
             .kind(1)
             .content(&self.content);
@@ -47,7 +44,6 @@ impl NewPost {
                 .tag_str(&hex::encode(replying_to.id()))
                 .tag_str("")
                 .tag_str("reply")
-                .sign(seckey)
         } else {
             // we're replying to a post that isn't in a thread,
             // just add a single reply-to-root tag
@@ -57,7 +53,6 @@ impl NewPost {
                 .tag_str(&hex::encode(replying_to.id()))
                 .tag_str("")
                 .tag_str("root")
-                .sign(seckey)
         };
 
         let mut seen_p: HashSet<&[u8; 32]> = HashSet::new();
@@ -94,12 +89,9 @@ impl NewPost {
         }
 
         builder
-            .sign(seckey)
-            .build()
-            .expect("expected build to work")
     }
 
-    pub fn to_quote(&self, seckey: &[u8; 32], quoting: &Note) -> Note {
+    pub fn to_quote<'a>(&self, quoting: &Note) -> NoteBuilder<'a> {
         let new_content = format!(
             "{}\nnostr:{}",
             self.content,
@@ -115,8 +107,5 @@ impl NewPost {
             .start_tag()
             .tag_str("p")
             .tag_str(&hex::encode(quoting.pubkey()))
-            .sign(seckey)
-            .build()
-            .expect("expected build to work")
     }
 }
@@ -1,7 +1,10 @@
 use enostr::{Filter, Pubkey};
 use nostrdb::{FilterBuilder, Ndb, ProfileRecord, Transaction};
 
-use notedeck::{filter::default_limit, FilterState, MuteFun, NoteCache, NoteRef};
+use notedeck::{
+    filter::{default_limit, note_kinds},
+    FilterState, MuteFun, NoteCache, NoteRef,
+};
 
 use crate::{
     multi_subscriber::MultiSubscriber,
@@ -76,7 +79,7 @@ impl Profile {
     fn filters_raw(pk: &[u8; 32]) -> Vec<FilterBuilder> {
         vec![Filter::new()
             .authors([pk])
-            .kinds([1])
+            .kinds(note_kinds())
             .limit(default_limit())]
     }
 }
@@ -0,0 +1,347 @@
+use enostr::{Pubkey, RelayPool, UnsignedNote};
+use nostrdb::{Filter, Ndb, Note, NoteBuilder, Transaction};
+use notedeck::{Accounts, Destination};
+use serde_json::{Map, Value};
+use std::fmt;
+use tracing::{error, info};
+
+/// Kind 0 metadata being edited. The keys we know are split out into
+/// fields, anything else in the existing metadata is kept as it was.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProfileState {
+    pub name: String,
+    pub display_name: String,
+    pub about: String,
+    pub picture: String,
+    pub banner: String,
+    pub website: String,
+    pub nip05: String,
+    pub lud06: String,
+    pub lud16: String,
+    pub bot: bool,
+    other: Map<String, Value>,
+    /// When the metadata we started from was published, if we had any
+    pub created_at: Option<u64>,
+    /// The picture and banner the preview shows, see
+    /// [`ProfileState::update_preview`]
+    preview_picture: Option<String>,
+    preview_banner: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ProfileStateError {
+    InvalidUrl(&'static str),
+    InvalidNip05,
+    InvalidLud06,
+    InvalidLud16,
+}
+
+impl fmt::Display for ProfileStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileStateError::InvalidUrl(field) => {
+                write!(f, "{} must be an http or https url", field)
+            }
+            ProfileStateError::InvalidNip05 => {
+                write!(f, "Nostr address must look like name@example.com")
+            }
+            ProfileStateError::InvalidLud06 => write!(f, "LNURL must start with lnurl"),
+            ProfileStateError::InvalidLud16 => {
+                write!(f, "Lightning address must look like name@example.com")
+            }
+        }
+    }
+}
+
+impl ProfileState {
+    /// The latest metadata we have for `pubkey`, or an empty profile
+    pub fn from_ndb(ndb: &Ndb, txn: &Transaction, pubkey: &Pubkey) -> Self {
+        let filter = Filter::new()
+            .authors([pubkey.bytes()])
+            .kinds([0])
+            .limit(1)
+            .build();
+
+        match ndb.query(txn, &[filter], 1) {
+            Ok(results) if !results.is_empty() => ProfileState::from_note(&results[0].note),
+            Ok(_) => ProfileState::default(),
+            Err(err) => {
+                error!("profile metadata query failed: {}", err);
+                ProfileState::default()
+            }
+        }
+    }
+
+    pub fn from_note(note: &Note) -> Self {
+        let mut state = ProfileState::from_json(note.content());
+        state.created_at = Some(note.created_at());
+        state
+    }
+
+    pub fn from_json(json: &str) -> Self {
+        let mut other = match serde_json::from_str::<Value>(json) {
+            Ok(Value::Object(map)) => map,
+            _ => Map::new(),
+        };
+
+        let mut take = |key: &str| match other.remove(key) {
+            Some(Value::String(s)) => s,
+            Some(value) => {
+                // not a string, leave it alone
+                other.insert(key.to_owned(), value);
+                String::new()
+            }
+            None => String::new(),
+        };
+
+        let mut state = ProfileState {
+            name: take("name"),
+            display_name: take("display_name"),
+            about: take("about"),
+            picture: take("picture"),
+            banner: take("banner"),
+            website: take("website"),
+            nip05: take("nip05"),
+            lud06: take("lud06"),
+            lud16: take("lud16"),
+            ..ProfileState::default()
+        };
+
+        if let Some(Value::Bool(bot)) = other.get("bot") {
+            state.bot = *bot;
+            other.remove("bot");
+        }
+        state.other = other;
+        state.update_preview();
+
+        state
+    }
+
+    /// Show the picture and banner fields in the preview, when they're
+    /// http urls. The form calls this when they lose focus, so we don't
+    /// fetch every url typed on the way to the real one.
+    pub fn update_preview(&mut self) {
+        let preview = |url: &str| Some(url.trim().to_owned()).filter(|url| is_http_url(url));
+        self.preview_picture = preview(&self.picture);
+        self.preview_banner = preview(&self.banner);
+    }
+
+    pub fn preview_picture(&self) -> Option<&str> {
+        self.preview_picture.as_deref()
+    }
+
+    pub fn preview_banner(&self) -> Option<&str> {
+        self.preview_banner.as_deref()
+    }
+
+    fn fields(&self) -> [(&'static str, &str); 9] {
+        [
+            ("name", &self.name),
+            ("display_name", &self.display_name),
+            ("about", &self.about),
+            ("picture", &self.picture),
+            ("banner", &self.banner),
+            ("website", &self.website),
+            ("nip05", &self.nip05),
+            ("lud06", &self.lud06),
+            ("lud16", &self.lud16),
+        ]
+    }
+
+    /// The kind 0 content. Empty fields are left out.
+    pub fn to_json(&self) -> String {
+        let mut map = self.other.clone();
+        for (key, value) in self.fields() {
+            let value = if key == "about" { value } else { value.trim() };
+            if !value.is_empty() {
+                map.insert(key.to_owned(), Value::String(value.to_owned()));
+            }
+        }
+
+        if self.bot {
+            map.insert("bot".to_owned(), Value::Bool(true));
+        }
+
+        Value::Object(map).to_string()
+    }
+
+    pub fn validate(&self) -> Result<(), ProfileStateError> {
+        for (field, url) in [
+            ("Picture", &self.picture),
+            ("Banner", &self.banner),
+            ("Website", &self.website),
+        ] {
+            if !url.trim().is_empty() && !is_http_url(url.trim()) {
+                return Err(ProfileStateError::InvalidUrl(field));
+            }
+        }
+
+        let nip05 = self.nip05.trim();
+        if !nip05.is_empty() && !is_nip05(nip05) {
+            return Err(ProfileStateError::InvalidNip05);
+        }
+
+        let lud06 = self.lud06.trim();
+        if !lud06.is_empty() && !lud06.to_lowercase().starts_with("lnurl") {
+            return Err(ProfileStateError::InvalidLud06);
+        }
+
+        let lud16 = self.lud16.trim();
+        if !lud16.is_empty() && !is_lud16(lud16) {
+            return Err(ProfileStateError::InvalidLud16);
+        }
+
+        Ok(())
+    }
+
+    pub fn to_note<'a>(&self) -> NoteBuilder<'a> {
+        let content = self.to_json();
+        NoteBuilder::new().kind(0).content(&content)
+    }
+}
+
+fn is_http_url(s: &str) -> bool {
+    url::Url::parse(s).is_ok_and(|url| {
+        matches!(url.scheme(), "http" | "https") && url.host_str().is_some_and(|h| !h.is_empty())
+    })
+}
+
+fn is_domain(domain: &str) -> bool {
+    domain.contains('.')
+        && url::Url::parse(&format!("https://{}", domain))
+            .is_ok_and(|url| url.host_str() == Some(&domain.to_lowercase()))
+}
+
+/// `name@domain`, where NIP-05 allows a-z0-9-_. in the name
+fn is_nip05(s: &str) -> bool {
+    let (local, domain) = if let Some(parts) = s.split_once('@') {
+        parts
+    } else {
+        return false;
+    };
+
+    !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        && is_domain(domain)
+}
+
+fn is_lud16(s: &str) -> bool {
+    let (local, domain) = if let Some(parts) = s.split_once('@') {
+        parts
+    } else {
+        return false;
+    };
+
+    !local.is_empty() && !local.contains(char::is_whitespace) && is_domain(domain)
+}
+
+/// Publish the selected account's new metadata
+pub fn publish(
+    ndb: &Ndb,
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    state: &ProfileState,
+) {
+    let pubkey = if let Some(pubkey) = accounts.selected_signer() {
+        pubkey
+    } else {
+        error!("can't edit a profile without a signer");
+        return;
+    };
+
+    let note = match UnsignedNote::from_builder(pubkey, state.to_note()) {
+        Ok(note) => note,
+        Err(err) => {
+            error!("could not build profile: {}", err);
+            return;
+        }
+    };
+
+    info!("publishing profile for {}", pubkey.hex());
+    if let Err(err) = accounts.sign_and_send(ndb, pool, wakeup, note, Destination::Outbox) {
+        error!("could not publish profile: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_only_takes_finished_urls() {
+        let mut state =
+            ProfileState::from_json(r#"{"picture":"https://example.com/me.png","banner":"nope"}"#);
+        assert_eq!(state.preview_picture(), Some("https://example.com/me.png"));
+        assert_eq!(state.preview_banner(), None);
+
+        // typing doesn't change the preview until the field is done
+        state.picture = "https://exa".to_owned();
+        state.banner = " https://example.com/banner.png ".to_owned();
+        assert_eq!(state.preview_picture(), Some("https://example.com/me.png"));
+        assert_eq!(state.preview_banner(), None);
+
+        state.update_preview();
+        assert_eq!(state.preview_picture(), Some("https://exa"));
+        assert_eq!(
+            state.preview_banner(),
+            Some("https://example.com/banner.png")
+        );
+
+        state.picture = "file:///etc/passwd".to_owned();
+        state.update_preview();
+        assert_eq!(state.preview_picture(), None);
+    }
+
+    #[test]
+    fn test_keeps_unknown_keys() {
+        let json = r#"{"name":"jb55","about":"hi","lud16":"jb55@sendsats.lol","custom":{"a":1},"bot":false,"picture":7}"#;
+        let mut state = ProfileState::from_json(json);
+        assert_eq!(state.name, "jb55");
+        assert_eq!(state.lud16, "jb55@sendsats.lol");
+        assert_eq!(state.picture, "");
+        assert!(!state.bot);
+
+        state.about = "".to_owned();
+        state.display_name = " Will ".to_owned();
+        state.bot = true;
+
+        let value: Value = serde_json::from_str(&state.to_json()).unwrap();
+        assert_eq!(value["name"], "jb55");
+        assert_eq!(value["display_name"], "Will");
+        assert_eq!(value["custom"]["a"], 1);
+        assert_eq!(value["picture"], 7);
+        assert_eq!(value["bot"], true);
+        assert!(value.get("about").is_none());
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut state = ProfileState {
+            picture: "https://example.com/me.png".to_owned(),
+            website: "http://jb55.com".to_owned(),
+            nip05: "_@jb55.com".to_owned(),
+            lud16: "jb55@sendsats.lol".to_owned(),
+            ..ProfileState::default()
+        };
+        assert_eq!(state.validate(), Ok(()));
+
+        state.banner = "ftp://example.com/banner.png".to_owned();
+        assert_eq!(
+            state.validate(),
+            Err(ProfileStateError::InvalidUrl("Banner"))
+        );
+        state.banner = "".to_owned();
+
+        state.nip05 = "jb55".to_owned();
+        assert_eq!(state.validate(), Err(ProfileStateError::InvalidNip05));
+        state.nip05 = "j b@jb55.com".to_owned();
+        assert_eq!(state.validate(), Err(ProfileStateError::InvalidNip05));
+        state.nip05 = "".to_owned();
+
+        state.lud16 = "jb55@localhost".to_owned();
+        assert_eq!(state.validate(), Err(ProfileStateError::InvalidLud16));
+    }
+}
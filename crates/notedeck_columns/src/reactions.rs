@@ -0,0 +1,511 @@
+use enostr::{NoteId, Pubkey, RelayPool, UnsignedNote};
+use nostrdb::{Filter, Ndb, Note, NoteBuilder, Transaction};
+use notedeck::{Accounts, Destination};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// What a kind 7 reaction says
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Reaction {
+    /// "+", or an empty reaction
+    Like,
+    /// "-"
+    Dislike,
+    Emoji(String),
+    /// A NIP-30 custom emoji, `:shortcode:` with an `emoji` tag for its image
+    Custom {
+        shortcode: String,
+        url: String,
+    },
+}
+
+impl Reaction {
+    pub fn from_note(note: &Note) -> Reaction {
+        let content = note.content().trim();
+        match content {
+            "" | "+" => return Reaction::Like,
+            "-" => return Reaction::Dislike,
+            _ => {}
+        }
+
+        let shortcode = content
+            .strip_prefix(':')
+            .and_then(|s| s.strip_suffix(':'))
+            .filter(|s| !s.is_empty());
+        if let Some(shortcode) = shortcode {
+            if let Some(url) = emoji_url(note, shortcode) {
+                return Reaction::Custom {
+                    shortcode: shortcode.to_owned(),
+                    url,
+                };
+            }
+        }
+
+        Reaction::Emoji(content.to_owned())
+    }
+
+    /// What the user typed in as a reaction: any emoji or text, or a
+    /// NIP-30 `:shortcode:` that `custom` finds the image for. None if
+    /// there's nothing to send, or it's a shortcode we don't know.
+    pub fn parse(input: &str, custom: impl FnOnce(&str) -> Option<String>) -> Option<Reaction> {
+        let input = input.trim();
+        if input.is_empty() {
+            return None;
+        }
+
+        let shortcode = input
+            .strip_prefix(':')
+            .and_then(|s| s.strip_suffix(':'))
+            .filter(|s| !s.is_empty());
+        if let Some(shortcode) = shortcode {
+            return custom(shortcode).map(|url| Reaction::Custom {
+                shortcode: shortcode.to_owned(),
+                url,
+            });
+        }
+
+        Some(Reaction::Emoji(input.to_owned()))
+    }
+
+    /// The reaction's `content`
+    pub fn content(&self) -> String {
+        match self {
+            Reaction::Like => "+".to_owned(),
+            Reaction::Dislike => "-".to_owned(),
+            Reaction::Emoji(emoji) => emoji.to_owned(),
+            Reaction::Custom { shortcode, .. } => format!(":{}:", shortcode),
+        }
+    }
+
+    /// An unsigned kind 7 reacting to `target`
+    pub fn to_note<'a>(&self, target: &Note) -> NoteBuilder<'a> {
+        let content = self.content();
+        let mut builder = NoteBuilder::new()
+            .kind(7)
+            .content(&content)
+            .start_tag()
+            .tag_str("e")
+            .tag_str(&hex::encode(target.id()))
+            .start_tag()
+            .tag_str("p")
+            .tag_str(&hex::encode(target.pubkey()))
+            .start_tag()
+            .tag_str("k")
+            .tag_str(&target.kind().to_string());
+
+        if let Reaction::Custom { shortcode, url } = self {
+            builder = builder
+                .start_tag()
+                .tag_str("emoji")
+                .tag_str(shortcode)
+                .tag_str(url);
+        }
+
+        builder
+    }
+}
+
+fn emoji_url(note: &Note, shortcode: &str) -> Option<String> {
+    for tag in note.tags() {
+        if tag.count() < 3 || tag.get_unchecked(0).variant().str() != Some("emoji") {
+            continue;
+        }
+
+        if tag.get_unchecked(1).variant().str() == Some(shortcode) {
+            return tag.get_unchecked(2).variant().str().map(str::to_owned);
+        }
+    }
+    None
+}
+
+/// The image for `:shortcode:` in `account`'s NIP-30 emoji list (kind
+/// 10030), either listed directly or in one of the emoji sets it points to
+pub fn custom_emoji_url(
+    ndb: &Ndb,
+    txn: &Transaction,
+    account: &Pubkey,
+    shortcode: &str,
+) -> Option<String> {
+    let filter = Filter::new()
+        .authors([account.bytes()])
+        .kinds([10030])
+        .limit(1)
+        .build();
+    let results = ndb.query(txn, &[filter], 1).ok()?;
+    let list = &results.first()?.note;
+    if let Some(url) = emoji_url(list, shortcode) {
+        return Some(url);
+    }
+
+    for tag in list.tags() {
+        if tag.count() < 2 || tag.get_unchecked(0).variant().str() != Some("a") {
+            continue;
+        }
+
+        // "30030:<pubkey>:<d tag>"
+        let address = if let Some(address) = tag.get_unchecked(1).variant().str() {
+            address
+        } else {
+            continue;
+        };
+        let mut parts = address.splitn(3, ':');
+        let (author, d) = match (parts.next(), parts.next(), parts.next()) {
+            (Some("30030"), Some(author), Some(d)) => (author, d),
+            _ => continue,
+        };
+        let author = if let Ok(author) = Pubkey::from_hex(author) {
+            author
+        } else {
+            continue;
+        };
+
+        let filter = Filter::new()
+            .authors([author.bytes()])
+            .kinds([30030])
+            .tags([d.to_owned()], 'd')
+            .limit(1)
+            .build();
+        let results = if let Ok(results) = ndb.query(txn, &[filter], 1) {
+            results
+        } else {
+            continue;
+        };
+        if let Some(url) = results.first().and_then(|r| emoji_url(&r.note, shortcode)) {
+            return Some(url);
+        }
+    }
+
+    None
+}
+
+/// React to `note_id` as the selected account. The reaction is stored
+/// locally first so the note shows it right away. Returns false if it
+/// couldn't be signed or sent.
+pub fn react(
+    ndb: &Ndb,
+    txn: &Transaction,
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    note_id: &NoteId,
+    reaction: &Reaction,
+) -> bool {
+    let pubkey = if let Some(pubkey) = accounts.selected_signer() {
+        pubkey
+    } else {
+        error!("can't react without a signer");
+        return false;
+    };
+
+    let target = if let Ok(note) = ndb.get_note_by_id(txn, note_id.bytes()) {
+        note
+    } else {
+        error!("can't react to unknown note {}", note_id.hex());
+        return false;
+    };
+
+    let note = match UnsignedNote::from_builder(pubkey, reaction.to_note(&target)) {
+        Ok(note) => note,
+        Err(err) => {
+            error!("could not build reaction: {}", err);
+            return false;
+        }
+    };
+
+    info!("reacting {} to {}", reaction.content(), note_id.hex());
+    if let Err(err) = accounts.sign_and_send(ndb, pool, wakeup, note, Destination::Outbox) {
+        error!("could not react to {}: {}", note_id.hex(), err);
+        return false;
+    }
+    true
+}
+
+/// The reactions to a note that we have locally
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct NoteReactions {
+    /// Grouped by what they say, most common first
+    pub groups: Vec<(Reaction, u64)>,
+    /// How the selected account reacted, if it did
+    pub ours: Option<Reaction>,
+}
+
+impl NoteReactions {
+    pub fn new<'a>(
+        reactions: impl IntoIterator<Item = (Reaction, &'a [u8; 32])>,
+        account: Option<&Pubkey>,
+    ) -> Self {
+        let mut counts: HashMap<Reaction, u64> = HashMap::new();
+        let mut ours = None;
+        for (reaction, author) in reactions {
+            if account.is_some_and(|pk| pk.bytes() == author) {
+                ours = Some(reaction.clone());
+            }
+            *counts.entry(reaction).or_default() += 1;
+        }
+
+        let mut groups: Vec<(Reaction, u64)> = counts.into_iter().collect();
+        groups.sort_by(|(a, a_count), (b, b_count)| {
+            b_count
+                .cmp(a_count)
+                .then_with(|| a.content().cmp(&b.content()))
+        });
+
+        NoteReactions { groups, ours }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.groups.iter().map(|(_, count)| count).sum()
+    }
+}
+
+struct CachedReactions {
+    queried: Instant,
+    reactions: NoteReactions,
+}
+
+/// Reactions to the notes we're showing, from local nostrdb queries.
+/// Each note is queried again every few seconds while it's on screen.
+#[derive(Default)]
+pub struct Reactions {
+    account: Option<Pubkey>,
+    cache: HashMap<NoteId, CachedReactions>,
+    last_pruned: Option<Instant>,
+}
+
+impl Reactions {
+    fn expires_in() -> Duration {
+        Duration::from_secs(3)
+    }
+
+    /// The most reactions we look at for a single note
+    fn limit() -> u64 {
+        500
+    }
+
+    pub fn account(&self) -> Option<&Pubkey> {
+        self.account.as_ref()
+    }
+
+    /// Whose reactions are "ours". Changing accounts forgets everything.
+    pub fn set_account(&mut self, account: Option<Pubkey>) {
+        if self.account != account {
+            self.account = account;
+            self.cache.clear();
+        }
+    }
+
+    pub fn get(&mut self, ndb: &Ndb, txn: &Transaction, note_id: &NoteId) -> &NoteReactions {
+        self.prune();
+
+        let expired = self
+            .cache
+            .get(note_id)
+            .map_or(true, |cached| cached.queried.elapsed() > Self::expires_in());
+
+        if expired {
+            let reactions = query_reactions(ndb, txn, note_id, self.account.as_ref());
+            self.cache.insert(
+                *note_id,
+                CachedReactions {
+                    queried: Instant::now(),
+                    reactions,
+                },
+            );
+        }
+
+        &self.cache[note_id].reactions
+    }
+
+    /// Forget the notes that have scrolled off screen, every so often.
+    /// Anything still on screen is queried again anyway.
+    fn prune(&mut self) {
+        let now = Instant::now();
+        if self
+            .last_pruned
+            .is_some_and(|pruned| now.duration_since(pruned) < Self::expires_in())
+        {
+            return;
+        }
+        self.last_pruned = Some(now);
+
+        self.cache
+            .retain(|_, cached| now.duration_since(cached.queried) <= Self::expires_in());
+    }
+
+    /// Show a reaction we just sent before nostrdb has it
+    pub fn reacted(&mut self, note_id: &NoteId, reaction: &Reaction) {
+        let cached = self
+            .cache
+            .entry(*note_id)
+            .or_insert_with(|| CachedReactions {
+                queried: Instant::now(),
+                reactions: NoteReactions::default(),
+            });
+        cached.queried = Instant::now();
+
+        let reactions = &mut cached.reactions;
+        if reactions.ours.is_some() {
+            return;
+        }
+        reactions.ours = Some(reaction.clone());
+        if let Some((_, count)) = reactions.groups.iter_mut().find(|(r, _)| r == reaction) {
+            *count += 1;
+        } else {
+            reactions.groups.push((reaction.clone(), 1));
+        }
+    }
+}
+
+fn query_reactions(
+    ndb: &Ndb,
+    txn: &Transaction,
+    note_id: &NoteId,
+    account: Option<&Pubkey>,
+) -> NoteReactions {
+    let filter = Filter::new()
+        .kinds([7])
+        .event(note_id.bytes())
+        .limit(Reactions::limit())
+        .build();
+
+    let results = match ndb.query(txn, &[filter], Reactions::limit() as i32) {
+        Ok(results) => results,
+        Err(err) => {
+            error!("reactions query failed: {}", err);
+            return NoteReactions::default();
+        }
+    };
+
+    NoteReactions::new(
+        results
+            .iter()
+            .map(|qr| (Reaction::from_note(&qr.note), qr.note.pubkey())),
+        account,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enostr::FullKeypair;
+
+    fn note_with(content: &str, seckey: &[u8; 32]) -> Note<'static> {
+        NoteBuilder::new()
+            .kind(1)
+            .content(content)
+            .sign(seckey)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reaction_tags() {
+        let keypair = FullKeypair::generate();
+        let seckey = keypair.secret_key.to_secret_bytes();
+        let target = note_with("hello", &seckey);
+
+        let custom = Reaction::Custom {
+            shortcode: "soapbox".to_owned(),
+            url: "https://example.com/soapbox.png".to_owned(),
+        };
+        let reaction = custom.to_note(&target).sign(&seckey).build().unwrap();
+        assert_eq!(reaction.kind(), 7);
+        assert_eq!(reaction.content(), ":soapbox:");
+
+        let json: serde_json::Value = serde_json::from_str(&reaction.json().unwrap()).unwrap();
+        let tags = json["tags"].as_array().unwrap();
+        assert_eq!(tags[0][0], "e");
+        assert_eq!(tags[0][1], hex::encode(target.id()));
+        assert_eq!(tags[1][0], "p");
+        assert_eq!(tags[1][1], hex::encode(target.pubkey()));
+        assert_eq!(tags[2][0], "k");
+        assert_eq!(tags[2][1], "1");
+        assert_eq!(tags[3][0], "emoji");
+        assert_eq!(tags[3][2], "https://example.com/soapbox.png");
+
+        assert_eq!(Reaction::from_note(&reaction), custom);
+    }
+
+    #[test]
+    fn test_reaction_content() {
+        let keypair = FullKeypair::generate();
+        let seckey = keypair.secret_key.to_secret_bytes();
+
+        assert_eq!(
+            Reaction::from_note(&note_with("+", &seckey)),
+            Reaction::Like
+        );
+        assert_eq!(Reaction::from_note(&note_with("", &seckey)), Reaction::Like);
+        assert_eq!(
+            Reaction::from_note(&note_with("-", &seckey)),
+            Reaction::Dislike
+        );
+        assert_eq!(
+            Reaction::from_note(&note_with("🤙", &seckey)),
+            Reaction::Emoji("🤙".to_owned())
+        );
+        // a shortcode without an emoji tag is just text
+        assert_eq!(
+            Reaction::from_note(&note_with(":nope:", &seckey)),
+            Reaction::Emoji(":nope:".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_typed_reactions() {
+        let url = "https://example.com/soapbox.png".to_owned();
+        let known = |shortcode: &str| (shortcode == "soapbox").then(|| url.clone());
+
+        assert_eq!(
+            Reaction::parse(" 🦀 ", known),
+            Some(Reaction::Emoji("🦀".to_owned()))
+        );
+        assert_eq!(
+            Reaction::parse(":soapbox:", known),
+            Some(Reaction::Custom {
+                shortcode: "soapbox".to_owned(),
+                url: url.clone(),
+            })
+        );
+        assert_eq!(Reaction::parse(":unknown:", known), None);
+        assert_eq!(Reaction::parse("  ", known), None);
+    }
+
+    #[test]
+    fn test_prune_forgets_old_notes() {
+        let mut cache = Reactions::default();
+        let old = NoteId::new([1; 32]);
+        let new = NoteId::new([2; 32]);
+        cache.reacted(&old, &Reaction::Like);
+        cache.reacted(&new, &Reaction::Like);
+        cache.cache.get_mut(&old).unwrap().queried = Instant::now() - Duration::from_secs(60);
+
+        cache.prune();
+        assert!(!cache.cache.contains_key(&old));
+        assert!(cache.cache.contains_key(&new));
+    }
+
+    #[test]
+    fn test_grouping() {
+        let me = Pubkey::new([1; 32]);
+        let reactions = NoteReactions::new(
+            [
+                (Reaction::Like, &[2; 32]),
+                (Reaction::Emoji("🤙".to_owned()), &[1; 32]),
+                (Reaction::Like, &[3; 32]),
+            ],
+            Some(&me),
+        );
+
+        assert_eq!(reactions.total(), 3);
+        assert_eq!(reactions.groups[0], (Reaction::Like, 2));
+        assert_eq!(reactions.ours, Some(Reaction::Emoji("🤙".to_owned())));
+
+        let mut cache = Reactions::default();
+        let id = NoteId::new([9; 32]);
+        cache.reacted(&id, &Reaction::Like);
+        cache.reacted(&id, &Reaction::Dislike);
+        assert_eq!(cache.cache[&id].reactions.groups, vec![(Reaction::Like, 1)]);
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use ehttp::{Request, Response};
+use enostr::{ProxyConfig, RelayInfoDocument, RelayPool};
+use notedeck::{storage, DataPath, DataPathType};
+use poll_promise::Promise;
+use tracing::{debug, error};
+
+/// Fetches NIP-11 info documents for the relays in the pool, and keeps
+/// them on disk so we don't ask every time we start
+pub struct RelayInfos {
+    cache_dir: PathBuf,
+    fetching: HashMap<String, Promise<Result<String, String>>>,
+
+    /// Relays we already loaded or tried to fetch this session
+    looked_up: HashSet<String>,
+}
+
+impl RelayInfos {
+    pub fn new(path: &DataPath) -> Self {
+        let cache_dir = path.path(DataPathType::Cache).join("relay_info");
+
+        RelayInfos {
+            cache_dir,
+            fetching: HashMap::new(),
+            looked_up: HashSet::new(),
+        }
+    }
+
+    /// Cached documents are refetched after this long
+    pub fn expires_in() -> Duration {
+        Duration::from_secs(24 * 60 * 60)
+    }
+
+    /// Look up relays we haven't seen yet, and hand the pool any documents
+    /// that came in since the last call
+    pub fn update(&mut self, pool: &mut RelayPool, egui_ctx: &egui::Context) {
+        for url in pool.urls() {
+            if !self.looked_up.insert(url.clone()) {
+                continue;
+            }
+
+            if let Some(info) = self.read_cached(&url) {
+                pool.set_info(&url, info);
+            } else if let Some(promise) = fetch_info(&url, pool.proxy(), egui_ctx) {
+                self.fetching.insert(url, promise);
+            }
+        }
+
+        let done: Vec<String> = self
+            .fetching
+            .iter()
+            .filter(|(_, promise)| promise.ready().is_some())
+            .map(|(url, _)| url.clone())
+            .collect();
+
+        for url in done {
+            let json = match self.fetching.remove(&url).map(Promise::block_and_take) {
+                Some(Ok(json)) => json,
+                Some(Err(err)) => {
+                    debug!("couldn't fetch relay info for {}: {}", url, err);
+                    continue;
+                }
+                None => continue,
+            };
+
+            match RelayInfoDocument::from_json(&json) {
+                Ok(info) => {
+                    if let Err(err) = storage::write_file(&self.cache_dir, file_name(&url), &json) {
+                        error!("could not cache relay info for {}: {}", url, err);
+                    }
+                    pool.set_info(&url, info);
+                }
+                Err(err) => debug!("bad relay info from {}: {}", url, err),
+            }
+        }
+    }
+
+    fn read_cached(&self, url: &str) -> Option<RelayInfoDocument> {
+        let path = self.cache_dir.join(file_name(url));
+        if is_stale(&path) {
+            return None;
+        }
+
+        let json = std::fs::read_to_string(path).ok()?;
+        RelayInfoDocument::from_json(&json).ok()
+    }
+}
+
+fn fetch_info(
+    relay_url: &str,
+    proxy: &ProxyConfig,
+    egui_ctx: &egui::Context,
+) -> Option<Promise<Result<String, String>>> {
+    let url = enostr::info_url(relay_url)?;
+    let mut request = Request::get(url);
+    request
+        .headers
+        .insert("Accept".to_owned(), "application/nostr+json".to_owned());
+
+    let (sender, promise) = Promise::new();
+    let ctx = egui_ctx.clone();
+    notedeck::http::fetch(proxy, request, move |response: Result<Response, String>| {
+        let result = response.and_then(|resp| {
+            if resp.ok {
+                resp.text()
+                    .map(str::to_owned)
+                    .ok_or_else(|| "not utf8".to_owned())
+            } else {
+                Err(format!("{} {}", resp.status, resp.status_text))
+            }
+        });
+        sender.send(result);
+        ctx.request_repaint();
+    });
+
+    Some(promise)
+}
+
+fn is_stale(path: &Path) -> bool {
+    let modified = if let Ok(modified) = std::fs::metadata(path).and_then(|m| m.modified()) {
+        modified
+    } else {
+        return true;
+    };
+
+    SystemTime::now()
+        .duration_since(modified)
+        .map(|age| age > RelayInfos::expires_in())
+        .unwrap_or(false)
+}
+
+fn file_name(relay_url: &str) -> String {
+    let name: String = relay_url
+        .trim_end_matches('/')
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    format!("{}.json", name)
+}
@@ -1,5 +1,6 @@
 use enostr::RelayPool;
-pub use enostr::RelayStatus;
+pub use enostr::{RelayAuthStatus, RelayInfoDocument, RelayStats, RelayStatus};
+use std::time::{Duration, Instant};
 
 /// The interface to a RelayPool for UI components.
 /// Represents all user-facing operations that can be performed for a user's relays
@@ -10,6 +11,14 @@ pub struct RelayPoolManager<'a> {
 pub struct RelayInfo<'a> {
     pub relay_url: &'a str,
     pub status: &'a RelayStatus,
+    pub auth: &'a RelayAuthStatus,
+    pub stats: &'a RelayStats,
+
+    /// The relay's NIP-11 document, if we have it
+    pub info: Option<&'a RelayInfoDocument>,
+
+    /// How long until we try to connect again, if we are disconnected
+    pub reconnect_in: Option<Duration>,
 }
 
 impl<'a> RelayPoolManager<'a> {
@@ -24,6 +33,15 @@ impl<'a> RelayPoolManager<'a> {
             .map(|relay| RelayInfo {
                 relay_url: &relay.relay.url,
                 status: &relay.relay.status,
+                auth: &relay.relay.auth,
+                stats: &relay.relay.stats,
+                info: relay.relay.info.as_ref(),
+                reconnect_in: match relay.relay.status {
+                    RelayStatus::Disconnected => {
+                        Some(relay.reconnect_at.saturating_duration_since(Instant::now()))
+                    }
+                    _ => None,
+                },
             })
             .collect()
     }
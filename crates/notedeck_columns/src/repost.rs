@@ -0,0 +1,175 @@
+use enostr::{NoteId, RelayPool, UnsignedNote};
+use nostrdb::{Ndb, Note, NoteBuilder, Transaction};
+use notedeck::{Accounts, Destination};
+use tracing::{debug, error, info};
+
+/// Kind 6 reposts kind 1 notes, kind 16 is a generic repost of
+/// anything else
+pub fn is_repost(note: &Note) -> bool {
+    matches!(note.kind(), 6 | 16)
+}
+
+/// The id of the note a repost is reposting
+pub fn reposted_id(note: &Note) -> Option<[u8; 32]> {
+    if !is_repost(note) {
+        return None;
+    }
+
+    for tag in note.tags() {
+        if tag.count() < 2 {
+            continue;
+        }
+
+        if tag.get_unchecked(0).variant().str() != Some("e") {
+            continue;
+        }
+
+        if let Some(id) = tag.get_unchecked(1).variant().id() {
+            return Some(*id);
+        }
+    }
+
+    None
+}
+
+pub fn reposted_note<'a>(ndb: &Ndb, txn: &'a Transaction, note: &Note) -> Option<Note<'a>> {
+    let id = reposted_id(note)?;
+    ndb.get_note_by_id(txn, &id).ok()
+}
+
+/// Reposts carry the reposted note as json in their content. If we
+/// don't have that note yet, hand it to nostrdb, which checks its
+/// signature, so we can show it without asking relays for it.
+pub fn ingest_reposted(ndb: &Ndb, txn: &Transaction, note: &Note) {
+    let id = if let Some(id) = reposted_id(note) {
+        id
+    } else {
+        return;
+    };
+
+    if ndb.get_note_by_id(txn, &id).is_ok() || note.content().is_empty() {
+        return;
+    }
+
+    if let Err(err) = ndb.process_event(&format!("[\"EVENT\",\"repost\",{}]", note.content())) {
+        debug!(
+            "couldn't process reposted note {}: {:?}",
+            hex::encode(id),
+            err
+        );
+    }
+}
+
+/// An unsigned repost of `reposting`, with it embedded in the content.
+/// Fails if the note can't be turned into json.
+pub fn to_repost<'a>(reposting: &Note) -> Result<NoteBuilder<'a>, nostrdb::Error> {
+    let json = reposting.json()?;
+    let kind = if reposting.kind() == 1 { 6 } else { 16 };
+
+    let mut builder = NoteBuilder::new()
+        .kind(kind)
+        .content(&json)
+        .start_tag()
+        .tag_str("e")
+        .tag_str(&hex::encode(reposting.id()))
+        .tag_str("")
+        .start_tag()
+        .tag_str("p")
+        .tag_str(&hex::encode(reposting.pubkey()));
+
+    if kind == 16 {
+        builder = builder
+            .start_tag()
+            .tag_str("k")
+            .tag_str(&reposting.kind().to_string());
+    }
+
+    Ok(builder)
+}
+
+/// Repost `note_id` as the selected account
+pub fn repost(
+    ndb: &Ndb,
+    txn: &Transaction,
+    accounts: &mut Accounts,
+    pool: &mut RelayPool,
+    wakeup: impl Fn() + Send + Sync + Clone + 'static,
+    note_id: &NoteId,
+) {
+    let pubkey = if let Some(pubkey) = accounts.selected_signer() {
+        pubkey
+    } else {
+        error!("can't repost without a signer");
+        return;
+    };
+
+    let reposting = if let Ok(note) = ndb.get_note_by_id(txn, note_id.bytes()) {
+        note
+    } else {
+        error!("can't repost unknown note {}", note_id.hex());
+        return;
+    };
+
+    let builder = match to_repost(&reposting) {
+        Ok(builder) => builder,
+        Err(err) => {
+            error!("could not repost {}: {:?}", note_id.hex(), err);
+            return;
+        }
+    };
+
+    let note = match UnsignedNote::from_builder(pubkey, builder) {
+        Ok(note) => note,
+        Err(err) => {
+            error!("could not build repost: {}", err);
+            return;
+        }
+    };
+
+    info!("reposting {}", note_id.hex());
+    if let Err(err) = accounts.sign_and_send(ndb, pool, wakeup, note, Destination::Outbox) {
+        error!("could not repost {}: {}", note_id.hex(), err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use enostr::FullKeypair;
+
+    #[test]
+    fn test_repost_kinds() {
+        let keypair = FullKeypair::generate();
+        let seckey = keypair.secret_key.to_secret_bytes();
+
+        let text = NoteBuilder::new()
+            .kind(1)
+            .content("hello")
+            .sign(&seckey)
+            .build()
+            .unwrap();
+        let repost = to_repost(&text).unwrap().sign(&seckey).build().unwrap();
+        assert_eq!(repost.kind(), 6);
+        assert!(is_repost(&repost));
+        assert_eq!(reposted_id(&repost), Some(*text.id()));
+        assert_eq!(repost.content(), text.json().unwrap());
+
+        let article = NoteBuilder::new()
+            .kind(30023)
+            .content("long form")
+            .sign(&seckey)
+            .build()
+            .unwrap();
+        let repost = to_repost(&article).unwrap().sign(&seckey).build().unwrap();
+        assert_eq!(repost.kind(), 16);
+        assert_eq!(reposted_id(&repost), Some(*article.id()));
+
+        let json: serde_json::Value = serde_json::from_str(&repost.json().unwrap()).unwrap();
+        let tags = json["tags"].as_array().unwrap();
+        assert_eq!(tags[1][1], hex::encode(article.pubkey()));
+        assert_eq!(tags[2][0], "k");
+        assert_eq!(tags[2][1], "30023");
+
+        assert_eq!(reposted_id(&text), None);
+    }
+}
@@ -7,6 +7,7 @@ use std::{
 use crate::{
     accounts::AccountsRoute,
     column::Columns,
+    dms::ConversationId,
     timeline::{TimelineId, TimelineRoute},
     ui::add_column::AddColumnRoute,
 };
@@ -22,6 +23,9 @@ pub enum Route {
     Support,
     NewDeck,
     EditDeck(usize),
+    Muted,
+    EditProfile,
+    Conversation(ConversationId),
 }
 
 impl Route {
@@ -65,6 +69,18 @@ impl Route {
         Route::Accounts(AccountsRoute::AddAccount)
     }
 
+    pub fn muted() -> Self {
+        Route::Muted
+    }
+
+    pub fn edit_profile() -> Self {
+        Route::EditProfile
+    }
+
+    pub fn conversation(id: ConversationId) -> Self {
+        Route::Conversation(id)
+    }
+
     pub fn title(&self, columns: &Columns) -> Cow<'static, str> {
         match self {
             Route::Timeline(tlr) => match tlr {
@@ -98,6 +114,9 @@ impl Route {
             Route::Support => Cow::Borrowed("Damus Support"),
             Route::NewDeck => Cow::Borrowed("Add Deck"),
             Route::EditDeck(_) => Cow::Borrowed("Edit Deck"),
+            Route::Muted => Cow::Borrowed("Muted"),
+            Route::EditProfile => Cow::Borrowed("Edit Profile"),
+            Route::Conversation(_) => Cow::Borrowed("Conversation"),
         }
     }
 }
@@ -209,6 +228,9 @@ impl fmt::Display for Route {
             Route::Support => write!(f, "Support"),
             Route::NewDeck => write!(f, "Add Deck"),
             Route::EditDeck(_) => write!(f, "Edit Deck"),
+            Route::Muted => write!(f, "Muted"),
+            Route::EditProfile => write!(f, "Edit Profile"),
+            Route::Conversation(_) => write!(f, "Conversation"),
         }
     }
 }
@@ -361,6 +361,9 @@ enum Keyword {
     Support,
     Deck,
     Edit,
+    Muted,
+    EditProfile,
+    Messages,
 }
 
 impl Keyword {
@@ -396,6 +399,9 @@ impl Keyword {
         ("support", Keyword::Support, false),
         ("deck", Keyword::Deck, false),
         ("edit", Keyword::Edit, true),
+        ("muted", Keyword::Muted, false),
+        ("edit_profile", Keyword::EditProfile, false),
+        ("messages", Keyword::Messages, false),
     ];
 
     fn has_payload(&self) -> bool {
@@ -483,6 +489,10 @@ fn serialize_route(route: &Route, columns: &Columns) -> Option<String> {
                             selections.push(Selection::Keyword(Keyword::Hashtag));
                             selections.push(Selection::Payload(hashtag.to_string()));
                         }
+                        TimelineKind::Messages(pubkey_source) => {
+                            selections.push(Selection::Keyword(Keyword::Messages));
+                            selections.extend(generate_pubkey_selections(pubkey_source));
+                        }
                     }
                 }
             }
@@ -538,6 +548,11 @@ fn serialize_route(route: &Route, columns: &Columns) -> Option<String> {
             selections.push(Selection::Keyword(Keyword::Edit));
             selections.push(Selection::Payload(index.to_string()));
         }
+        Route::Muted => selections.push(Selection::Keyword(Keyword::Muted)),
+        Route::EditProfile => selections.push(Selection::Keyword(Keyword::EditProfile)),
+        // conversations are only known once their messages are decrypted,
+        // so we don't restore them
+        Route::Conversation(_) => {}
     }
 
     if selections.is_empty() {
@@ -648,6 +663,21 @@ fn selections_to_route(selections: Vec<Selection>) -> Option<CleanIntermediaryRo
             )),
             _ => None,
         },
+        Selection::Keyword(Keyword::Messages) => match selections.get(1)? {
+            Selection::Keyword(Keyword::Explicit) => {
+                if let Selection::Payload(hex) = selections.get(2)? {
+                    Some(CleanIntermediaryRoute::ToTimeline(TimelineKind::messages(
+                        PubkeySource::Explicit(Pubkey::from_hex(hex.as_str()).ok()?),
+                    )))
+                } else {
+                    None
+                }
+            }
+            Selection::Keyword(Keyword::DeckAuthor) => Some(CleanIntermediaryRoute::ToTimeline(
+                TimelineKind::messages(PubkeySource::DeckAuthor),
+            )),
+            _ => None,
+        },
         Selection::Keyword(Keyword::Universe) => {
             Some(CleanIntermediaryRoute::ToTimeline(TimelineKind::Universe))
         }
@@ -722,6 +752,10 @@ fn selections_to_route(selections: Vec<Selection>) -> Option<CleanIntermediaryRo
         Selection::Keyword(Keyword::Support) => {
             Some(CleanIntermediaryRoute::ToRoute(Route::Support))
         }
+        Selection::Keyword(Keyword::Muted) => Some(CleanIntermediaryRoute::ToRoute(Route::Muted)),
+        Selection::Keyword(Keyword::EditProfile) => {
+            Some(CleanIntermediaryRoute::ToRoute(Route::EditProfile))
+        }
         Selection::Keyword(Keyword::Deck) => match selections.get(1)? {
             Selection::Keyword(Keyword::New) => {
                 Some(CleanIntermediaryRoute::ToRoute(Route::NewDeck))
@@ -17,6 +17,10 @@ pub enum SubKind {
     /// Filter.
     // TODO: generalize this to any list?
     FetchingContactList(TimelineId),
+
+    /// We are fetching the relay lists of a timeline's authors so that we
+    /// can find their notes on their outbox relays
+    FetchingRelayLists(TimelineId),
 }
 
 /// Subscriptions that need to be tracked at various stages. Sometimes we
@@ -25,6 +29,14 @@ pub enum SubKind {
 #[derive(Default)]
 pub struct Subscriptions {
     pub subs: HashMap<String, SubKind>,
+
+    /// The outbox subscription id of each timeline that has been routed
+    /// to its authors' relays
+    pub outbox: HashMap<TimelineId, String>,
+
+    /// The timeline each remote timeline subscription is for, so we know
+    /// where to catch up from after a relay reconnects
+    pub timelines: HashMap<String, TimelineId>,
 }
 
 pub fn new_sub_id() -> String {
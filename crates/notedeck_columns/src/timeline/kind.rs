@@ -1,8 +1,12 @@
+use crate::dms;
 use crate::error::Error;
 use crate::timeline::Timeline;
 use enostr::{Filter, Pubkey};
 use nostrdb::{Ndb, Transaction};
-use notedeck::{filter::default_limit, FilterError, FilterState};
+use notedeck::{
+    filter::{default_limit, note_kinds},
+    FilterError, FilterState,
+};
 use serde::{Deserialize, Serialize};
 use std::{borrow::Cow, fmt::Display};
 use tracing::{error, warn};
@@ -57,6 +61,9 @@ pub enum TimelineKind {
     Generic,
 
     Hashtag(String),
+
+    /// NIP-17 direct messages, plus legacy NIP-04 ones
+    Messages(PubkeySource),
 }
 
 impl Display for TimelineKind {
@@ -68,6 +75,7 @@ impl Display for TimelineKind {
             TimelineKind::Profile(_) => f.write_str("Profile"),
             TimelineKind::Universe => f.write_str("Universe"),
             TimelineKind::Hashtag(_) => f.write_str("Hashtag"),
+            TimelineKind::Messages(_) => f.write_str("Messages"),
         }
     }
 }
@@ -81,6 +89,7 @@ impl TimelineKind {
             TimelineKind::Universe => None,
             TimelineKind::Generic => None,
             TimelineKind::Hashtag(_ht) => None,
+            TimelineKind::Messages(pk_src) => Some(pk_src),
         }
     }
 
@@ -104,12 +113,26 @@ impl TimelineKind {
         TimelineKind::Notifications(pk)
     }
 
+    pub fn messages(pk: PubkeySource) -> Self {
+        TimelineKind::Messages(pk)
+    }
+
+    pub fn is_messages(&self) -> bool {
+        matches!(self, TimelineKind::Messages(_))
+    }
+
+    /// Gift wraps are backdated by up to two days, so asking relays only
+    /// for notes newer than the ones we have would miss messages
+    pub fn can_since_optimize(&self) -> bool {
+        !self.is_messages()
+    }
+
     pub fn into_timeline(self, ndb: &Ndb, default_user: Option<&[u8; 32]>) -> Option<Timeline> {
         match self {
             TimelineKind::Universe => Some(Timeline::new(
                 TimelineKind::Universe,
                 FilterState::ready(vec![Filter::new()
-                    .kinds([1])
+                    .kinds(note_kinds())
                     .limit(default_limit())
                     .build()]),
             )),
@@ -127,7 +150,7 @@ impl TimelineKind {
 
                 let filter = Filter::new()
                     .authors([pk])
-                    .kinds([1])
+                    .kinds(note_kinds())
                     .limit(default_limit())
                     .build();
 
@@ -157,6 +180,18 @@ impl TimelineKind {
 
             TimelineKind::Hashtag(hashtag) => Some(Timeline::hashtag(hashtag)),
 
+            TimelineKind::Messages(pk_src) => {
+                let pk = match &pk_src {
+                    PubkeySource::DeckAuthor => default_user?,
+                    PubkeySource::Explicit(pk) => pk.bytes(),
+                };
+
+                Some(Timeline::new(
+                    TimelineKind::messages(pk_src),
+                    FilterState::ready(dms::filters(pk)),
+                ))
+            }
+
             TimelineKind::List(ListKind::Contact(pk_src)) => {
                 let pk = match &pk_src {
                     PubkeySource::DeckAuthor => default_user?,
@@ -204,6 +239,7 @@ impl TimelineKind {
             TimelineKind::Universe => Cow::Borrowed("Universe"),
             TimelineKind::Generic => Cow::Borrowed("Custom"),
             TimelineKind::Hashtag(hashtag) => Cow::Owned(format!("#{}", hashtag)),
+            TimelineKind::Messages(_pubkey_source) => Cow::Borrowed("Messages"),
         }
     }
 }
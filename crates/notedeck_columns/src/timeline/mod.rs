@@ -2,6 +2,7 @@ use crate::{
     column::Columns,
     decks::DecksCache,
     error::Error,
+    repost,
     subscriptions::{self, SubKind, Subscriptions},
     Result,
 };
@@ -11,12 +12,13 @@ use notedeck::{
     UnknownIds,
 };
 
+use std::collections::HashSet;
 use std::fmt;
 use std::sync::atomic::{AtomicU32, Ordering};
 
 use egui_virtual_list::VirtualList;
-use enostr::{Relay, RelayPool};
-use nostrdb::{Filter, Ndb, Note, Subscription, Transaction};
+use enostr::{FilterSpec, NegentropySupport, Relay, RelayPool};
+use nostrdb::{Filter, Ndb, Note, NoteKey, Subscription, Transaction};
 use std::cell::RefCell;
 use std::hash::Hash;
 use std::rc::Rc;
@@ -26,6 +28,9 @@ use tracing::{debug, error, info, warn};
 pub mod kind;
 pub mod route;
 
+/// Most local notes we describe to a relay in a negentropy sync
+const SYNC_QUERY_LIMIT: i32 = 10_000;
+
 pub use kind::{PubkeySource, TimelineKind};
 pub use route::TimelineRoute;
 
@@ -68,7 +73,8 @@ impl ViewFilter {
     }
 
     pub fn filter_notes(cache: &CachedNote, note: &Note) -> bool {
-        !cache.reply.borrow(note.tags()).is_reply()
+        // a repost's e tag isn't a reply
+        repost::is_repost(note) || !cache.reply.borrow(note.tags()).is_reply()
     }
 
     fn identity(_cache: &CachedNote, _note: &Note) -> bool {
@@ -183,6 +189,9 @@ pub struct Timeline {
 
     /// Our nostrdb subscription
     pub subscription: Option<Subscription>,
+
+    /// Ids of the notes we show a repost of instead of the note itself
+    reposted: HashSet<[u8; 32]>,
 }
 
 impl Timeline {
@@ -236,7 +245,43 @@ impl Timeline {
             views,
             subscription,
             selected_view,
+            reposted: HashSet::new(),
+        }
+    }
+
+    /// Whether we already show `note` in another form: it's a repost of
+    /// a note we show, or of something we show another repost of, or
+    /// it's a note we show a repost of. `pending` are the notes about to
+    /// be added along with this one.
+    fn is_redundant_repost(
+        &mut self,
+        ndb: &Ndb,
+        txn: &Transaction,
+        note: &Note,
+        pending: &[NoteKey],
+    ) -> bool {
+        let id = if let Some(id) = repost::reposted_id(note) {
+            id
+        } else {
+            // the repost came first
+            return self.reposted.contains(note.id());
+        };
+
+        if self.reposted.contains(&id) {
+            return true;
+        }
+
+        let shown = ndb.get_notekey_by_id(txn, &id).is_ok_and(|key| {
+            pending.contains(&key)
+                || self
+                    .notes(ViewFilter::NotesAndReplies)
+                    .iter()
+                    .any(|nr| nr.key == key)
+        });
+        if !shown {
+            self.reposted.insert(id);
         }
+        shown
     }
 
     pub fn current_view(&self) -> &TimelineTab {
@@ -284,16 +329,21 @@ impl Timeline {
 
         let mut new_refs: Vec<(Note, NoteRef)> = Vec::with_capacity(new_note_ids.len());
 
-        for key in new_note_ids {
+        for key in new_note_ids.iter().copied() {
             let note = if let Ok(note) = ndb.get_note_by_key(txn, key) {
                 note
             } else {
                 error!("hit race condition in poll_notes_into_view: https://github.com/damus-io/nostrdb/issues/35 note {:?} was not added to timeline", key);
                 continue;
             };
-            if is_muted(&note) {
+            if is_note_muted(ndb, txn, note_cache, &note, is_muted) {
+                continue;
+            }
+
+            if timeline.is_redundant_repost(ndb, txn, &note, &new_note_ids) {
                 continue;
             }
+            repost::ingest_reposted(ndb, txn, &note);
 
             UnknownIds::update_from_note(txn, ndb, unknown_ids, note_cache, &note);
 
@@ -398,7 +448,7 @@ pub fn setup_new_timeline(
     is_muted: &MuteFun,
 ) {
     // if we're ready, setup local subs
-    if is_timeline_ready(ndb, pool, note_cache, timeline, is_muted) {
+    if is_timeline_ready(ndb, pool, subs, note_cache, timeline, is_muted) {
         if let Err(err) = setup_timeline_nostrdb_sub(ndb, note_cache, timeline, is_muted) {
             error!("setup_new_timeline: {err}");
         }
@@ -428,7 +478,19 @@ pub fn send_initial_timeline_filters(
         .find(|r| r.relay.url == relay_id)?
         .relay;
 
+    // columns with the same filters can share a remote subscription,
+    // nostrdb hands the notes to each of them
+    let mut sent: HashSet<String> = HashSet::new();
     for timeline in columns.timelines_mut() {
+        if let FilterState::Ready(filters) = timeline.filter.get(&relay.url) {
+            if let Some(key) = FilterSpec::key_of(filters) {
+                if !sent.insert(key) {
+                    debug!("{:?} shares its remote subscription", timeline.kind);
+                    continue;
+                }
+            }
+        }
+
         send_initial_timeline_filter(ndb, since_optimize, subs, relay, timeline);
     }
 
@@ -461,38 +523,32 @@ pub fn send_initial_timeline_filter(
 
         FilterState::Ready(filter) => {
             let filter = filter.to_owned();
-            let new_filters = filter.into_iter().map(|f| {
-                // limit the size of remote filters
-                let default_limit = filter::default_remote_limit();
-                let mut lim = f.limit().unwrap_or(default_limit);
-                let mut filter = f;
-                if lim > default_limit {
-                    lim = default_limit;
-                    filter = filter.limit_mut(lim);
-                }
-
-                let notes = timeline.notes(ViewFilter::NotesAndReplies);
+            let window = if can_since_optimize && timeline.kind.can_since_optimize() {
+                sync_window(ndb, timeline, &filter)
+            } else {
+                None
+            };
 
-                // Should we since optimize? Not always. For example
-                // if we only have a few notes locally. One way to
-                // determine this is by looking at the current filter
-                // and seeing what its limit is. If we have less
-                // notes than the limit, we might want to backfill
-                // older notes
-                if can_since_optimize && filter::should_since_optimize(lim, notes.len()) {
-                    filter = filter::since_optimize_filter(filter, notes);
+            let new_filters =
+                if window.is_some() && relay.negentropy == NegentropySupport::Supported {
+                    // the sync below fills in anything we are missing, so we
+                    // only need what's newer
+                    newer_timeline_filters(timeline, &filter)
                 } else {
-                    warn!("Skipping since optimization for {:?}: number of local notes is less than limit, attempting to backfill.", filter);
-                }
-
-                filter
-            }).collect();
+                    remote_timeline_filters(can_since_optimize, timeline, &filter)
+                };
 
             //let sub_id = damus.gen_subid(&SubKind::Initial);
             let sub_id = subscriptions::new_sub_id();
             subs.subs.insert(sub_id.clone(), SubKind::Initial);
+            subs.timelines.insert(sub_id.clone(), timeline.id);
 
             relay.subscribe(sub_id, new_filters);
+
+            if let Some((window, have)) = window {
+                let limit = filter::default_remote_limit() as usize;
+                relay.sync(subscriptions::new_sub_id(), window, have, limit);
+            }
         }
 
         // we need some data first
@@ -502,6 +558,121 @@ pub fn send_initial_timeline_filter(
     }
 }
 
+/// Send a relay that reconnected all of our subscriptions again. The ones
+/// for timelines only ask for notes newer than what the timeline has, so
+/// we catch up on what we missed while we were gone.
+pub fn resubscribe(pool: &mut RelayPool, relay_url: &str, subs: &Subscriptions, columns: &Columns) {
+    pool.resubscribe(relay_url, |sub_id, filters| {
+        let timeline = subs
+            .timelines
+            .get(sub_id)
+            .and_then(|id| columns.find_timeline(*id));
+
+        if let Some(timeline) = timeline {
+            newer_timeline_filters(timeline, &filters)
+        } else {
+            filters
+        }
+    });
+}
+
+/// Prepare a timeline's filters for sending to a relay. Remote limits are
+/// capped, and we only ask for notes newer than the ones we already have
+/// when that makes sense.
+pub fn remote_timeline_filters(
+    can_since_optimize: bool,
+    timeline: &Timeline,
+    filters: &[Filter],
+) -> Vec<Filter> {
+    filters.iter().map(|f| {
+        // limit the size of remote filters
+        let default_limit = filter::default_remote_limit();
+        let mut lim = f.limit().unwrap_or(default_limit);
+        let mut filter = f.to_owned();
+        if lim > default_limit {
+            lim = default_limit;
+            filter = filter.limit_mut(lim);
+        }
+
+        let notes = timeline.notes(ViewFilter::NotesAndReplies);
+
+        // Should we since optimize? Not always. For example
+        // if we only have a few notes locally. One way to
+        // determine this is by looking at the current filter
+        // and seeing what its limit is. If we have less
+        // notes than the limit, we might want to backfill
+        // older notes
+        if can_since_optimize
+            && timeline.kind.can_since_optimize()
+            && filter::should_since_optimize(lim, notes.len())
+        {
+            filter = filter::since_optimize_filter(filter, notes);
+        } else {
+            warn!("Skipping since optimization for {:?}: number of local notes is less than limit, attempting to backfill.", filter);
+        }
+
+        filter
+    }).collect()
+}
+
+/// Only ask for notes newer than the ones we have
+fn newer_timeline_filters(timeline: &Timeline, filters: &[Filter]) -> Vec<Filter> {
+    let notes = timeline.notes(ViewFilter::NotesAndReplies);
+    let default_limit = filter::default_remote_limit();
+
+    filters
+        .iter()
+        .map(|f| {
+            let mut filter = f.to_owned();
+            if f.limit().unwrap_or(default_limit) > default_limit {
+                filter = filter.limit_mut(default_limit);
+            }
+
+            if timeline.kind.can_since_optimize() {
+                filter::since_optimize_filter(filter, notes)
+            } else {
+                filter
+            }
+        })
+        .collect()
+}
+
+/// The stretch of a timeline we have notes for, and the notes we have in
+/// it, for a NIP-77 sync to find the ones we are missing in between
+fn sync_window(
+    ndb: &Ndb,
+    timeline: &Timeline,
+    filters: &[Filter],
+) -> Option<(Filter, Vec<(u64, [u8; 32])>)> {
+    // NEG-OPEN only takes a single filter
+    if filters.len() != 1 {
+        return None;
+    }
+
+    let notes = timeline.notes(ViewFilter::NotesAndReplies);
+    let newest = notes.first()?.created_at;
+    let oldest = notes.last()?.created_at;
+
+    let mut json: serde_json::Value = serde_json::from_str(&filters[0].json().ok()?).ok()?;
+    let fields = json.as_object_mut()?;
+    // relays reconcile everything that matches, a limit would only
+    // confuse things
+    fields.remove("limit");
+    fields.insert("since".to_owned(), oldest.into());
+    fields.insert("until".to_owned(), newest.into());
+    let window = Filter::from_json(&json.to_string()).ok()?;
+
+    let txn = Transaction::new(ndb).ok()?;
+    let have = ndb
+        .query(&txn, &[window.clone()], SYNC_QUERY_LIMIT)
+        .ok()?
+        .into_iter()
+        .map(|result| (result.note.created_at(), *result.note.id()))
+        .collect();
+
+    Some((window, have))
+}
+
 fn fetch_contact_list(
     filter: Vec<Filter>,
     ndb: &Ndb,
@@ -549,6 +720,19 @@ fn setup_initial_timeline(
     Ok(())
 }
 
+/// Whether to hide `note`, or the note it reposts if we have it already
+fn is_note_muted(
+    ndb: &Ndb,
+    txn: &Transaction,
+    note_cache: &mut NoteCache,
+    note: &Note,
+    is_muted: &MuteFun,
+) -> bool {
+    is_muted(note, ndb, note_cache)
+        || repost::reposted_note(ndb, txn, note)
+            .is_some_and(|reposted| is_muted(&reposted, ndb, note_cache))
+}
+
 pub fn copy_notes_into_timeline(
     timeline: &mut Timeline,
     txn: &Transaction,
@@ -564,18 +748,28 @@ pub fn copy_notes_into_timeline(
         filters
     };
 
+    let keys: Vec<NoteKey> = notes.iter().map(|nr| nr.key).collect();
+
     for note_ref in notes {
+        let note = if let Ok(note) = ndb.get_note_by_key(txn, note_ref.key) {
+            note
+        } else {
+            continue;
+        };
+
+        if is_note_muted(ndb, txn, note_cache, &note, is_muted)
+            || timeline.is_redundant_repost(ndb, txn, &note, &keys)
+        {
+            continue;
+        }
+        repost::ingest_reposted(ndb, txn, &note);
+
         for (view, filter) in filters.iter().enumerate() {
-            if let Ok(note) = ndb.get_note_by_key(txn, note_ref.key) {
-                if is_muted(&note) {
-                    continue;
-                }
-                if filter(
-                    note_cache.cached_note_or_insert_mut(note_ref.key, &note),
-                    &note,
-                ) {
-                    timeline.views[view].notes.push(note_ref)
-                }
+            if filter(
+                note_cache.cached_note_or_insert_mut(note_ref.key, &note),
+                &note,
+            ) {
+                timeline.views[view].notes.push(note_ref)
             }
         }
     }
@@ -624,6 +818,7 @@ fn setup_timeline_nostrdb_sub(
 pub fn is_timeline_ready(
     ndb: &Ndb,
     pool: &mut RelayPool,
+    subs: &mut Subscriptions,
     note_cache: &mut NoteCache,
     timeline: &mut Timeline,
     is_muted: &MuteFun,
@@ -690,8 +885,328 @@ pub fn is_timeline_ready(
             //let ck = &timeline.kind;
             //let subid = damus.gen_subid(&SubKind::Column(ck.clone()));
             let subid = subscriptions::new_sub_id();
+            subs.timelines.insert(subid.clone(), timeline.id);
             pool.subscribe(subid, filter);
             true
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{self, get_active_columns_mut};
+    use crate::Damus;
+    use enostr::{FullKeypair, RelayEvent, RelayMessage};
+    use mock_relay::MockRelay;
+    use nostrdb::{Config, NoteBuilder};
+    use notedeck::{
+        Accounts, AppContext, Args, DataPath, ImageCache, KeyStorageType, ThemeHandler,
+    };
+    use std::time::{Duration, Instant};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn signed_event(keypair: &FullKeypair, kind: u32, p_tags: &[&enostr::Pubkey]) -> String {
+        let mut builder = NoteBuilder::new().kind(kind).content("hello");
+        for pk in p_tags {
+            builder = builder.start_tag().tag_str("p").tag_str(&pk.hex());
+        }
+
+        builder
+            .sign(&keypair.secret_key.to_secret_bytes())
+            .build()
+            .expect("note")
+            .json()
+            .expect("json")
+    }
+
+    /// Hand relay messages to the app until we see EOSE for `sub_id`.
+    /// Returns the events we got for it.
+    fn wait_for_eose(damus: &mut Damus, ctx: &mut AppContext<'_>, sub_id: &str) -> Vec<String> {
+        let mut events = Vec::new();
+        let start = Instant::now();
+        while start.elapsed() < TIMEOUT {
+            let ev = if let Some(ev) = ctx.pool.try_recv() {
+                ev.into_owned()
+            } else {
+                std::thread::sleep(Duration::from_millis(5));
+                continue;
+            };
+
+            if let RelayEvent::Message(msg) = RelayEvent::from(&ev.event) {
+                app::process_message(damus, ctx, &ev.relay, &msg);
+                match msg {
+                    RelayMessage::Event(id, txt) if id == sub_id => events.push(txt.to_owned()),
+                    RelayMessage::Eose(id) if id == sub_id => return events,
+                    _ => {}
+                }
+            }
+        }
+
+        panic!("no EOSE for {}", sub_id);
+    }
+
+    fn send_filter(damus: &mut Damus, ctx: &mut AppContext<'_>, id: TimelineId) {
+        let timeline = get_active_columns_mut(ctx.accounts, &mut damus.decks_cache)
+            .find_timeline_mut(id)
+            .expect("timeline");
+        send_initial_timeline_filter(
+            ctx.ndb,
+            false,
+            &mut damus.subscriptions,
+            &mut ctx.pool.relays[0].relay,
+            timeline,
+        );
+    }
+
+    fn sub_of_kind(subs: &Subscriptions, is_kind: impl Fn(&SubKind) -> bool) -> String {
+        subs.subs
+            .iter()
+            .find(|(_, kind)| is_kind(kind))
+            .map(|(sub_id, _)| sub_id.clone())
+            .expect("subscription")
+    }
+
+    /// Hand `note` to nostrdb and wait until it's there
+    fn ingest(ndb: &Ndb, note: &Note) -> NoteRef {
+        ndb.process_event(&format!("[\"EVENT\",\"test\",{}]", note.json().unwrap()))
+            .unwrap();
+
+        let start = Instant::now();
+        loop {
+            let txn = Transaction::new(ndb).unwrap();
+            if let Ok(key) = ndb.get_notekey_by_id(&txn, note.id()) {
+                return NoteRef {
+                    key,
+                    created_at: note.created_at(),
+                };
+            }
+            assert!(start.elapsed() < TIMEOUT, "note never showed up");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// A note, and someone else's repost of it, both in nostrdb
+    fn note_and_repost(ndb: &Ndb) -> (NoteRef, NoteRef) {
+        let author = FullKeypair::generate().secret_key.to_secret_bytes();
+        let reposter = FullKeypair::generate().secret_key.to_secret_bytes();
+        let note = NoteBuilder::new()
+            .kind(1)
+            .content("muted words")
+            .sign(&author)
+            .build()
+            .unwrap();
+        let reposted = repost::to_repost(&note)
+            .unwrap()
+            .sign(&reposter)
+            .build()
+            .unwrap();
+
+        (ingest(ndb, &note), ingest(ndb, &reposted))
+    }
+
+    fn shown(timeline: &Timeline) -> Vec<NoteKey> {
+        timeline
+            .notes(ViewFilter::NotesAndReplies)
+            .iter()
+            .map(|nr| nr.key)
+            .collect()
+    }
+
+    #[test]
+    fn test_reposts_dedup_in_either_order() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ndb = Ndb::new(tmp.path().to_str().unwrap(), &Config::new()).expect("ndb");
+        let mut note_cache = NoteCache::default();
+        let is_muted = |_: &Note, _: &Ndb, _: &mut NoteCache| false;
+        let (note, reposted) = note_and_repost(&ndb);
+        let txn = Transaction::new(&ndb).unwrap();
+
+        let mut copy = |batches: &[&[NoteRef]]| {
+            let mut timeline = Timeline::new(TimelineKind::Universe, FilterState::ready(vec![]));
+            for batch in batches {
+                copy_notes_into_timeline(
+                    &mut timeline,
+                    &txn,
+                    &ndb,
+                    &mut note_cache,
+                    batch.to_vec(),
+                    &is_muted,
+                );
+            }
+            shown(&timeline)
+        };
+
+        // the note first, then its repost
+        assert_eq!(copy(&[&[note], &[reposted]]), vec![note.key]);
+        // the repost first, then the note
+        assert_eq!(copy(&[&[reposted], &[note]]), vec![reposted.key]);
+        // both at once
+        assert_eq!(copy(&[&[reposted, note]]), vec![note.key]);
+    }
+
+    #[test]
+    fn test_reposts_of_muted_notes_are_hidden() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ndb = Ndb::new(tmp.path().to_str().unwrap(), &Config::new()).expect("ndb");
+        let mut note_cache = NoteCache::default();
+        let is_muted =
+            |note: &Note, _: &Ndb, _: &mut NoteCache| note.content().contains("muted words");
+        let (_, reposted) = note_and_repost(&ndb);
+        let txn = Transaction::new(&ndb).unwrap();
+
+        let mut timeline = Timeline::new(TimelineKind::Universe, FilterState::ready(vec![]));
+        copy_notes_into_timeline(
+            &mut timeline,
+            &txn,
+            &ndb,
+            &mut note_cache,
+            vec![reposted],
+            &is_muted,
+        );
+        assert!(shown(&timeline).is_empty());
+    }
+
+    #[test]
+    fn test_contact_list_timeline_from_relay() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ndb = Ndb::new(tmp.path().to_str().unwrap(), &Config::new()).expect("ndb");
+        let path = DataPath::new(tmp.path());
+        let args = Args::parse(&[]);
+        let mut img_cache = ImageCache::new(tmp.path().join("img"));
+        let mut unknown_ids = UnknownIds::default();
+        let mut pool = RelayPool::new();
+        let mut note_cache = NoteCache::default();
+        let mut accounts = Accounts::new(KeyStorageType::None, vec![]);
+        let mut theme = ThemeHandler::new(&path);
+        let egui = egui::Context::default();
+        let mut damus = Damus::mock(tmp.path());
+        let is_muted = |_: &Note, _: &Ndb, _: &mut NoteCache| false;
+
+        let user = FullKeypair::generate();
+        let friend = FullKeypair::generate();
+        let relay = MockRelay::start().unwrap();
+        relay
+            .add_event_json(&signed_event(&user, 3, &[&friend.pubkey]))
+            .unwrap();
+        relay
+            .add_event_json(&signed_event(&friend, 1, &[]))
+            .unwrap();
+        pool.add_url(relay.url(), || {}).unwrap();
+
+        let mut ctx = AppContext {
+            ndb: &ndb,
+            img_cache: &mut img_cache,
+            unknown_ids: &mut unknown_ids,
+            pool: &mut pool,
+            note_cache: &mut note_cache,
+            accounts: &mut accounts,
+            path: &path,
+            args: &args,
+            theme: &mut theme,
+            egui: &egui,
+        };
+
+        let timeline = TimelineKind::contact_list(PubkeySource::Explicit(user.pubkey))
+            .into_timeline(&ndb, None)
+            .expect("timeline");
+        assert!(timeline.filter.get_any_ready().is_none());
+        let timeline_id = timeline.id;
+        damus
+            .columns_mut(ctx.accounts)
+            .add_new_timeline_column(timeline);
+
+        // we don't have the contact list, so we have to fetch it first.
+        // The app moves the timeline on when the fetch is done.
+        send_filter(&mut damus, &mut ctx, timeline_id);
+        let fetch_id = sub_of_kind(&damus.subscriptions, |kind| {
+            matches!(kind, SubKind::FetchingContactList(_))
+        });
+        assert_eq!(wait_for_eose(&mut damus, &mut ctx, &fetch_id).len(), 1);
+
+        // nostrdb ingests in the background
+        let start = Instant::now();
+        loop {
+            let timeline = get_active_columns_mut(ctx.accounts, &mut damus.decks_cache)
+                .find_timeline_mut(timeline_id)
+                .expect("timeline");
+            if is_timeline_ready(
+                ctx.ndb,
+                ctx.pool,
+                &mut damus.subscriptions,
+                ctx.note_cache,
+                timeline,
+                &is_muted,
+            ) {
+                break;
+            }
+            assert!(start.elapsed() < TIMEOUT, "contact list never showed up");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // now we can ask for the notes of the people we follow
+        send_filter(&mut damus, &mut ctx, timeline_id);
+        let initial_id = sub_of_kind(&damus.subscriptions, |kind| {
+            matches!(kind, SubKind::Initial)
+        });
+        let notes = wait_for_eose(&mut damus, &mut ctx, &initial_id);
+        assert_eq!(notes.len(), 1);
+
+        let req = relay
+            .received_of("REQ")
+            .into_iter()
+            .find(|req| req[1] == initial_id.as_str())
+            .expect("initial REQ");
+        assert_eq!(req[2]["authors"][0], friend.pubkey.hex());
+    }
+
+    #[test]
+    fn test_prune_forgets_removed_timelines() {
+        let tmp = tempfile::TempDir::new().unwrap();
+        let ndb = Ndb::new(tmp.path().to_str().unwrap(), &Config::new()).expect("ndb");
+        let mut pool = RelayPool::new();
+        let mut accounts = Accounts::new(KeyStorageType::None, vec![]);
+        let mut damus = Damus::mock(tmp.path());
+
+        let timeline =
+            TimelineKind::contact_list(PubkeySource::Explicit(FullKeypair::generate().pubkey))
+                .into_timeline(&ndb, None)
+                .expect("timeline");
+        let live_id = timeline.id;
+        damus
+            .columns_mut(&mut accounts)
+            .add_new_timeline_column(timeline);
+        let gone_id = TimelineId::new(live_id.0 + 1000);
+
+        let subs = &mut damus.subscriptions;
+        let live_sub = subscriptions::new_sub_id();
+        subs.subs.insert(live_sub.clone(), SubKind::Initial);
+        subs.timelines.insert(live_sub.clone(), live_id);
+        for kind in [
+            SubKind::Initial,
+            SubKind::FetchingRelayLists(gone_id),
+            SubKind::FetchingContactList(gone_id),
+        ] {
+            let subid = subscriptions::new_sub_id();
+            if let SubKind::Initial = kind {
+                subs.timelines.insert(subid.clone(), gone_id);
+            }
+            subs.subs.insert(subid, kind);
+        }
+        subs.outbox.insert(gone_id, subscriptions::new_sub_id());
+
+        crate::outbox::prune(
+            &mut accounts,
+            &mut pool,
+            &mut damus.subscriptions,
+            &damus.decks_cache,
+        );
+
+        let subs = &damus.subscriptions;
+        assert_eq!(subs.subs.keys().collect::<Vec<_>>(), vec![&live_sub]);
+        assert_eq!(subs.timelines.get(&live_sub), Some(&live_id));
+        assert_eq!(subs.timelines.len(), 1);
+        assert!(subs.outbox.is_empty());
+    }
+}
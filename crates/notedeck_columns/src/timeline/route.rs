@@ -8,14 +8,14 @@ use crate::{
     timeline::{TimelineId, TimelineKind},
     ui::{
         self,
-        note::{NoteOptions, QuoteRepostView},
+        note::{NoteContext, NoteOptions, QuoteRepostView},
         profile::ProfileView,
     },
 };
 
 use enostr::{NoteId, Pubkey};
 use nostrdb::{Ndb, Transaction};
-use notedeck::{Accounts, ImageCache, MuteFun, NoteCache, UnknownIds};
+use notedeck::{Accounts, ImageCache, MuteFun, Muted, NoteCache, UnknownIds};
 
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 pub enum TimelineRoute {
@@ -37,11 +37,22 @@ pub fn render_timeline_route(
     threads: &mut NotesHolderStorage<Thread>,
     profiles: &mut NotesHolderStorage<Profile>,
     accounts: &mut Accounts,
+    note_context: NoteContext,
     route: TimelineRoute,
     col: usize,
     textmode: bool,
     ui: &mut egui::Ui,
 ) -> Option<RenderNavAction> {
+    let muted = accounts.get_selected_account_muted();
+    let can_sign = accounts.selected_signer().is_some();
+    let note_context = NoteContext {
+        contacts: accounts.get_selected_account_contacts(),
+        muted: muted.as_deref(),
+        can_follow: can_sign && accounts.selected_account_contacts_fetched(),
+        can_mute: can_sign && accounts.selected_account_muted_fetched(),
+        ..note_context
+    };
+
     match route {
         TimelineRoute::Timeline(timeline_id) => {
             let note_options = {
@@ -64,6 +75,7 @@ pub fn render_timeline_route(
                 img_cache,
                 note_options,
             )
+            .context(note_context)
             .ui(ui);
 
             note_action.map(RenderNavAction::NoteAction)
@@ -79,6 +91,7 @@ pub fn render_timeline_route(
             textmode,
         )
         .id_source(egui::Id::new(("threadscroll", col)))
+        .context(note_context)
         .ui(ui, &accounts.mutefun())
         .map(Into::into),
 
@@ -98,7 +111,7 @@ pub fn render_timeline_route(
             };
 
             let id = egui::Id::new(("post", col, note.key().unwrap()));
-            let poster = accounts.selected_or_first_nsec()?;
+            let poster = accounts.selected_or_first_signer()?;
 
             let action = {
                 let draft = drafts.reply_mut(note.id());
@@ -115,16 +128,32 @@ pub fn render_timeline_route(
             action.map(Into::into)
         }
 
-        TimelineRoute::Profile(pubkey) => render_profile_route(
-            &pubkey,
-            ndb,
-            profiles,
-            img_cache,
-            note_cache,
-            col,
-            ui,
-            &accounts.mutefun(),
-        ),
+        TimelineRoute::Profile(pubkey) => {
+            // you can't mute yourself
+            let is_selected = accounts
+                .get_selected_account()
+                .is_some_and(|acc| acc.pubkey == pubkey);
+            let muted = if is_selected {
+                None
+            } else {
+                accounts.get_selected_account_muted()
+            };
+            let editable = is_selected && accounts.can_sign(&pubkey);
+
+            render_profile_route(
+                &pubkey,
+                ndb,
+                profiles,
+                img_cache,
+                note_cache,
+                note_context,
+                muted.as_deref(),
+                editable,
+                col,
+                ui,
+                &accounts.mutefun(),
+            )
+        }
 
         TimelineRoute::Quote(id) => {
             let txn = Transaction::new(ndb).expect("txn");
@@ -138,7 +167,7 @@ pub fn render_timeline_route(
 
             let id = egui::Id::new(("post", col, note.key().unwrap()));
 
-            let poster = accounts.selected_or_first_nsec()?;
+            let poster = accounts.selected_or_first_signer()?;
             let draft = drafts.quote_mut(note.id());
 
             let response = egui::ScrollArea::vertical().show(ui, |ui| {
@@ -159,6 +188,9 @@ pub fn render_profile_route(
     profiles: &mut NotesHolderStorage<Profile>,
     img_cache: &mut ImageCache,
     note_cache: &mut NoteCache,
+    note_context: NoteContext,
+    muted: Option<&Muted>,
+    editable: bool,
     col: usize,
     ui: &mut egui::Ui,
     is_muted: &MuteFun,
@@ -172,6 +204,9 @@ pub fn render_profile_route(
         img_cache,
         NoteOptions::default(),
     )
+    .context(note_context)
+    .muted(muted)
+    .editable(editable)
     .ui(ui, is_muted);
 
     note_action.map(RenderNavAction::NoteAction)
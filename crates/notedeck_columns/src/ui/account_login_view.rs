@@ -1,23 +1,39 @@
 use crate::key_parsing::AcquireKeyError;
 use crate::login_manager::AcquireKeyState;
+use crate::relay_pool_manager::create_wakeup;
 use crate::ui::{Preview, PreviewConfig, View};
 use egui::TextEdit;
 use egui::{Align, Button, Color32, Frame, InnerResponse, Margin, RichText, Vec2};
-use enostr::Keypair;
+use enostr::nip46::BunkerSession;
+use enostr::{FilledKeypair, Keypair, ProxyConfig, Pubkey};
 use notedeck::NotedeckTextStyle;
 
 pub struct AccountLoginView<'a> {
     manager: &'a mut AcquireKeyState,
+    proxy: &'a ProxyConfig,
+    export: Option<FilledKeypair<'a>>,
 }
 
 pub enum AccountLoginResponse {
     CreateNew,
     LoginWith(Keypair),
+    /// Sign with a remote signer, the account's key stays with it
+    LoginWithBunker(Pubkey, BunkerSession),
 }
 
 impl<'a> AccountLoginView<'a> {
-    pub fn new(state: &'a mut AcquireKeyState) -> Self {
-        AccountLoginView { manager: state }
+    pub fn new(state: &'a mut AcquireKeyState, proxy: &'a ProxyConfig) -> Self {
+        AccountLoginView {
+            manager: state,
+            proxy,
+            export: None,
+        }
+    }
+
+    /// Offer to export this key as an `ncryptsec1` string
+    pub fn exportable(mut self, keypair: Option<FilledKeypair<'a>>) -> Self {
+        self.export = keypair;
+        self
     }
 
     pub fn ui(&mut self, ui: &mut egui::Ui) -> InnerResponse<Option<AccountLoginResponse>> {
@@ -40,10 +56,29 @@ impl<'a> AccountLoginView<'a> {
             ui.vertical_centered_justified(|ui| {
                 ui.add(login_textedit(self.manager));
 
+                if self.manager.is_ncryptsec() {
+                    ui.add_space(8.0);
+                    ui.add(passphrase_textedit(self.manager));
+                }
+
                 self.loading_and_error(ui);
 
                 if ui.add(login_button()).clicked() {
-                    self.manager.apply_acquire();
+                    if self.manager.is_bunker_uri() {
+                        self.manager
+                            .apply_bunker(self.proxy, create_wakeup(ui.ctx()));
+                    } else {
+                        self.manager.apply_acquire(self.proxy);
+                    }
+                }
+
+                if ui
+                    .add(Button::new(RichText::new("Login with bunker://")).frame(false))
+                    .on_hover_text("Keep your key in a remote signer (NIP-46)")
+                    .clicked()
+                {
+                    self.manager
+                        .apply_bunker(self.proxy, create_wakeup(ui.ctx()));
                 }
             });
 
@@ -61,6 +96,13 @@ impl<'a> AccountLoginView<'a> {
                     self.manager.should_create_new();
                 }
             });
+
+            if let Some(keypair) = self.export {
+                ui.add_space(16.0);
+                ui.collapsing("Export your key", |ui| {
+                    self.export_ui(ui, keypair);
+                });
+            }
         });
 
         if self.manager.check_for_create_new() {
@@ -70,9 +112,54 @@ impl<'a> AccountLoginView<'a> {
         if let Some(keypair) = self.manager.check_for_successful_login() {
             return Some(AccountLoginResponse::LoginWith(keypair));
         }
+
+        if let Some((pubkey, session)) =
+            self.manager.check_for_bunker_login(create_wakeup(ui.ctx()))
+        {
+            return Some(AccountLoginResponse::LoginWithBunker(pubkey, session));
+        }
         None
     }
 
+    fn export_ui(&mut self, ui: &mut egui::Ui, keypair: FilledKeypair) {
+        ui.label(
+            RichText::new("Encrypt the selected account's secret key with a passphrase (NIP-49), to log in with elsewhere")
+                .color(ui.visuals().weak_text_color())
+                .text_style(NotedeckTextStyle::Body.text_style()),
+        );
+        ui.add_space(8.0);
+
+        ui.add(export_textedit(self.manager));
+        ui.add(export_confirm_textedit(self.manager));
+        if self.manager.export_mismatch() {
+            ui.label(
+                RichText::new("The passphrases don't match").color(ui.visuals().warn_fg_color),
+            );
+        }
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(self.manager.can_export(), Button::new("Export"))
+                .clicked()
+            {
+                self.manager.apply_export(keypair);
+            }
+            if self.manager.is_exporting() {
+                ui.add(egui::Spinner::new());
+            }
+        });
+
+        if let Some(ncryptsec) = self.manager.exported() {
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                ui.add(egui::Label::new(RichText::new(ncryptsec).monospace()).truncate());
+                if ui.button("Copy").clicked() {
+                    ui.ctx().copy_text(ncryptsec.to_owned());
+                }
+            });
+        }
+    }
+
     fn loading_and_error(&mut self, ui: &mut egui::Ui) {
         ui.add_space(8.0);
 
@@ -80,6 +167,10 @@ impl<'a> AccountLoginView<'a> {
             if self.manager.is_awaiting_network() {
                 ui.add(egui::Spinner::new());
             }
+
+            if let Some(url) = self.manager.bunker_auth_url() {
+                ui.hyperlink_to("Approve this login with your signer", url);
+            }
         });
 
         if let Some(err) = self.manager.check_for_error() {
@@ -96,9 +187,12 @@ fn show_error(ui: &mut egui::Ui, err: &AcquireKeyError) {
             AcquireKeyError::InvalidKey => {
                 egui::Label::new(RichText::new("Invalid key.").color(ui.visuals().error_fg_color))
             }
-            AcquireKeyError::Nip05Failed(e) => {
+            AcquireKeyError::Nip05Failed(e) | AcquireKeyError::Bunker(e) => {
                 egui::Label::new(RichText::new(e).color(ui.visuals().error_fg_color))
             }
+            AcquireKeyError::WrongPassphrase => egui::Label::new(
+                RichText::new("Wrong passphrase.").color(ui.visuals().error_fg_color),
+            ),
         };
         ui.add(error_label.truncate());
     });
@@ -130,7 +224,7 @@ fn login_textedit(manager: &mut AcquireKeyState) -> TextEdit {
     manager.get_acquire_textedit(|text| {
         egui::TextEdit::singleline(text)
             .hint_text(
-                RichText::new("Enter your public key (npub), nostr address (e.g. vrod@damus.io), or private key (nsec) here...")
+                RichText::new("Enter your public key (npub), nostr address (e.g. vrod@damus.io), private key (nsec or ncryptsec), or bunker:// url here...")
                     .text_style(NotedeckTextStyle::Body.text_style()),
             )
             .vertical_align(Align::Center)
@@ -139,16 +233,47 @@ fn login_textedit(manager: &mut AcquireKeyState) -> TextEdit {
     })
 }
 
+fn passphrase_textedit(manager: &mut AcquireKeyState) -> TextEdit {
+    manager.get_passphrase_textedit(|text| {
+        egui::TextEdit::singleline(text)
+            .password(true)
+            .hint_text(
+                RichText::new("Passphrase for your encrypted key")
+                    .text_style(NotedeckTextStyle::Body.text_style()),
+            )
+            .vertical_align(Align::Center)
+            .min_size(Vec2::new(0.0, 40.0))
+            .margin(Margin::same(12.0))
+    })
+}
+
+fn export_textedit(manager: &mut AcquireKeyState) -> TextEdit {
+    manager.get_export_textedit(|text| {
+        egui::TextEdit::singleline(text)
+            .password(true)
+            .hint_text("Passphrase")
+    })
+}
+
+fn export_confirm_textedit(manager: &mut AcquireKeyState) -> TextEdit {
+    manager.get_export_confirm_textedit(|text| {
+        egui::TextEdit::singleline(text)
+            .password(true)
+            .hint_text("Passphrase again")
+    })
+}
+
 mod preview {
     use super::*;
 
     pub struct AccountLoginPreview {
         manager: AcquireKeyState,
+        proxy: ProxyConfig,
     }
 
     impl View for AccountLoginPreview {
         fn ui(&mut self, ui: &mut egui::Ui) {
-            AccountLoginView::new(&mut self.manager).ui(ui);
+            AccountLoginView::new(&mut self.manager, &self.proxy).ui(ui);
         }
     }
 
@@ -158,7 +283,10 @@ mod preview {
         fn preview(cfg: PreviewConfig) -> Self::Prev {
             let _ = cfg;
             let manager = AcquireKeyState::new();
-            AccountLoginPreview { manager }
+            AccountLoginPreview {
+                manager,
+                proxy: ProxyConfig::default(),
+            }
         }
     }
 }
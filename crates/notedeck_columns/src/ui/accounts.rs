@@ -18,6 +18,8 @@ pub enum AccountsViewResponse {
     SelectAccount(usize),
     RemoveAccount(usize),
     RouteToLogin,
+    /// Encrypt the stored keys with a (new) passphrase
+    ChangePassphrase,
 }
 
 #[derive(Debug)]
@@ -37,7 +39,11 @@ impl<'a> AccountsView<'a> {
 
     pub fn ui(&mut self, ui: &mut Ui) -> InnerResponse<Option<AccountsViewResponse>> {
         Frame::none().outer_margin(12.0).show(ui, |ui| {
-            if let Some(resp) = Self::top_section_buttons_widget(ui).inner {
+            let passphrase = self
+                .accounts
+                .can_encrypt_keys()
+                .then(|| self.accounts.is_encrypted());
+            if let Some(resp) = Self::top_section_buttons_widget(ui, passphrase).inner {
                 return Some(resp);
             }
 
@@ -110,16 +116,25 @@ impl<'a> AccountsView<'a> {
 
     fn top_section_buttons_widget(
         ui: &mut egui::Ui,
+        passphrase: Option<bool>,
     ) -> InnerResponse<Option<AccountsViewResponse>> {
         ui.allocate_ui_with_layout(
             Vec2::new(ui.available_size_before_wrap().x, 32.0),
             Layout::left_to_right(egui::Align::Center),
             |ui| {
                 if ui.add(add_account_button()).clicked() {
-                    Some(AccountsViewResponse::RouteToLogin)
-                } else {
-                    None
+                    return Some(AccountsViewResponse::RouteToLogin);
                 }
+
+                // whether the keys are encrypted, if they can be
+                let encrypted = passphrase?;
+                let mut resp = None;
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    if ui.add(passphrase_button(encrypted)).clicked() {
+                        resp = Some(AccountsViewResponse::ChangePassphrase);
+                    }
+                });
+                resp
             },
         )
     }
@@ -187,6 +202,15 @@ fn add_account_button() -> Button<'static> {
     .frame(false)
 }
 
+fn passphrase_button(encrypted: bool) -> Button<'static> {
+    let text = if encrypted {
+        "Change passphrase"
+    } else {
+        "Encrypt keys"
+    };
+    Button::new(RichText::new(text)).frame(false)
+}
+
 fn sign_out_button() -> egui::Button<'static> {
     egui::Button::new(RichText::new("Sign out"))
 }
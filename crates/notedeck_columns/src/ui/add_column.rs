@@ -5,6 +5,7 @@ use egui::{
     pos2, vec2, Align, Button, Color32, FontId, Id, ImageSource, Margin, Pos2, Rect, RichText,
     Separator, Ui, Vec2,
 };
+use enostr::ProxyConfig;
 use nostrdb::Ndb;
 use tracing::error;
 
@@ -38,6 +39,7 @@ enum AddColumnOption {
     ExternalNotification,
     Notification(PubkeySource),
     Home(PubkeySource),
+    Messages(PubkeySource),
     UndecidedHashtag,
     Hashtag(String),
 }
@@ -71,6 +73,9 @@ impl AddColumnOption {
                 tlk.into_timeline(ndb, cur_account.map(|a| a.pubkey.bytes()))
                     .map(AddColumnResponse::Timeline)
             }
+            AddColumnOption::Messages(pubkey) => TimelineKind::messages(pubkey)
+                .into_timeline(ndb, cur_account.map(|a| a.pubkey.bytes()))
+                .map(AddColumnResponse::Timeline),
             AddColumnOption::ExternalNotification => Some(AddColumnResponse::ExternalNotification),
             AddColumnOption::UndecidedHashtag => Some(AddColumnResponse::Hashtag),
             AddColumnOption::Hashtag(hashtag) => TimelineKind::Hashtag(hashtag)
@@ -84,6 +89,8 @@ pub struct AddColumnView<'a> {
     key_state_map: &'a mut HashMap<Id, AcquireKeyState>,
     ndb: &'a Ndb,
     cur_account: Option<&'a UserAccount>,
+    proxy: &'a ProxyConfig,
+    can_sign: bool,
 }
 
 impl<'a> AddColumnView<'a> {
@@ -91,14 +98,24 @@ impl<'a> AddColumnView<'a> {
         key_state_map: &'a mut HashMap<Id, AcquireKeyState>,
         ndb: &'a Ndb,
         cur_account: Option<&'a UserAccount>,
+        proxy: &'a ProxyConfig,
     ) -> Self {
         Self {
             key_state_map,
             ndb,
             cur_account,
+            proxy,
+            can_sign: false,
         }
     }
 
+    /// Whether we can sign for the current account, with its secret key
+    /// or a remote signer. Its messages can't be read otherwise.
+    pub fn can_sign(mut self, can_sign: bool) -> Self {
+        self.can_sign = can_sign;
+        self
+    }
+
     pub fn ui(&mut self, ui: &mut Ui) -> Option<AddColumnResponse> {
         let mut selected_option: Option<AddColumnResponse> = None;
         for column_option_data in self.get_base_options() {
@@ -147,7 +164,7 @@ impl<'a> AddColumnView<'a> {
             ui.add(text_edit);
 
             if ui.button("Add").clicked() {
-                key_state.apply_acquire();
+                key_state.apply_acquire(self.proxy);
             }
 
             if key_state.is_awaiting_network() {
@@ -296,6 +313,15 @@ impl<'a> AddColumnView<'a> {
                 icon: egui::include_image!("../../../../assets/icons/home_icon_dark_4x.png"),
                 option: AddColumnOption::Home(source.clone()),
             });
+
+            if self.can_sign {
+                vec.push(ColumnOptionData {
+                    title: "Messages",
+                    description: "Private conversations with other people",
+                    icon: egui::include_image!("../../../../assets/icons/reply-dark.png"),
+                    option: AddColumnOption::Messages(source),
+                });
+            }
         }
         vec.push(ColumnOptionData {
             title: "Notifications",
@@ -362,7 +388,9 @@ pub fn render_add_column_routes(
         &mut app.view_state.id_state_map,
         ctx.ndb,
         ctx.accounts.get_selected_account(),
-    );
+        &ctx.args.proxy,
+    )
+    .can_sign(ctx.accounts.selected_signer().is_some());
     let resp = match route {
         AddColumnRoute::Base => add_column_view.ui(ui),
         AddColumnRoute::UndecidedNotification => add_column_view.notifications_ui(ui),
@@ -206,6 +206,9 @@ impl<'a> NavTitle<'a> {
             Route::Relays => {}
             Route::NewDeck => {}
             Route::EditDeck(_) => {}
+            Route::Muted => {}
+            Route::EditProfile => {}
+            Route::Conversation(_) => {}
         }
     }
 
@@ -0,0 +1,330 @@
+use egui::{Align, Button, Layout, RichText, ScrollArea, TextEdit};
+use enostr::Pubkey;
+use nostrdb::{Ndb, Transaction};
+use notedeck::{time_ago_since, ImageCache};
+
+use crate::{
+    abbrev::floor_char_boundary,
+    colors,
+    dms::{Conversation, ConversationId, DirectMessage},
+};
+
+use super::{hline, padding, profile::preview::get_profile_url, ProfilePic, Username};
+
+/// What's being written in a conversation, and why it couldn't be sent
+#[derive(Default)]
+pub struct ConversationViewState {
+    pub draft: String,
+    pub error: Option<String>,
+}
+
+/// An account's conversations, most recent first
+pub struct ConversationsView<'a> {
+    ndb: &'a Ndb,
+    txn: &'a Transaction,
+    img_cache: &'a mut ImageCache,
+    account: &'a Pubkey,
+    conversations: &'a [&'a Conversation],
+    readable: bool,
+}
+
+impl<'a> ConversationsView<'a> {
+    pub fn new(
+        ndb: &'a Ndb,
+        txn: &'a Transaction,
+        img_cache: &'a mut ImageCache,
+        account: &'a Pubkey,
+        conversations: &'a [&'a Conversation],
+    ) -> Self {
+        ConversationsView {
+            ndb,
+            txn,
+            img_cache,
+            account,
+            conversations,
+            readable: true,
+        }
+    }
+
+    /// Without the account's secret key or remote signer we can't
+    /// decrypt anything
+    pub fn readable(mut self, readable: bool) -> Self {
+        self.readable = readable;
+        self
+    }
+
+    /// Returns the conversation that was clicked
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<ConversationId> {
+        if !self.readable {
+            padding(16.0, ui, |ui| {
+                ui.label("Add this account's secret key or remote signer to read its messages");
+            });
+            return None;
+        }
+
+        if self.conversations.is_empty() {
+            padding(16.0, ui, |ui| {
+                ui.label(RichText::new("No messages yet").color(ui.visuals().weak_text_color()));
+            });
+            return None;
+        }
+
+        let conversations = self.conversations;
+        let mut opened = None;
+        ScrollArea::vertical()
+            .id_salt(("conversations", self.account))
+            .show(ui, |ui| {
+                for conversation in conversations {
+                    if self.conversation_ui(ui, conversation).clicked() {
+                        opened = Some(conversation.id);
+                    }
+                    hline(ui);
+                }
+            });
+
+        opened
+    }
+
+    fn conversation_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        conversation: &Conversation,
+    ) -> egui::Response {
+        let others: Vec<&Pubkey> = conversation.others(self.account).collect();
+
+        let response = padding(12.0, ui, |ui| {
+            ui.horizontal(|ui| {
+                let first = others.first().copied().unwrap_or(self.account);
+                let profile = self.ndb.get_profile_by_pubkey(self.txn, first.bytes()).ok();
+                ui.add(
+                    ProfilePic::new(self.img_cache, get_profile_url(profile.as_ref()))
+                        .size(ProfilePic::medium_size()),
+                );
+
+                ui.vertical(|ui| {
+                    ui.horizontal_wrapped(|ui| {
+                        participants_ui(ui, self.ndb, self.txn, &others);
+                        if conversation.has_legacy() {
+                            ui.label(
+                                RichText::new("NIP-04")
+                                    .small()
+                                    .color(ui.visuals().warn_fg_color),
+                            );
+                        }
+                    });
+
+                    if let Some(last) = conversation.last_message() {
+                        ui.horizontal(|ui| {
+                            ui.label(
+                                RichText::new(preview(&last.content))
+                                    .color(ui.visuals().weak_text_color()),
+                            );
+                            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                ui.label(
+                                    RichText::new(time_ago_since(last.created_at))
+                                        .small()
+                                        .color(ui.visuals().weak_text_color()),
+                                );
+                            });
+                        });
+                    }
+                });
+            });
+        })
+        .response;
+
+        ui.interact(
+            response.rect,
+            ui.id().with(("conversation", conversation.id)),
+            egui::Sense::click(),
+        )
+        .on_hover_cursor(egui::CursorIcon::PointingHand)
+    }
+}
+
+/// The messages in a conversation, with a box to reply in
+pub struct ConversationView<'a> {
+    ndb: &'a Ndb,
+    txn: &'a Transaction,
+    img_cache: &'a mut ImageCache,
+    account: &'a Pubkey,
+    conversation: &'a Conversation,
+    state: &'a mut ConversationViewState,
+    can_send: bool,
+}
+
+impl<'a> ConversationView<'a> {
+    pub fn new(
+        ndb: &'a Ndb,
+        txn: &'a Transaction,
+        img_cache: &'a mut ImageCache,
+        account: &'a Pubkey,
+        conversation: &'a Conversation,
+        state: &'a mut ConversationViewState,
+    ) -> Self {
+        ConversationView {
+            ndb,
+            txn,
+            img_cache,
+            account,
+            conversation,
+            state,
+            can_send: true,
+        }
+    }
+
+    /// Without a signer we can read the conversation but not reply
+    pub fn can_send(mut self, can_send: bool) -> Self {
+        self.can_send = can_send;
+        self
+    }
+
+    /// Returns true when the draft should be sent
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        let send = egui::TopBottomPanel::bottom(("dm_compose", self.conversation.id))
+            .show_inside(ui, |ui| padding(8.0, ui, |ui| self.compose_ui(ui)).inner)
+            .inner;
+
+        let conversation = self.conversation;
+        egui::CentralPanel::default()
+            .frame(egui::Frame::none())
+            .show_inside(ui, |ui| {
+                if conversation.has_legacy() {
+                    padding(12.0, ui, |ui| {
+                        ui.label(
+                            RichText::new(
+                                "Some of these messages use the old NIP-04 encryption, which lets \
+                                 anyone see who they are between. Replies are sent as private \
+                                 NIP-17 messages.",
+                            )
+                            .color(ui.visuals().warn_fg_color),
+                        );
+                    });
+                    hline(ui);
+                }
+
+                ScrollArea::vertical()
+                    .id_salt(("dm_messages", conversation.id))
+                    .stick_to_bottom(true)
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        for message in &conversation.messages {
+                            padding(8.0, ui, |ui| {
+                                self.message_ui(ui, message);
+                            });
+                        }
+                    });
+            });
+
+        send
+    }
+
+    fn message_ui(&mut self, ui: &mut egui::Ui, message: &DirectMessage) {
+        let ours = message.author == *self.account;
+        let profile = self
+            .ndb
+            .get_profile_by_pubkey(self.txn, message.author.bytes())
+            .ok();
+
+        ui.horizontal(|ui| {
+            ui.add(
+                ProfilePic::new(self.img_cache, get_profile_url(profile.as_ref()))
+                    .size(ProfilePic::small_size()),
+            );
+            ui.add(Username::new(profile.as_ref(), message.author.bytes()).abbreviated(20));
+            ui.label(
+                RichText::new(time_ago_since(message.created_at))
+                    .small()
+                    .color(ui.visuals().weak_text_color()),
+            );
+            if message.legacy {
+                ui.label(
+                    RichText::new("NIP-04")
+                        .small()
+                        .color(ui.visuals().warn_fg_color),
+                )
+                .on_hover_text("Sent with the old NIP-04 encryption");
+            }
+        });
+
+        let text = RichText::new(&message.content);
+        if ours {
+            ui.label(text.strong());
+        } else {
+            ui.label(text);
+        }
+    }
+
+    fn compose_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        if !self.can_send {
+            ui.label(
+                RichText::new("Add this account's secret key or remote signer to reply")
+                    .color(ui.visuals().weak_text_color()),
+            );
+            return false;
+        }
+
+        if let Some(error) = &self.state.error {
+            ui.label(RichText::new(error).color(ui.visuals().error_fg_color));
+            ui.add_space(4.0);
+        }
+
+        ui.horizontal(|ui| {
+            let send_width = 60.0;
+            let response = ui.add(
+                TextEdit::multiline(&mut self.state.draft)
+                    .hint_text("Private message")
+                    .desired_rows(2)
+                    .desired_width(ui.available_width() - send_width - 8.0),
+            );
+
+            // shift+enter for a new line
+            let submitted = response.has_focus()
+                && ui.input(|i| i.key_pressed(egui::Key::Enter) && !i.modifiers.shift);
+            if submitted {
+                // the enter was typed into the draft
+                let trimmed = self.state.draft.trim_end_matches('\n').len();
+                self.state.draft.truncate(trimmed);
+            }
+
+            let empty = self.state.draft.trim().is_empty();
+            let clicked = ui
+                .add_enabled(
+                    !empty,
+                    Button::new("Send")
+                        .rounding(8.0)
+                        .fill(colors::PINK)
+                        .min_size(egui::vec2(send_width, 40.0)),
+                )
+                .clicked();
+
+            !empty && (clicked || submitted)
+        })
+        .inner
+    }
+}
+
+fn participants_ui(ui: &mut egui::Ui, ndb: &Ndb, txn: &Transaction, pubkeys: &[&Pubkey]) {
+    if pubkeys.is_empty() {
+        ui.label("Note to self");
+        return;
+    }
+
+    for (i, pk) in pubkeys.iter().enumerate() {
+        if i > 0 {
+            ui.label(",");
+        }
+        let profile = ndb.get_profile_by_pubkey(txn, pk.bytes()).ok();
+        ui.add(Username::new(profile.as_ref(), pk.bytes()).abbreviated(20));
+    }
+}
+
+fn preview(content: &str) -> String {
+    let line = content.lines().next().unwrap_or_default();
+    let end = floor_char_boundary(line, 60);
+    if end < content.len() {
+        format!("{}…", &line[..end])
+    } else {
+        line.to_owned()
+    }
+}
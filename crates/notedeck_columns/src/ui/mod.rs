@@ -6,11 +6,15 @@ pub mod column;
 pub mod configure_deck;
 pub mod edit_deck;
 pub mod mention;
+pub mod messages;
+pub mod muted;
 pub mod note;
+pub mod passphrase;
 pub mod preview;
 pub mod profile;
 pub mod relay;
 pub mod side_panel;
+pub mod signer_approval;
 pub mod support;
 pub mod thread;
 pub mod timeline;
@@ -18,11 +22,15 @@ pub mod username;
 
 pub use accounts::AccountsView;
 pub use mention::Mention;
+pub use messages::{ConversationView, ConversationsView};
+pub use muted::MutedView;
 pub use note::{NoteResponse, NoteView, PostReplyView, PostView};
+pub use passphrase::PassphraseView;
 pub use preview::{Preview, PreviewApp, PreviewConfig};
 pub use profile::{ProfilePic, ProfilePreview};
 pub use relay::RelayView;
 pub use side_panel::{DesktopSidePanel, SidePanelAction};
+pub use signer_approval::SignerApprovalView;
 pub use thread::ThreadView;
 pub use timeline::TimelineView;
 pub use username::Username;
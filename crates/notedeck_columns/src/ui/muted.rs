@@ -0,0 +1,245 @@
+use egui::{Align, Button, Layout, Margin, RichText, ScrollArea, Vec2};
+use enostr::NoteId;
+use nostrdb::{Ndb, Transaction};
+use notedeck::{Mute, Muted, NotedeckTextStyle};
+
+use crate::{abbrev::floor_char_boundary, actionbar::MuteAction, colors};
+
+use super::{padding, Username};
+
+/// What the text field on the mute screen adds
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum MuteInputKind {
+    #[default]
+    Word,
+    Hashtag,
+}
+
+pub struct MutedViewState {
+    input: String,
+    kind: MuteInputKind,
+    private: bool,
+}
+
+impl Default for MutedViewState {
+    fn default() -> Self {
+        MutedViewState {
+            input: String::new(),
+            kind: MuteInputKind::default(),
+            private: true,
+        }
+    }
+}
+
+/// Lists the selected account's mutes and lets you add words and
+/// hashtags, unmute things, or move them between the public and private
+/// parts of the list
+pub struct MutedView<'a> {
+    ndb: &'a Ndb,
+    muted: &'a Muted,
+    state: &'a mut MutedViewState,
+    editable: bool,
+    fetched: bool,
+}
+
+impl<'a> MutedView<'a> {
+    pub fn new(ndb: &'a Ndb, muted: &'a Muted, state: &'a mut MutedViewState) -> Self {
+        MutedView {
+            ndb,
+            muted,
+            state,
+            editable: true,
+            fetched: true,
+        }
+    }
+
+    /// Without a signer we can't publish a new list
+    pub fn editable(mut self, editable: bool) -> Self {
+        self.editable = editable;
+        self
+    }
+
+    /// Until a relay answers, the list may be stale and publishing over
+    /// it would unmute things
+    pub fn fetched(mut self, fetched: bool) -> Self {
+        self.fetched = fetched;
+        self
+    }
+
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<MuteAction> {
+        padding(16.0, ui, |ui| {
+            if !self.editable {
+                ui.label(
+                    RichText::new("Add this account's secret key or remote signer to edit its mutes")
+                        .color(ui.visuals().weak_text_color()),
+                );
+                ui.add_space(8.0);
+            } else if !self.fetched {
+                ui.label(
+                    RichText::new("Still loading your mute list from your relays")
+                        .color(ui.visuals().weak_text_color()),
+                );
+                ui.add_space(8.0);
+            } else if !self.muted.can_edit_private() {
+                ui.label(
+                    RichText::new("Your private mutes couldn't be decrypted, only public mutes can be changed")
+                        .color(ui.visuals().weak_text_color()),
+                );
+                ui.add_space(8.0);
+            }
+
+            ui.add_enabled_ui(self.editable && self.fetched, |ui| {
+                let mut action = self.add_ui(ui);
+
+                ui.add_space(16.0);
+
+                ScrollArea::vertical()
+                    .auto_shrink([false; 2])
+                    .show(ui, |ui| {
+                        if let Some(entry_action) = self.entries_ui(ui) {
+                            action = Some(entry_action);
+                        }
+                    });
+
+                action
+            })
+            .inner
+        })
+        .inner
+    }
+
+    fn add_ui(&mut self, ui: &mut egui::Ui) -> Option<MuteAction> {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut self.state.kind, MuteInputKind::Word, "Word");
+            ui.selectable_value(&mut self.state.kind, MuteInputKind::Hashtag, "Hashtag");
+            let can_private = self.muted.can_edit_private();
+            if !can_private {
+                self.state.private = false;
+            }
+            ui.add_enabled(
+                can_private,
+                egui::Checkbox::new(&mut self.state.private, "Private"),
+            );
+        });
+
+        ui.add_space(8.0);
+
+        let hint = match self.state.kind {
+            MuteInputKind::Word => "Word or phrase to mute",
+            MuteInputKind::Hashtag => "Hashtag to mute",
+        };
+        let text_edit = egui::TextEdit::singleline(&mut self.state.input)
+            .hint_text(RichText::new(hint).text_style(NotedeckTextStyle::Body.text_style()))
+            .vertical_align(Align::Center)
+            .desired_width(f32::INFINITY)
+            .min_size(Vec2::new(0.0, 40.0))
+            .margin(Margin::same(12.0));
+        let text_resp = ui.add(text_edit);
+
+        ui.add_space(8.0);
+        let submitted = text_resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        let clicked = ui
+            .add_sized(
+                egui::vec2(60.0, 40.0),
+                Button::new("Mute").rounding(8.0).fill(colors::PINK),
+            )
+            .clicked();
+        if !clicked && !submitted {
+            return None;
+        }
+
+        let input = self.state.input.trim();
+        let mute = match self.state.kind {
+            MuteInputKind::Word if !input.is_empty() => Mute::Word(input.to_owned()),
+            MuteInputKind::Hashtag => {
+                let hashtag = input.trim_start_matches('#');
+                if hashtag.is_empty() {
+                    return None;
+                }
+                Mute::Hashtag(hashtag.to_lowercase())
+            }
+            MuteInputKind::Word => return None,
+        };
+
+        self.state.input.clear();
+        Some(MuteAction::Mute {
+            mute,
+            private: self.state.private,
+        })
+    }
+
+    fn entries_ui(&self, ui: &mut egui::Ui) -> Option<MuteAction> {
+        let entries = self.muted.entries();
+        if entries.is_empty() {
+            ui.label("Nothing muted yet");
+            return None;
+        }
+
+        let txn = Transaction::new(self.ndb).expect("txn");
+        let mut action: Option<MuteAction> = None;
+
+        for (mute, private) in entries {
+            ui.horizontal(|ui| {
+                self.entry_label(ui, &txn, &mute);
+
+                ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                    if ui.button("Unmute").clicked() {
+                        action = Some(MuteAction::Unmute(mute.clone()));
+                    }
+
+                    let (label, hover) = if private {
+                        ("Private", "Only you can see this, click to make it public")
+                    } else {
+                        ("Public", "Anyone can see this, click to make it private")
+                    };
+                    let can_toggle = private || self.muted.can_edit_private();
+                    if ui
+                        .add_enabled(can_toggle, Button::new(label))
+                        .on_hover_text(hover)
+                        .clicked()
+                    {
+                        action = Some(MuteAction::Mute {
+                            mute: mute.clone(),
+                            private: !private,
+                        });
+                    }
+                });
+            });
+            super::hline(ui);
+        }
+
+        action
+    }
+
+    fn entry_label(&self, ui: &mut egui::Ui, txn: &Transaction, mute: &Mute) {
+        match mute {
+            Mute::Pubkey(pk) => {
+                let profile = self.ndb.get_profile_by_pubkey(txn, pk).ok();
+                ui.add(Username::new(profile.as_ref(), pk).abbreviated(20));
+            }
+            Mute::Hashtag(hashtag) => {
+                ui.label(format!("#{}", hashtag));
+            }
+            Mute::Word(word) => {
+                ui.label(format!("\"{}\"", word));
+            }
+            Mute::Thread(id) => {
+                let text = if let Ok(note) = self.ndb.get_note_by_id(txn, id) {
+                    let content = note.content();
+                    let end = floor_char_boundary(content, 40);
+                    if end < content.len() {
+                        format!("Thread: {}…", &content[..end])
+                    } else {
+                        format!("Thread: {}", content)
+                    }
+                } else {
+                    let bech = NoteId::new(*id)
+                        .to_bech()
+                        .unwrap_or_else(|| hex::encode(id));
+                    format!("Thread: {}", &bech[..bech.len().min(16)])
+                };
+                ui.label(text);
+            }
+        }
+    }
+}
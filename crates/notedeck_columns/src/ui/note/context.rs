@@ -1,24 +1,42 @@
 use egui::{Rect, Vec2};
 use enostr::{NoteId, Pubkey};
-use nostrdb::{Note, NoteKey};
+use nostrdb::{Note, NoteKey, NoteReply};
+use notedeck::Mute;
 use tracing::error;
 
+use crate::actionbar::{ContactAction, MuteAction, NoteAction};
+
 #[derive(Clone)]
-#[allow(clippy::enum_variant_names)]
 pub enum NoteContextSelection {
     CopyText,
     CopyPubkey,
     CopyNoteId,
     CopyNoteJSON,
+    Follow,
+    Unfollow,
+    MuteUser,
+    MuteThread,
+    MuteHashtag(String),
+    Delete,
 }
 
 impl NoteContextSelection {
-    pub fn process(&self, ui: &mut egui::Ui, note: &Note<'_>) {
-        match self {
+    /// Copy selections are handled here, follows, mutes and deletes need
+    /// the account so they come back as a [`NoteAction`]. Mutes are
+    /// private if `private_mutes`, which can't be when the private part of
+    /// the list couldn't be decrypted, say with a remote signer.
+    pub fn process(
+        &self,
+        ui: &mut egui::Ui,
+        note: &Note<'_>,
+        private_mutes: bool,
+    ) -> Option<NoteAction> {
+        let mute = match self {
             NoteContextSelection::CopyText => {
                 ui.output_mut(|w| {
                     w.copied_text = note.content().to_string();
                 });
+                return None;
             }
             NoteContextSelection::CopyPubkey => {
                 ui.output_mut(|w| {
@@ -26,6 +44,7 @@ impl NoteContextSelection {
                         w.copied_text = bech;
                     }
                 });
+                return None;
             }
             NoteContextSelection::CopyNoteId => {
                 ui.output_mut(|w| {
@@ -33,17 +52,53 @@ impl NoteContextSelection {
                         w.copied_text = bech;
                     }
                 });
+                return None;
             }
             NoteContextSelection::CopyNoteJSON => {
                 ui.output_mut(|w| match note.json() {
                     Ok(json) => w.copied_text = json,
                     Err(err) => error!("error copying note json: {err}"),
                 });
+                return None;
             }
-        }
+            NoteContextSelection::Follow => {
+                let pubkey = Pubkey::new(*note.pubkey());
+                return Some(NoteAction::Contact(ContactAction::Follow(pubkey)));
+            }
+            NoteContextSelection::Unfollow => {
+                let pubkey = Pubkey::new(*note.pubkey());
+                return Some(NoteAction::Contact(ContactAction::Unfollow(pubkey)));
+            }
+            NoteContextSelection::Delete => {
+                return Some(NoteAction::Delete(NoteId::new(*note.id())));
+            }
+            NoteContextSelection::MuteUser => Mute::Pubkey(*note.pubkey()),
+            NoteContextSelection::MuteThread => {
+                let root = NoteReply::new(note.tags()).root().map(|r| *r.id);
+                Mute::Thread(root.unwrap_or(*note.id()))
+            }
+            NoteContextSelection::MuteHashtag(hashtag) => Mute::Hashtag(hashtag.clone()),
+        };
+
+        Some(NoteAction::Mute(MuteAction::Mute {
+            mute,
+            private: private_mutes,
+        }))
     }
 }
 
+/// What the context menu offers besides copying
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoteMenu {
+    /// Whether we follow the author, None without our contact list
+    pub following: Option<bool>,
+    /// Whether the contact list can be published
+    pub can_follow: bool,
+    /// Whether the mute list can be published
+    pub can_mute: bool,
+    pub can_delete: bool,
+}
+
 pub struct NoteContextButton {
     put_at: Option<Rect>,
     note_key: NoteKey,
@@ -149,6 +204,8 @@ impl NoteContextButton {
     pub fn menu(
         ui: &mut egui::Ui,
         button_response: egui::Response,
+        note: &Note<'_>,
+        menu: NoteMenu,
     ) -> Option<NoteContextSelection> {
         #[cfg(feature = "profiling")]
         puffin::profile_function!();
@@ -173,12 +230,83 @@ impl NoteContextButton {
                 context_selection = Some(NoteContextSelection::CopyNoteJSON);
                 ui.close_menu();
             }
+
+            ui.separator();
+
+            // without the contact list we don't know, and can't publish it
+            let follow = match menu.following {
+                Some(true) => Some(("Unfollow user", NoteContextSelection::Unfollow)),
+                Some(false) => Some(("Follow user", NoteContextSelection::Follow)),
+                // fetched, and there's no list yet
+                None if menu.can_follow => Some(("Follow user", NoteContextSelection::Follow)),
+                None => None,
+            };
+            if let Some((label, selection)) = follow {
+                if ui
+                    .add_enabled(menu.can_follow, egui::Button::new(label))
+                    .on_disabled_hover_text("Your contact list can't be changed yet")
+                    .clicked()
+                {
+                    context_selection = Some(selection);
+                    ui.close_menu();
+                }
+            }
+
+            let mut mutes = vec![
+                ("Mute user".to_owned(), NoteContextSelection::MuteUser),
+                ("Mute thread".to_owned(), NoteContextSelection::MuteThread),
+            ];
+            for hashtag in note_hashtags(note) {
+                mutes.push((
+                    format!("Mute #{}", hashtag),
+                    NoteContextSelection::MuteHashtag(hashtag),
+                ));
+            }
+            for (label, selection) in mutes {
+                if ui
+                    .add_enabled(menu.can_mute, egui::Button::new(label))
+                    .on_disabled_hover_text("Your mute list can't be changed yet")
+                    .clicked()
+                {
+                    context_selection = Some(selection);
+                    ui.close_menu();
+                }
+            }
+
+            if menu.can_delete {
+                ui.separator();
+                let delete = egui::Button::new(
+                    egui::RichText::new("Delete").color(ui.visuals().error_fg_color),
+                );
+                if ui.add(delete).clicked() {
+                    context_selection = Some(NoteContextSelection::Delete);
+                    ui.close_menu();
+                }
+            }
         });
 
         context_selection
     }
 }
 
+/// The note's `t` tags, without duplicates
+fn note_hashtags(note: &Note<'_>) -> Vec<String> {
+    let mut hashtags: Vec<String> = Vec::new();
+    for tag in note.tags() {
+        if tag.count() < 2 || tag.get_unchecked(0).variant().str() != Some("t") {
+            continue;
+        }
+
+        if let Some(hashtag) = tag.get_unchecked(1).variant().str() {
+            let hashtag = hashtag.to_lowercase();
+            if !hashtags.contains(&hashtag) {
+                hashtags.push(hashtag);
+            }
+        }
+    }
+    hashtags
+}
+
 fn stationary_arbitrary_menu_button<R>(
     ui: &mut egui::Ui,
     button_response: egui::Response,
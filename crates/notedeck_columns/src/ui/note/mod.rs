@@ -6,7 +6,7 @@ pub mod quote_repost;
 pub mod reply;
 
 pub use contents::NoteContents;
-pub use context::{NoteContextButton, NoteContextSelection};
+pub use context::{NoteContextButton, NoteContextSelection, NoteMenu};
 pub use options::NoteOptions;
 pub use post::{PostAction, PostResponse, PostType, PostView};
 pub use quote_repost::QuoteRepostView;
@@ -14,14 +14,21 @@ pub use reply::PostReplyView;
 
 use crate::{
     actionbar::NoteAction,
+    colors,
+    counts::{format_count, Counts},
+    deletions::Deletions,
+    images::ImageType,
+    reactions::{custom_emoji_url, NoteReactions, Reaction, Reactions},
+    repost,
     ui::{self, View},
 };
 
 use egui::emath::{pos2, Vec2};
+use egui::load::SizedTexture;
 use egui::{Id, Label, Pos2, Rect, Response, RichText, Sense};
-use enostr::{NoteId, Pubkey};
+use enostr::{NoteId, Pubkey, Publish, PublishState, PublishTracker};
 use nostrdb::{Ndb, Note, NoteKey, NoteReply, Transaction};
-use notedeck::{CachedNote, ImageCache, NoteCache, NotedeckTextStyle};
+use notedeck::{CachedNote, Contacts, ImageCache, Muted, NoteCache, NotedeckTextStyle};
 
 use super::profile::preview::{get_display_name, one_line_display_name_widget};
 
@@ -32,6 +39,43 @@ pub struct NoteView<'a> {
     parent: Option<NoteKey>,
     note: &'a nostrdb::Note<'a>,
     flags: NoteOptions,
+    context: NoteContext<'a>,
+}
+
+/// What notes are shown with besides themselves: how publishing went,
+/// counts, reactions, deletions, who we follow and what we muted.
+/// Whatever is left out isn't shown.
+#[derive(Default)]
+pub struct NoteContext<'a> {
+    pub publishes: Option<&'a PublishTracker>,
+    pub counts: Option<&'a mut Counts>,
+    pub reactions: Option<&'a mut Reactions>,
+    pub deletions: Option<&'a mut Deletions>,
+    pub contacts: Option<&'a Contacts>,
+    /// Reposted notes can show up after their repost, so they're checked
+    /// when shown
+    pub muted: Option<&'a Muted>,
+    /// Whether the contact list and the mute list can be published, with
+    /// a signer and once they're fetched. Their buttons are disabled
+    /// otherwise.
+    pub can_follow: bool,
+    pub can_mute: bool,
+}
+
+impl NoteContext<'_> {
+    /// Lend the context to a nested view
+    pub fn reborrow(&mut self) -> NoteContext<'_> {
+        NoteContext {
+            publishes: self.publishes,
+            counts: self.counts.as_deref_mut(),
+            reactions: self.reactions.as_deref_mut(),
+            deletions: self.deletions.as_deref_mut(),
+            contacts: self.contacts,
+            muted: self.muted,
+            can_follow: self.can_follow,
+            can_mute: self.can_mute,
+        }
+    }
 }
 
 pub struct NoteResponse {
@@ -169,6 +213,7 @@ impl<'a> NoteView<'a> {
             parent,
             note,
             flags,
+            context: NoteContext::default(),
         }
     }
 
@@ -230,6 +275,20 @@ impl<'a> NoteView<'a> {
         self
     }
 
+    /// Show publish status, counts and reactions in the action bar, hide
+    /// deleted notes, and offer following the author
+    pub fn context(mut self, context: NoteContext<'a>) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// How publishing this note went, for notes we sent
+    fn publish(&self) -> Option<&'a Publish> {
+        self.context
+            .publishes
+            .and_then(|publishes| publishes.get(&NoteId::new(*self.note.id())))
+    }
+
     fn textmode_ui(&mut self, ui: &mut egui::Ui) -> egui::Response {
         let note_key = self.note.key().expect("todo: implement non-db notes");
         let txn = self.note.txn().expect("todo: implement non-db notes");
@@ -332,46 +391,105 @@ impl<'a> NoteView<'a> {
     }
 
     pub fn show(&mut self, ui: &mut egui::Ui) -> NoteResponse {
-        if self.options().has_textmode() {
+        if self.is_deleted() {
+            NoteResponse::new(deleted_ui(ui))
+        } else if self.options().has_textmode() {
             NoteResponse::new(self.textmode_ui(ui))
+        } else if repost::is_repost(self.note) {
+            self.show_repost(ui)
         } else {
-            let txn = self.note.txn().expect("txn");
-            if let Some(note_to_repost) = get_reposted_note(self.ndb, txn, self.note) {
-                let profile = self.ndb.get_profile_by_pubkey(txn, self.note.pubkey());
+            self.show_standard(ui)
+        }
+    }
 
-                let style = NotedeckTextStyle::Small;
-                ui.horizontal(|ui| {
-                    ui.vertical(|ui| {
-                        ui.add_space(2.0);
-                        ui.add_sized([20.0, 20.0], repost_icon(ui.visuals().dark_mode));
-                    });
-                    ui.add_space(6.0);
-                    let resp = ui.add(one_line_display_name_widget(
-                        ui.visuals(),
-                        get_display_name(profile.as_ref().ok()),
-                        style,
-                    ));
-                    if let Ok(rec) = &profile {
-                        resp.on_hover_ui_at_pointer(|ui| {
-                            ui.set_max_width(300.0);
-                            ui.add(ui::ProfilePreview::new(rec, self.img_cache));
-                        });
-                    }
-                    let color = ui.style().visuals.noninteractive().fg_stroke.color;
-                    ui.add_space(4.0);
-                    ui.label(
-                        RichText::new("Reposted")
-                            .color(color)
-                            .text_style(style.text_style()),
-                    );
-                });
-                NoteView::new(self.ndb, self.note_cache, self.img_cache, &note_to_repost).show(ui)
+    fn is_deleted(&mut self) -> bool {
+        let txn = if let Some(txn) = self.note.txn() {
+            txn
+        } else {
+            return false;
+        };
+
+        self.context
+            .deletions
+            .as_deref_mut()
+            .is_some_and(|deletions| deletions.is_deleted(self.ndb, txn, self.note))
+    }
+
+    /// "X reposted" over the reposted note
+    fn show_repost(&mut self, ui: &mut egui::Ui) -> NoteResponse {
+        let txn = self.note.txn().expect("txn");
+        let profile = self.ndb.get_profile_by_pubkey(txn, self.note.pubkey());
+
+        let style = NotedeckTextStyle::Small;
+        let header_resp = ui.horizontal(|ui| {
+            ui.vertical(|ui| {
+                ui.add_space(2.0);
+                ui.add_sized([20.0, 20.0], repost_icon(ui.visuals().dark_mode));
+            });
+            ui.add_space(6.0);
+            let resp = ui
+                .add(one_line_display_name_widget(
+                    ui.visuals(),
+                    get_display_name(profile.as_ref().ok()),
+                    style,
+                ))
+                .interact(Sense::click());
+            let resp = if let Ok(rec) = &profile {
+                resp.on_hover_ui_at_pointer(|ui| {
+                    ui.set_max_width(300.0);
+                    ui.add(ui::ProfilePreview::new(rec, self.img_cache));
+                })
             } else {
-                self.show_standard(ui)
-            }
+                resp
+            };
+            let color = ui.style().visuals.noninteractive().fg_stroke.color;
+            ui.add_space(4.0);
+            ui.label(
+                RichText::new("reposted")
+                    .color(color)
+                    .text_style(style.text_style()),
+            );
+            resp
+        });
+
+        let reposted = if let Some(note) = repost::reposted_note(self.ndb, txn, self.note) {
+            note
+        } else {
+            // the embedded note is still being processed, or is missing
+            ui.label(
+                RichText::new("Loading reposted note...")
+                    .color(ui.visuals().weak_text_color())
+                    .text_style(style.text_style()),
+            );
+            return NoteResponse::new(header_resp.response);
+        };
+
+        if self
+            .context
+            .muted
+            .is_some_and(|muted| muted.is_muted(&reposted, self.ndb, self.note_cache))
+        {
+            ui.label(
+                RichText::new("Reposted a note you muted")
+                    .color(ui.visuals().weak_text_color())
+                    .text_style(style.text_style()),
+            );
+            return NoteResponse::new(header_resp.response);
         }
+
+        let mut resp = NoteView::new(self.ndb, self.note_cache, self.img_cache, &reposted)
+            .note_options(self.flags)
+            .context(self.context.reborrow())
+            .show(ui);
+
+        if header_resp.inner.clicked() {
+            resp.action = Some(NoteAction::OpenProfile(Pubkey::new(*self.note.pubkey())));
+        }
+
+        resp
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn note_header(
         ui: &mut egui::Ui,
         note_cache: &mut NoteCache,
@@ -379,6 +497,7 @@ impl<'a> NoteView<'a> {
         profile: &Result<nostrdb::ProfileRecord<'_>, nostrdb::Error>,
         options: NoteOptions,
         container_right: Pos2,
+        menu: NoteMenu,
     ) -> NoteResponse {
         #[cfg(feature = "profiling")]
         puffin::profile_function!();
@@ -400,7 +519,7 @@ impl<'a> NoteView<'a> {
                 };
 
                 let resp = ui.add(NoteContextButton::new(note_key).place_at(context_pos));
-                NoteContextButton::menu(ui, resp.clone())
+                NoteContextButton::menu(ui, resp.clone(), note, menu)
             } else {
                 None
             }
@@ -417,6 +536,20 @@ impl<'a> NoteView<'a> {
 
         let mut note_action: Option<NoteAction> = None;
         let mut selected_option: Option<NoteContextSelection> = None;
+        let publish = self.publish();
+        let menu = NoteMenu {
+            following: self
+                .context
+                .contacts
+                .map(|contacts| contacts.is_following(self.note.pubkey())),
+            can_follow: self.context.can_follow,
+            can_mute: self.context.can_mute,
+            can_delete: self
+                .context
+                .deletions
+                .as_deref()
+                .is_some_and(|deletions| deletions.can_delete(self.note)),
+        };
 
         let hitbox_id = note_hitbox_id(note_key, self.options(), self.parent);
         let profile = self.ndb.get_profile_by_pubkey(txn, self.note.pubkey());
@@ -448,6 +581,7 @@ impl<'a> NoteView<'a> {
                                     &profile,
                                     self.options(),
                                     container_right,
+                                    menu,
                                 )
                                 .context_selection;
                             })
@@ -481,15 +615,29 @@ impl<'a> NoteView<'a> {
                 ui.add(&mut contents);
 
                 if let Some(action) = contents.action() {
-                    note_action = Some(*action);
+                    note_action = Some(action.clone());
                 }
 
                 if self.options().has_actionbar() {
-                    if let Some(action) = render_note_actionbar(ui, self.note.id(), note_key).inner
+                    if let Some(action) = render_note_actionbar(
+                        ui,
+                        self.ndb,
+                        txn,
+                        self.img_cache,
+                        self.note.id(),
+                        note_key,
+                        self.context.counts.as_deref_mut(),
+                        self.context.reactions.as_deref_mut(),
+                    )
+                    .inner
                     {
                         note_action = Some(action);
                     }
                 }
+
+                if let Some(publish) = publish {
+                    render_publish_status(ui, publish);
+                }
             })
             .response
         } else {
@@ -507,6 +655,7 @@ impl<'a> NoteView<'a> {
                         &profile,
                         self.options(),
                         container_right,
+                        menu,
                     )
                     .context_selection;
                     ui.horizontal(|ui| {
@@ -535,16 +684,29 @@ impl<'a> NoteView<'a> {
                     ui.add(&mut contents);
 
                     if let Some(action) = contents.action() {
-                        note_action = Some(*action);
+                        note_action = Some(action.clone());
                     }
 
                     if self.options().has_actionbar() {
-                        if let Some(action) =
-                            render_note_actionbar(ui, self.note.id(), note_key).inner
+                        if let Some(action) = render_note_actionbar(
+                            ui,
+                            self.ndb,
+                            txn,
+                            self.img_cache,
+                            self.note.id(),
+                            note_key,
+                            self.context.counts.as_deref_mut(),
+                            self.context.reactions.as_deref_mut(),
+                        )
+                        .inner
                         {
                             note_action = Some(action);
                         }
                     }
+
+                    if let Some(publish) = publish {
+                        render_publish_status(ui, publish);
+                    }
                 });
             })
             .response
@@ -562,30 +724,6 @@ impl<'a> NoteView<'a> {
     }
 }
 
-fn get_reposted_note<'a>(ndb: &Ndb, txn: &'a Transaction, note: &Note) -> Option<Note<'a>> {
-    let new_note_id: &[u8; 32] = if note.kind() == 6 {
-        let mut res = None;
-        for tag in note.tags().iter() {
-            if tag.count() == 0 {
-                continue;
-            }
-
-            if let Some("e") = tag.get(0).and_then(|t| t.variant().str()) {
-                if let Some(note_id) = tag.get(1).and_then(|f| f.variant().id()) {
-                    res = Some(note_id);
-                    break;
-                }
-            }
-        }
-        res?
-    } else {
-        return None;
-    };
-
-    let note = ndb.get_note_by_id(txn, new_note_id).ok();
-    note.filter(|note| note.kind() == 1)
-}
-
 fn note_hitbox_id(
     note_key: NoteKey,
     note_options: NoteOptions,
@@ -634,28 +772,293 @@ fn note_hitbox_clicked(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_note_actionbar(
     ui: &mut egui::Ui,
+    ndb: &Ndb,
+    txn: &Transaction,
+    img_cache: &mut ImageCache,
     note_id: &[u8; 32],
     note_key: NoteKey,
+    mut counts: Option<&mut Counts>,
+    reactions: Option<&mut Reactions>,
 ) -> egui::InnerResponse<Option<NoteAction>> {
     #[cfg(feature = "profiling")]
     puffin::profile_function!();
 
+    let note_id = NoteId::new(*note_id);
+
     ui.horizontal(|ui| {
         let reply_resp = reply_button(ui, note_key);
-        let quote_resp = quote_repost_button(ui, note_key);
+        if let Some(counts) = counts.as_deref_mut() {
+            action_count(ui, Some(counts.replies(ndb, txn, &note_id)));
+        }
+
+        let repost_resp = quote_repost_button(ui, note_key);
+        let repost_choice = repost_menu(ui, note_key, &repost_resp);
+
+        let account = reactions.as_deref().and_then(Reactions::account).copied();
+        let note_reactions = reactions.map(|reactions| reactions.get(ndb, txn, &note_id));
+        let ours = note_reactions.and_then(|reactions| reactions.ours.as_ref());
+
+        let like_resp = like_button(ui, note_key, ours);
+
+        // relays that can count know about reactions we don't have
+        let relay_total = counts.and_then(|counts| counts.reactions(&note_id));
+        let total = match (note_reactions.map(NoteReactions::total), relay_total) {
+            (Some(ours), Some(relays)) => Some(ours.max(relays)),
+            (ours, relays) => ours.or(relays),
+        };
+        action_count(ui, total);
+
+        let mut picked = None;
+        if let Some(note_reactions) = note_reactions {
+            picked = reaction_groups(ui, img_cache, note_reactions);
+        }
+        // a shortcode someone already reacted with, or one of ours
+        let custom = |shortcode: &str| {
+            note_reactions
+                .and_then(|reactions| {
+                    reactions
+                        .groups
+                        .iter()
+                        .find_map(|(reaction, _)| match reaction {
+                            Reaction::Custom { shortcode: s, url } if s == shortcode => {
+                                Some(url.clone())
+                            }
+                            _ => None,
+                        })
+                })
+                .or_else(|| account.and_then(|pk| custom_emoji_url(ndb, txn, &pk, shortcode)))
+        };
+        if let Some(emoji) = emoji_picker(ui, note_key, ours.is_none(), custom) {
+            picked = Some(emoji);
+        }
 
         if reply_resp.clicked() {
-            Some(NoteAction::Reply(NoteId::new(*note_id)))
-        } else if quote_resp.clicked() {
-            Some(NoteAction::Quote(NoteId::new(*note_id)))
+            Some(NoteAction::Reply(note_id))
+        } else if let Some(choice) = repost_choice {
+            Some(choice.action(note_id))
+        } else if like_resp.clicked() && ours.is_none() {
+            Some(NoteAction::React {
+                note_id,
+                reaction: Reaction::Like,
+            })
+        } else if ours.is_none() {
+            picked.map(|reaction| NoteAction::React { note_id, reaction })
         } else {
             None
         }
     })
 }
 
+/// A heart, filled in if we already reacted
+fn like_button(ui: &mut egui::Ui, note_key: NoteKey, ours: Option<&Reaction>) -> egui::Response {
+    let (rect, size, resp) =
+        ui::anim::hover_expand_small(ui, ui.id().with(("like_anim", note_key)));
+
+    let color = if ours.is_some() {
+        colors::PINK
+    } else {
+        ui.visuals().noninteractive().fg_stroke.color
+    };
+
+    let put_resp = ui.put(
+        rect,
+        Label::new(RichText::new("♥").size(size).color(color)).selectable(false),
+    );
+
+    let resp = resp.union(put_resp);
+    match ours {
+        Some(Reaction::Like) => resp.on_hover_text("You liked this"),
+        Some(reaction) => resp.on_hover_text(format!("You reacted {}", reaction.content())),
+        None => resp.on_hover_text("Like"),
+    }
+}
+
+/// The emoji people reacted with and how many times. Clicking one
+/// reacts with the same emoji.
+fn reaction_groups(
+    ui: &mut egui::Ui,
+    img_cache: &mut ImageCache,
+    reactions: &NoteReactions,
+) -> Option<Reaction> {
+    let mut picked = None;
+    let can_react = reactions.ours.is_none();
+
+    for (reaction, count) in &reactions.groups {
+        // likes are the heart, and we don't show dislikes
+        if matches!(reaction, Reaction::Like | Reaction::Dislike) {
+            continue;
+        }
+
+        let count = format_count(*count);
+        let button = match reaction {
+            Reaction::Custom { shortcode, url } => match custom_emoji(ui, img_cache, url) {
+                Some(texture) => egui::Button::image_and_text(
+                    egui::Image::new(SizedTexture::from_handle(&texture)).max_height(14.0),
+                    RichText::new(count).size(10.0),
+                ),
+                None => egui::Button::new(
+                    RichText::new(format!(":{}: {}", shortcode, count)).size(10.0),
+                ),
+            },
+            _ => egui::Button::new(
+                RichText::new(format!("{} {}", reaction.content(), count)).size(10.0),
+            ),
+        };
+
+        let selected = reactions.ours.as_ref() == Some(reaction);
+        let resp = ui.add_enabled(can_react || selected, button.small().selected(selected));
+        if resp.clicked() && can_react {
+            picked = Some(reaction.clone());
+        }
+    }
+
+    picked
+}
+
+fn custom_emoji(
+    ui: &mut egui::Ui,
+    img_cache: &mut ImageCache,
+    url: &str,
+) -> Option<egui::TextureHandle> {
+    if img_cache.map().get(url).is_none() {
+        let res = crate::images::fetch_img(img_cache, ui.ctx(), url, ImageType::Content(64, 64));
+        img_cache.map_mut().insert(url.to_owned(), res);
+    }
+
+    match img_cache.map()[url].ready() {
+        Some(Ok(texture)) => Some(texture.clone()),
+        _ => None,
+    }
+}
+
+/// A few common emoji, and a field for any other emoji or a NIP-30
+/// `:shortcode:` that `custom` knows the image for
+fn emoji_picker(
+    ui: &mut egui::Ui,
+    note_key: NoteKey,
+    enabled: bool,
+    custom: impl Fn(&str) -> Option<String>,
+) -> Option<Reaction> {
+    const EMOJIS: [&str; 12] = [
+        "🤙", "👍", "😂", "🔥", "🫂", "👀", "🙏", "🎉", "😮", "😢", "💯", "⚡",
+    ];
+
+    let mut picked = None;
+    ui.push_id(("emoji_picker", note_key), |ui| {
+        ui.add_enabled_ui(enabled, |ui| {
+            ui.menu_button(RichText::new("☺").size(12.0), |ui| {
+                egui::Grid::new("emojis").show(ui, |ui| {
+                    for (i, emoji) in EMOJIS.iter().enumerate() {
+                        if ui.button(RichText::new(*emoji).size(18.0)).clicked() {
+                            picked = Some(Reaction::Emoji(emoji.to_string()));
+                            ui.close_menu();
+                        }
+                        if i % 6 == 5 {
+                            ui.end_row();
+                        }
+                    }
+                });
+
+                ui.separator();
+
+                // what's typed so far, and whether it was an unknown shortcode
+                let id = ui.id().with("emoji_input");
+                let (mut input, mut unknown) = ui
+                    .data_mut(|d| d.get_temp::<(String, bool)>(id))
+                    .unwrap_or_default();
+
+                let resp = ui.add(
+                    egui::TextEdit::singleline(&mut input)
+                        .hint_text("Any emoji or :shortcode:")
+                        .desired_width(150.0),
+                );
+                if resp.changed() {
+                    unknown = false;
+                }
+                if resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    if let Some(reaction) = Reaction::parse(&input, &custom) {
+                        picked = Some(reaction);
+                        input.clear();
+                        ui.close_menu();
+                    } else {
+                        unknown = !input.trim().is_empty();
+                    }
+                }
+                if unknown {
+                    ui.colored_label(ui.visuals().error_fg_color, "No custom emoji by that name");
+                }
+
+                ui.data_mut(|d| d.insert_temp(id, (input, unknown)));
+            });
+        });
+    });
+
+    picked
+}
+
+fn deleted_ui(ui: &mut egui::Ui) -> egui::Response {
+    ui.label(
+        RichText::new("This note was deleted by its author")
+            .italics()
+            .color(ui.visuals().weak_text_color()),
+    )
+}
+
+/// "sent to 4/6 relays", with what each relay said on hover
+fn render_publish_status(ui: &mut egui::Ui, publish: &Publish) {
+    let accepted = publish.accepted();
+    let total = publish.total();
+
+    let (text, color) = if !publish.is_done() {
+        (
+            format!("sending... {}/{} relays", accepted, total),
+            ui.visuals().weak_text_color(),
+        )
+    } else if accepted == 0 {
+        (
+            format!("failed to send to {} relays", total),
+            ui.visuals().error_fg_color,
+        )
+    } else if publish.failed() > 0 {
+        (
+            format!("sent to {}/{} relays", accepted, total),
+            ui.visuals().warn_fg_color,
+        )
+    } else {
+        (
+            format!("sent to {}/{} relays", accepted, total),
+            ui.visuals().weak_text_color(),
+        )
+    };
+
+    let resp =
+        ui.add(Label::new(RichText::new(text).size(10.0).color(color)).sense(Sense::hover()));
+
+    resp.on_hover_ui(|ui| {
+        for (relay, state) in &publish.relays {
+            let state = match state {
+                PublishState::Queued => "waiting for connection".to_owned(),
+                PublishState::Sent => "sent".to_owned(),
+                PublishState::Accepted => "accepted".to_owned(),
+                PublishState::Rejected(reason) => format!("rejected: {}", reason),
+                PublishState::TimedOut => "gave up waiting to send".to_owned(),
+            };
+            ui.label(format!("{}: {}", relay, state));
+        }
+    });
+}
+
+fn action_count(ui: &mut egui::Ui, count: Option<u64>) {
+    if let Some(count) = count {
+        if count > 0 {
+            secondary_label(ui, format_count(count));
+        }
+    }
+}
+
 fn secondary_label(ui: &mut egui::Ui, s: impl Into<String>) {
     let color = ui.style().visuals.noninteractive().fg_stroke.color;
     ui.add(Label::new(RichText::new(s).size(10.0).color(color)));
@@ -710,6 +1113,51 @@ fn repost_icon(dark_mode: bool) -> egui::Image<'static> {
     egui::Image::new(img_data)
 }
 
+#[derive(Clone, Copy)]
+enum RepostChoice {
+    Repost,
+    Quote,
+}
+
+impl RepostChoice {
+    fn action(self, note_id: NoteId) -> NoteAction {
+        match self {
+            RepostChoice::Repost => NoteAction::Repost(note_id),
+            RepostChoice::Quote => NoteAction::Quote(note_id),
+        }
+    }
+}
+
+/// Clicking the repost button asks whether to repost or quote
+fn repost_menu(
+    ui: &mut egui::Ui,
+    note_key: NoteKey,
+    button_resp: &egui::Response,
+) -> Option<RepostChoice> {
+    let popup_id = ui.id().with(("repost_menu", note_key));
+    if button_resp.clicked() {
+        ui.memory_mut(|mem| mem.toggle_popup(popup_id));
+    }
+
+    egui::popup_below_widget(
+        ui,
+        popup_id,
+        button_resp,
+        egui::PopupCloseBehavior::CloseOnClick,
+        |ui| {
+            ui.set_min_width(80.0);
+            if ui.button("Repost").clicked() {
+                Some(RepostChoice::Repost)
+            } else if ui.button("Quote").clicked() {
+                Some(RepostChoice::Quote)
+            } else {
+                None
+            }
+        },
+    )
+    .flatten()
+}
+
 fn quote_repost_button(ui: &mut egui::Ui, note_key: NoteKey) -> egui::Response {
     let (rect, size, resp) =
         ui::anim::hover_expand_small(ui, ui.id().with(("repost_anim", note_key)));
@@ -4,11 +4,11 @@ use crate::ui::{self, Preview, PreviewConfig, View};
 use crate::Result;
 use egui::widgets::text_edit::TextEdit;
 use egui::{Frame, Layout};
-use enostr::{FilledKeypair, FullKeypair, NoteId, RelayPool};
+use enostr::{FullKeypair, NoteId, Pubkey, RelayPool, UnsignedNote};
 use nostrdb::{Config, Ndb, Transaction};
 use tracing::info;
 
-use notedeck::{ImageCache, NoteCache};
+use notedeck::{Accounts, Destination, ImageCache, NoteCache};
 
 use super::contents::render_note_preview;
 
@@ -18,7 +18,7 @@ pub struct PostView<'a> {
     post_type: PostType,
     img_cache: &'a mut ImageCache,
     note_cache: &'a mut NoteCache,
-    poster: FilledKeypair<'a>,
+    poster: Pubkey,
     id_source: Option<egui::Id>,
 }
 
@@ -43,28 +43,30 @@ impl PostAction {
         &self,
         ndb: &Ndb,
         txn: &Transaction,
+        accounts: &mut Accounts,
         pool: &mut RelayPool,
+        wakeup: impl Fn() + Send + Sync + Clone + 'static,
         drafts: &mut Drafts,
     ) -> Result<()> {
-        let seckey = self.post.account.secret_key.to_secret_bytes();
-
-        let note = match self.post_type {
-            PostType::New => self.post.to_note(&seckey),
+        let builder = match self.post_type {
+            PostType::New => self.post.to_note(),
 
             PostType::Reply(target) => {
                 let replying_to = ndb.get_note_by_id(txn, target.bytes())?;
-                self.post.to_reply(&seckey, &replying_to)
+                self.post.to_reply(&replying_to)
             }
 
             PostType::Quote(target) => {
                 let quoting = ndb.get_note_by_id(txn, target.bytes())?;
-                self.post.to_quote(&seckey, &quoting)
+                self.post.to_quote(&quoting)
             }
         };
 
-        let raw_msg = format!("[\"EVENT\",{}]", note.json().unwrap());
-        info!("sending {}", raw_msg);
-        pool.send(&enostr::ClientMessage::raw(raw_msg));
+        let note = UnsignedNote::from_builder(self.post.account, builder)?;
+        info!("sending {}", note.id().hex());
+        accounts
+            .sign_and_send(ndb, pool, wakeup, note, Destination::Outbox)
+            .map_err(|err| err.to_string())?;
         drafts.get_from_post_type(&self.post_type).clear();
 
         Ok(())
@@ -83,7 +85,7 @@ impl<'a> PostView<'a> {
         post_type: PostType,
         img_cache: &'a mut ImageCache,
         note_cache: &'a mut NoteCache,
-        poster: FilledKeypair<'a>,
+        poster: Pubkey,
     ) -> Self {
         let id_source: Option<egui::Id> = None;
         PostView {
@@ -110,7 +112,7 @@ impl<'a> PostView<'a> {
         // TODO: refactor pfp control to do all of this for us
         let poster_pfp = self
             .ndb
-            .get_profile_by_pubkey(txn, self.poster.pubkey.bytes())
+            .get_profile_by_pubkey(txn, self.poster.bytes())
             .as_ref()
             .ok()
             .and_then(|p| Some(ui::ProfilePic::from_profile(self.img_cache, p)?.size(pfp_size)));
@@ -214,10 +216,8 @@ impl<'a> PostView<'a> {
                                     )
                                     .clicked()
                                 {
-                                    let new_post = NewPost::new(
-                                        self.draft.buffer.clone(),
-                                        self.poster.to_full(),
-                                    );
+                                    let new_post =
+                                        NewPost::new(self.draft.buffer.clone(), self.poster);
                                     Some(PostAction::new(self.post_type.clone(), new_post))
                                 } else {
                                     None
@@ -289,7 +289,7 @@ mod preview {
                 PostType::New,
                 &mut self.img_cache,
                 &mut self.note_cache,
-                self.poster.to_filled(),
+                self.poster.pubkey,
             )
             .ui(&txn, ui);
         }
@@ -1,4 +1,4 @@
-use enostr::{FilledKeypair, NoteId};
+use enostr::{NoteId, Pubkey};
 use nostrdb::Ndb;
 use notedeck::{ImageCache, NoteCache};
 
@@ -8,7 +8,7 @@ use super::{PostResponse, PostType};
 
 pub struct QuoteRepostView<'a> {
     ndb: &'a Ndb,
-    poster: FilledKeypair<'a>,
+    poster: Pubkey,
     note_cache: &'a mut NoteCache,
     img_cache: &'a mut ImageCache,
     draft: &'a mut Draft,
@@ -19,7 +19,7 @@ pub struct QuoteRepostView<'a> {
 impl<'a> QuoteRepostView<'a> {
     pub fn new(
         ndb: &'a Ndb,
-        poster: FilledKeypair<'a>,
+        poster: Pubkey,
         note_cache: &'a mut NoteCache,
         img_cache: &'a mut ImageCache,
         draft: &'a mut Draft,
@@ -1,14 +1,14 @@
 use crate::draft::Draft;
 use crate::ui;
 use crate::ui::note::{PostResponse, PostType};
-use enostr::{FilledKeypair, NoteId};
+use enostr::{NoteId, Pubkey};
 use nostrdb::Ndb;
 
 use notedeck::{ImageCache, NoteCache};
 
 pub struct PostReplyView<'a> {
     ndb: &'a Ndb,
-    poster: FilledKeypair<'a>,
+    poster: Pubkey,
     note_cache: &'a mut NoteCache,
     img_cache: &'a mut ImageCache,
     draft: &'a mut Draft,
@@ -19,7 +19,7 @@ pub struct PostReplyView<'a> {
 impl<'a> PostReplyView<'a> {
     pub fn new(
         ndb: &'a Ndb,
-        poster: FilledKeypair<'a>,
+        poster: Pubkey,
         draft: &'a mut Draft,
         note_cache: &'a mut NoteCache,
         img_cache: &'a mut ImageCache,
@@ -0,0 +1,286 @@
+use egui::{Align2, Button, RichText, TextEdit, Vec2};
+use nostrdb::Ndb;
+use notedeck::{Accounts, KeyStoreUpdate, NotedeckTextStyle};
+use poll_promise::Promise;
+use tracing::{error, info};
+
+use crate::colors::PINK;
+
+/// What the user typed into the passphrase window, and whether it's open
+#[derive(Default)]
+pub struct PassphraseViewState {
+    passphrase: String,
+    confirm: String,
+    error: Option<String>,
+    /// Set when the user would rather not unlock right now
+    dismissed: bool,
+    /// Set when the user asked to (re)encrypt their keys
+    changing: bool,
+    /// The unlock or passphrase change running in the background
+    working: Option<Working>,
+}
+
+/// Whether the key storage is being unlocked or re-encrypted, and the
+/// result once it's done
+enum Working {
+    Unlocking(Promise<notedeck::Result<KeyStoreUpdate>>),
+    Changing(Promise<notedeck::Result<KeyStoreUpdate>>),
+}
+
+impl Working {
+    fn is_done(&self) -> bool {
+        match self {
+            Working::Unlocking(promise) | Working::Changing(promise) => promise.ready().is_some(),
+        }
+    }
+}
+
+impl PassphraseViewState {
+    /// Open the window to set a new passphrase. If the keys are locked,
+    /// they need unlocking first.
+    pub fn open_change(&mut self) {
+        self.dismissed = false;
+        self.changing = true;
+    }
+
+    /// Pick up the unlocked or re-encrypted keys, once they're ready
+    pub fn poll(&mut self, ndb: &Ndb, accounts: &mut Accounts) {
+        if !self.working.as_ref().is_some_and(Working::is_done) {
+            return;
+        }
+
+        match self.working.take() {
+            Some(Working::Unlocking(promise)) => match promise.block_and_take() {
+                Ok(update) => {
+                    info!("unlocked stored keys");
+                    let undecryptable = update.undecryptable().len();
+                    accounts.key_store_updated(ndb, update);
+                    if undecryptable > 0 {
+                        // keep the window up, so they know which can't post
+                        self.passphrase.clear();
+                        self.error = Some(format!(
+                            "{} of your keys didn't decrypt with this passphrase",
+                            undecryptable
+                        ));
+                    } else if self.changing {
+                        // on to the new passphrase
+                        self.passphrase.clear();
+                        self.error = None;
+                    } else {
+                        self.close();
+                    }
+                }
+                Err(err) => {
+                    error!("could not unlock stored keys: {}", err);
+                    self.passphrase.clear();
+                    self.error = Some("Wrong passphrase".to_owned());
+                }
+            },
+            Some(Working::Changing(promise)) => match promise.block_and_take() {
+                Ok(update) => {
+                    info!("stored keys encrypted with a new passphrase");
+                    accounts.key_store_updated(ndb, update);
+                    self.close();
+                }
+                Err(err) => {
+                    error!("could not change passphrase: {}", err);
+                    self.error = Some(err.to_string());
+                }
+            },
+            None => {}
+        }
+    }
+
+    fn close(&mut self) {
+        *self = PassphraseViewState {
+            dismissed: true,
+            ..Default::default()
+        };
+    }
+
+    fn is_open(&self, locked: bool) -> bool {
+        // an error after unlocking says which keys are still missing
+        !self.dismissed && (locked || self.changing || self.error.is_some())
+    }
+}
+
+pub enum PassphraseAction {
+    Unlock(String),
+    Change(String),
+    Dismiss,
+}
+
+impl PassphraseAction {
+    /// Start unlocking or re-encrypting the keys. See
+    /// [`PassphraseViewState::poll`] for when it's done.
+    pub fn process(self, accounts: &Accounts, state: &mut PassphraseViewState) {
+        match self {
+            PassphraseAction::Unlock(passphrase) => {
+                state.error = None;
+                state.working = Some(Working::Unlocking(accounts.unlock(&passphrase)));
+            }
+            PassphraseAction::Change(passphrase) => {
+                state.error = None;
+                state.working = Some(Working::Changing(accounts.change_passphrase(&passphrase)));
+            }
+            PassphraseAction::Dismiss => state.close(),
+        }
+    }
+}
+
+/// Asks for the passphrase the stored secret keys are encrypted with, or
+/// for a new one to encrypt them with
+pub struct PassphraseView<'a> {
+    state: &'a mut PassphraseViewState,
+    locked: bool,
+    encrypted: bool,
+}
+
+impl<'a> PassphraseView<'a> {
+    pub fn new(state: &'a mut PassphraseViewState, accounts: &Accounts) -> Self {
+        PassphraseView {
+            state,
+            locked: accounts.is_locked(),
+            encrypted: accounts.is_encrypted(),
+        }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) -> Option<PassphraseAction> {
+        if !self.state.is_open(self.locked) {
+            return None;
+        }
+
+        let title = if self.locked {
+            "Unlock your accounts"
+        } else if !self.state.changing {
+            "Some keys are still locked"
+        } else if self.encrypted {
+            "Change passphrase"
+        } else {
+            "Encrypt your keys"
+        };
+
+        egui::Window::new(title)
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, Vec2::ZERO)
+            .show(ctx, |ui| {
+                if self.locked {
+                    self.unlock_ui(ui)
+                } else if !self.state.changing {
+                    self.undecryptable_ui(ui)
+                } else {
+                    self.change_ui(ui)
+                }
+            })
+            .and_then(|resp| resp.inner)
+            .flatten()
+    }
+
+    fn unlock_ui(&mut self, ui: &mut egui::Ui) -> Option<PassphraseAction> {
+        ui.label(
+            RichText::new(
+                "Your secret keys are encrypted. Until you unlock them, your accounts can't post.",
+            )
+            .text_style(NotedeckTextStyle::Body.text_style()),
+        );
+        ui.add_space(8.0);
+
+        let field = ui.add(passphrase_textedit(
+            &mut self.state.passphrase,
+            "Passphrase",
+        ));
+        let entered = field.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        self.error_ui(ui);
+
+        ui.add_space(8.0);
+        let working = self.state.working.is_some();
+        ui.horizontal(|ui| {
+            if ui.add_enabled(!working, primary_button("Unlock")).clicked() || (entered && !working)
+            {
+                return Some(PassphraseAction::Unlock(self.state.passphrase.clone()));
+            }
+            if ui.add_enabled(!working, Button::new("Not now")).clicked() {
+                return Some(PassphraseAction::Dismiss);
+            }
+            if working {
+                ui.spinner();
+            }
+            None
+        })
+        .inner
+    }
+
+    fn change_ui(&mut self, ui: &mut egui::Ui) -> Option<PassphraseAction> {
+        ui.label(
+            RichText::new("Your secret keys are stored encrypted with this passphrase. You'll need it every time you start up.")
+                .text_style(NotedeckTextStyle::Body.text_style()),
+        );
+        ui.add_space(8.0);
+
+        ui.add(passphrase_textedit(
+            &mut self.state.passphrase,
+            "New passphrase",
+        ));
+        ui.add(passphrase_textedit(
+            &mut self.state.confirm,
+            "Confirm passphrase",
+        ));
+
+        let matches = self.state.passphrase == self.state.confirm;
+        if !matches && !self.state.confirm.is_empty() {
+            ui.colored_label(ui.visuals().error_fg_color, "The passphrases don't match");
+        }
+        self.error_ui(ui);
+
+        ui.add_space(8.0);
+        let working = self.state.working.is_some();
+        ui.horizontal(|ui| {
+            let can_save = matches && !self.state.passphrase.is_empty() && !working;
+            if ui.add_enabled(can_save, primary_button("Save")).clicked() {
+                return Some(PassphraseAction::Change(self.state.passphrase.clone()));
+            }
+            if ui.add_enabled(!working, Button::new("Cancel")).clicked() {
+                return Some(PassphraseAction::Dismiss);
+            }
+            if working {
+                ui.spinner();
+            }
+            None
+        })
+        .inner
+    }
+
+    fn undecryptable_ui(&mut self, ui: &mut egui::Ui) -> Option<PassphraseAction> {
+        self.error_ui(ui);
+        ui.label(
+            RichText::new("Those accounts can't post until you log in to them again.")
+                .text_style(NotedeckTextStyle::Body.text_style()),
+        );
+
+        ui.add_space(8.0);
+        ui.add(primary_button("OK"))
+            .clicked()
+            .then_some(PassphraseAction::Dismiss)
+    }
+
+    fn error_ui(&self, ui: &mut egui::Ui) {
+        if let Some(err) = &self.state.error {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+    }
+}
+
+fn passphrase_textedit<'a>(text: &'a mut String, hint: &'a str) -> TextEdit<'a> {
+    TextEdit::singleline(text)
+        .password(true)
+        .hint_text(hint)
+        .min_size(Vec2::new(0.0, 32.0))
+        .desired_width(f32::INFINITY)
+}
+
+fn primary_button(text: &str) -> Button<'static> {
+    Button::new(RichText::new(text.to_owned()).color(egui::Color32::WHITE))
+        .fill(PINK)
+        .min_size(Vec2::new(80.0, 32.0))
+}
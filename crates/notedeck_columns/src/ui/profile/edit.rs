@@ -0,0 +1,116 @@
+use egui::{Button, RichText, ScrollArea, TextEdit};
+use notedeck::ImageCache;
+
+use crate::{profile::DisplayName, profile_state::ProfileState, ui::padding};
+
+use super::{preview::display_name_widget, ProfilePic, ProfilePreview};
+
+/// A form for the selected account's kind 0 metadata, with a preview of
+/// how the profile will look
+pub struct EditProfileView<'a> {
+    state: &'a mut ProfileState,
+    img_cache: &'a mut ImageCache,
+}
+
+impl<'a> EditProfileView<'a> {
+    pub fn new(state: &'a mut ProfileState, img_cache: &'a mut ImageCache) -> Self {
+        EditProfileView { state, img_cache }
+    }
+
+    /// Returns true when saved with valid metadata
+    pub fn ui(&mut self, ui: &mut egui::Ui) -> bool {
+        ScrollArea::vertical()
+            .id_salt("edit_profile")
+            .show(ui, |ui| {
+                self.preview_ui(ui);
+                padding(16.0, ui, |ui| self.form_ui(ui)).inner
+            })
+            .inner
+    }
+
+    fn preview_ui(&mut self, ui: &mut egui::Ui) {
+        let banner = self.state.preview_banner();
+        ui.add_sized([ui.available_size().x, 80.0], |ui: &mut egui::Ui| {
+            ProfilePreview::banner(ui, self.img_cache, banner)
+        });
+
+        padding(12.0, ui, |ui| {
+            let picture = self
+                .state
+                .preview_picture()
+                .unwrap_or(ProfilePic::no_pfp_url());
+            ui.add(ProfilePic::new(self.img_cache, picture).size(80.0));
+            ui.add(display_name_widget(display_name(self.state), false));
+            if !self.state.about.is_empty() {
+                ui.label(&self.state.about);
+            }
+        });
+    }
+
+    fn form_ui(&mut self, ui: &mut egui::Ui) -> bool {
+        if self.state.created_at.is_none() {
+            ui.label(
+                RichText::new(
+                    "We couldn't find your existing profile, saving will replace it on relays",
+                )
+                .color(ui.visuals().warn_fg_color),
+            );
+            ui.add_space(8.0);
+        }
+
+        let state = &mut *self.state;
+        field(ui, "Display name", &mut state.display_name);
+        field(ui, "Username", &mut state.name);
+
+        ui.label("About");
+        ui.add(
+            TextEdit::multiline(&mut state.about)
+                .desired_rows(4)
+                .desired_width(f32::INFINITY),
+        );
+        ui.add_space(8.0);
+
+        // the preview catches up once the url is typed out
+        let picture = field(ui, "Picture url", &mut state.picture);
+        let banner = field(ui, "Banner url", &mut state.banner);
+        if picture.lost_focus() || banner.lost_focus() {
+            state.update_preview();
+        }
+        field(ui, "Website", &mut state.website);
+        field(ui, "Nostr address (NIP-05)", &mut state.nip05);
+        field(ui, "Lightning address", &mut state.lud16);
+        field(ui, "LNURL", &mut state.lud06);
+        ui.checkbox(&mut state.bot, "This account is a bot");
+        ui.add_space(16.0);
+
+        let valid = state.validate();
+        if let Err(err) = &valid {
+            ui.label(RichText::new(err.to_string()).color(ui.visuals().error_fg_color));
+            ui.add_space(8.0);
+        }
+
+        ui.add_enabled(valid.is_ok(), Button::new("Save")).clicked()
+    }
+}
+
+fn field(ui: &mut egui::Ui, label: &str, value: &mut String) -> egui::Response {
+    ui.label(label);
+    let resp = ui.add(TextEdit::singleline(value).desired_width(f32::INFINITY));
+    ui.add_space(8.0);
+    resp
+}
+
+fn display_name(state: &ProfileState) -> DisplayName<'_> {
+    let display_name = state.display_name.trim();
+    let name = state.name.trim();
+
+    match (display_name.is_empty(), name.is_empty()) {
+        (true, true) => DisplayName::One("??"),
+        (false, true) => DisplayName::One(display_name),
+        (true, false) => DisplayName::One(name),
+        (false, false) => DisplayName::Both {
+            display_name,
+            username: name,
+        },
+    }
+}
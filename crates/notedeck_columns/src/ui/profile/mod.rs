@@ -1,8 +1,10 @@
+pub mod edit;
 pub mod picture;
 pub mod preview;
 
 use crate::notes_holder::NotesHolder;
-use crate::ui::note::NoteOptions;
+use crate::ui::note::{NoteContext, NoteOptions};
+pub use edit::EditProfileView;
 use egui::{ScrollArea, Widget};
 use enostr::Pubkey;
 use nostrdb::{Ndb, Transaction};
@@ -10,10 +12,15 @@ pub use picture::ProfilePic;
 pub use preview::ProfilePreview;
 use tracing::error;
 
-use crate::{actionbar::NoteAction, notes_holder::NotesHolderStorage, profile::Profile};
+use crate::{
+    actionbar::{ContactAction, MuteAction, NoteAction},
+    counts::format_count,
+    notes_holder::NotesHolderStorage,
+    profile::Profile,
+};
 
 use super::timeline::{tabs_ui, TimelineTabView};
-use notedeck::{ImageCache, MuteFun, NoteCache};
+use notedeck::{ImageCache, Mute, MuteFun, Muted, NoteCache};
 
 pub struct ProfileView<'a> {
     pubkey: &'a Pubkey,
@@ -23,6 +30,9 @@ pub struct ProfileView<'a> {
     ndb: &'a Ndb,
     note_cache: &'a mut NoteCache,
     img_cache: &'a mut ImageCache,
+    context: NoteContext<'a>,
+    muted: Option<&'a Muted>,
+    editable: bool,
 }
 
 impl<'a> ProfileView<'a> {
@@ -43,9 +53,32 @@ impl<'a> ProfileView<'a> {
             note_cache,
             img_cache,
             note_options,
+            context: NoteContext::default(),
+            muted: None,
+            editable: false,
         }
     }
 
+    /// What the profile's notes are shown with. Its follows also give
+    /// the follow button, and its counts the follower count
+    pub fn context(mut self, context: NoteContext<'a>) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// The selected account's mutes. Follow and mute buttons are only
+    /// shown with it, leave it out for your own profile
+    pub fn muted(mut self, muted: Option<&'a Muted>) -> Self {
+        self.muted = muted;
+        self
+    }
+
+    /// Our own profile, with a secret key to publish changes
+    pub fn editable(mut self, editable: bool) -> Self {
+        self.editable = editable;
+        self
+    }
+
     pub fn ui(&mut self, ui: &mut egui::Ui, is_muted: &MuteFun) -> Option<NoteAction> {
         let scroll_id = egui::Id::new(("profile_scroll", self.col_id, self.pubkey));
 
@@ -56,6 +89,18 @@ impl<'a> ProfileView<'a> {
                 if let Ok(profile) = self.ndb.get_profile_by_pubkey(&txn, self.pubkey.bytes()) {
                     ProfilePreview::new(&profile, self.img_cache).ui(ui);
                 }
+                let account_action = if self.editable {
+                    ui.button("Edit profile")
+                        .clicked()
+                        .then_some(NoteAction::EditProfile)
+                } else {
+                    self.account_actions(ui)
+                };
+                if let Some(counts) = self.context.counts.as_deref_mut() {
+                    if let Some(followers) = counts.followers(self.pubkey) {
+                        ui.label(format!("{} followers", format_count(followers)));
+                    }
+                }
                 let profile = self
                     .profiles
                     .notes_holder_mutated(
@@ -70,7 +115,9 @@ impl<'a> ProfileView<'a> {
                 profile.timeline.selected_view = tabs_ui(ui);
 
                 // poll for new notes and insert them into our existing notes
-                if let Err(e) = profile.poll_notes_into_view(&txn, self.ndb, is_muted) {
+                if let Err(e) =
+                    profile.poll_notes_into_view(&txn, self.ndb, self.note_cache, is_muted)
+                {
                     error!("Profile::poll_notes_into_view: {e}");
                 }
 
@@ -85,8 +132,54 @@ impl<'a> ProfileView<'a> {
                     self.note_cache,
                     self.img_cache,
                 )
+                .context(self.context.reborrow())
                 .show(ui)
+                .or(account_action)
             })
             .inner
     }
+
+    /// Follow and mute buttons, when looking at someone else's profile
+    fn account_actions(&self, ui: &mut egui::Ui) -> Option<NoteAction> {
+        let muted = self.muted?;
+        let pubkey = *self.pubkey;
+
+        ui.horizontal(|ui| {
+            let following = self
+                .context
+                .contacts
+                .map(|contacts| contacts.is_following(pubkey.bytes()));
+            let (label, action) = if following == Some(true) {
+                ("Unfollow", ContactAction::Unfollow(pubkey))
+            } else {
+                ("Follow", ContactAction::Follow(pubkey))
+            };
+            // only once it's fetched, publishing a stale or empty contact
+            // list would unfollow people
+            let follow = ui
+                .add_enabled(self.context.can_follow, egui::Button::new(label))
+                .on_disabled_hover_text("Your contact list can't be changed yet")
+                .clicked()
+                .then_some(action);
+
+            let mute = Mute::Pubkey(*pubkey.bytes());
+            let (label, mute_action) = if muted.contains(&mute) {
+                ("Unmute", MuteAction::Unmute(mute))
+            } else {
+                // publicly if the private mutes couldn't be decrypted
+                let private = muted.can_edit_private();
+                ("Mute", MuteAction::Mute { mute, private })
+            };
+            let mute_action = ui
+                .add_enabled(self.context.can_mute, egui::Button::new(label))
+                .on_disabled_hover_text("Your mute list can't be changed yet")
+                .clicked()
+                .then_some(mute_action);
+
+            follow
+                .map(NoteAction::Contact)
+                .or(mute_action.map(NoteAction::Mute))
+        })
+        .inner
+    }
 }
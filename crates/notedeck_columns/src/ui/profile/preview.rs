@@ -1,6 +1,6 @@
+use crate::images::ImageType;
 use crate::ui::ProfilePic;
 use crate::{colors, images, DisplayName};
-use egui::load::TexturePoll;
 use egui::{Frame, Label, RichText, Sense, Widget};
 use egui_extras::Size;
 use enostr::{NoteId, Pubkey};
@@ -8,6 +8,9 @@ use nostrdb::{Ndb, ProfileRecord, Transaction};
 
 use notedeck::{DataPath, DataPathType, ImageCache, NotedeckTextStyle, UserAccount};
 
+/// Banners are scaled down to fit in a square this big
+const BANNER_MAX_SIZE: u32 = 1000;
+
 pub struct ProfilePreview<'a, 'cache> {
     profile: &'a ProfileRecord<'a>,
     cache: &'cache mut ImageCache,
@@ -30,27 +33,35 @@ impl<'a, 'cache> ProfilePreview<'a, 'cache> {
 
     fn banner_texture(
         ui: &mut egui::Ui,
-        profile: &ProfileRecord<'_>,
+        img_cache: &mut ImageCache,
+        banner: Option<&str>,
     ) -> Option<egui::load::SizedTexture> {
-        // TODO: cache banner
-        let banner = profile.record().profile().and_then(|p| p.banner());
-
-        if let Some(banner) = banner {
-            let texture_load_res =
-                egui::Image::new(banner).load_for_size(ui.ctx(), ui.available_size());
-            if let Ok(texture_poll) = texture_load_res {
-                match texture_poll {
-                    TexturePoll::Pending { .. } => {}
-                    TexturePoll::Ready { texture, .. } => return Some(texture),
-                }
-            }
+        let banner = banner?;
+
+        // go through the image cache rather than egui's http loader, which
+        // knows nothing about our proxy settings
+        if !img_cache.map().contains_key(banner) {
+            let promise = images::fetch_img(
+                img_cache,
+                ui.ctx(),
+                banner,
+                ImageType::Content(BANNER_MAX_SIZE, BANNER_MAX_SIZE),
+            );
+            img_cache.map_mut().insert(banner.to_owned(), promise);
         }
 
-        None
+        match img_cache.map()[banner].ready()? {
+            Ok(texture) => Some(egui::load::SizedTexture::from_handle(texture)),
+            Err(_) => None,
+        }
     }
 
-    fn banner(ui: &mut egui::Ui, profile: &ProfileRecord<'_>) -> egui::Response {
-        if let Some(texture) = Self::banner_texture(ui, profile) {
+    pub fn banner(
+        ui: &mut egui::Ui,
+        img_cache: &mut ImageCache,
+        banner: Option<&str>,
+    ) -> egui::Response {
+        if let Some(texture) = Self::banner_texture(ui, img_cache, banner) {
             images::aspect_fill(
                 ui,
                 Sense::hover(),
@@ -79,7 +90,11 @@ impl egui::Widget for ProfilePreview<'_, '_> {
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
         ui.vertical(|ui| {
             ui.add_sized([ui.available_size().x, 80.0], |ui: &mut egui::Ui| {
-                ProfilePreview::banner(ui, self.profile)
+                ProfilePreview::banner(
+                    ui,
+                    self.cache,
+                    self.profile.record().profile().and_then(|p| p.banner()),
+                )
             });
 
             self.body(ui);
@@ -217,7 +232,7 @@ pub fn get_account_url<'a>(
     }
 }
 
-fn display_name_widget(
+pub fn display_name_widget(
     display_name: DisplayName<'_>,
     add_placeholder_space: bool,
 ) -> impl egui::Widget + '_ {
@@ -1,16 +1,111 @@
-use crate::relay_pool_manager::{RelayPoolManager, RelayStatus};
-use crate::ui::{Preview, PreviewConfig, View};
+use crate::relay_pool_manager::{
+    RelayAuthStatus, RelayInfo, RelayInfoDocument, RelayPoolManager, RelayStatus,
+};
+use crate::ui::{Preview, PreviewConfig, ProfilePic, View};
 use egui::{Align, Button, Frame, Layout, Margin, Rgba, RichText, Rounding, Ui, Vec2};
+use std::collections::BTreeSet;
+use std::time::Duration;
 
 use enostr::RelayPool;
-use notedeck::NotedeckTextStyle;
+use nostrdb::Ndb;
+use notedeck::{Accounts, ImageCache, NotedeckTextStyle, RelaySpec};
+use tracing::error;
+
+/// Where a relay typed into the relay screen goes
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub enum RelayInputKind {
+    /// Advertised for reading and writing
+    #[default]
+    Both,
+    Read,
+    Write,
+    /// Only used by us, not advertised
+    Local,
+}
+
+#[derive(Default)]
+pub struct RelayViewState {
+    input: String,
+    kind: RelayInputKind,
+    error: Option<String>,
+}
+
+/// An edit to the selected account's relays
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum RelayAction {
+    /// Add to the NIP-65 list, or change the markers of a relay in it
+    Advertise(RelaySpec),
+    Unadvertise(String),
+    AddLocal(String),
+    RemoveLocal(String),
+}
+
+impl RelayAction {
+    pub fn process(self, ndb: &Ndb, accounts: &mut Accounts, pool: &mut RelayPool) {
+        let ok = match &self {
+            RelayAction::Advertise(spec) => accounts.advertise_relay(ndb, pool, spec.clone()),
+            RelayAction::Unadvertise(url) => accounts.unadvertise_relay(ndb, pool, url),
+            RelayAction::AddLocal(url) => accounts.add_local_relay(url.clone()),
+            RelayAction::RemoveLocal(url) => accounts.remove_local_relay(url),
+        };
+
+        if !ok {
+            error!("could not apply {:?}", self);
+        }
+    }
+}
+
+/// The selected account's relays, as shown on the relay screen
+pub struct AccountRelays<'a> {
+    pub advertised: Option<&'a BTreeSet<RelaySpec>>,
+    pub local: Option<&'a BTreeSet<String>>,
+    /// Without a secret key we can't publish a new relay list
+    pub editable: bool,
+    /// Whether a relay has sent us the list, or told us there's none.
+    /// Until then it can't be edited.
+    pub fetched: bool,
+}
 
 pub struct RelayView<'a> {
     manager: RelayPoolManager<'a>,
+
+    /// For relay icons, they aren't shown without it
+    img_cache: Option<&'a mut ImageCache>,
+
+    /// The selected account's relay lists, only shown with it
+    account: Option<(AccountRelays<'a>, &'a mut RelayViewState)>,
 }
 
 impl View for RelayView<'_> {
     fn ui(&mut self, ui: &mut egui::Ui) {
+        let _ = self.show(ui);
+    }
+}
+
+impl<'a> RelayView<'a> {
+    pub fn new(manager: RelayPoolManager<'a>) -> Self {
+        RelayView {
+            manager,
+            img_cache: None,
+            account: None,
+        }
+    }
+
+    pub fn img_cache(mut self, img_cache: &'a mut ImageCache) -> Self {
+        self.img_cache = Some(img_cache);
+        self
+    }
+
+    pub fn account_relays(
+        mut self,
+        relays: AccountRelays<'a>,
+        state: &'a mut RelayViewState,
+    ) -> Self {
+        self.account = Some((relays, state));
+        self
+    }
+
+    pub fn show(&mut self, ui: &mut egui::Ui) -> Option<RelayAction> {
         ui.add_space(24.0);
 
         ui.horizontal(|ui| {
@@ -19,13 +114,6 @@ impl View for RelayView<'_> {
                     RichText::new("Relays").text_style(NotedeckTextStyle::Heading2.text_style()),
                 );
             });
-
-            // TODO: implement manually adding relays
-            // ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
-            //     if ui.add(add_relay_button()).clicked() {
-            //         // TODO: navigate to 'add relay view'
-            //     };
-            // });
         });
 
         ui.add_space(8.0);
@@ -34,16 +122,23 @@ impl View for RelayView<'_> {
             .scroll_bar_visibility(egui::scroll_area::ScrollBarVisibility::AlwaysHidden)
             .auto_shrink([false; 2])
             .show(ui, |ui| {
+                let action = self.account.as_mut().and_then(|(relays, state)| {
+                    let action = account_relays_ui(ui, relays, state);
+                    ui.add_space(16.0);
+                    ui.label(
+                        RichText::new("Connections")
+                            .text_style(NotedeckTextStyle::Heading3.text_style()),
+                    );
+                    action
+                });
+
                 if let Some(indices) = self.show_relays(ui) {
                     self.manager.remove_relays(indices);
                 }
-            });
-    }
-}
 
-impl<'a> RelayView<'a> {
-    pub fn new(manager: RelayPoolManager<'a>) -> Self {
-        RelayView { manager }
+                action
+            })
+            .inner
     }
 
     pub fn panel(&mut self, ui: &mut egui::Ui) {
@@ -51,7 +146,7 @@ impl<'a> RelayView<'a> {
     }
 
     /// Show the current relays, and returns the indices of relays the user requested to delete
-    fn show_relays(&'a self, ui: &mut Ui) -> Option<Vec<usize>> {
+    fn show_relays(&mut self, ui: &mut Ui) -> Option<Vec<usize>> {
         let mut indices_to_remove: Option<Vec<usize>> = None;
         for (index, relay_info) in self.manager.get_relay_infos().iter().enumerate() {
             ui.add_space(8.0);
@@ -59,6 +154,11 @@ impl<'a> RelayView<'a> {
                 relay_frame(ui).show(ui, |ui| {
                     ui.horizontal(|ui| {
                         ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                            let icon = relay_info.info.and_then(|info| info.icon.as_deref());
+                            if let (Some(img_cache), Some(icon)) = (self.img_cache.as_mut(), icon) {
+                                ui.add(ProfilePic::new(img_cache, icon).size(ICON_SIZE));
+                            }
+
                             Frame::none()
                                 // This frame is needed to add margin because the label will be added to the outer frame first and centered vertically before the connection status is added so the vertical centering isn't accurate.
                                 // TODO: remove this hack and actually center the url & status at the same time
@@ -68,7 +168,8 @@ impl<'a> RelayView<'a> {
                                         .id_salt(index)
                                         .max_width(
                                             ui.max_rect().width()
-                                                - get_right_side_width(relay_info.status),
+                                                - get_right_side_width(relay_info.status)
+                                                - get_auth_width(relay_info.auth),
                                         ) // TODO: refactor to dynamically check the size of the 'right to left' portion and set the max width to be the screen width minus padding minus 'right to left' width
                                         .show(ui, |ui| {
                                             ui.label(
@@ -94,8 +195,15 @@ impl<'a> RelayView<'a> {
                             };
 
                             show_connection_status(ui, relay_info.status);
+                            show_auth_status(ui, relay_info.auth);
                         });
                     });
+
+                    show_relay_stats(ui, relay_info);
+
+                    if let Some(info) = relay_info.info {
+                        show_info_document(ui, index, info);
+                    }
                 });
             });
         }
@@ -104,6 +212,175 @@ impl<'a> RelayView<'a> {
     }
 }
 
+const ICON_SIZE: f32 = 24.0;
+
+fn account_relays_ui(
+    ui: &mut Ui,
+    relays: &AccountRelays<'_>,
+    state: &mut RelayViewState,
+) -> Option<RelayAction> {
+    if !relays.editable {
+        ui.label(
+            RichText::new("Add this account's secret key to edit its relay list")
+                .color(ui.visuals().weak_text_color()),
+        );
+        ui.add_space(8.0);
+    }
+    let editable = relays.editable && relays.fetched;
+
+    let mut action = add_relay_ui(ui, editable, state);
+
+    ui.add_space(8.0);
+    ui.label(RichText::new("Relay list").text_style(NotedeckTextStyle::Heading3.text_style()));
+    match relays.advertised {
+        Some(advertised) if !advertised.is_empty() => {
+            for spec in advertised {
+                if let Some(spec_action) = advertised_relay_ui(ui, editable, spec) {
+                    action = Some(spec_action);
+                }
+            }
+        }
+        _ if !relays.fetched => {
+            ui.label(
+                RichText::new("Looking for your relay list...")
+                    .color(ui.visuals().weak_text_color()),
+            );
+        }
+        _ => {
+            ui.label(
+                RichText::new("No relay list yet, adding a relay publishes one")
+                    .color(ui.visuals().weak_text_color()),
+            );
+        }
+    }
+
+    ui.add_space(8.0);
+    ui.label(RichText::new("Local relays").text_style(NotedeckTextStyle::Heading3.text_style()));
+    match relays.local {
+        Some(local) if !local.is_empty() => {
+            for url in local {
+                ui.horizontal(|ui| {
+                    relay_url_label(ui, url);
+                    ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                        if ui
+                            .button("Remove")
+                            .on_hover_text("Stop using this relay")
+                            .clicked()
+                        {
+                            action = Some(RelayAction::RemoveLocal(url.clone()));
+                        }
+                    });
+                });
+            }
+        }
+        _ => {
+            ui.label(
+                RichText::new("Relays only this device uses, they aren't advertised")
+                    .color(ui.visuals().weak_text_color()),
+            );
+        }
+    }
+
+    action
+}
+
+fn add_relay_ui(ui: &mut Ui, editable: bool, state: &mut RelayViewState) -> Option<RelayAction> {
+    ui.horizontal(|ui| {
+        ui.add_enabled_ui(editable, |ui| {
+            ui.selectable_value(&mut state.kind, RelayInputKind::Both, "Read & write");
+            ui.selectable_value(&mut state.kind, RelayInputKind::Read, "Read");
+            ui.selectable_value(&mut state.kind, RelayInputKind::Write, "Write");
+        });
+        ui.selectable_value(&mut state.kind, RelayInputKind::Local, "Local only");
+    });
+    if !editable && state.kind != RelayInputKind::Local {
+        state.kind = RelayInputKind::Local;
+    }
+
+    ui.add_space(8.0);
+
+    let mut action = None;
+    ui.horizontal(|ui| {
+        let text_resp = ui.add(
+            egui::TextEdit::singleline(&mut state.input)
+                .hint_text("wss://relay.example.com")
+                .font(NotedeckTextStyle::Monospace.text_style())
+                .desired_width(ui.available_width() - 100.0),
+        );
+        let submitted = text_resp.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+        if !ui.add(add_relay_button()).clicked() && !submitted {
+            return;
+        }
+
+        let url = if let Some(url) = RelaySpec::parse_url(&state.input) {
+            url
+        } else {
+            state.error = Some("Relay urls look like wss://relay.example.com".to_owned());
+            return;
+        };
+
+        state.input.clear();
+        state.error = None;
+        action = Some(match state.kind {
+            RelayInputKind::Both => RelayAction::Advertise(RelaySpec::new(url, false, false)),
+            RelayInputKind::Read => RelayAction::Advertise(RelaySpec::new(url, true, false)),
+            RelayInputKind::Write => RelayAction::Advertise(RelaySpec::new(url, false, true)),
+            RelayInputKind::Local => RelayAction::AddLocal(url),
+        });
+    });
+
+    if let Some(err) = &state.error {
+        ui.label(RichText::new(err).color(ui.visuals().error_fg_color));
+    }
+
+    action
+}
+
+fn advertised_relay_ui(ui: &mut Ui, editable: bool, spec: &RelaySpec) -> Option<RelayAction> {
+    ui.horizontal(|ui| {
+        relay_url_label(ui, &spec.url);
+
+        ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+            ui.add_enabled_ui(editable, |ui| {
+                if ui.button("Remove").clicked() {
+                    return Some(RelayAction::Unadvertise(spec.url.clone()));
+                }
+
+                // a relay has to be at least one of the two
+                let mut write = spec.is_writable();
+                let mut read = spec.is_readable();
+                let write_changed = ui
+                    .add_enabled(read, egui::Checkbox::new(&mut write, "Write"))
+                    .changed();
+                let read_changed = ui
+                    .add_enabled(write, egui::Checkbox::new(&mut read, "Read"))
+                    .changed();
+                if !write_changed && !read_changed {
+                    return None;
+                }
+
+                let both = read && write;
+                Some(RelayAction::Advertise(RelaySpec::new(
+                    spec.url.clone(),
+                    read && !both,
+                    write && !both,
+                )))
+            })
+            .inner
+        })
+        .inner
+    })
+    .inner
+}
+
+fn relay_url_label(ui: &mut Ui, url: &str) {
+    ui.label(
+        RichText::new(url)
+            .text_style(NotedeckTextStyle::Monospace.text_style())
+            .color(ui.visuals().noninteractive().fg_stroke.color),
+    );
+}
+
 fn get_right_side_width(status: &RelayStatus) -> f32 {
     match status {
         RelayStatus::Connected => 150.0,
@@ -112,7 +389,15 @@ fn get_right_side_width(status: &RelayStatus) -> f32 {
     }
 }
 
-#[allow(unused)]
+fn get_auth_width(auth: &RelayAuthStatus) -> f32 {
+    match auth {
+        RelayAuthStatus::None | RelayAuthStatus::Authenticated => 0.0,
+        RelayAuthStatus::Required | RelayAuthStatus::Pending(_) | RelayAuthStatus::Failed(_) => {
+            120.0
+        }
+    }
+}
+
 fn add_relay_button() -> egui::Button<'static> {
     Button::new("+ Add relay").min_size(Vec2::new(0.0, 32.0))
 }
@@ -163,6 +448,169 @@ fn show_connection_status(ui: &mut Ui, status: &RelayStatus) {
     });
 }
 
+fn show_auth_status(ui: &mut Ui, auth: &RelayAuthStatus) {
+    let (label_text, fg_color) = match auth {
+        RelayAuthStatus::None | RelayAuthStatus::Authenticated => return,
+        RelayAuthStatus::Required => ("Auth required", ui.visuals().warn_fg_color),
+        RelayAuthStatus::Pending(_) => ("Authenticating...", ui.visuals().warn_fg_color),
+        RelayAuthStatus::Failed(_) => ("Auth failed", ui.visuals().error_fg_color),
+    };
+    let bg_color = egui::lerp(Rgba::from(fg_color)..=Rgba::BLACK, 0.8).into();
+
+    let frame = Frame::none()
+        .rounding(Rounding::same(100.0))
+        .fill(bg_color)
+        .inner_margin(Margin::symmetric(12.0, 4.0));
+
+    let resp = frame
+        .show(ui, |ui| {
+            ui.label(RichText::new(label_text).color(fg_color));
+        })
+        .response;
+
+    match auth {
+        RelayAuthStatus::Required => {
+            resp.on_hover_text(
+                "This relay requires authentication. Log in with your nsec to use it.",
+            );
+        }
+        RelayAuthStatus::Failed(reason) => {
+            resp.on_hover_text(reason);
+        }
+        _ => {}
+    }
+}
+
+/// A line of connection health numbers under the relay url
+fn show_relay_stats(ui: &mut Ui, relay_info: &RelayInfo) {
+    let stats = relay_info.stats;
+    let mut parts: Vec<String> = Vec::new();
+
+    if let Some(reconnect_in) = relay_info.reconnect_in {
+        parts.push(format!("retrying in {}s", reconnect_in.as_secs()));
+    }
+    if let Some(latency) = stats.latency {
+        parts.push(format!("ping {}", format_duration(latency)));
+    }
+    if let Some(eose) = stats.average_eose_latency() {
+        parts.push(format!("EOSE {}", format_duration(eose)));
+    }
+    parts.push(format!(
+        "{} msgs, {}",
+        stats.messages_received,
+        format_bytes(stats.bytes_received)
+    ));
+    parts.push(format!(
+        "{} connects, {} drops",
+        stats.connect_attempts, stats.disconnects
+    ));
+
+    let weak = ui.visuals().weak_text_color();
+    ui.horizontal_wrapped(|ui| {
+        ui.label(
+            RichText::new(parts.join(" · "))
+                .text_style(NotedeckTextStyle::Small.text_style())
+                .color(weak),
+        );
+
+        if let Some(err) = &stats.last_error {
+            ui.label(
+                RichText::new("last error")
+                    .text_style(NotedeckTextStyle::Small.text_style())
+                    .color(ui.visuals().error_fg_color),
+            )
+            .on_hover_text(err);
+        }
+    });
+}
+
+/// What the relay says about itself in its NIP-11 document
+fn show_info_document(ui: &mut Ui, index: usize, info: &RelayInfoDocument) {
+    let title = info.name.as_deref().unwrap_or("Relay info");
+
+    egui::CollapsingHeader::new(title)
+        .id_salt(("relay_info", index))
+        .show(ui, |ui| {
+            if let Some(description) = &info.description {
+                ui.label(description);
+            }
+
+            let weak = ui.visuals().weak_text_color();
+            let small = |text: String| {
+                RichText::new(text)
+                    .text_style(NotedeckTextStyle::Small.text_style())
+                    .color(weak)
+            };
+
+            if let Some(contact) = &info.contact {
+                ui.label(small(format!("contact: {}", contact)));
+            }
+
+            if let Some(software) = &info.software {
+                let version = info.version.as_deref().unwrap_or_default();
+                ui.label(small(format!("software: {} {}", software, version)));
+            }
+
+            if !info.supported_nips.is_empty() {
+                let nips: Vec<String> = info.supported_nips.iter().map(u32::to_string).collect();
+                ui.label(small(format!("NIPs: {}", nips.join(", "))));
+            }
+
+            ui.horizontal_wrapped(|ui| {
+                let max_message_length =
+                    info.limitation.as_ref().and_then(|l| l.max_message_length);
+                if let Some(max) = max_message_length {
+                    limitation_pill(
+                        ui,
+                        &format!("max message {}", format_bytes(max)),
+                        ui.visuals().selection.bg_fill,
+                    );
+                }
+                if info.auth_required() {
+                    limitation_pill(ui, "Auth required", ui.visuals().warn_fg_color);
+                }
+                if info.payment_required() {
+                    limitation_pill(ui, "Payment required", ui.visuals().warn_fg_color);
+                }
+            });
+        });
+}
+
+fn limitation_pill(ui: &mut Ui, text: &str, fg_color: egui::Color32) {
+    let bg_color = egui::lerp(Rgba::from(fg_color)..=Rgba::BLACK, 0.8).into();
+
+    Frame::none()
+        .rounding(Rounding::same(100.0))
+        .fill(bg_color)
+        .inner_margin(Margin::symmetric(8.0, 2.0))
+        .show(ui, |ui| {
+            ui.label(
+                RichText::new(text)
+                    .text_style(NotedeckTextStyle::Small.text_style())
+                    .color(fg_color),
+            );
+        });
+}
+
+fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis < 1000 {
+        format!("{}ms", millis)
+    } else {
+        format!("{:.1}s", duration.as_secs_f32())
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
 fn get_connection_icon(status: &RelayStatus) -> egui::Image<'static> {
     let img_data = match status {
         RelayStatus::Connected => {
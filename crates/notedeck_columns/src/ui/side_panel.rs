@@ -32,6 +32,7 @@ pub struct DesktopSidePanel<'a> {
     img_cache: &'a mut ImageCache,
     selected_account: Option<&'a UserAccount>,
     decks_cache: &'a DecksCache,
+    can_compose: bool,
 }
 
 impl View for DesktopSidePanel<'_> {
@@ -50,6 +51,7 @@ pub enum SidePanelAction {
     Search,
     ExpandSidePanel,
     Support,
+    Muted,
     NewDeck,
     SwitchDeck(usize),
     EditDeck(usize),
@@ -79,9 +81,17 @@ impl<'a> DesktopSidePanel<'a> {
             img_cache,
             selected_account,
             decks_cache,
+            can_compose: false,
         }
     }
 
+    /// Whether we can sign for the selected account, with its key or a
+    /// remote signer
+    pub fn can_compose(mut self, can_compose: bool) -> Self {
+        self.can_compose = can_compose;
+        self
+    }
+
     pub fn show(&mut self, ui: &mut egui::Ui) -> SidePanelResponse {
         let mut frame = egui::Frame::none().inner_margin(Margin::same(8.0));
 
@@ -109,9 +119,7 @@ impl<'a> DesktopSidePanel<'a> {
                         ui.add_space(4.0);
                         ui.add(milestone_name());
                         ui.add_space(16.0);
-                        let is_interactive = self
-                            .selected_account
-                            .is_some_and(|s| s.secret_key.is_some());
+                        let is_interactive = self.can_compose;
                         let compose_resp = ui.add(compose_note_button(is_interactive));
                         let compose_resp = if is_interactive {
                             compose_resp
@@ -213,6 +221,10 @@ impl<'a> DesktopSidePanel<'a> {
                             None
                         };
 
+                        let muted_resp = ui
+                            .add(Button::new("🔇").frame(false))
+                            .on_hover_text("Muted users, words and hashtags");
+
                         let support_resp = ui.add(support_button());
 
                         let optional_inner = if pfp_resp.clicked() {
@@ -230,6 +242,8 @@ impl<'a> DesktopSidePanel<'a> {
                                 SidePanelAction::Support,
                                 support_resp,
                             ))
+                        } else if muted_resp.clicked() {
+                            Some(egui::InnerResponse::new(SidePanelAction::Muted, muted_resp))
                         } else if let Some((theme, resp)) = save_theme {
                             Some(egui::InnerResponse::new(
                                 SidePanelAction::SaveTheme(theme),
@@ -338,6 +352,13 @@ impl<'a> DesktopSidePanel<'a> {
                     router.route_to(Route::Support);
                 }
             }
+            SidePanelAction::Muted => {
+                if router.routes().iter().any(|&r| r == Route::Muted) {
+                    router.go_back();
+                } else {
+                    router.route_to(Route::muted());
+                }
+            }
             SidePanelAction::NewDeck => {
                 if router.routes().iter().any(|&r| r == Route::NewDeck) {
                     router.go_back();
@@ -0,0 +1,58 @@
+use egui::{Align2, RichText, Vec2};
+use std::collections::HashSet;
+
+/// The approval links the user has waved away
+#[derive(Default)]
+pub struct SignerApprovalViewState {
+    dismissed: HashSet<String>,
+}
+
+/// Links to approve what a remote signer is holding back. Some signers
+/// only sign once the user says so on a web page.
+pub struct SignerApprovalView<'a> {
+    state: &'a mut SignerApprovalViewState,
+    urls: Vec<String>,
+}
+
+impl<'a> SignerApprovalView<'a> {
+    pub fn new(state: &'a mut SignerApprovalViewState, urls: Vec<String>) -> Self {
+        SignerApprovalView { state, urls }
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        let urls = &self.urls;
+        self.state.dismissed.retain(|url| urls.contains(url));
+
+        let waiting: Vec<&String> = self
+            .urls
+            .iter()
+            .filter(|url| !self.state.dismissed.contains(*url))
+            .collect();
+        if waiting.is_empty() {
+            return;
+        }
+
+        egui::Window::new("Approve in your signer")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::RIGHT_BOTTOM, Vec2::new(-16.0, -16.0))
+            .show(ctx, |ui| {
+                ui.label(
+                    RichText::new("Your remote signer is waiting for you to approve a request")
+                        .color(ui.visuals().weak_text_color()),
+                );
+
+                for url in &waiting {
+                    if ui.hyperlink_to("Approve", *url).clicked() {
+                        self.state.dismissed.insert((*url).clone());
+                    }
+                }
+
+                if ui.button("Dismiss").clicked() {
+                    self.state
+                        .dismissed
+                        .extend(waiting.iter().map(|url| (*url).clone()));
+                }
+            });
+    }
+}
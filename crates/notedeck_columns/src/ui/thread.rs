@@ -2,7 +2,7 @@ use crate::{
     actionbar::NoteAction,
     notes_holder::{NotesHolder, NotesHolderStorage},
     thread::Thread,
-    ui::note::NoteOptions,
+    ui::note::{NoteContext, NoteOptions},
 };
 
 use nostrdb::{Ndb, NoteKey, Transaction};
@@ -20,6 +20,7 @@ pub struct ThreadView<'a> {
     selected_note_id: &'a [u8; 32],
     textmode: bool,
     id_source: egui::Id,
+    context: NoteContext<'a>,
 }
 
 impl<'a> ThreadView<'a> {
@@ -43,6 +44,7 @@ impl<'a> ThreadView<'a> {
             selected_note_id,
             textmode,
             id_source,
+            context: NoteContext::default(),
         }
     }
 
@@ -51,6 +53,11 @@ impl<'a> ThreadView<'a> {
         self
     }
 
+    pub fn context(mut self, context: NoteContext<'a>) -> Self {
+        self.context = context;
+        self
+    }
+
     pub fn ui(&mut self, ui: &mut egui::Ui, is_muted: &MuteFun) -> Option<NoteAction> {
         let txn = Transaction::new(self.ndb).expect("txn");
 
@@ -102,7 +109,7 @@ impl<'a> ThreadView<'a> {
                 // TODO(jb55): skip poll if ThreadResult is fresh?
 
                 // poll for new notes and insert them into our existing notes
-                match thread.poll_notes_into_view(&txn, self.ndb, is_muted) {
+                match thread.poll_notes_into_view(&txn, self.ndb, self.note_cache, is_muted) {
                     Ok(action) => {
                         action.process_action(&txn, self.ndb, self.unknown_ids, self.note_cache)
                     }
@@ -123,6 +130,7 @@ impl<'a> ThreadView<'a> {
                     self.note_cache,
                     self.img_cache,
                 )
+                .context(self.context.reborrow())
                 .show(ui)
             })
             .inner
@@ -1,11 +1,16 @@
 use crate::actionbar::NoteAction;
 use crate::timeline::TimelineTab;
-use crate::{column::Columns, timeline::TimelineId, ui, ui::note::NoteOptions};
+use crate::{
+    column::Columns,
+    timeline::TimelineId,
+    ui,
+    ui::note::{NoteContext, NoteOptions},
+};
 use egui::containers::scroll_area::ScrollBarVisibility;
 use egui::{Direction, Layout};
 use egui_tabs::TabColor;
 use nostrdb::{Ndb, Transaction};
-use notedeck::{ImageCache, NoteCache};
+use notedeck::{ImageCache, Muted, NoteCache};
 use tracing::{error, warn};
 
 pub struct TimelineView<'a> {
@@ -16,6 +21,7 @@ pub struct TimelineView<'a> {
     img_cache: &'a mut ImageCache,
     note_options: NoteOptions,
     reverse: bool,
+    context: NoteContext<'a>,
 }
 
 impl<'a> TimelineView<'a> {
@@ -36,71 +42,21 @@ impl<'a> TimelineView<'a> {
             img_cache,
             reverse,
             note_options,
+            context: NoteContext::default(),
         }
     }
 
     pub fn ui(&mut self, ui: &mut egui::Ui) -> Option<NoteAction> {
-        timeline_ui(
-            ui,
-            self.ndb,
-            self.timeline_id,
-            self.columns,
-            self.note_cache,
-            self.img_cache,
-            self.reverse,
-            self.note_options,
-        )
-    }
-
-    pub fn reversed(mut self) -> Self {
-        self.reverse = true;
-        self
-    }
-}
-
-#[allow(clippy::too_many_arguments)]
-fn timeline_ui(
-    ui: &mut egui::Ui,
-    ndb: &Ndb,
-    timeline_id: TimelineId,
-    columns: &mut Columns,
-    note_cache: &mut NoteCache,
-    img_cache: &mut ImageCache,
-    reversed: bool,
-    note_options: NoteOptions,
-) -> Option<NoteAction> {
-    //padding(4.0, ui, |ui| ui.heading("Notifications"));
-    /*
-    let font_id = egui::TextStyle::Body.resolve(ui.style());
-    let row_height = ui.fonts(|f| f.row_height(&font_id)) + ui.spacing().item_spacing.y;
-
-    */
-
-    let scroll_id = {
-        let timeline = if let Some(timeline) = columns.find_timeline_mut(timeline_id) {
-            timeline
-        } else {
-            error!("tried to render timeline in column, but timeline was missing");
-            // TODO (jb55): render error when timeline is missing?
-            // this shouldn't happen...
-            return None;
-        };
+        //padding(4.0, ui, |ui| ui.heading("Notifications"));
+        /*
+        let font_id = egui::TextStyle::Body.resolve(ui.style());
+        let row_height = ui.fonts(|f| f.row_height(&font_id)) + ui.spacing().item_spacing.y;
 
-        timeline.selected_view = tabs_ui(ui);
+        */
 
-        // need this for some reason??
-        ui.add_space(3.0);
-
-        egui::Id::new(("tlscroll", timeline.view_id()))
-    };
-
-    egui::ScrollArea::vertical()
-        .id_salt(scroll_id)
-        .animated(false)
-        .auto_shrink([false, false])
-        .scroll_bar_visibility(ScrollBarVisibility::AlwaysVisible)
-        .show(ui, |ui| {
-            let timeline = if let Some(timeline) = columns.find_timeline_mut(timeline_id) {
+        let scroll_id = {
+            let timeline = if let Some(timeline) = self.columns.find_timeline_mut(self.timeline_id)
+            {
                 timeline
             } else {
                 error!("tried to render timeline in column, but timeline was missing");
@@ -109,19 +65,57 @@ fn timeline_ui(
                 return None;
             };
 
-            let txn = Transaction::new(ndb).expect("failed to create txn");
-            TimelineTabView::new(
-                timeline.current_view(),
-                reversed,
-                note_options,
-                &txn,
-                ndb,
-                note_cache,
-                img_cache,
-            )
-            .show(ui)
-        })
-        .inner
+            timeline.selected_view = tabs_ui(ui);
+
+            // need this for some reason??
+            ui.add_space(3.0);
+
+            egui::Id::new(("tlscroll", timeline.view_id()))
+        };
+
+        egui::ScrollArea::vertical()
+            .id_salt(scroll_id)
+            .animated(false)
+            .auto_shrink([false, false])
+            .scroll_bar_visibility(ScrollBarVisibility::AlwaysVisible)
+            .show(ui, |ui| {
+                let timeline =
+                    if let Some(timeline) = self.columns.find_timeline_mut(self.timeline_id) {
+                        timeline
+                    } else {
+                        error!("tried to render timeline in column, but timeline was missing");
+                        // TODO (jb55): render error when timeline is missing?
+                        // this shouldn't happen...
+                        return None;
+                    };
+
+                let txn = Transaction::new(self.ndb).expect("failed to create txn");
+                TimelineTabView::new(
+                    timeline.current_view(),
+                    self.reverse,
+                    self.note_options,
+                    &txn,
+                    self.ndb,
+                    self.note_cache,
+                    self.img_cache,
+                )
+                .context(self.context.reborrow())
+                .show(ui)
+            })
+            .inner
+    }
+
+    pub fn reversed(mut self) -> Self {
+        self.reverse = true;
+        self
+    }
+
+    /// Publish status, counts, reactions, deletions and follows to show
+    /// notes with
+    pub fn context(mut self, context: NoteContext<'a>) -> Self {
+        self.context = context;
+        self
+    }
 }
 
 pub fn tabs_ui(ui: &mut egui::Ui) -> i32 {
@@ -216,6 +210,7 @@ pub struct TimelineTabView<'a> {
     ndb: &'a Ndb,
     note_cache: &'a mut NoteCache,
     img_cache: &'a mut ImageCache,
+    context: NoteContext<'a>,
 }
 
 impl<'a> TimelineTabView<'a> {
@@ -236,9 +231,17 @@ impl<'a> TimelineTabView<'a> {
             ndb,
             note_cache,
             img_cache,
+            context: NoteContext::default(),
         }
     }
 
+    /// Publish status, counts, reactions, deletions and follows to show
+    /// notes with
+    pub fn context(mut self, context: NoteContext<'a>) -> Self {
+        self.context = context;
+        self
+    }
+
     pub fn show(&mut self, ui: &mut egui::Ui) -> Option<NoteAction> {
         let mut action: Option<NoteAction> = None;
         let len = self.tab.notes.len();
@@ -269,6 +272,7 @@ impl<'a> TimelineTabView<'a> {
                 ui::padding(8.0, ui, |ui| {
                     let resp = ui::NoteView::new(self.ndb, self.note_cache, self.img_cache, &note)
                         .note_options(self.note_options)
+                        .context(self.context.reborrow())
                         .show(ui);
 
                     if let Some(note_action) = resp.action {
@@ -276,7 +280,11 @@ impl<'a> TimelineTabView<'a> {
                     }
 
                     if let Some(context) = resp.context_selection {
-                        context.process(ui, &note);
+                        let private_mutes =
+                            self.context.muted.map_or(true, Muted::can_edit_private);
+                        if let Some(note_action) = context.process(ui, &note, private_mutes) {
+                            action = Some(note_action);
+                        }
                     }
                 });
 
@@ -1,7 +1,16 @@
 use std::collections::HashMap;
 
+use enostr::Pubkey;
+
 use crate::deck_state::DeckState;
+use crate::dms::ConversationId;
 use crate::login_manager::AcquireKeyState;
+use crate::profile_state::ProfileState;
+use crate::ui::messages::ConversationViewState;
+use crate::ui::muted::MutedViewState;
+use crate::ui::passphrase::PassphraseViewState;
+use crate::ui::relay::RelayViewState;
+use crate::ui::signer_approval::SignerApprovalViewState;
 
 /// Various state for views
 #[derive(Default)]
@@ -10,6 +19,12 @@ pub struct ViewState {
     pub id_to_deck_state: HashMap<egui::Id, DeckState>,
     pub id_state_map: HashMap<egui::Id, AcquireKeyState>,
     pub id_string_map: HashMap<egui::Id, String>,
+    pub muted: MutedViewState,
+    pub relays: RelayViewState,
+    pub edit_profile: HashMap<Pubkey, ProfileState>,
+    pub conversations: HashMap<ConversationId, ConversationViewState>,
+    pub passphrase: PassphraseViewState,
+    pub signer_approval: SignerApprovalViewState,
 }
 
 impl ViewState {